@@ -0,0 +1,312 @@
+use crate::error::{create_output_dir, write_output, Error, Result};
+use crate::file_name::sanitize;
+use crate::hash_algo::fnv1a64;
+use crate::workspace::user_cache_dir;
+use miniz_oxide::inflate::TINFLStatus;
+use std::path::{Component, Path, PathBuf};
+
+const LOCAL_HEADER_SIG: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIG: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY_SIG: u32 = 0x0605_4b50;
+const END_OF_DIRECTORY_LEN: usize = 22;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// Whether a tileset path names a zip archive rather than a directory.
+pub fn is_archive(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|x| x.eq_ignore_ascii_case("zip"))
+}
+
+/// A file in a zip archive.
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    method: u16,
+    encrypted: bool,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    header_offset: usize,
+}
+
+/// A zip archive read into memory. Only stored and deflated files are
+/// supported, which is what every common zip tool writes; ZIP64 archives,
+/// larger than 4 GiB, are not.
+pub struct Archive {
+    path: PathBuf,
+    data: Vec<u8>,
+    entries: Vec<Entry>,
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> Result<Archive> {
+        let data = std::fs::read(path).map_err(|source| Error::ReadInput {
+            path: path.to_owned(),
+            source,
+        })?;
+        Archive::parse(path, data)
+    }
+
+    fn bad(&self, message: String) -> Error {
+        Error::BadArchive {
+            path: self.path.clone(),
+            message,
+        }
+    }
+
+    pub(crate) fn parse(path: &Path, data: Vec<u8>) -> Result<Archive> {
+        let mut archive = Archive {
+            path: path.to_owned(),
+            data,
+            entries: vec![],
+        };
+        let truncated = || "truncated or not a zip archive".to_string();
+        // The end of central directory record is followed only by a comment
+        // of up to 64 KiB
+        let data = &archive.data;
+        let lowest = data.len().saturating_sub(END_OF_DIRECTORY_LEN + 0xffff);
+        let end = (lowest..=data.len().saturating_sub(END_OF_DIRECTORY_LEN))
+            .rev()
+            .find(|&at| u32_at(data, at) == Some(END_OF_DIRECTORY_SIG))
+            .ok_or_else(|| archive.bad(truncated()))?;
+        let count = u16_at(data, end + 10).unwrap_or(0) as usize;
+        let directory = u32_at(data, end + 16).unwrap_or(0);
+        if directory == u32::MAX {
+            return Err(archive.bad("ZIP64 archives are not supported".to_string()));
+        }
+
+        let mut entries = vec![];
+        let mut at = directory as usize;
+        for _ in 0..count {
+            let header = (|| {
+                if u32_at(data, at)? != CENTRAL_HEADER_SIG {
+                    return None;
+                }
+                let name_len = u16_at(data, at + 28)? as usize;
+                let extra_len = u16_at(data, at + 30)? as usize;
+                let comment_len = u16_at(data, at + 32)? as usize;
+                let name = data.get(at + 46..at + 46 + name_len)?;
+                let entry = Entry {
+                    name: String::from_utf8_lossy(name).replace('\\', "/"),
+                    method: u16_at(data, at + 10)?,
+                    encrypted: u16_at(data, at + 8)? & 1 != 0,
+                    crc: u32_at(data, at + 16)?,
+                    compressed_size: u32_at(data, at + 20)? as usize,
+                    size: u32_at(data, at + 24)? as usize,
+                    header_offset: u32_at(data, at + 42)? as usize,
+                };
+                Some((entry, 46 + name_len + extra_len + comment_len))
+            })();
+            let (entry, len) = header.ok_or_else(|| archive.bad(truncated()))?;
+            entries.push(entry);
+            at += len;
+        }
+        archive.entries = entries;
+        Ok(archive)
+    }
+
+    /// Paths of the files in the archive, with `/` separators.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries
+            .iter()
+            .map(|x| x.name.as_str())
+            .filter(|x| !x.ends_with('/'))
+    }
+
+    fn read(&self, entry: &Entry) -> Result<Vec<u8>> {
+        let bad = |message: &str| self.bad(format!("'{}' {}", entry.name, message));
+        if entry.encrypted {
+            return Err(bad("is encrypted"));
+        }
+        let at = entry.header_offset;
+        let start = (|| {
+            if u32_at(&self.data, at)? != LOCAL_HEADER_SIG {
+                return None;
+            }
+            let name_len = u16_at(&self.data, at + 26)? as usize;
+            let extra_len = u16_at(&self.data, at + 28)? as usize;
+            Some(at + 30 + name_len + extra_len)
+        })();
+        let raw = start
+            .and_then(|x| self.data.get(x..x + entry.compressed_size))
+            .ok_or_else(|| bad("is truncated"))?;
+        let data = match entry.method {
+            METHOD_STORED => raw.to_vec(),
+            // Inflating stops at the declared size, so that a small entry
+            // can't expand without bound
+            METHOD_DEFLATED => miniz_oxide::inflate::decompress_to_vec_with_limit(raw, entry.size)
+                .map_err(|x| match x {
+                    TINFLStatus::HasMoreOutput => bad("is larger than its declared size"),
+                    _ => bad("has corrupt compressed data"),
+                })?,
+            method => return Err(bad(&format!("uses unsupported compression {}", method))),
+        };
+        if data.len() != entry.size || crc32fast::hash(&data) != entry.crc {
+            return Err(bad("fails its checksum"));
+        }
+        Ok(data)
+    }
+
+    /// Directory of the archive holding `tile_config.json`, as a prefix of
+    /// file names: empty for the root, otherwise ending in `/`. Archives
+    /// holding several tilesets are taken to mean the outermost one.
+    fn tileset_prefix(&self) -> Result<String> {
+        self.names()
+            .filter_map(|x| x.strip_suffix("tile_config.json"))
+            .filter(|x| x.is_empty() || x.ends_with('/'))
+            .min_by_key(|x| (x.matches('/').count(), x.to_string()))
+            .map(str::to_string)
+            .ok_or_else(|| self.bad("has no tile_config.json".to_string()))
+    }
+
+    /// Writes the files of the tileset in the archive into `dir`, returning
+    /// how many were written.
+    pub fn unpack_tileset(&self, dir: &Path) -> Result<usize> {
+        let prefix = self.tileset_prefix()?;
+        let mut written = 0;
+        for entry in &self.entries {
+            let relative = match entry.name.strip_prefix(&prefix) {
+                Some(x) if !x.is_empty() && !x.ends_with('/') => Path::new(x),
+                _ => continue,
+            };
+            if !relative
+                .components()
+                .all(|x| matches!(x, Component::Normal(_)))
+            {
+                return Err(self.bad(format!("'{}' points outside the archive", entry.name)));
+            }
+            let path = dir.join(relative);
+            if let Some(parent) = path.parent() {
+                create_output_dir(parent)?;
+            }
+            write_output(&path, self.read(entry)?)?;
+            written += 1;
+        }
+        Ok(written)
+    }
+}
+
+/// Unpacks the tileset in a zip archive into a directory of the user cache
+/// named after the archive, replacing what an earlier run unpacked there,
+/// and returns that directory. Outputs of commands run on it end up there
+/// too.
+pub fn unpack_to_cache(path: &Path) -> Result<PathBuf> {
+    let archive = Archive::open(path)?;
+    let full = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    let stem = full
+        .file_stem()
+        .map_or("tileset".into(), |x| x.to_string_lossy());
+    // Archives of the same name in different places get their own directory
+    let key = fnv1a64(full.to_string_lossy().as_bytes()) as u32;
+    let dir = user_cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("archives")
+        .join(format!("{}-{:08x}", sanitize(&stem), key));
+    let _ = std::fs::remove_dir_all(&dir);
+    create_output_dir(&dir)?;
+    archive.unpack_tileset(&dir)?;
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Zip archive of the given files, deflating those whose name ends in
+    /// `.json`.
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut data = vec![];
+        let mut directory = vec![];
+        for (name, contents) in files {
+            let deflate = name.ends_with(".json");
+            let stored = if deflate {
+                miniz_oxide::deflate::compress_to_vec(contents, 6)
+            } else {
+                contents.to_vec()
+            };
+            let method = if deflate {
+                METHOD_DEFLATED
+            } else {
+                METHOD_STORED
+            };
+            let mut common = vec![];
+            common.extend(method.to_le_bytes());
+            common.extend([0; 4]);
+            common.extend(crc32fast::hash(contents).to_le_bytes());
+            common.extend((stored.len() as u32).to_le_bytes());
+            common.extend((contents.len() as u32).to_le_bytes());
+            common.extend((name.len() as u16).to_le_bytes());
+            common.extend([0; 2]);
+
+            let offset = data.len() as u32;
+            data.extend(LOCAL_HEADER_SIG.to_le_bytes());
+            data.extend([20, 0, 0, 0]);
+            data.extend(&common);
+            data.extend(name.as_bytes());
+            data.extend(&stored);
+
+            directory.extend(CENTRAL_HEADER_SIG.to_le_bytes());
+            directory.extend([20, 0, 20, 0, 0, 0]);
+            directory.extend(&common);
+            directory.extend([0; 10]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+        let directory_offset = data.len() as u32;
+        let directory_len = directory.len() as u32;
+        data.extend(directory);
+        data.extend(END_OF_DIRECTORY_SIG.to_le_bytes());
+        data.extend([0; 4]);
+        data.extend((files.len() as u16).to_le_bytes());
+        data.extend((files.len() as u16).to_le_bytes());
+        data.extend(directory_len.to_le_bytes());
+        data.extend(directory_offset.to_le_bytes());
+        data.extend([0; 2]);
+        data
+    }
+
+    #[test]
+    fn reads_the_outermost_tileset() {
+        let data = zip(&[
+            ("Tiles/tile_config.json", b"{\"tile_info\": []}"),
+            ("Tiles/gfx/main.png", b"png"),
+            ("Tiles/old/tile_config.json", b"{}"),
+            ("readme.txt", b"hi"),
+        ]);
+        let archive = Archive::parse(Path::new("t.zip"), data).unwrap();
+        assert_eq!(archive.names().count(), 4);
+        assert_eq!(archive.tileset_prefix().unwrap(), "Tiles/");
+        assert_eq!(
+            archive.read(&archive.entries[0]).unwrap(),
+            b"{\"tile_info\": []}"
+        );
+        assert_eq!(archive.read(&archive.entries[1]).unwrap(), b"png");
+
+        let mut corrupt = zip(&[("tile_config.json", b"abc")]);
+        corrupt[30 + "tile_config.json".len()] ^= 1;
+        let archive = Archive::parse(Path::new("t.zip"), corrupt).unwrap();
+        assert!(archive.read(&archive.entries[0]).is_err());
+        assert!(Archive::parse(Path::new("t.zip"), b"not a zip".to_vec()).is_err());
+    }
+
+    #[test]
+    fn stops_inflating_at_declared_size() {
+        let contents = vec![b' '; 1 << 16];
+        let mut archive =
+            Archive::parse(Path::new("t.zip"), zip(&[("tile_config.json", &contents)])).unwrap();
+        archive.entries[0].size = 100;
+        let err = archive.read(&archive.entries[0]).unwrap_err();
+        assert!(err.to_string().contains("larger than its declared size"));
+    }
+}
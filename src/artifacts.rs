@@ -0,0 +1,41 @@
+use crate::error::{create_output_dir, write_output, Result};
+use crate::warnings::Warning;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current UTC date as `YYYY-MM-DD`.
+pub fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0);
+    let (y, m, d) = civil_from_days((secs / 86400) as i64);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// Converts days since 1970-01-01 into a proleptic Gregorian (year, month, day).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+/// Creates `<root>/<today>`, reusing it if an earlier run made it the same day.
+pub fn create_dated_dir(root: &Path) -> Result<PathBuf> {
+    let dir = root.join(today());
+    create_output_dir(&dir)?;
+    Ok(dir)
+}
+
+/// Writes validation problems one per line, or an empty file if there are none.
+pub fn write_problems(problems: &[Warning], path: &Path) -> Result<()> {
+    let lines: Vec<String> = problems.iter().map(|x| x.to_string()).collect();
+    write_output(path, lines.join("\n"))
+}
@@ -0,0 +1,681 @@
+use crate::error::{Error, Result};
+use crate::hash_algo::HashAlgo;
+use crate::null_sprite::NullSprite;
+use crate::progress::Progress;
+use crate::single_or_vec::SingleOrVec;
+use crate::sprite_id_with_weight::SpriteIdWithWeight;
+use crate::warnings::{warn, Code};
+use clap::{ArgEnum, Args};
+use image::imageops::{self, FilterType};
+use image::{GenericImage, GenericImageView, ImageFormat, Rgba, RgbaImage, SubImage};
+use rayon::prelude::*;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+/// Options affecting how sprite indices are turned into sprite hashes.
+#[derive(Args, Clone, Copy, Debug, Default)]
+pub struct HashOptions {
+    /// Sprite indices to treat as "no sprite" placeholders
+    #[clap(long, arg_enum, default_value = "none")]
+    pub null_sprite: NullSprite,
+    /// Algorithm hashing sprites; legacy matches dumps and reports of
+    /// earlier versions, but may change between Rust releases, unlike the
+    /// others
+    #[clap(long, arg_enum, default_value = "xxh64")]
+    pub hash_algo: HashAlgo,
+    /// Bits kept of every sprite hash. 64 makes collisions unlikely even
+    /// among hundreds of thousands of sprites, but its hashes don't match
+    /// those of 32-bit dumps and reports.
+    #[clap(long, arg_enum, default_value = "32")]
+    pub hash_bits: HashBits,
+    /// Hash a fixed sample of pixels per sprite instead of every pixel.
+    /// Several times faster, but small edits may go unnoticed.
+    #[clap(long)]
+    pub fast_hash: bool,
+    /// Treat sprites whose perceptual hashes are at least this percent similar as equal
+    #[clap(long)]
+    pub similarity: Option<f32>,
+    /// Treat sprites of the same size as equal if no color or alpha channel
+    /// differs by more than this percent, e.g. after a re-save by another
+    /// editor
+    #[clap(long)]
+    pub fuzz: Option<f32>,
+    /// Pixels allowed to differ by more than --fuzz in sprites treated as equal
+    #[clap(long, default_value = "0", requires = "fuzz")]
+    pub fuzz_pixels: usize,
+    /// Hash pixels with alpha below this as fully transparent black, so
+    /// exports differing only in the color of invisible pixels compare
+    /// equal; 1 ignores the color of fully transparent pixels only
+    #[clap(long)]
+    pub alpha_threshold: Option<u8>,
+}
+
+/// Width of sprite hashes.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashBits {
+    #[default]
+    #[clap(name = "32")]
+    Bits32,
+    #[clap(name = "64")]
+    Bits64,
+}
+
+impl HashBits {
+    pub fn bits(self) -> u32 {
+        match self {
+            HashBits::Bits32 => 32,
+            HashBits::Bits64 => 64,
+        }
+    }
+}
+
+impl HashOptions {
+    pub fn hash_mode(&self) -> &'static str {
+        if self.fast_hash {
+            "fast"
+        } else {
+            "exact"
+        }
+    }
+}
+
+/// Width, in sprites, of atlases written by merge and compose.
+pub const SHEET_COLUMNS: u32 = 16;
+
+/// Pixel coordinates sampled by the fast hash: the 4 corners, the center,
+/// and 4 evenly spaced full rows.
+fn fast_hash_samples(w: u32, h: u32) -> Vec<(u32, u32)> {
+    if w == 0 || h == 0 {
+        return vec![];
+    }
+    let mut ret = vec![
+        (0, 0),
+        (w - 1, 0),
+        (0, h - 1),
+        (w - 1, h - 1),
+        (w / 2, h / 2),
+    ];
+    let stride = std::cmp::max(1, h / 4);
+    for y in (0..h).step_by(stride as usize) {
+        for x in 0..w {
+            ret.push((x, y));
+        }
+    }
+    ret
+}
+
+/// Version of the sprite hash algorithm, bumped whenever the same pixels
+/// start hashing differently. Hashes are only comparable between runs with
+/// the same version and algorithm, and for the legacy algorithm, built by
+/// the same Rust release.
+pub const HASH_VERSION: u32 = 4;
+
+/// Hash of a sprite drawn with its top left corner at `origin` relative to
+/// its tile. Sprites drawn exactly over their tile hash as they did before
+/// offsets were taken into account.
+///
+/// The stable algorithms hash, as little-endian bytes: the width and height
+/// as `u32`, the origin as two `i32` unless it's `(0, 0)`, `fast` and a
+/// 0xff byte for the fast hash, then the RGBA bytes of every hashed pixel,
+/// row by row. The legacy algorithm hashes each pixel the way Rust hashes a
+/// `[u8; 4]`.
+fn hash_image<I: GenericImageView<Pixel = Rgba<u8>>>(
+    img: &I,
+    origin: (i32, i32),
+    opts: &HashOptions,
+) -> u64 {
+    let (w, h) = img.dimensions();
+    let mut hasher = opts.hash_algo.hasher();
+    w.hash(&mut hasher);
+    h.hash(&mut hasher);
+    if origin != (0, 0) {
+        origin.hash(&mut hasher);
+    }
+
+    let visible = |px: Rgba<u8>| match opts.alpha_threshold {
+        Some(threshold) if px.0[3] < threshold => Rgba([0, 0, 0, 0]),
+        _ => px,
+    };
+    let legacy = opts.hash_algo == HashAlgo::Legacy;
+    let hash_pixel = |mut hasher: &mut dyn Hasher, px: Rgba<u8>| {
+        if legacy {
+            visible(px).hash(&mut hasher);
+        } else {
+            hasher.write(&visible(px).0);
+        }
+    };
+    // Sampling a sprite of a few rows reads every pixel anyway, and more
+    // than once
+    let samples = if opts.fast_hash && h > 4 {
+        fast_hash_samples(w, h)
+    } else {
+        vec![]
+    };
+    if !samples.is_empty() {
+        opts.hash_mode().hash(&mut hasher);
+        for (x, y) in samples {
+            hash_pixel(&mut *hasher, img.get_pixel(x, y));
+        }
+    } else {
+        for (_, _, px) in img.pixels() {
+            hash_pixel(&mut *hasher, px);
+        }
+    }
+
+    let hash = hasher.finish();
+    match opts.hash_bits {
+        HashBits::Bits64 => hash,
+        // Intended narrowing conversion
+        HashBits::Bits32 => hash as u32 as u64,
+    }
+}
+
+pub struct TileAtlas {
+    pub file: String,
+    pub img: RgbaImage,
+    pub sprite_w: u32,
+    pub sprite_h: u32,
+    /// Where sprites are drawn relative to the top left corner of their tile
+    pub offset_x: i32,
+    pub offset_y: i32,
+    /// Size of a tile, from tile_info
+    pub tile_w: u32,
+    pub tile_h: u32,
+    pub tiles_x: u32,
+    pub tiles_y: u32,
+    pub tiles_start: u32,
+    pub tiles_end: u32,
+    /// Slot of each sprite in `img` once `retain_sprites` dropped the rest,
+    /// `None` while `img` holds the whole sheet
+    pub slots: Option<HashMap<u32, u32>>,
+}
+
+impl TileAtlas {
+    pub fn tiles_total(&self) -> u32 {
+        self.tiles_x * self.tiles_y
+    }
+
+    /// Whether the image is smaller than a single sprite, so that it holds
+    /// none.
+    pub fn is_empty(&self) -> bool {
+        self.tiles_total() == 0
+    }
+
+    pub fn is_evenly_divided(&self) -> bool {
+        self.img.width().is_multiple_of(self.sprite_w)
+            && self.img.height().is_multiple_of(self.sprite_h)
+    }
+
+    /// The atlas with every pixel enlarged to a `factor`x`factor` block, as
+    /// the game draws it at that pixelscale.
+    pub fn upscaled(self, factor: u32) -> TileAtlas {
+        let (w, h) = self.img.dimensions();
+        TileAtlas {
+            img: imageops::resize(&self.img, w * factor, h * factor, FilterType::Nearest),
+            sprite_w: self.sprite_w * factor,
+            sprite_h: self.sprite_h * factor,
+            offset_x: self.offset_x * factor as i32,
+            offset_y: self.offset_y * factor as i32,
+            tile_w: self.tile_w * factor,
+            tile_h: self.tile_h * factor,
+            ..self
+        }
+    }
+
+    /// Shrinks the atlas by a factor dividing its sprite size, keeping one
+    /// pixel of each block.
+    pub fn downscaled(self, factor: u32) -> TileAtlas {
+        let (w, h) = self.img.dimensions();
+        let factor_i = factor as i32;
+        TileAtlas {
+            img: imageops::resize(&self.img, w / factor, h / factor, FilterType::Nearest),
+            sprite_w: self.sprite_w / factor,
+            sprite_h: self.sprite_h / factor,
+            offset_x: self.offset_x / factor_i,
+            offset_y: self.offset_y / factor_i,
+            tile_w: self.tile_w / factor,
+            tile_h: self.tile_h / factor,
+            ..self
+        }
+    }
+
+    pub fn in_bounds(&self, tile_id: u32) -> bool {
+        tile_id >= self.tiles_start && tile_id < self.tiles_end
+    }
+
+    /// Whether the pixels of a sprite are at hand: it's in range, and wasn't
+    /// dropped by `retain_sprites`.
+    pub fn has_sprite(&self, tile_id: u32) -> bool {
+        self.in_bounds(tile_id) && self.slots.as_ref().is_none_or(|x| x.contains_key(&tile_id))
+    }
+
+    /// Drops the pixels of every sprite not in `keep`, packing the rest into
+    /// a `SHEET_COLUMNS` wide image, so that a large sheet takes only the
+    /// memory of the sprites still needed.
+    pub fn retain_sprites(&mut self, keep: &BTreeSet<u32>) {
+        let kept: Vec<u32> = keep
+            .range(self.tiles_start..self.tiles_end)
+            .copied()
+            .filter(|x| self.has_sprite(*x))
+            .collect();
+        let rows = (kept.len() as u32).div_ceil(SHEET_COLUMNS);
+        let columns = if kept.is_empty() { 0 } else { SHEET_COLUMNS };
+        let mut img = RgbaImage::new(columns * self.sprite_w, rows * self.sprite_h);
+        let mut slots = HashMap::new();
+        for (slot, &idx) in kept.iter().enumerate() {
+            let slot = slot as u32;
+            let x = slot % SHEET_COLUMNS * self.sprite_w;
+            let y = slot / SHEET_COLUMNS * self.sprite_h;
+            // Both come from sprites of the same size
+            img.copy_from(&self.get_sprite(idx), x, y).unwrap();
+            slots.insert(idx, slot);
+        }
+        self.img = img;
+        self.slots = Some(slots);
+    }
+
+    pub fn get_sprite(&self, tile_id: u32) -> SubImage<&RgbaImage> {
+        let (within_x, within_y) = match &self.slots {
+            None => {
+                let id_within_atlas = tile_id - self.tiles_start;
+                (
+                    id_within_atlas % self.tiles_x,
+                    id_within_atlas / self.tiles_x,
+                )
+            }
+            Some(slots) => {
+                let slot = slots[&tile_id];
+                (slot % SHEET_COLUMNS, slot / SHEET_COLUMNS)
+            }
+        };
+        self.img.view(
+            within_x * self.sprite_w,
+            within_y * self.sprite_h,
+            self.sprite_w,
+            self.sprite_h,
+        )
+    }
+
+    /// Whether sprites cover exactly their tile, so drawing them needs no
+    /// placement.
+    fn is_tile_sized(&self) -> bool {
+        (self.offset_x, self.offset_y) == (0, 0)
+            && (self.sprite_w, self.sprite_h) == (self.tile_w, self.tile_h)
+    }
+
+    /// A sprite as drawn in game: composited at the sheet's offset onto a
+    /// canvas covering its tile, with transparent rows and columns beyond
+    /// the tile trimmed. Returns the canvas and the position of its top left
+    /// corner relative to the tile, so that the same art in sheets with
+    /// different sprite sizes and offsets gives the same result.
+    pub fn get_placed_sprite(&self, tile_id: u32) -> (RgbaImage, (i32, i32)) {
+        let sprite = self.get_sprite(tile_id);
+        let (sw, sh) = (self.sprite_w as i32, self.sprite_h as i32);
+        let (ox, oy) = (self.offset_x, self.offset_y);
+        let visible = |x: i32, y: i32| {
+            let (sx, sy) = (x - ox, y - oy);
+            sx >= 0
+                && sy >= 0
+                && sx < sw
+                && sy < sh
+                && sprite.get_pixel(sx as u32, sy as u32).0[3] != 0
+        };
+        // Bounds of the tile, grown to fit every visible sprite pixel
+        let (mut x0, mut y0) = (0, 0);
+        let (mut x1, mut y1) = (self.tile_w as i32, self.tile_h as i32);
+        for y in oy..oy + sh {
+            for x in ox..ox + sw {
+                if visible(x, y) {
+                    x0 = x0.min(x);
+                    y0 = y0.min(y);
+                    x1 = x1.max(x + 1);
+                    y1 = y1.max(y + 1);
+                }
+            }
+        }
+        let canvas = RgbaImage::from_fn((x1 - x0) as u32, (y1 - y0) as u32, |x, y| {
+            let (sx, sy) = (x as i32 + x0 - ox, y as i32 + y0 - oy);
+            if sx >= 0 && sy >= 0 && sx < sw && sy < sh {
+                sprite.get_pixel(sx as u32, sy as u32)
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        });
+        (canvas, (x0, y0))
+    }
+
+    /// The pixels a sprite hash covers: the placed sprite, with pixels below
+    /// `--alpha-threshold` made transparent black. Sprites with equal hashes
+    /// and different hashed pixels are hash collisions.
+    pub fn get_hashed_pixels(&self, tile_id: u32, opts: &HashOptions) -> (RgbaImage, (i32, i32)) {
+        let (mut canvas, origin) = self.get_placed_sprite(tile_id);
+        if let Some(threshold) = opts.alpha_threshold {
+            for px in canvas.pixels_mut().filter(|x| x.0[3] < threshold) {
+                *px = Rgba([0, 0, 0, 0]);
+            }
+        }
+        (canvas, origin)
+    }
+
+    pub fn get_sprite_hash(&self, tile_id: u32, opts: &HashOptions) -> u64 {
+        if !self.in_bounds(tile_id) {
+            warn(
+                Code::OutOfRange,
+                format!(
+                    "tile {} outside active atlas range {}..{}",
+                    tile_id, self.tiles_start, self.tiles_end
+                ),
+            );
+            return 0;
+        }
+
+        if self.is_tile_sized() {
+            return hash_image(&self.get_sprite(tile_id), (0, 0), opts);
+        }
+        let (canvas, origin) = self.get_placed_sprite(tile_id);
+        hash_image(&canvas, origin, opts)
+    }
+
+    pub fn dump_sprites_to_dir(&self, base_path: &Path, progress: &Progress) -> Result<()> {
+        for tile_id in self.tiles_start..self.tiles_end {
+            if !self.has_sprite(tile_id) {
+                continue;
+            }
+            let sprite_path = base_path.join(format!("{}.png", tile_id));
+            let subimg = self.get_sprite(tile_id);
+            save_png(&subimg.to_image(), &sprite_path)?;
+            progress.inc();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl TileAtlas {
+    /// Atlas of `img` cut into sprites of `sprite_w`x`sprite_h`, as large as
+    /// tiles and numbered from 0.
+    pub fn from_image(img: RgbaImage, sprite_w: u32, sprite_h: u32) -> TileAtlas {
+        let (tiles_x, tiles_y) = (img.width() / sprite_w, img.height() / sprite_h);
+        TileAtlas {
+            file: "main.png".to_string(),
+            img,
+            sprite_w,
+            sprite_h,
+            offset_x: 0,
+            offset_y: 0,
+            tile_w: sprite_w,
+            tile_h: sprite_h,
+            tiles_x,
+            tiles_y,
+            tiles_start: 0,
+            tiles_end: tiles_x * tiles_y,
+            slots: None,
+        }
+    }
+
+    /// Atlas of a row of 1x1 sprites with the given pixels, numbered from
+    /// `tiles_start`.
+    pub fn from_pixels(pixels: &[[u8; 4]], tiles_start: u32) -> TileAtlas {
+        let mut img = RgbaImage::new(pixels.len() as u32, 1);
+        for (x, &px) in pixels.iter().enumerate() {
+            img.put_pixel(x as u32, 0, Rgba(px));
+        }
+        TileAtlas {
+            tiles_start,
+            tiles_end: tiles_start + pixels.len() as u32,
+            ..TileAtlas::from_image(img, 1, 1)
+        }
+    }
+}
+
+/// Sprite hash of every sprite index in every atlas, by index. Sprites are
+/// hashed in parallel, but the result is in index order for any thread count.
+pub fn hash_all_sprites(atlases: &[TileAtlas], opts: &HashOptions) -> Vec<(u32, u64)> {
+    let sprites: Vec<(&TileAtlas, u32)> = atlases
+        .iter()
+        .flat_map(|atlas| (atlas.tiles_start..atlas.tiles_end).map(move |x| (atlas, x)))
+        .filter(|(atlas, x)| atlas.has_sprite(*x))
+        .collect();
+    let progress = Progress::new("Hashing sprites", sprites.len());
+    sprites
+        .into_par_iter()
+        .map(|(atlas, x)| {
+            progress.inc();
+            (x, atlas.get_sprite_hash(x, opts))
+        })
+        .collect()
+}
+
+/// Sprite of every hash, as an atlas and index.
+pub fn sprites_by_hash<'a>(
+    atlases: &'a [TileAtlas],
+    opts: &HashOptions,
+) -> HashMap<u64, (&'a TileAtlas, u32)> {
+    let mut ret = HashMap::new();
+    for (idx, hash) in hash_all_sprites(atlases, opts) {
+        if let Some(atlas) = atlases.iter().find(|x| x.in_bounds(idx)) {
+            ret.entry(hash).or_insert((atlas, idx));
+        }
+    }
+    ret
+}
+
+/// Sprite hash of each of the given sprite indices that falls into an atlas.
+/// Like `hash_all_sprites`, hashes in parallel with the same result for any
+/// thread count.
+pub fn hash_sprite_indices<I: IntoIterator<Item = u32>>(
+    atlases: &[TileAtlas],
+    indices: I,
+    opts: &HashOptions,
+) -> HashMap<u32, u64> {
+    let sprites: Vec<(&TileAtlas, u32)> = indices
+        .into_iter()
+        .filter_map(|x| atlases.iter().find(|a| a.has_sprite(x)).map(|a| (a, x)))
+        .collect();
+    let progress = Progress::new("Hashing sprites", sprites.len());
+    sprites
+        .into_par_iter()
+        .map(|(atlas, x)| {
+            progress.inc();
+            (x, atlas.get_sprite_hash(x, opts))
+        })
+        .collect()
+}
+
+pub fn save_png(img: &RgbaImage, path: &Path) -> Result<()> {
+    img.save_with_format(path, ImageFormat::Png)
+        .map_err(|source| Error::SaveImage {
+            path: path.to_owned(),
+            source,
+        })
+}
+
+/// Copies sprites into consecutive slots of `img`, row by row, starting at `first_row`.
+/// `path` is only used for errors.
+pub fn paste_sprites<I>(
+    img: &mut RgbaImage,
+    path: &Path,
+    first_row: u32,
+    columns: u32,
+    sprites: &[I],
+) -> Result<()>
+where
+    I: GenericImageView<Pixel = Rgba<u8>>,
+{
+    for (k, sprite) in sprites.iter().enumerate() {
+        let k = k as u32;
+        let (w, h) = sprite.dimensions();
+        img.copy_from(sprite, (k % columns) * w, (first_row + k / columns) * h)
+            .map_err(|source| Error::SaveImage {
+                path: path.to_owned(),
+                source,
+            })?;
+    }
+    Ok(())
+}
+
+/// Replaces sprite indices with sprite hashes from `hashes`, the result of
+/// `hash_all_sprites`, returning the indices that were outside all atlases
+/// (and were replaced with 0).
+pub fn hash_sprites(
+    ids: &mut SingleOrVec<SpriteIdWithWeight>,
+    hashes: &HashMap<u32, u64>,
+) -> Vec<u64> {
+    let mut out_of_range = vec![];
+    for spidw in &mut ids.0 {
+        for id in &mut spidw.id.0 {
+            *id = match u32::try_from(*id).ok().and_then(|x| hashes.get(&x)) {
+                Some(hash) => *hash,
+                None => {
+                    out_of_range.push(*id);
+                    0
+                }
+            };
+        }
+    }
+    out_of_range
+}
+
+pub fn save_tile_as(
+    atlases: &[TileAtlas],
+    tile_id: u32,
+    out_dir: &Path,
+    opts: &HashOptions,
+) -> Result<()> {
+    for atlas in atlases {
+        if atlas.in_bounds(tile_id) {
+            let tile_hash = atlas.get_sprite_hash(tile_id, opts);
+            let path = out_dir.join(format!("{:010}.png", tile_hash));
+            let subimg = atlas.get_sprite(tile_id);
+            return save_png(&subimg.to_image(), &path);
+        }
+    }
+    Err(Error::SpriteNotFound(tile_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Atlas of a single sprite with one opaque pixel at `(x, y)`.
+    fn atlas(sprite_w: u32, sprite_h: u32, offset_y: i32, (x, y): (u32, u32)) -> TileAtlas {
+        let mut img = RgbaImage::new(sprite_w, sprite_h);
+        img.put_pixel(x, y, Rgba([200, 100, 50, 255]));
+        TileAtlas {
+            offset_y,
+            tile_w: 4,
+            tile_h: 4,
+            ..TileAtlas::from_image(img, sprite_w, sprite_h)
+        }
+    }
+
+    #[test]
+    fn offsets_do_not_change_sprite_identity() {
+        let opts = HashOptions::default();
+        // The same pixel, 2 rows above the tile
+        let short = atlas(4, 8, -4, (1, 2));
+        let tall = atlas(4, 12, -8, (1, 6));
+        assert_eq!(
+            short.get_sprite_hash(0, &opts),
+            tall.get_sprite_hash(0, &opts)
+        );
+
+        let moved = atlas(4, 12, -8, (1, 5));
+        assert_ne!(
+            short.get_sprite_hash(0, &opts),
+            moved.get_sprite_hash(0, &opts)
+        );
+
+        // Nothing above the tile: same as a plain tile-sized sprite
+        let plain = atlas(4, 4, 0, (1, 2));
+        let padded = atlas(4, 8, -4, (1, 6));
+        assert_eq!(
+            plain.get_sprite_hash(0, &opts),
+            padded.get_sprite_hash(0, &opts)
+        );
+    }
+
+    #[test]
+    fn alpha_threshold_ignores_invisible_colors() {
+        let a = atlas(4, 4, 0, (1, 2));
+        let mut b = atlas(4, 4, 0, (1, 2));
+        b.img.put_pixel(0, 0, Rgba([255, 255, 255, 0]));
+        b.img.put_pixel(3, 3, Rgba([255, 255, 255, 10]));
+
+        let exact = HashOptions::default();
+        assert_ne!(a.get_sprite_hash(0, &exact), b.get_sprite_hash(0, &exact));
+        let opts = HashOptions {
+            alpha_threshold: Some(1),
+            ..Default::default()
+        };
+        assert_ne!(a.get_sprite_hash(0, &opts), b.get_sprite_hash(0, &opts));
+        let opts = HashOptions {
+            alpha_threshold: Some(16),
+            ..Default::default()
+        };
+        assert_eq!(a.get_sprite_hash(0, &opts), b.get_sprite_hash(0, &opts));
+    }
+
+    #[test]
+    fn upscaling_enlarges_pixels_and_placement() {
+        let big = atlas(4, 8, -4, (1, 2)).upscaled(2);
+        assert_eq!((big.sprite_w, big.sprite_h), (8, 16));
+        assert_eq!((big.tile_w, big.tile_h, big.offset_y), (8, 8, -8));
+        assert_eq!(big.tiles_total(), 1);
+
+        let (sprite, origin) = big.get_placed_sprite(0);
+        // Transparent rows above the pixel are trimmed
+        assert_eq!(origin, (0, -4));
+        let opaque: Vec<(u32, u32)> = sprite
+            .enumerate_pixels()
+            .filter(|(_, _, px)| px.0[3] != 0)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(opaque, [(2, 0), (3, 0), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn fast_hash_reads_small_sprites_whole() {
+        let opts = HashOptions {
+            fast_hash: true,
+            ..Default::default()
+        };
+        let tiny = atlas(1, 1, 0, (0, 0));
+        assert_eq!(
+            tiny.get_sprite_hash(0, &opts),
+            tiny.get_sprite_hash(0, &HashOptions::default())
+        );
+        assert!(fast_hash_samples(0, 8).is_empty());
+    }
+
+    #[test]
+    fn downscaling_undoes_upscaling() {
+        let opts = HashOptions::default();
+        let small = atlas(4, 8, -4, (1, 2));
+        let round_trip = atlas(4, 8, -4, (1, 2)).upscaled(3).downscaled(3);
+        assert_eq!((round_trip.tile_w, round_trip.offset_y), (4, -4));
+        assert_eq!(
+            small.get_sprite_hash(0, &opts),
+            round_trip.get_sprite_hash(0, &opts)
+        );
+    }
+
+    #[test]
+    fn retained_sprites_keep_their_pixels() {
+        let opts = HashOptions::default();
+        // 20 sprites of 1x1 in a row, each with its own red value
+        let pixels: Vec<[u8; 4]> = (0..20).map(|x| [x, 0, 0, 255]).collect();
+        let mut sheet = TileAtlas::from_pixels(&pixels, 10);
+        let before: Vec<u64> = (10..30).map(|x| sheet.get_sprite_hash(x, &opts)).collect();
+
+        sheet.retain_sprites(&BTreeSet::from([5, 12, 27, 29]));
+        assert_eq!(sheet.img.dimensions(), (SHEET_COLUMNS, 1));
+        assert!(!sheet.has_sprite(11) && !sheet.has_sprite(5));
+        for idx in [12, 27, 29] {
+            assert!(sheet.has_sprite(idx));
+            assert_eq!(sheet.get_sprite_hash(idx, &opts), before[idx as usize - 10]);
+        }
+        assert_eq!(hash_all_sprites(&[sheet], &opts).len(), 3);
+    }
+}
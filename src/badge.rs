@@ -0,0 +1,48 @@
+/// Approximate advance of one character of 11px Verdana, as used by shields.io.
+const CHAR_WIDTH: usize = 7;
+const PADDING: usize = 10;
+
+pub const COLOR_GOOD: &str = "#4c1";
+pub const COLOR_WARN: &str = "#fe7d37";
+pub const COLOR_UNKNOWN: &str = "#9f9f9f";
+
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn text_width(s: &str) -> usize {
+    s.chars().count() * CHAR_WIDTH + PADDING
+}
+
+/// Renders a flat shields.io-style badge with a grey label and a colored value.
+pub fn render_badge(label: &str, value: &str, color: &str) -> String {
+    let label_w = text_width(label);
+    let value_w = text_width(value);
+    let total_w = label_w + value_w;
+    let label = escape_xml(label);
+    let value = escape_xml(value);
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{total_w}" height="20" role="img" aria-label="{label}: {value}">
+<title>{label}: {value}</title>
+<linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>
+<clipPath id="r"><rect width="{total_w}" height="20" rx="3" fill="#fff"/></clipPath>
+<g clip-path="url(#r)">
+<rect width="{label_w}" height="20" fill="#555"/>
+<rect x="{label_w}" width="{value_w}" height="20" fill="{color}"/>
+<rect width="{total_w}" height="20" fill="url(#s)"/>
+</g>
+<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+<text x="{label_x}" y="15" fill="#010101" fill-opacity=".3">{label}</text>
+<text x="{label_x}" y="14">{label}</text>
+<text x="{value_x}" y="15" fill="#010101" fill-opacity=".3">{value}</text>
+<text x="{value_x}" y="14">{value}</text>
+</g>
+</svg>
+"##,
+        label_x = label_w / 2,
+        value_x = label_w + value_w / 2,
+    )
+}
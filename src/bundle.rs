@@ -0,0 +1,262 @@
+use crate::atlas::{paste_sprites, save_png, HashOptions, SHEET_COLUMNS};
+use crate::compose::walk_sorted;
+use crate::error::{create_output_dir, write_output, Error, Result};
+use crate::merge::{merge_as, MergeSummary};
+use crate::tileset::{SingleTile, Tileset};
+use image::io::Reader as ImageReader;
+use image::{GenericImageView, RgbaImage};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// A sprite of a contribution bundle: `<tile id>.png`.
+pub struct BundleSprite {
+    pub id: String,
+    pub path: PathBuf,
+    pub img: RgbaImage,
+}
+
+/// Reads every PNG below `dir`, keyed by file name without extension.
+pub fn load_bundle(dir: &Path) -> Result<Vec<BundleSprite>> {
+    if !dir.is_dir() {
+        return Err(Error::NoTilesetDir(dir.to_owned()));
+    }
+    let mut files = vec![];
+    walk_sorted(dir, &mut files)?;
+
+    let mut ret: Vec<BundleSprite> = vec![];
+    for path in files {
+        let id = match (path.extension(), path.file_stem()) {
+            (Some(ext), Some(stem)) if ext == "png" => stem.to_string_lossy().to_string(),
+            _ => continue,
+        };
+        if let Some(other) = ret.iter().find(|x| x.id == id) {
+            return Err(Error::BadTileset {
+                path: path.clone(),
+                message: format!("id '{}' also drawn by '{}'", id, other.path.display()),
+            });
+        }
+        let img = ImageReader::open(&path)
+            .map_err(|source| Error::ReadInput {
+                path: path.clone(),
+                source,
+            })?
+            .decode()
+            .map_err(|source| Error::DecodeImage {
+                path: path.clone(),
+                source,
+            })?
+            .to_rgba8();
+        ret.push(BundleSprite { id, path, img });
+    }
+    Ok(ret)
+}
+
+/// A sprite of the target tileset the bundle would replace.
+#[derive(Debug)]
+pub struct OverwrittenCell {
+    /// Bundle id replacing the sprite
+    pub id: String,
+    pub sprite: u32,
+    pub sheet: String,
+    /// Column and row of the sprite in its sheet
+    pub cell: (u32, u32),
+    /// Other ids drawn with the same sprite, which keep it
+    pub shared_with: Vec<String>,
+}
+
+/// What importing a bundle would do to the target tileset.
+#[derive(Debug, Default)]
+pub struct BundleReport {
+    /// Ids the target defines with different art
+    pub updated: Vec<String>,
+    /// Ids the target already draws with exactly the bundle's sprite
+    pub unchanged: Vec<String>,
+    /// Ids the target doesn't define
+    pub new: Vec<String>,
+    pub overwritten: Vec<OverwrittenCell>,
+}
+
+/// Foreground sprite indices of a tile, in order, without repeats.
+fn fg_sprites(tile: &SingleTile) -> Vec<u32> {
+    let mut ret: Vec<u32> = vec![];
+    for sprite in tile.fg().iter().flat_map(|x| x.indices()) {
+        if !ret.contains(&sprite) {
+            ret.push(sprite);
+        }
+    }
+    ret
+}
+
+/// Compares the bundle's sprites with the foreground sprites of the ids they
+/// are named after.
+pub fn bundle_report(ts: &Tileset, bundle: &[BundleSprite]) -> Result<BundleReport> {
+    let (vars, atlases) = ts.generate_variations(false, &HashOptions::default())?;
+    let by_id: HashMap<&str, &SingleTile> = vars.iter().map(|x| (x.id.0[0].as_str(), x)).collect();
+    let mut users: HashMap<u32, Vec<&str>> = HashMap::new();
+    for var in &vars {
+        for sprite in fg_sprites(var) {
+            users.entry(sprite).or_default().push(var.id.0[0].as_str());
+        }
+    }
+
+    let mut ret = BundleReport::default();
+    for sprite in bundle {
+        let tile = match by_id.get(sprite.id.as_str()) {
+            Some(x) => x,
+            None => {
+                ret.new.push(sprite.id.clone());
+                continue;
+            }
+        };
+        let current = fg_sprites(tile);
+        let same = match current.as_slice() {
+            [idx] => atlases
+                .iter()
+                .find(|x| x.in_bounds(*idx))
+                .map(|x| x.get_sprite(*idx))
+                .is_some_and(|x| {
+                    x.dimensions() == sprite.img.dimensions()
+                        && x.pixels().zip(sprite.img.pixels()).all(|(a, b)| a.2 == *b)
+                }),
+            _ => false,
+        };
+        if same {
+            ret.unchanged.push(sprite.id.clone());
+            continue;
+        }
+        ret.updated.push(sprite.id.clone());
+        for idx in current {
+            let atlas = match atlases.iter().find(|x| x.in_bounds(idx)) {
+                Some(x) => x,
+                None => continue,
+            };
+            let within = idx - atlas.tiles_start;
+            ret.overwritten.push(OverwrittenCell {
+                id: sprite.id.clone(),
+                sprite: idx,
+                sheet: atlas.file.clone(),
+                cell: (within % atlas.tiles_x, within / atlas.tiles_x),
+                shared_with: users[&idx]
+                    .iter()
+                    .filter(|x| **x != sprite.id)
+                    .map(|x| x.to_string())
+                    .collect(),
+            });
+        }
+    }
+    Ok(ret)
+}
+
+/// Writes the bundle as a tileset in `dir`, one sheet per sprite size, with
+/// offsets and `rotates`/`height_3d` taken from the target so the merged
+/// entries draw like the ones they replace.
+fn stage_bundle(ts: &Tileset, bundle: &[&BundleSprite], dir: &Path) -> Result<()> {
+    create_output_dir(dir)?;
+    let tile_info = ts.tile_info();
+    let mut by_size: BTreeMap<(u32, u32), Vec<&BundleSprite>> = BTreeMap::new();
+    for sprite in bundle {
+        by_size
+            .entry(sprite.img.dimensions())
+            .or_default()
+            .push(sprite);
+    }
+    let definitions: HashMap<&str, &SingleTile> = ts
+        .tiles_new
+        .iter()
+        .flat_map(|x| x.tiles.iter())
+        .flat_map(|x| x.base.id.0.iter().map(move |id| (id.as_str(), &x.base)))
+        .collect();
+
+    let mut sheets = vec![];
+    let mut next_index = 0;
+    for ((w, h), sprites) in by_size {
+        let file = format!("bundle_{}x{}.png", w, h);
+        let count = sprites.len() as u32;
+        let columns = std::cmp::min(count, SHEET_COLUMNS);
+        let path = dir.join(&file);
+        let mut img = RgbaImage::new(columns * w, count.div_ceil(columns) * h);
+        let images: Vec<RgbaImage> = sprites.iter().map(|x| x.img.clone()).collect();
+        paste_sprites(&mut img, &path, 0, columns, &images)?;
+        save_png(&img, &path)?;
+
+        let mut sheet = Map::new();
+        sheet.insert("file".to_string(), json!(file));
+        sheet.insert("sprite_width".to_string(), json!(w));
+        sheet.insert("sprite_height".to_string(), json!(h));
+        let same_size = ts.tiles_new.iter().find(|x| {
+            (x.sprite_width, x.sprite_height) == (Some(w), Some(h))
+                || ((w, h) == (tile_info.width, tile_info.height) && x.sprite_width.is_none())
+        });
+        if let Some(target) = same_size {
+            if let Some(x) = target.sprite_offset_x.filter(|x| *x != 0) {
+                sheet.insert("sprite_offset_x".to_string(), json!(x));
+            }
+            if let Some(y) = target.sprite_offset_y.filter(|y| *y != 0) {
+                sheet.insert("sprite_offset_y".to_string(), json!(y));
+            }
+        }
+        let tiles: Vec<Value> = sprites
+            .iter()
+            .enumerate()
+            .map(|(k, sprite)| {
+                let mut entry = json!({ "id": sprite.id, "fg": next_index + k as u32 });
+                if let Some(old) = definitions.get(sprite.id.as_str()) {
+                    if let Some(rotates) = old.rotates {
+                        entry["rotates"] = json!(rotates);
+                    }
+                    if old.height_3d != 0 {
+                        entry["height_3d"] = json!(old.height_3d);
+                    }
+                }
+                entry
+            })
+            .collect();
+        sheet.insert("tiles".to_string(), json!(tiles));
+        sheets.push(Value::Object(sheet));
+        next_index += count.div_ceil(columns) * columns;
+    }
+
+    let config = json!({
+        "tile_info": [{ "width": tile_info.width, "height": tile_info.height }],
+        "tiles-new": sheets,
+    });
+    write_output(
+        &dir.join("tile_config.json"),
+        serde_json::to_string_pretty(&config)?,
+    )
+}
+
+/// Merges the updated and new sprites of the bundle in `bundle_dir` into
+/// `ts`, each as the only foreground sprite of its id, through a temporary
+/// tileset.
+pub fn apply_bundle(
+    ts: &Tileset,
+    bundle: &[BundleSprite],
+    report: &BundleReport,
+    bundle_dir: &Path,
+) -> Result<MergeSummary> {
+    let bundle: Vec<&BundleSprite> = bundle
+        .iter()
+        .filter(|x| !report.unchanged.contains(&x.id))
+        .collect();
+    if bundle.is_empty() {
+        return Ok(MergeSummary::default());
+    }
+    let staging = std::env::temp_dir().join(format!("tileset-bundle-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&staging);
+    let result = stage_bundle(ts, &bundle, &staging).and_then(|_| {
+        let staged = Tileset::load(&staging)?;
+        let ids: Vec<String> = bundle.iter().map(|x| x.id.clone()).collect();
+        merge_as(
+            ts,
+            &staged,
+            &ids,
+            &HashOptions::default(),
+            "import-bundle",
+            bundle_dir,
+        )
+    });
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
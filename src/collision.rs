@@ -0,0 +1,141 @@
+use crate::atlas::{HashBits, HashOptions, TileAtlas};
+use crate::hash_algo::xxh64;
+use crate::progress::Progress;
+use crate::sprite_tag::full_tag;
+use image::RgbaImage;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// A sprite of tileset A (side 0) or B (side 1), by index.
+pub type SideSprite = (usize, u32);
+
+/// Pixels a sprite hash covers, with their position relative to the tile.
+type HashedPixels = (RgbaImage, (i32, i32));
+
+/// Sprites sharing a hash although the pixels they hash differ.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Collision {
+    pub hash: u64,
+    /// Sprites with the same pixels, each in side and index order, ordered
+    /// by their first sprite. The first group keeps the hash.
+    pub groups: Vec<Vec<SideSprite>>,
+    /// Width of the run's hashes, to tag `hash` like its other sprites.
+    pub hash_bits: HashBits,
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let groups: Vec<String> = self
+            .groups
+            .iter()
+            .map(|group| {
+                let sprites: Vec<String> = group
+                    .iter()
+                    .map(|(side, idx)| format!("{} {}", ["A", "B"][*side], idx))
+                    .collect();
+                sprites.join(", ")
+            })
+            .collect();
+        write!(
+            f,
+            "sprite hash {} is shared by sprites that differ ({}); they are compared by pixels instead",
+            full_tag(self.hash, self.hash_bits),
+            groups.join(" / ")
+        )
+    }
+}
+
+/// Hash standing in for that of the `n`th group of colliding sprites.
+/// Derived from the colliding hash so that both tilesets get the same one,
+/// and in 32-bit runs almost always wider than any real hash.
+fn fallback_hash(hash: u64, n: usize) -> u64 {
+    xxh64(&hash.to_le_bytes(), n as u64)
+}
+
+/// Finds sprites of both tilesets sharing a hash although the pixels they
+/// hash differ, and gives every group of them but the first a hash of its
+/// own in `hashes`, so that they compare as the different sprites they are
+/// rather than as equal. `atlases` must hold every sprite in `hashes`.
+pub fn split_collisions(
+    atlases: [&[TileAtlas]; 2],
+    hashes: [&mut HashMap<u32, u64>; 2],
+    opts: &HashOptions,
+) -> Vec<Collision> {
+    let mut by_hash: BTreeMap<u64, Vec<SideSprite>> = BTreeMap::new();
+    for (side, side_hashes) in hashes.iter().enumerate() {
+        for (&idx, &hash) in side_hashes.iter() {
+            by_hash.entry(hash).or_default().push((side, idx));
+        }
+    }
+    let shared: Vec<(u64, Vec<SideSprite>)> =
+        by_hash.into_iter().filter(|x| x.1.len() > 1).collect();
+
+    let progress = Progress::new("Checking hash collisions", shared.len());
+    let collisions: Vec<Collision> = shared
+        .into_par_iter()
+        .filter_map(|(hash, mut sprites)| {
+            progress.inc();
+            sprites.sort_unstable();
+            let mut groups: Vec<(HashedPixels, Vec<SideSprite>)> = vec![];
+            for (side, idx) in sprites {
+                let atlas = atlases[side].iter().find(|x| x.has_sprite(idx)).unwrap();
+                let pixels = atlas.get_hashed_pixels(idx, opts);
+                match groups.iter_mut().find(|x| x.0 == pixels) {
+                    Some(group) => group.1.push((side, idx)),
+                    None => groups.push((pixels, vec![(side, idx)])),
+                }
+            }
+            (groups.len() > 1).then(|| Collision {
+                hash,
+                groups: groups.into_iter().map(|x| x.1).collect(),
+                hash_bits: opts.hash_bits,
+            })
+        })
+        .collect();
+
+    for collision in &collisions {
+        for (n, group) in collision.groups.iter().enumerate().skip(1) {
+            for &(side, idx) in group {
+                hashes[side].insert(idx, fallback_hash(collision.hash, n));
+            }
+        }
+    }
+    collisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_sprites_that_only_share_a_hash() {
+        let red = [255, 0, 0, 255];
+        let a = [TileAtlas::from_pixels(
+            &[red, [0, 0, 255, 255], [9, 9, 9, 0]],
+            0,
+        )];
+        let b = [TileAtlas::from_pixels(&[red, [0, 0, 0, 0]], 0)];
+        // Every sprite hashing the same; invisible ones are equal with the
+        // alpha threshold
+        let mut hashes_a = HashMap::from([(0, 5), (1, 5), (2, 6)]);
+        let mut hashes_b = HashMap::from([(0, 5), (1, 6)]);
+        let opts = HashOptions {
+            alpha_threshold: Some(1),
+            ..Default::default()
+        };
+        let collisions = split_collisions([&a, &b], [&mut hashes_a, &mut hashes_b], &opts);
+        assert_eq!(
+            collisions,
+            [Collision {
+                hash: 5,
+                groups: vec![vec![(0, 0), (1, 0)], vec![(0, 1)]],
+                hash_bits: HashBits::Bits32,
+            }]
+        );
+        assert_eq!((hashes_a[&0], hashes_b[&0]), (5, 5));
+        assert_eq!(hashes_a[&1], fallback_hash(5, 1));
+        assert_eq!((hashes_a[&2], hashes_b[&1]), (6, 6));
+        assert!(collisions[0].to_string().contains("(A 0, B 0 / A 1)"));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::atlas::TileAtlas;
+use crate::progress::Progress;
+use image::{GenericImageView, Rgba};
+use rayon::prelude::*;
+use std::str::FromStr;
+
+/// An opaque color, written as `#rrggbb` (the `#` is optional).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color(pub [u8; 3]);
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if hex.len() != 6 || !hex.bytes().all(|x| x.is_ascii_hexdigit()) {
+            return Err(format!("'{}' is not a color, expected #rrggbb", s));
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+        Ok(Color([channel(0), channel(1), channel(2)]))
+    }
+}
+
+impl Color {
+    /// Whether a visible pixel is within `tolerance` of this color on every
+    /// channel. Fully transparent pixels never match, whatever their color.
+    pub fn matches(self, px: Rgba<u8>, tolerance: u8) -> bool {
+        px.0[3] != 0
+            && self
+                .0
+                .iter()
+                .zip(px.0.iter())
+                .all(|(a, b)| a.abs_diff(*b) <= tolerance)
+    }
+}
+
+/// Every sprite index with pixels matching `color`, with the number of
+/// matching pixels, in index order.
+pub fn find_color(atlases: &[TileAtlas], color: Color, tolerance: u8) -> Vec<(u32, usize)> {
+    let sprites: Vec<(&TileAtlas, u32)> = atlases
+        .iter()
+        .flat_map(|atlas| (atlas.tiles_start..atlas.tiles_end).map(move |x| (atlas, x)))
+        .collect();
+    let progress = Progress::new("Searching sprites", sprites.len());
+    sprites
+        .into_par_iter()
+        .filter_map(|(atlas, x)| {
+            progress.inc();
+            let count = atlas
+                .get_sprite(x)
+                .pixels()
+                .filter(|(_, _, px)| color.matches(*px, tolerance))
+                .count();
+            (count > 0).then_some((x, count))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!("#ff00ff".parse(), Ok(Color([255, 0, 255])));
+        assert_eq!("10A0b0".parse(), Ok(Color([16, 160, 176])));
+        assert!("#ff00f".parse::<Color>().is_err());
+        assert!("#gg00ff".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn matches_within_tolerance() {
+        let pink = Color([255, 0, 255]);
+        assert!(pink.matches(Rgba([255, 0, 255, 255]), 0));
+        assert!(!pink.matches(Rgba([250, 0, 255, 255]), 0));
+        assert!(pink.matches(Rgba([250, 4, 255, 128]), 5));
+        assert!(!pink.matches(Rgba([255, 0, 255, 0]), 5));
+    }
+}
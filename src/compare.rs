@@ -0,0 +1,842 @@
+use crate::atlas::{sprites_by_hash, HashBits, HashOptions, TileAtlas};
+use crate::badge;
+use crate::collision::split_collisions;
+use crate::error::Result;
+use crate::extras::Extras;
+use crate::fuzz::fuzzy_equal;
+use crate::hash_cache::{HashCache, CACHE_DIR_NAME};
+use crate::id_order::IdOrder;
+use crate::messages::{Lang, Msg};
+use crate::multitile::{describe_subtile_changes, multitile_subtiles};
+use crate::parallel;
+use crate::perceptual::PerceptualHash;
+use crate::property_matrix::{property_matrix, PropertyRow};
+use crate::single_or_vec::SingleOrVec;
+use crate::sprite_id_with_weight::SpriteIdWithWeight;
+use crate::tileset::{SingleTile, Tileset};
+use crate::variation_diff::{self, EditSummary};
+use crate::warnings::{warn, Code};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+
+/// Options for `compare`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CompareOptions {
+    pub hash: HashOptions,
+    /// Order of ids in every list of the result
+    pub order: IdOrder,
+    /// Compare ids only by the sprites they draw, ignoring weights, order
+    /// of variations and every structural field, and skip the structural
+    /// diffs of overlay ordering, subtiles and properties
+    pub pixels_only: bool,
+    /// Decode and hash one sheet at a time, keeping only the sprites of ids
+    /// that differ, instead of every sheet of both tilesets at once
+    pub low_memory: bool,
+    /// Reuse and record sprite hashes in `.tsc-cache/` of the working
+    /// directory, so that sheets hashed by an earlier run aren't decoded
+    pub hash_cache: bool,
+}
+
+/// Outcome of comparing tileset A against tileset B.
+pub struct ComparisonResult {
+    /// Expanded variations of tileset A, with sprite indices replaced by hashes
+    pub variations_a: Vec<SingleTile>,
+    pub variations_b: Vec<SingleTile>,
+    pub atlases_a: Vec<TileAtlas>,
+    pub atlases_b: Vec<TileAtlas>,
+    pub duplicates_a: Vec<String>,
+    pub duplicates_b: Vec<String>,
+    pub only_in_a: Vec<String>,
+    pub only_in_b: Vec<String>,
+    /// Ids present in both tilesets whose definitions differ,
+    /// unknown if duplicates prevented the diff.
+    pub changed: Option<Vec<String>>,
+    /// Ids whose definitions differ only by perceptually similar or fuzzily
+    /// equal sprites, and were treated as equal.
+    pub similar: Vec<String>,
+    /// Fallback glyph sets that differ, e.g. `white bold: 3 glyph(s) changed`
+    pub ascii_changes: Vec<String>,
+    /// `overlay_ordering` ids whose order changed, e.g. `worn_hat: 5000 -> 6000`
+    pub ordering_changes: Vec<String>,
+    /// Multitiles whose missing subtiles differ, e.g. `t_wall: A complete;
+    /// B lacks corner`
+    pub subtile_changes: Vec<String>,
+    /// Which properties match, for every id in both tilesets
+    pub properties: Vec<PropertyRow>,
+}
+
+impl ComparisonResult {
+    /// Definitions of every changed id in tileset A and tileset B.
+    pub fn changed_pairs(&self) -> Vec<(&SingleTile, &SingleTile)> {
+        let changed = match &self.changed {
+            Some(changed) => changed,
+            None => return vec![],
+        };
+        let by_id = |vars: &[SingleTile]| -> HashMap<String, usize> {
+            vars.iter()
+                .enumerate()
+                .map(|(idx, x)| (x.id.0[0].clone(), idx))
+                .collect()
+        };
+        let by_id_a = by_id(&self.variations_a);
+        let by_id_b = by_id(&self.variations_b);
+        changed
+            .iter()
+            .filter_map(|id| {
+                let a = by_id_a.get(id)?;
+                let b = by_id_b.get(id)?;
+                Some((&self.variations_a[*a], &self.variations_b[*b]))
+            })
+            .collect()
+    }
+
+    /// Names of the outputs that would differ between this result and `other`.
+    pub fn differing_outputs(&self, other: &ComparisonResult) -> Result<Vec<&'static str>> {
+        let mut ret = vec![];
+        if serde_json::to_string(&self.variations_a)? != serde_json::to_string(&other.variations_a)?
+            || serde_json::to_string(&self.variations_b)?
+                != serde_json::to_string(&other.variations_b)?
+        {
+            ret.push("dump.json");
+        }
+        if self.duplicates_a != other.duplicates_a || self.duplicates_b != other.duplicates_b {
+            ret.push("duplicates.txt");
+        }
+        if self.only_in_a != other.only_in_a || self.only_in_b != other.only_in_b {
+            ret.push("exclusives.txt");
+        }
+        if self.changed != other.changed || self.similar != other.similar {
+            ret.push("different.txt");
+        }
+        if self.ascii_changes != other.ascii_changes {
+            ret.push("ascii.txt");
+        }
+        if self.ordering_changes != other.ordering_changes {
+            ret.push("ordering.txt");
+        }
+        if self.subtile_changes != other.subtile_changes {
+            ret.push("subtiles.txt");
+        }
+        if self.properties != other.properties {
+            ret.push("properties.txt");
+        }
+        Ok(ret)
+    }
+
+    /// Whether the tilesets differ in ids, definitions, fallback glyphs,
+    /// overlay ordering or multitile subtiles.
+    pub fn differs(&self) -> bool {
+        !self.only_in_a.is_empty()
+            || !self.only_in_b.is_empty()
+            || !self.ascii_changes.is_empty()
+            || !self.ordering_changes.is_empty()
+            || !self.subtile_changes.is_empty()
+            || self.changed.as_ref().is_some_and(|x| !x.is_empty())
+    }
+
+    /// `identical`, `different`, or `unknown` if duplicates prevented the diff.
+    pub fn status(&self) -> &'static str {
+        match &self.changed {
+            None => "unknown",
+            Some(_) if self.differs() => "different",
+            Some(_) => "identical",
+        }
+    }
+
+    /// Plain-text summary of the comparison: a verdict line, then every
+    /// non-empty id list with its ids indented below it.
+    pub fn verdict(&self, lang: Lang) -> String {
+        let verdict = match self.status() {
+            "unknown" => Msg::VerdictUnknown,
+            "different" => Msg::VerdictDifferent,
+            _ => Msg::VerdictIdentical,
+        };
+        let mut ret = format!("{}: {}\n", lang.text(Msg::Verdict), lang.text(verdict));
+        let none = vec![];
+        let lists = [
+            (Msg::DuplicatesInA, &self.duplicates_a),
+            (Msg::DuplicatesInB, &self.duplicates_b),
+            (Msg::OnlyInA, &self.only_in_a),
+            (Msg::OnlyInB, &self.only_in_b),
+            (Msg::Changed, self.changed.as_ref().unwrap_or(&none)),
+            (Msg::Similar, &self.similar),
+            (Msg::AsciiChanges, &self.ascii_changes),
+            (Msg::OrderingChanges, &self.ordering_changes),
+            (Msg::SubtileChanges, &self.subtile_changes),
+        ];
+        for (title, items) in lists {
+            if items.is_empty() {
+                continue;
+            }
+            ret += &format!("{}: {}\n", lang.text(title), items.len());
+            for item in items {
+                ret += &format!("  {}\n", item);
+            }
+        }
+        ret
+    }
+
+    pub fn badge(&self, lang: Lang) -> String {
+        let (value, color) = match &self.changed {
+            Some(changed) => {
+                let total = changed.len() + self.only_in_a.len() + self.only_in_b.len();
+                let color = if total == 0 {
+                    badge::COLOR_GOOD
+                } else {
+                    badge::COLOR_WARN
+                };
+                (total.to_string(), color)
+            }
+            None => (
+                lang.text(Msg::BadgeUnknown).to_string(),
+                badge::COLOR_UNKNOWN,
+            ),
+        };
+        badge::render_badge(lang.text(Msg::BadgeLabel), &value, color)
+    }
+}
+
+pub fn find_duplicates(vars: &[SingleTile]) -> Vec<&str> {
+    let mut ids: Vec<&str> = vars.iter().map(|x| x.id.0[0].as_str()).collect();
+    ids.sort_unstable();
+    let (_, dups) = ids.partition_dedup();
+    dups.to_vec()
+}
+
+/// Describes how the fg/bg variation lists and unknown fields of a tile
+/// changed from `a` to `b`, e.g. `fg +1 variation`, or `None` if they're the same.
+pub fn describe_variation_changes(a: &SingleTile, b: &SingleTile) -> Option<String> {
+    let lists = [
+        ("fg", a.fg(), b.fg(), a.fg.is_some(), b.fg.is_some()),
+        ("bg", a.bg(), b.bg(), a.bg.is_some(), b.bg.is_some()),
+    ];
+    let mut parts: Vec<String> = lists
+        .iter()
+        .filter_map(|(role, la, lb, present_a, present_b)| {
+            let summary = EditSummary::new(&variation_diff::edit_script(la, lb));
+            match (present_a, present_b) {
+                _ if !summary.is_unchanged() => Some(format!("{} {}", role, summary)),
+                (false, true) => Some(format!("{} absent -> empty", role)),
+                (true, false) => Some(format!("{} empty -> absent", role)),
+                _ => None,
+            }
+        })
+        .collect();
+    parts.extend(Extras::describe_changes(&a.extra, &b.extra));
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(", "))
+    }
+}
+
+/// Perceptual hash of every sprite, by its exact sprite hash.
+fn perceptual_hashes(atlases: &[TileAtlas], opts: &HashOptions) -> HashMap<u64, PerceptualHash> {
+    let mut ret = HashMap::new();
+    for atlas in atlases {
+        for tile_id in (atlas.tiles_start..atlas.tiles_end).filter(|x| atlas.has_sprite(*x)) {
+            ret.entry(atlas.get_sprite_hash(tile_id, opts))
+                .or_insert_with(|| PerceptualHash::new(&atlas.get_sprite(tile_id)));
+        }
+    }
+    ret
+}
+
+/// Whether two hashed tiles are equal apart from sprites that
+/// `sprites_match` accepts as alike, given their hashes.
+fn tiles_similar(a: &SingleTile, b: &SingleTile, sprites_match: &dyn Fn(u64, u64) -> bool) -> bool {
+    let lists_similar = |la: &Option<SingleOrVec<SpriteIdWithWeight>>,
+                         lb: &Option<SingleOrVec<SpriteIdWithWeight>>| {
+        let (la, lb) = match (la, lb) {
+            (Some(la), Some(lb)) => (&la.0, &lb.0),
+            (la, lb) => return la.is_none() && lb.is_none(),
+        };
+        la.len() == lb.len()
+            && la.iter().zip(lb.iter()).all(|(sa, sb)| {
+                sa.weight == sb.weight
+                    && sa.id.0.len() == sb.id.0.len()
+                    && sa
+                        .id
+                        .0
+                        .iter()
+                        .zip(sb.id.0.iter())
+                        .all(|(ha, hb)| ha == hb || sprites_match(*ha, *hb))
+            })
+    };
+    let strip = |x: &SingleTile| SingleTile {
+        fg: None,
+        bg: None,
+        ..x.clone()
+    };
+    strip(a) == strip(b) && lists_similar(&a.fg, &b.fg) && lists_similar(&a.bg, &b.bg)
+}
+
+/// Fallback glyph set of an `ascii` entry: its offset within the sheet and
+/// the hash of each of the 256 glyph sprites, if it exists.
+struct GlyphSet {
+    offset: i32,
+    glyphs: Vec<Option<u64>>,
+}
+
+/// Brings the sprites drawn by the given ids back into atlases of
+/// `hash_used_sprites_lazily`.
+fn load_sprites_of_ids(
+    ts: &Tileset,
+    atlases: &mut [TileAtlas],
+    ids: &HashSet<&str>,
+    opts: &HashOptions,
+) -> Result<()> {
+    let sprites: BTreeSet<u32> = ts
+        .expand_variations(&[], false, opts)
+        .iter()
+        .filter(|x| ids.contains(x.id.0[0].as_str()))
+        .flat_map(|x| x.variations())
+        .flat_map(|x| x.indices())
+        .collect();
+    ts.load_sprites(atlases, &sprites)
+}
+
+/// Glyph sets of every `ascii` entry, by color and boldness. Like the game,
+/// a later entry replaces an earlier one with the same color and boldness.
+fn glyph_sets(
+    ts: &Tileset,
+    atlases: &[TileAtlas],
+    opts: &HashOptions,
+) -> BTreeMap<(String, bool), GlyphSet> {
+    let mut ret = BTreeMap::new();
+    for (sheet, atlas) in ts.tiles_new.iter().zip(atlases) {
+        for ascii in &sheet.ascii {
+            let glyphs = (0..256)
+                .map(|ch| {
+                    let idx = i64::from(atlas.tiles_start) + i64::from(ascii.offset) + ch;
+                    u32::try_from(idx)
+                        .ok()
+                        .filter(|x| atlas.in_bounds(*x))
+                        .map(|x| atlas.get_sprite_hash(x, opts))
+                })
+                .collect();
+            let set = GlyphSet {
+                offset: ascii.offset,
+                glyphs,
+            };
+            ret.insert((ascii.color.clone(), ascii.bold), set);
+        }
+    }
+    ret
+}
+
+/// Describes the differences between the fallback glyph sets of two tilesets.
+fn describe_ascii_changes(
+    a: &BTreeMap<(String, bool), GlyphSet>,
+    b: &BTreeMap<(String, bool), GlyphSet>,
+) -> Vec<String> {
+    let name = |(color, bold): &(String, bool)| {
+        if *bold {
+            format!("{} bold", color)
+        } else {
+            color.clone()
+        }
+    };
+    let mut ret = vec![];
+    for (key, set_a) in a {
+        let set_b = match b.get(key) {
+            Some(set_b) => set_b,
+            None => {
+                ret.push(format!("{}: removed", name(key)));
+                continue;
+            }
+        };
+        let mut parts = vec![];
+        if set_a.offset != set_b.offset {
+            parts.push(format!("offset {} -> {}", set_a.offset, set_b.offset));
+        }
+        let changed = set_a
+            .glyphs
+            .iter()
+            .zip(&set_b.glyphs)
+            .filter(|(x, y)| x != y)
+            .count();
+        if changed > 0 {
+            parts.push(format!("{} glyph(s) changed", changed));
+        }
+        if !parts.is_empty() {
+            ret.push(format!("{}: {}", name(key), parts.join(", ")));
+        }
+    }
+    for key in b.keys().filter(|x| !a.contains_key(*x)) {
+        ret.push(format!("{}: added", name(key)));
+    }
+    ret
+}
+
+/// Order of every id listed in `overlay_ordering`; later entries override
+/// earlier ones.
+fn overlay_orders(ts: &Tileset) -> HashMap<&str, i32> {
+    ts.overlay_ordering
+        .iter()
+        .flat_map(|x| x.id.0.iter().map(move |id| (id.as_str(), x.order)))
+        .collect()
+}
+
+/// Describes the `overlay_ordering` ids whose order changed, was added or
+/// was removed, e.g. `worn_hat: 5000 -> 6000`.
+fn describe_ordering_changes(ts1: &Tileset, ts2: &Tileset, order: IdOrder) -> Vec<String> {
+    let (a, b) = (overlay_orders(ts1), overlay_orders(ts2));
+    let ids = sorted_ids(
+        a.keys()
+            .chain(b.keys().filter(|x| !a.contains_key(*x)))
+            .copied(),
+        order,
+    );
+    ids.into_iter()
+        .filter_map(|id| match (a.get(id.as_str()), b.get(id.as_str())) {
+            (Some(x), Some(y)) if x != y => Some(format!("{}: {} -> {}", id, x, y)),
+            (Some(x), None) => Some(format!("{}: removed (was {})", id, x)),
+            (None, Some(y)) => Some(format!("{}: added ({})", id, y)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn sorted_ids<'a, I: IntoIterator<Item = &'a str>>(ids: I, order: IdOrder) -> Vec<String> {
+    let mut ret: Vec<&str> = ids.into_iter().collect();
+    order.sort(&mut ret);
+    ret.into_iter().map(|x| x.to_string()).collect()
+}
+
+/// Differences known from the tile configs alone, before any sheet is
+/// decoded.
+#[derive(Debug, Default)]
+pub struct EarlyDiff {
+    pub only_in_a: Vec<String>,
+    pub only_in_b: Vec<String>,
+    /// Ids in both tilesets whose properties other than sprites differ
+    pub changed_properties: Vec<String>,
+    /// Sheets of tileset A defining any of the above
+    pub sheets_a: Vec<String>,
+    pub sheets_b: Vec<String>,
+}
+
+/// Finds the ids that differ regardless of sprite content, and the sheets
+/// defining them, so those sheets can be decoded first.
+pub fn early_diff(ts1: &Tileset, ts2: &Tileset, opts: &CompareOptions) -> EarlyDiff {
+    let without_sprites = |ts: &Tileset| -> HashMap<String, SingleTile> {
+        ts.expand_variations(&[], false, &opts.hash)
+            .into_iter()
+            .map(|mut x| {
+                x.fg = None;
+                x.bg = None;
+                (x.id.0[0].clone(), x)
+            })
+            .collect()
+    };
+    let (vars1, vars2) = (without_sprites(ts1), without_sprites(ts2));
+    let only = |x: &HashMap<String, SingleTile>, y: &HashMap<String, SingleTile>| {
+        sorted_ids(
+            x.keys()
+                .filter(|id| !y.contains_key(*id))
+                .map(String::as_str),
+            opts.order,
+        )
+    };
+    let changed_properties = sorted_ids(
+        vars1
+            .iter()
+            .filter(|(id, x)| vars2.get(*id).is_some_and(|y| y != *x))
+            .map(|(id, _)| id.as_str()),
+        opts.order,
+    );
+    let mut ret = EarlyDiff {
+        only_in_a: only(&vars1, &vars2),
+        only_in_b: only(&vars2, &vars1),
+        changed_properties,
+        ..Default::default()
+    };
+    let sheets = |ts: &Tileset, only: &[String], changed: &[String]| {
+        let sheet_of = ts.sheets_by_id();
+        let mut ret: Vec<String> = vec![];
+        for id in only.iter().chain(changed) {
+            if let Some(sheet) = sheet_of.get(id) {
+                if !ret.iter().any(|x| x == sheet) {
+                    ret.push(sheet.to_string());
+                }
+            }
+        }
+        ret
+    };
+    ret.sheets_a = sheets(ts1, &ret.only_in_a, &ret.changed_properties);
+    ret.sheets_b = sheets(ts2, &ret.only_in_b, &ret.changed_properties);
+    ret
+}
+
+/// Makes tilesets drawn at different pixelscales comparable: if one scale is
+/// a whole multiple of the other, sprites of the smaller one are enlarged to
+/// match when loaded, and a note saying so is returned. Otherwise warns that
+/// every sprite is likely to differ.
+pub fn match_pixelscales(ts1: &mut Tileset, ts2: &mut Tileset) -> Option<String> {
+    let scale1 = ts1.tile_info().pixelscale;
+    let scale2 = ts2.tile_info().pixelscale;
+    if scale1 == scale2 {
+        return None;
+    }
+    let (small, large) = if scale1 < scale2 {
+        (ts1, scale2 / scale1)
+    } else {
+        (ts2, scale1 / scale2)
+    };
+    let factor = large.round();
+    if scale1 > 0.0 && scale2 > 0.0 && (large - factor).abs() < 1e-3 {
+        small.upscale = Some(factor as u32);
+        return Some(format!(
+            "NOTE: pixelscale differs ({} vs {}), sprites of '{}' are enlarged {}x to compare.",
+            scale1,
+            scale2,
+            small.base_path.display(),
+            factor
+        ));
+    }
+    warn(
+        Code::PixelscaleMismatch,
+        format!(
+            "pixelscale differs ({} vs {}) and cannot be matched by enlarging sprites, \
+             sprites are compared as is",
+            scale1, scale2
+        ),
+    );
+    None
+}
+
+/// Sprite sizes of every sheet, for checking that a factor divides them all.
+fn sprite_sizes(ts: &Tileset) -> Vec<(u32, u32)> {
+    let (w, h) = (ts.tile_info().width, ts.tile_info().height);
+    ts.tiles_new
+        .iter()
+        .map(|x| (x.sprite_width.unwrap_or(w), x.sprite_height.unwrap_or(h)))
+        .collect()
+}
+
+/// Makes a tileset comparable with a higher resolution variant of itself,
+/// e.g. 32x32 and 64x64 tiles: if tile sizes differ by a whole factor that
+/// divides every sprite size of the larger one, its sprites are shrunk with
+/// nearest-neighbor sampling when loaded. Returns a note saying what was
+/// done, or why it couldn't be.
+pub fn match_resolutions(ts1: &mut Tileset, ts2: &mut Tileset) -> Option<String> {
+    let size1 = (ts1.tile_info().width, ts1.tile_info().height);
+    let size2 = (ts2.tile_info().width, ts2.tile_info().height);
+    if size1 == size2 {
+        return None;
+    }
+    let (large, small_size, large_size) = if size1.0 > size2.0 {
+        (ts1, size2, size1)
+    } else {
+        (ts2, size1, size2)
+    };
+    let describe = |(w, h): (u32, u32)| format!("{}x{}", w, h);
+    let factor = large_size.0 / std::cmp::max(small_size.0, 1);
+    let divides = |(w, h): (u32, u32)| factor > 1 && w % factor == 0 && h % factor == 0;
+    if small_size.0 * factor != large_size.0
+        || small_size.1 * factor != large_size.1
+        || !sprite_sizes(large).into_iter().all(divides)
+    {
+        return Some(format!(
+            "NOTE: tile sizes differ ({} vs {}) by no whole factor dividing every sprite, \
+             sprites are compared as is.",
+            describe(size1),
+            describe(size2)
+        ));
+    }
+    large.downscale = Some(factor);
+    Some(format!(
+        "NOTE: tile sizes differ ({} vs {}), sprites of '{}' are shrunk {}x to compare.",
+        describe(size1),
+        describe(size2),
+        large.base_path.display(),
+        factor
+    ))
+}
+
+/// Runs the comparison on a single thread and on several, returning the
+/// result of the parallel run and the outputs in which the two runs differ.
+/// Outputs must not depend on the thread count, so any difference is a bug.
+pub fn self_check(
+    ts1: &Tileset,
+    ts2: &Tileset,
+    opts: &CompareOptions,
+) -> Result<(ComparisonResult, Vec<&'static str>)> {
+    let jobs = std::cmp::max(parallel::current_jobs(), 2);
+    let serial = parallel::with_jobs(1, || compare(ts1, ts2, opts))??;
+    let result = parallel::with_jobs(jobs, || compare(ts1, ts2, opts))??;
+    let differing = serial.differing_outputs(&result)?;
+    Ok((result, differing))
+}
+
+/// What a tile looks like in game: its id and the distinct sprites of its
+/// fg and bg variations, without weights or any other field.
+fn appearance(tile: &SingleTile) -> SingleTile {
+    let sprites = |list: &[SpriteIdWithWeight]| {
+        let mut ret: Vec<SpriteIdWithWeight> = list
+            .iter()
+            .map(|x| SpriteIdWithWeight {
+                id: x.id.clone(),
+                weight: None,
+            })
+            .collect();
+        ret.sort();
+        ret.dedup();
+        (!ret.is_empty()).then_some(SingleOrVec(ret))
+    };
+    SingleTile {
+        id: tile.id.clone(),
+        fg: sprites(tile.fg()),
+        bg: sprites(tile.bg()),
+        rotates: None,
+        multitile: false,
+        animated: false,
+        height_3d: 0,
+        extra: Extras::default(),
+    }
+}
+
+/// Note that `compare` won't check sprite hash collisions with `opts`, if
+/// so: lazily hashed sprites aren't at hand to tell apart, and the fast hash
+/// is allowed to miss differences anyway.
+pub fn collision_note(opts: &CompareOptions) -> Option<&'static str> {
+    if opts.hash.fast_hash {
+        Some("NOTE: fast hash mode, small sprite edits and hash collisions may go unnoticed.")
+    } else if !(opts.low_memory || opts.hash_cache) {
+        None
+    } else if opts.hash.hash_bits == HashBits::Bits32 {
+        Some("NOTE: sprite hash collisions are not checked with --low-memory or --hash-cache; pass --hash-bits 64 to make them unlikely.")
+    } else {
+        Some("NOTE: sprite hash collisions are not checked with --low-memory or --hash-cache, though unlikely with 64-bit hashes.")
+    }
+}
+
+/// Compares two tilesets by sprite content, without writing anything.
+pub fn compare(ts1: &Tileset, ts2: &Tileset, opts: &CompareOptions) -> Result<ComparisonResult> {
+    let cache = match opts.hash_cache {
+        true => HashCache::open(Path::new(CACHE_DIR_NAME), &opts.hash)?,
+        false => None,
+    };
+    // Atlases generated lazily only hold the sprites loaded into them
+    let lazily = opts.low_memory || cache.is_some();
+    let (hashed1, hashed2) = if opts.low_memory {
+        (
+            ts1.hash_used_sprites_lazily(&opts.hash, cache.as_ref()),
+            ts2.hash_used_sprites_lazily(&opts.hash, cache.as_ref()),
+        )
+    } else if cache.is_some() {
+        rayon::join(
+            || ts1.hash_used_sprites_lazily(&opts.hash, cache.as_ref()),
+            || ts2.hash_used_sprites_lazily(&opts.hash, cache.as_ref()),
+        )
+    } else {
+        // Each tileset also decodes its sheets in parallel; running both at
+        // once keeps the workers busy while one of them hashes its last
+        // large sheet
+        rayon::join(
+            || ts1.hash_used_sprites(&opts.hash),
+            || ts2.hash_used_sprites(&opts.hash),
+        )
+    };
+    compare_with(ts1, ts2, hashed1?, hashed2?, lazily, opts)
+}
+
+/// Atlases of a tileset and the hashes of its used sprites, as returned by
+/// `Tileset::hash_used_sprites`.
+pub type Hashed = (Vec<TileAtlas>, HashMap<u32, u64>);
+
+/// `compare` of tilesets already hashed by `Tileset::hash_used_sprites`, for
+/// callers that need their atlases for more than the comparison.
+pub fn compare_hashed(
+    ts1: &Tileset,
+    ts2: &Tileset,
+    hashed1: Hashed,
+    hashed2: Hashed,
+    opts: &CompareOptions,
+) -> Result<ComparisonResult> {
+    compare_with(ts1, ts2, hashed1, hashed2, false, opts)
+}
+
+fn compare_with(
+    ts1: &Tileset,
+    ts2: &Tileset,
+    hashed1: Hashed,
+    hashed2: Hashed,
+    lazily: bool,
+    opts: &CompareOptions,
+) -> Result<ComparisonResult> {
+    let order = opts.order;
+    let (mut atlases1, mut hashes1) = hashed1;
+    let (mut atlases2, mut hashes2) = hashed2;
+    // Sprites are only at hand when hashed eagerly, and the fast hash is
+    // allowed to miss differences
+    if !lazily && !opts.hash.fast_hash {
+        let collisions = split_collisions(
+            [&atlases1, &atlases2],
+            [&mut hashes1, &mut hashes2],
+            &opts.hash,
+        );
+        for collision in collisions {
+            warn(Code::HashCollision, collision.to_string());
+        }
+    }
+    let vars1 = ts1.expand_with_hashes(true, &hashes1, &opts.hash);
+    let vars2 = ts2.expand_with_hashes(true, &hashes2, &opts.hash);
+
+    let dups1 = sorted_ids(find_duplicates(&vars1), order);
+    let dups2 = sorted_ids(find_duplicates(&vars2), order);
+    let do_diff = dups1.is_empty() && dups2.is_empty();
+
+    let ids_1: HashSet<&str> = vars1.iter().map(|x| x.id.0[0].as_str()).collect();
+    let ids_2: HashSet<&str> = vars2.iter().map(|x| x.id.0[0].as_str()).collect();
+
+    let only_in_a = sorted_ids(ids_1.difference(&ids_2).cloned(), order);
+    let only_in_b = sorted_ids(ids_2.difference(&ids_1).cloned(), order);
+
+    let looks1: Vec<SingleTile>;
+    let looks2: Vec<SingleTile>;
+    let (diff1, diff2) = if opts.pixels_only {
+        looks1 = vars1.iter().map(appearance).collect();
+        looks2 = vars2.iter().map(appearance).collect();
+        (&looks1, &looks2)
+    } else {
+        (&vars1, &vars2)
+    };
+
+    // Reports and similar sprites only need the sprites of ids that differ
+    let mut differing: HashSet<&str> = only_in_a
+        .iter()
+        .chain(&only_in_b)
+        .map(String::as_str)
+        .collect();
+
+    let mut similar = vec![];
+    let changed = if do_diff {
+        let idx1: HashSet<&SingleTile> = diff1.iter().collect();
+        let idx2: HashSet<&SingleTile> = diff2.iter().collect();
+
+        let mut in_1_only: HashSet<&SingleTile> = idx1
+            .difference(&idx2)
+            .cloned()
+            .filter(|x| ids_2.contains(x.id.0[0].as_str()))
+            .collect();
+        if lazily {
+            differing.extend(in_1_only.iter().map(|x| x.id.0[0].as_str()));
+            load_sprites_of_ids(ts1, &mut atlases1, &differing, &opts.hash)?;
+            load_sprites_of_ids(ts2, &mut atlases2, &differing, &opts.hash)?;
+        }
+
+        if opts.hash.similarity.is_some() || opts.hash.fuzz.is_some() {
+            let (phash1, phash2) = match opts.hash.similarity {
+                Some(_) => (
+                    perceptual_hashes(&atlases1, &opts.hash),
+                    perceptual_hashes(&atlases2, &opts.hash),
+                ),
+                None => Default::default(),
+            };
+            let (sprites1, sprites2) = match opts.hash.fuzz {
+                Some(_) => (
+                    sprites_by_hash(&atlases1, &opts.hash),
+                    sprites_by_hash(&atlases2, &opts.hash),
+                ),
+                None => Default::default(),
+            };
+            let sprites_match = |ha: u64, hb: u64| {
+                let similar = opts.hash.similarity.is_some_and(|threshold| {
+                    match (phash1.get(&ha), phash2.get(&hb)) {
+                        (Some(pa), Some(pb)) => pa.is_similar(pb, threshold),
+                        _ => false,
+                    }
+                });
+                similar
+                    || opts.hash.fuzz.is_some_and(|fuzz| {
+                        match (sprites1.get(&ha), sprites2.get(&hb)) {
+                            (Some((aa, ia)), Some((ab, ib))) => fuzzy_equal(
+                                &aa.get_sprite(*ia),
+                                &ab.get_sprite(*ib),
+                                fuzz,
+                                opts.hash.fuzz_pixels,
+                            ),
+                            _ => false,
+                        }
+                    })
+            };
+            let by_id2: HashMap<&str, &SingleTile> = idx2
+                .difference(&idx1)
+                .map(|x| (x.id.0[0].as_str(), *x))
+                .collect();
+            let similar_ids: HashSet<&str> = in_1_only
+                .iter()
+                .filter(|x| {
+                    let id = x.id.0[0].as_str();
+                    by_id2
+                        .get(id)
+                        .map(|y| tiles_similar(x, y, &sprites_match))
+                        .unwrap_or(false)
+                })
+                .map(|x| x.id.0[0].as_str())
+                .collect();
+            in_1_only.retain(|x| !similar_ids.contains(x.id.0[0].as_str()));
+            similar = sorted_ids(similar_ids, order);
+        }
+
+        Some(sorted_ids(
+            in_1_only.iter().map(|x| x.id.0[0].as_str()),
+            order,
+        ))
+    } else {
+        warn(
+            Code::DiffSkipped,
+            "duplicate tiles found in at least one tileset, diff will not be generated.",
+        );
+        if lazily {
+            load_sprites_of_ids(ts1, &mut atlases1, &differing, &opts.hash)?;
+            load_sprites_of_ids(ts2, &mut atlases2, &differing, &opts.hash)?;
+        }
+        None
+    };
+
+    // Fallback glyphs have no ids, so filtered runs leave them out
+    let ascii_changes = if ts1.filter.is_some() || ts2.filter.is_some() {
+        vec![]
+    } else {
+        describe_ascii_changes(
+            &glyph_sets(ts1, &atlases1, &opts.hash),
+            &glyph_sets(ts2, &atlases2, &opts.hash),
+        )
+    };
+
+    // Ordering entries name ids without their overlay prefixes, which id
+    // filters don't match
+    let ordering_changes = if ts1.filter.is_some() || ts2.filter.is_some() || opts.pixels_only {
+        vec![]
+    } else {
+        describe_ordering_changes(ts1, ts2, order)
+    };
+    let (subtile_changes, properties) = if opts.pixels_only {
+        (vec![], vec![])
+    } else {
+        (
+            describe_subtile_changes(&multitile_subtiles(ts1), &multitile_subtiles(ts2), order),
+            property_matrix(&vars1, &vars2, order),
+        )
+    };
+
+    Ok(ComparisonResult {
+        variations_a: vars1,
+        variations_b: vars2,
+        atlases_a: atlases1,
+        atlases_b: atlases2,
+        duplicates_a: dups1,
+        duplicates_b: dups2,
+        only_in_a,
+        only_in_b,
+        changed,
+        similar,
+        ascii_changes,
+        ordering_changes,
+        subtile_changes,
+        properties,
+    })
+}
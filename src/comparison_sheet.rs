@@ -0,0 +1,226 @@
+use crate::atlas::{save_png, HashOptions, TileAtlas};
+use crate::compare::ComparisonResult;
+use crate::error::Result;
+use crate::tileset::{SingleTile, Tileset};
+use image::imageops::overlay;
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::path::Path;
+
+const BACKGROUND: Rgba<u8> = Rgba([43, 43, 43, 255]);
+const GRID: Rgba<u8> = Rgba([85, 85, 85, 255]);
+const TEXT: Rgba<u8> = Rgba([221, 221, 221, 255]);
+
+/// Pixels around the contents of every cell.
+const PADDING: u32 = 4;
+const GLYPH_W: u32 = 3;
+const GLYPH_H: u32 = 5;
+/// Glyphs are drawn at this many pixels per font pixel.
+const SCALE: u32 = 2;
+
+/// A 3x5 pixel font, one row per byte, most significant of the low 3 bits
+/// leftmost. Letters are uppercase only; lowercase is drawn in uppercase.
+const GLYPHS: &[(char, [u8; 5])] = &[
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b110, 0b001, 0b010, 0b100, 0b111]),
+    ('3', [0b110, 0b001, 0b010, 0b001, 0b110]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b110, 0b001, 0b110]),
+    ('6', [0b011, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b110]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('(', [0b010, 0b100, 0b100, 0b100, 0b010]),
+    (')', [0b010, 0b001, 0b001, 0b001, 0b010]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('#', [0b101, 0b111, 0b101, 0b111, 0b101]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('\'', [0b010, 0b010, 0b000, 0b000, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('*', [0b101, 0b010, 0b101, 0b000, 0b000]),
+    ('&', [0b010, 0b101, 0b010, 0b101, 0b011]),
+    ('?', [0b110, 0b001, 0b010, 0b000, 0b010]),
+];
+
+/// Drawn for characters the font lacks, e.g. CJK or emoji.
+const MISSING_GLYPH: [u8; 5] = [0b111, 0b101, 0b101, 0b101, 0b111];
+
+fn glyph(c: char) -> [u8; 5] {
+    let c = c.to_ascii_uppercase();
+    GLYPHS
+        .iter()
+        .find(|x| x.0 == c)
+        .map_or(MISSING_GLYPH, |x| x.1)
+}
+
+fn text_width(text: &str) -> u32 {
+    text.chars().count() as u32 * (GLYPH_W + 1) * SCALE
+}
+
+fn draw_text(img: &mut RgbaImage, x: u32, y: u32, text: &str) {
+    for (k, c) in text.chars().enumerate() {
+        let left = x + k as u32 * (GLYPH_W + 1) * SCALE;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_W {
+                if bits & (1 << (GLYPH_W - 1 - col)) == 0 {
+                    continue;
+                }
+                for dy in 0..SCALE {
+                    for dx in 0..SCALE {
+                        let px = left + col * SCALE + dx;
+                        let py = y + row as u32 * SCALE + dy;
+                        img.put_pixel(px, py, TEXT);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Every fg then bg sprite of a tile, in variation order.
+fn tile_sprites(tile: Option<&SingleTile>, atlases: &[TileAtlas]) -> Vec<RgbaImage> {
+    let tile = match tile {
+        Some(tile) => tile,
+        None => return vec![],
+    };
+    tile.variations()
+        .flat_map(|x| x.indices())
+        .filter_map(|idx| atlases.iter().find(|x| x.in_bounds(idx)).map(|x| (x, idx)))
+        .map(|(atlas, idx)| atlas.get_placed_sprite(idx).0)
+        .collect()
+}
+
+/// Width of sprites drawn in a row, with `PADDING` between them.
+fn sprites_width(sprites: &[RgbaImage]) -> u32 {
+    let gaps = sprites.len().saturating_sub(1) as u32 * PADDING;
+    sprites.iter().map(|x| x.width()).sum::<u32>() + gaps
+}
+
+/// Renders one PNG with a row per changed id: the id, then the fg and bg
+/// sprites of the id in tileset A, then those in tileset B. Ids are drawn
+/// in a small built-in font that shows characters outside printable ASCII
+/// as boxes. Returns the number of rows.
+pub fn write_comparison_sheet(
+    path: &Path,
+    ts_a: &Tileset,
+    ts_b: &Tileset,
+    result: &ComparisonResult,
+    opts: &HashOptions,
+) -> Result<usize> {
+    let raw_a = ts_a.expand_variations(&result.atlases_a, false, opts);
+    let raw_b = ts_b.expand_variations(&result.atlases_b, false, opts);
+    let by_id = |vars: &[SingleTile]| -> HashMap<String, usize> {
+        vars.iter()
+            .enumerate()
+            .map(|(idx, x)| (x.id.0[0].clone(), idx))
+            .collect()
+    };
+    let (by_id_a, by_id_b) = (by_id(&raw_a), by_id(&raw_b));
+
+    let header = ("ID".to_string(), vec![], vec![]);
+    let mut rows = vec![header];
+    for id in result.changed.iter().flatten() {
+        let a = by_id_a.get(id).map(|x| &raw_a[*x]);
+        let b = by_id_b.get(id).map(|x| &raw_b[*x]);
+        rows.push((
+            id.clone(),
+            tile_sprites(a, &result.atlases_a),
+            tile_sprites(b, &result.atlases_b),
+        ));
+    }
+
+    let text_h = GLYPH_H * SCALE;
+    let id_w = rows.iter().map(|x| text_width(&x.0)).max().unwrap_or(0);
+    let a_w = rows
+        .iter()
+        .map(|x| sprites_width(&x.1))
+        .fold(text_width("A"), std::cmp::max);
+    let b_w = rows
+        .iter()
+        .map(|x| sprites_width(&x.2))
+        .fold(text_width("B"), std::cmp::max);
+    let heights: Vec<u32> = rows
+        .iter()
+        .map(|(_, a, b)| {
+            a.iter()
+                .chain(b.iter())
+                .map(|x| x.height())
+                .fold(text_h, std::cmp::max)
+                + 2 * PADDING
+        })
+        .collect();
+
+    // Columns are separated by 1 pixel grid lines
+    let widths = [id_w, a_w, b_w].map(|w| w + 2 * PADDING);
+    let column_x = [1, widths[0] + 2, widths[0] + widths[1] + 3];
+    let width = column_x[2] + widths[2] + 1;
+    let height = heights.iter().map(|x| x + 1).sum::<u32>() + 1;
+    let mut img = RgbaImage::from_pixel(width, height, BACKGROUND);
+
+    let mut y = 1;
+    for (k, (id, sprites_a, sprites_b)) in rows.iter().enumerate() {
+        let text_y = y + (heights[k] - text_h) / 2;
+        draw_text(&mut img, column_x[0] + PADDING, text_y, id);
+        if k == 0 {
+            draw_text(&mut img, column_x[1] + PADDING, text_y, "A");
+            draw_text(&mut img, column_x[2] + PADDING, text_y, "B");
+        }
+        for (x0, sprites) in [(column_x[1], sprites_a), (column_x[2], sprites_b)] {
+            let mut x = x0 + PADDING;
+            for sprite in sprites {
+                overlay(&mut img, sprite, x, y + PADDING);
+                x += sprite.width() + PADDING;
+            }
+        }
+        y += heights[k];
+        for x in 0..width {
+            img.put_pixel(x, y, GRID);
+        }
+        y += 1;
+    }
+    for x in [0, column_x[1] - 1, column_x[2] - 1, width - 1] {
+        for y in 0..height {
+            img.put_pixel(x, y, GRID);
+        }
+    }
+    for x in 0..width {
+        img.put_pixel(x, 0, GRID);
+    }
+
+    save_png(&img, path)?;
+    Ok(rows.len() - 1)
+}
@@ -0,0 +1,265 @@
+use crate::atlas::{paste_sprites, save_png, SHEET_COLUMNS};
+use crate::error::{create_output_dir, write_output, Error, Result};
+use crate::raw_config::{read_json, visit_sprites};
+use image::io::Reader as ImageReader;
+use image::RgbaImage;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// What a compose run produced.
+#[derive(Debug, Default)]
+pub struct ComposeSummary {
+    pub sheets: usize,
+    pub sprites: usize,
+    pub entries: usize,
+    /// Sheets listed in `tile_info.json` that were not composed: fallback
+    /// sheets and sheets without sprites
+    pub skipped: Vec<String>,
+}
+
+/// A sheet of the source tree: one `pngs_<name>_<w>x<h>` directory.
+struct SourceSheet {
+    file: String,
+    props: Map<String, Value>,
+    w: u32,
+    h: u32,
+    /// Sprite names and images, in index order
+    sprites: Vec<(String, PathBuf)>,
+    /// Per-tile JSON files, in walk order
+    entries: Vec<PathBuf>,
+}
+
+/// Every file below `dir`, depth first, sorted by name within each directory.
+pub(crate) fn walk_sorted(dir: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    let read_err = |source| Error::ReadInput {
+        path: dir.to_owned(),
+        source,
+    };
+    let mut paths = vec![];
+    for entry in std::fs::read_dir(dir).map_err(read_err)? {
+        paths.push(entry.map_err(read_err)?.path());
+    }
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            walk_sorted(&path, out)?;
+        } else {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Replaces sprite names in a raw tile entry with sprite indices,
+/// returning the first name that has no sprite.
+fn resolve_entry(entry: &mut Value, indices: &HashMap<String, u32>) -> Option<String> {
+    let mut missing = None;
+    visit_sprites(entry, &mut |value| {
+        if let Value::String(name) = value {
+            match indices.get(name.as_str()) {
+                Some(idx) => *value = Value::from(*idx),
+                None => {
+                    missing.get_or_insert_with(|| name.clone());
+                }
+            }
+        }
+    });
+    missing
+}
+
+fn bad_source(path: &Path, message: String) -> Error {
+    Error::BadTileset {
+        path: path.to_owned(),
+        message,
+    }
+}
+
+/// Reads `tile_info.json` and lists the sprites and entries of every sheet.
+fn load_source(source: &Path, summary: &mut ComposeSummary) -> Result<(Value, Vec<SourceSheet>)> {
+    let info_path = source.join("tile_info.json");
+    if !info_path.is_file() {
+        return Err(Error::NoInputFile(info_path));
+    }
+    let info = match read_json(&info_path)? {
+        Value::Array(items) if !items.is_empty() => items,
+        _ => {
+            return Err(bad_source(
+                &info_path,
+                "expected a non-empty array".to_string(),
+            ))
+        }
+    };
+    let tile_info = info[0].clone();
+    let dimension = |key: &str| {
+        tile_info
+            .get(key)
+            .and_then(Value::as_u64)
+            .map(|x| x as u32)
+            .ok_or_else(|| bad_source(&info_path, format!("tile_info has no '{}'", key)))
+    };
+    let (default_w, default_h) = (dimension("width")?, dimension("height")?);
+
+    let mut sheets = vec![];
+    for item in &info[1..] {
+        let (file, props) = match item.as_object().and_then(|x| x.iter().next()) {
+            Some((file, Value::Object(props))) => (file.clone(), props.clone()),
+            _ => {
+                return Err(bad_source(
+                    &info_path,
+                    format!("expected {{\"<sheet>.png\": {{ ... }}}}, found {}", item),
+                ))
+            }
+        };
+        if props.get("fallback").and_then(Value::as_bool) == Some(true) {
+            summary.skipped.push(file);
+            continue;
+        }
+        let size = |key: &str, default: u32| {
+            props
+                .get(key)
+                .and_then(Value::as_u64)
+                .map_or(default, |x| x as u32)
+        };
+        let (w, h) = (
+            size("sprite_width", default_w),
+            size("sprite_height", default_h),
+        );
+        let stem = file.strip_suffix(".png").unwrap_or(&file);
+        let dir = source.join(format!("pngs_{}_{}x{}", stem, w, h));
+        if !dir.is_dir() {
+            return Err(Error::NoTilesetDir(dir));
+        }
+
+        let mut files = vec![];
+        walk_sorted(&dir, &mut files)?;
+        let mut sheet = SourceSheet {
+            file,
+            props,
+            w,
+            h,
+            sprites: vec![],
+            entries: vec![],
+        };
+        for path in files {
+            let name = path.file_stem().map(|x| x.to_string_lossy().to_string());
+            match (path.extension().and_then(|x| x.to_str()), name) {
+                (Some("png"), Some(name)) => sheet.sprites.push((name, path)),
+                (Some("json"), _) => sheet.entries.push(path),
+                _ => {}
+            }
+        }
+        sheets.push(sheet);
+    }
+    Ok((tile_info, sheets))
+}
+
+/// Builds packed atlases and `tile_config.json` in `out_dir` from a
+/// compose.py-style source tree: `tile_info.json` listing the sheets, and for
+/// each sheet a `pngs_<name>_<w>x<h>` directory of one PNG per sprite plus
+/// JSON tile entries referring to sprites by file name without extension.
+///
+/// Sprites are numbered in sheet order, and by path within each sheet.
+pub fn compose(source: &Path, out_dir: &Path) -> Result<ComposeSummary> {
+    let mut summary = ComposeSummary::default();
+    let (tile_info, sheets) = load_source(source, &mut summary)?;
+
+    let mut indices: HashMap<String, u32> = HashMap::new();
+    let mut next_index = 0;
+    for sheet in &sheets {
+        for (k, (name, path)) in sheet.sprites.iter().enumerate() {
+            if indices
+                .insert(name.clone(), next_index + k as u32)
+                .is_some()
+            {
+                return Err(bad_source(
+                    path,
+                    format!("duplicate sprite name '{}'", name),
+                ));
+            }
+        }
+        let count = sheet.sprites.len() as u32;
+        let columns = std::cmp::min(count, SHEET_COLUMNS);
+        if columns > 0 {
+            next_index += count.div_ceil(columns) * columns;
+        }
+    }
+
+    create_output_dir(out_dir)?;
+    let mut tiles_new = vec![];
+    for sheet in sheets {
+        if sheet.sprites.is_empty() {
+            summary.skipped.push(sheet.file);
+            continue;
+        }
+
+        let mut images: Vec<RgbaImage> = vec![];
+        for (_, path) in &sheet.sprites {
+            let img = ImageReader::open(path)
+                .map_err(|source| Error::ReadInput {
+                    path: path.clone(),
+                    source,
+                })?
+                .decode()
+                .map_err(|source| Error::DecodeImage {
+                    path: path.clone(),
+                    source,
+                })?
+                .to_rgba8();
+            if img.dimensions() != (sheet.w, sheet.h) {
+                return Err(bad_source(
+                    path,
+                    format!(
+                        "sprite is {}x{}, but sheet '{}' has sprites of {}x{}",
+                        img.width(),
+                        img.height(),
+                        sheet.file,
+                        sheet.w,
+                        sheet.h
+                    ),
+                ));
+            }
+            images.push(img);
+        }
+
+        let count = images.len() as u32;
+        let columns = std::cmp::min(count, SHEET_COLUMNS);
+        let rows = count.div_ceil(columns);
+        let out_path = out_dir.join(&sheet.file);
+        let mut atlas = RgbaImage::new(columns * sheet.w, rows * sheet.h);
+        paste_sprites(&mut atlas, &out_path, 0, columns, &images)?;
+        save_png(&atlas, &out_path)?;
+
+        let mut tiles = vec![];
+        for path in &sheet.entries {
+            let entries = match read_json(path)? {
+                Value::Array(items) => items,
+                other => vec![other],
+            };
+            for mut entry in entries {
+                if let Some(missing) = resolve_entry(&mut entry, &indices) {
+                    return Err(bad_source(path, format!("no sprite named '{}'", missing)));
+                }
+                tiles.push(entry);
+            }
+        }
+
+        summary.sheets += 1;
+        summary.sprites += images.len();
+        summary.entries += tiles.len();
+
+        let mut obj = Map::new();
+        obj.insert("file".to_string(), Value::from(sheet.file));
+        obj.extend(sheet.props);
+        obj.insert("tiles".to_string(), Value::from(tiles));
+        tiles_new.push(Value::Object(obj));
+    }
+
+    let mut config = Map::new();
+    config.insert("tile_info".to_string(), Value::from(vec![tile_info]));
+    config.insert("tiles-new".to_string(), Value::from(tiles_new));
+    let dump = serde_json::to_string_pretty(&config)?;
+    write_output(&out_dir.join("tile_config.json"), dump)?;
+
+    Ok(summary)
+}
@@ -0,0 +1,382 @@
+use crate::atlas::HashOptions;
+use crate::compose::walk_sorted;
+use crate::error::{Error, Result};
+use crate::tileset::Tileset;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::path::Path;
+
+/// Kinds of game objects the tileset is expected to draw.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    Terrain,
+    Furniture,
+    Item,
+    Monster,
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Category::Terrain => "terrain",
+            Category::Furniture => "furniture",
+            Category::Item => "items",
+            Category::Monster => "monsters",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Object types counted as items.
+const ITEM_TYPES: &[&str] = &[
+    "AMMO",
+    "ARMOR",
+    "BATTERY",
+    "BIONIC_ITEM",
+    "BOOK",
+    "COMESTIBLE",
+    "CONTAINER",
+    "ENGINE",
+    "GENERIC",
+    "GUN",
+    "GUNMOD",
+    "ITEM",
+    "MAGAZINE",
+    "PET_ARMOR",
+    "TOOL",
+    "TOOLMOD",
+    "TOOL_ARMOR",
+    "WHEEL",
+];
+
+fn category(object_type: &str) -> Option<Category> {
+    match object_type {
+        "terrain" => Some(Category::Terrain),
+        "furniture" => Some(Category::Furniture),
+        "MONSTER" => Some(Category::Monster),
+        x if ITEM_TYPES.contains(&x) => Some(Category::Item),
+        _ => None,
+    }
+}
+
+/// Prefixes of tileset ids the game draws without a JSON definition, such as
+/// the cursor or weather effects.
+const BUILTIN_PREFIXES: &[&str] = &[
+    "animation_",
+    "cursor",
+    "explosion",
+    "footstep",
+    "highlight",
+    "infrared_creature",
+    "lighting_",
+    "line_",
+    "npc_",
+    "player_",
+    "shadow",
+    "unknown",
+    "weather_",
+    "zombie_revival_indicator",
+];
+
+/// Prefixes and suffixes the game adds to object ids to name their tiles,
+/// e.g. `overlay_worn_` + an item id.
+const OVERLAY_PREFIXES: &[&str] = &["overlay_"];
+const GENDER_PREFIXES: &[&str] = &["male_", "female_"];
+const OVERLAY_KINDS: &[&str] = &[
+    "worn_",
+    "wielded_",
+    "mutation_active_",
+    "mutation_",
+    "effect_",
+];
+const OTHER_PREFIXES: &[&str] = &["vp_", "corpse_"];
+const SEASON_SUFFIXES: &[&str] = &[
+    "_season_spring",
+    "_season_summer",
+    "_season_autumn",
+    "_season_winter",
+];
+
+fn strip_any_prefix<'a>(id: &'a str, prefixes: &[&str]) -> Option<&'a str> {
+    prefixes.iter().find_map(|x| id.strip_prefix(x))
+}
+
+fn strip_any<'a>(id: &'a str, prefixes: &[&str]) -> &'a str {
+    strip_any_prefix(id, prefixes).unwrap_or(id)
+}
+
+/// The game object id a tile id names, with decorations removed.
+fn object_id(tile_id: &str) -> &str {
+    let mut id = SEASON_SUFFIXES
+        .iter()
+        .find_map(|x| tile_id.strip_suffix(x))
+        .unwrap_or(tile_id);
+    if let Some(rest) = strip_any_prefix(id, OVERLAY_PREFIXES) {
+        id = strip_any(strip_any(rest, GENDER_PREFIXES), OVERLAY_KINDS);
+    }
+    strip_any(id, OTHER_PREFIXES)
+}
+
+/// A JSON object of the game data with an id.
+#[derive(Debug, Default)]
+struct GameObject {
+    category: Option<Category>,
+    looks_like: Option<String>,
+    copy_from: Option<String>,
+}
+
+/// Ids of the game's JSON data, by id.
+#[derive(Debug, Default)]
+pub struct GameData {
+    objects: BTreeMap<String, GameObject>,
+}
+
+impl GameData {
+    /// Reads every object with an id from the .json files below `dir`,
+    /// usually the game's `data/json`.
+    pub fn load(dir: &Path) -> Result<GameData> {
+        if !dir.is_dir() {
+            return Err(Error::NoTilesetDir(dir.to_owned()));
+        }
+        let mut files = vec![];
+        walk_sorted(dir, &mut files)?;
+
+        let mut ret = GameData::default();
+        for path in files
+            .iter()
+            .filter(|x| x.extension() == Some("json".as_ref()))
+        {
+            let text = std::fs::read_to_string(path).map_err(|source| Error::ReadInput {
+                path: path.clone(),
+                source,
+            })?;
+            let value: Value =
+                serde_json::from_str(&text).map_err(|source| Error::ParseConfig {
+                    path: path.clone(),
+                    source,
+                })?;
+            match value {
+                Value::Array(objects) => objects.iter().for_each(|x| ret.add(x)),
+                object => ret.add(&object),
+            }
+        }
+        Ok(ret)
+    }
+
+    fn add(&mut self, object: &Value) {
+        let field = |name: &str| object.get(name).and_then(Value::as_str);
+        let ids: Vec<&str> = match object.get("id") {
+            Some(Value::String(id)) => vec![id],
+            Some(Value::Array(ids)) => ids.iter().filter_map(Value::as_str).collect(),
+            // Abstract objects only exist to be copied from
+            _ => return,
+        };
+        for id in ids {
+            self.objects.insert(
+                id.to_string(),
+                GameObject {
+                    category: field("type").and_then(category),
+                    looks_like: field("looks_like").map(str::to_string),
+                    copy_from: field("copy-from").map(str::to_string),
+                },
+            );
+        }
+    }
+
+    /// `looks_like` of an object, inherited through `copy-from`.
+    fn looks_like(&self, id: &str) -> Option<&str> {
+        let mut id = id;
+        for _ in 0..MAX_CHAIN {
+            let object = self.objects.get(id)?;
+            if let Some(x) = &object.looks_like {
+                return Some(x);
+            }
+            id = object.copy_from.as_deref()?;
+        }
+        None
+    }
+}
+
+/// Longest `looks_like` or `copy-from` chain followed; longer ones are
+/// likely cycles.
+const MAX_CHAIN: usize = 16;
+
+/// How the tileset covers one category of game objects.
+#[derive(Debug, Default)]
+pub struct CategoryCoverage {
+    /// Ids with a tile of their own
+    pub covered: usize,
+    /// Ids drawn with the tile of another id through `looks_like`, with
+    /// that id
+    pub fallback: Vec<(String, String)>,
+    /// Ids without any tile, drawn as ASCII
+    pub missing: Vec<String>,
+}
+
+impl CategoryCoverage {
+    pub fn total(&self) -> usize {
+        self.covered + self.fallback.len() + self.missing.len()
+    }
+}
+
+/// What a tileset draws of the game's objects, and its ids the game no
+/// longer has.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    pub categories: BTreeMap<Category, CategoryCoverage>,
+    /// Tile ids naming no game object, ignoring ids the game draws without
+    /// a definition
+    pub stale: Vec<String>,
+}
+
+/// A line of counts per category, then the ids falling back or missing by
+/// category and the stale ids.
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (category, stats) in &self.categories {
+            writeln!(
+                f,
+                "{}: {} of {} have tiles, {} fall back, {} missing",
+                category,
+                stats.covered,
+                stats.total(),
+                stats.fallback.len(),
+                stats.missing.len()
+            )?;
+        }
+        for (category, stats) in &self.categories {
+            if !stats.fallback.is_empty() {
+                writeln!(f, "Falling back, {}:", category)?;
+                for (id, target) in &stats.fallback {
+                    writeln!(f, "  {} -> {}", id, target)?;
+                }
+            }
+            if !stats.missing.is_empty() {
+                writeln!(f, "Missing, {}:", category)?;
+                for id in &stats.missing {
+                    writeln!(f, "  {}", id)?;
+                }
+            }
+        }
+        if !self.stale.is_empty() {
+            writeln!(f, "Not in game data:")?;
+            for id in &self.stale {
+                writeln!(f, "  {}", id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Cross-references the ids of `ts` with the objects of `game`.
+pub fn coverage(ts: &Tileset, game: &GameData) -> Coverage {
+    let vars = ts.expand_variations(&[], false, &HashOptions::default());
+    // Ids last defined by filler sheets don't count as drawn
+    let sheets = ts.sheets_by_id();
+    let filler: HashSet<&str> = ts
+        .tiles_new
+        .iter()
+        .filter(|x| ts.is_filler(x))
+        .map(|x| x.file.as_str())
+        .collect();
+    let tile_ids: HashSet<&str> = vars
+        .iter()
+        .map(|x| x.id.0[0].as_str())
+        .filter(|x| !sheets.get(*x).is_some_and(|x| filler.contains(x)))
+        .collect();
+
+    let mut ret = Coverage::default();
+    for (id, object) in &game.objects {
+        let category = match object.category {
+            Some(x) => x,
+            None => continue,
+        };
+        let entry = ret.categories.entry(category).or_default();
+        if tile_ids.contains(id.as_str()) {
+            entry.covered += 1;
+            continue;
+        }
+        let mut target = id.as_str();
+        let mut found = None;
+        for _ in 0..MAX_CHAIN {
+            match game.looks_like(target) {
+                Some(x) if tile_ids.contains(x) => {
+                    found = Some(x);
+                    break;
+                }
+                Some(x) => target = x,
+                None => break,
+            }
+        }
+        match found {
+            Some(x) => entry.fallback.push((id.clone(), x.to_string())),
+            None => entry.missing.push(id.clone()),
+        }
+    }
+
+    // Additional tiles are named after their base tile
+    let base_ids: BTreeSet<&str> = ts
+        .tiles_new
+        .iter()
+        .filter(|x| !ts.is_filler(x))
+        .flat_map(|x| x.tiles.iter())
+        .flat_map(|x| x.base.id.0.iter())
+        .map(String::as_str)
+        .filter(|x| ts.keeps(x))
+        .collect();
+    ret.stale = base_ids
+        .into_iter()
+        .filter(|x| !BUILTIN_PREFIXES.iter().any(|p| x.starts_with(p)))
+        .filter(|x| !game.objects.contains_key(*x) && !game.objects.contains_key(object_id(x)))
+        .map(str::to_string)
+        .collect();
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn classifies_game_ids() {
+        let mut game = GameData::default();
+        for object in [
+            json!({ "type": "terrain", "id": "t_wall" }),
+            json!({ "type": "terrain", "id": "t_wall_log", "copy-from": "t_wall_wood" }),
+            json!({ "type": "terrain", "id": "t_wall_wood", "looks_like": "t_wall" }),
+            json!({ "type": "furniture", "id": ["f_chair", "f_stool"] }),
+            json!({ "type": "ARMOR", "id": "hat" }),
+            json!({ "type": "mutation", "id": "HORNS" }),
+            json!({ "type": "MONSTER", "abstract": "mon_base" }),
+        ] {
+            game.add(&object);
+        }
+        let ts: Tileset = serde_json::from_str(
+            r#"{
+              "tile_info": [ { "width": 32, "height": 32 } ],
+              "tiles-new": [ { "file": "main.png", "tiles": [
+                { "id": ["t_wall", "f_chair", "overlay_worn_hat", "overlay_mutation_HORNS"] },
+                { "id": ["t_removed", "cursor"] }
+              ] } ]
+            }"#,
+        )
+        .unwrap();
+
+        let result = coverage(&ts, &game);
+        let terrain = &result.categories[&Category::Terrain];
+        assert_eq!(terrain.covered, 1);
+        assert_eq!(
+            terrain.fallback,
+            [
+                ("t_wall_log".to_string(), "t_wall".to_string()),
+                ("t_wall_wood".to_string(), "t_wall".to_string())
+            ]
+        );
+        assert_eq!(result.categories[&Category::Furniture].missing, ["f_stool"]);
+        assert_eq!(result.categories[&Category::Item].missing, ["hat"]);
+        assert!(!result.categories.contains_key(&Category::Monster));
+        assert_eq!(result.stale, ["t_removed"]);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::atlas::{save_png, TileAtlas};
+use crate::error::{create_output_dir, write_output, Error, Result};
+use crate::file_name::{sanitize, unique_name};
+use crate::raw_config::{read_tile_config, visit_sprites};
+use crate::tileset::Tileset;
+use image::GenericImageView;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// What a decompose run produced.
+#[derive(Debug, Default)]
+pub struct DecomposeSummary {
+    pub sheets: usize,
+    pub sprites: usize,
+    pub entries: usize,
+    /// Sprites no tile refers to, written as `unused_<index>.png`
+    pub unused_sprites: usize,
+}
+
+fn first_id(entry: &Value) -> String {
+    match &entry["id"] {
+        Value::String(id) => id.clone(),
+        Value::Array(ids) => ids
+            .first()
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_string(),
+        _ => String::new(),
+    }
+}
+
+fn is_blank(atlas: &TileAtlas, idx: u32) -> bool {
+    atlas
+        .get_sprite(idx)
+        .pixels()
+        .all(|(_, _, px)| px.0[3] == 0)
+}
+
+/// Explodes a packed tileset into a compose.py-style source tree in
+/// `out_dir`: `tile_info.json`, and for each sheet a `pngs_<name>_<w>x<h>`
+/// directory with one PNG per sprite and one JSON file per tile entry.
+///
+/// Sprites are named after the first entry using them (`t_wall`, `t_wall_2`, ...)
+/// and placed in the directory of the sheet whose image they come from.
+/// Fallback sheets are copied as they are.
+pub fn decompose(ts: &Tileset, out_dir: &Path) -> Result<DecomposeSummary> {
+    let config_path = ts.base_path.join("tile_config.json");
+    let raw = read_tile_config(&config_path)?;
+    let atlases = ts.load_atlases()?;
+    let mut summary = DecomposeSummary::default();
+
+    let raw_sheets: Vec<Value> = match raw.get("tiles-new") {
+        Some(Value::Array(sheets)) => sheets.clone(),
+        _ => vec![],
+    };
+    let fallback: Vec<bool> = raw_sheets
+        .iter()
+        .map(|sheet| {
+            let has = |key: &str| sheet[key].as_array().is_some_and(|x| !x.is_empty());
+            has("ascii") && !has("tiles")
+        })
+        .collect();
+
+    let dirs: Vec<PathBuf> = atlases
+        .iter()
+        .map(|atlas| {
+            let stem = atlas.file.strip_suffix(".png").unwrap_or(&atlas.file);
+            out_dir.join(format!(
+                "pngs_{}_{}x{}",
+                stem, atlas.sprite_w, atlas.sprite_h
+            ))
+        })
+        .collect();
+    let atlas_of = |idx: u32| atlases.iter().position(|x| x.in_bounds(idx));
+
+    // Name sprites after their first user
+    let mut names: HashMap<u32, String> = HashMap::new();
+    let mut taken: HashSet<String> = HashSet::new();
+    for sheet in &raw_sheets {
+        for entry in sheet["tiles"].as_array().into_iter().flatten() {
+            let base = sanitize(&first_id(entry));
+            let mut entry = entry.clone();
+            visit_sprites(&mut entry, &mut |value| {
+                let idx = value.as_u64().and_then(|x| u32::try_from(x).ok());
+                if let Some(idx) = idx.filter(|x| atlas_of(*x).is_some()) {
+                    names
+                        .entry(idx)
+                        .or_insert_with(|| unique_name(&base, &mut taken));
+                }
+            });
+        }
+    }
+    for (atlas, _) in atlases.iter().zip(&fallback).filter(|x| !x.1) {
+        for idx in atlas.tiles_start..atlas.tiles_end {
+            if !names.contains_key(&idx) && !is_blank(atlas, idx) {
+                names.insert(idx, unique_name(&format!("unused_{}", idx), &mut taken));
+                summary.unused_sprites += 1;
+            }
+        }
+    }
+
+    for (dir, _) in dirs.iter().zip(&fallback).filter(|x| !x.1) {
+        create_output_dir(dir)?;
+    }
+    for (idx, name) in &names {
+        if let Some(i) = atlas_of(*idx) {
+            let path = dirs[i].join(format!("{}.png", name));
+            save_png(&atlases[i].get_sprite(*idx).to_image(), &path)?;
+            summary.sprites += 1;
+        }
+    }
+
+    // The entry the game goes by
+    let last_info = raw["tile_info"].as_array().and_then(|x| x.last());
+    let mut tile_info = vec![last_info.cloned().unwrap_or_default()];
+    for (i, sheet) in raw_sheets.iter().enumerate() {
+        let file = sheet["file"].as_str().unwrap_or_default().to_string();
+        let mut props = Map::new();
+        if fallback[i] {
+            props.insert("fallback".to_string(), Value::from(true));
+            std::fs::copy(ts.base_path.join(&file), out_dir.join(&file)).map_err(|source| {
+                Error::WriteOutput {
+                    path: out_dir.join(&file),
+                    source,
+                }
+            })?;
+        } else {
+            for (key, value) in sheet.as_object().into_iter().flatten() {
+                if !matches!(key.as_str(), "file" | "tiles" | "ascii") {
+                    props.insert(key.clone(), value.clone());
+                }
+            }
+
+            let mut entry_names: HashSet<String> = HashSet::new();
+            for entry in sheet["tiles"].as_array().into_iter().flatten() {
+                let mut entry = entry.clone();
+                visit_sprites(&mut entry, &mut |value| {
+                    let idx = value.as_u64().and_then(|x| u32::try_from(x).ok());
+                    if let Some(name) = idx.and_then(|x| names.get(&x)) {
+                        *value = Value::from(name.as_str());
+                    }
+                });
+                let name = unique_name(&sanitize(&first_id(&entry)), &mut entry_names);
+                let dump = serde_json::to_string_pretty(&entry)?;
+                write_output(&dirs[i].join(format!("{}.json", name)), dump)?;
+                summary.entries += 1;
+            }
+            summary.sheets += 1;
+        }
+        let mut item = Map::new();
+        item.insert(file, Value::Object(props));
+        tile_info.push(Value::Object(item));
+    }
+
+    let dump = serde_json::to_string_pretty(&tile_info)?;
+    write_output(&out_dir.join("tile_info.json"), dump)?;
+
+    Ok(summary)
+}
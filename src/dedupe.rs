@@ -0,0 +1,59 @@
+use crate::atlas::{hash_all_sprites, HashOptions, TileAtlas};
+use image::RgbaImage;
+use std::collections::BTreeMap;
+
+/// A sprite as drawn in game, with its position relative to the tile.
+type PlacedSprite = (RgbaImage, (i32, i32));
+
+fn placed_sprite(atlases: &[TileAtlas], idx: u32) -> PlacedSprite {
+    let atlas = atlases.iter().find(|x| x.in_bounds(idx)).unwrap();
+    atlas.get_placed_sprite(idx)
+}
+
+/// Groups of sprite indices that look exactly the same in game, each in
+/// index order, ordered by their first index. Fully transparent sprites,
+/// usually padding at the end of an atlas, are left out.
+pub fn duplicate_sprites(atlases: &[TileAtlas], opts: &HashOptions) -> Vec<Vec<u32>> {
+    let mut by_hash: BTreeMap<u64, Vec<u32>> = BTreeMap::new();
+    for (idx, hash) in hash_all_sprites(atlases, opts) {
+        by_hash.entry(hash).or_default().push(idx);
+    }
+
+    let mut ret = vec![];
+    for indices in by_hash.into_values().filter(|x| x.len() > 1) {
+        // Equal hashes may still be a collision, or a fast hash missing a
+        // difference, so split by content
+        let mut groups: Vec<(PlacedSprite, Vec<u32>)> = vec![];
+        for idx in indices {
+            let sprite = placed_sprite(atlases, idx);
+            if sprite.0.pixels().all(|px| px.0[3] == 0) {
+                continue;
+            }
+            match groups.iter_mut().find(|x| x.0 == sprite) {
+                Some(group) => group.1.push(idx),
+                None => groups.push((sprite, vec![idx])),
+            }
+        }
+        ret.extend(groups.into_iter().map(|x| x.1).filter(|x| x.len() > 1));
+    }
+    ret.sort();
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Atlas of 1x1 sprites with the given alpha values.
+    fn atlas(alphas: &[u8], tiles_start: u32) -> TileAtlas {
+        let pixels: Vec<[u8; 4]> = alphas.iter().map(|&x| [0, 0, 0, x]).collect();
+        TileAtlas::from_pixels(&pixels, tiles_start)
+    }
+
+    #[test]
+    fn groups_copies_across_atlases() {
+        let atlases = [atlas(&[255, 0, 128, 0], 0), atlas(&[128, 255, 7], 4)];
+        let groups = duplicate_sprites(&atlases, &HashOptions::default());
+        assert_eq!(groups, [vec![0, 5], vec![2, 4]]);
+    }
+}
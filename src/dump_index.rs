@@ -0,0 +1,106 @@
+use crate::error::{write_output, Error, Result};
+use crate::raw_config::read_json;
+use crate::tileset::SingleTile;
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+pub const DUMP_FILE_NAME: &str = "dump.json";
+/// Byte offset of the first record of each id in `dump.json`, so one record
+/// can be read without parsing the whole dump.
+pub const DUMP_INDEX_FILE_NAME: &str = "dump.index.json";
+
+/// Renders records the way `serde_json::to_string_pretty` renders the whole
+/// list, along with the byte offset of the first record of each id.
+fn render_dump(vars: &[&SingleTile]) -> Result<(String, BTreeMap<String, u64>)> {
+    if vars.is_empty() {
+        return Ok(("[]".to_string(), BTreeMap::new()));
+    }
+    let mut dump = "[".to_string();
+    let mut index = BTreeMap::new();
+    for (k, var) in vars.iter().enumerate() {
+        dump += if k == 0 { "\n  " } else { ",\n  " };
+        index
+            .entry(var.id.0[0].clone())
+            .or_insert(dump.len() as u64);
+        // Strings escape their newlines, so every line is a line of structure
+        dump += &serde_json::to_string_pretty(var)?.replace('\n', "\n  ");
+    }
+    dump += "\n]";
+    Ok((dump, index))
+}
+
+/// Writes `dump.json` and `dump.index.json` into `dir`.
+pub fn write_dump(vars: &[&SingleTile], dir: &Path) -> Result<()> {
+    let (dump, index) = render_dump(vars)?;
+    write_output(&dir.join(DUMP_FILE_NAME), dump)?;
+    write_output(
+        &dir.join(DUMP_INDEX_FILE_NAME),
+        serde_json::to_string(&index)?,
+    )
+}
+
+/// Reads the first record of `id` from the dump in `dir`, seeking to it
+/// through the index if there is one, and parsing the whole dump otherwise.
+pub fn read_dumped(dir: &Path, id: &str) -> Result<Option<SingleTile>> {
+    let dump_path = dir.join(DUMP_FILE_NAME);
+    let index_path = dir.join(DUMP_INDEX_FILE_NAME);
+    let parse_err = |source| Error::ParseConfig {
+        path: dump_path.clone(),
+        source,
+    };
+    if !index_path.is_file() {
+        let vars: Vec<SingleTile> =
+            serde_json::from_value(read_json(&dump_path)?).map_err(parse_err)?;
+        return Ok(vars.into_iter().find(|x| x.id.0[0] == id));
+    }
+
+    let index: BTreeMap<String, u64> =
+        serde_json::from_value(read_json(&index_path)?).map_err(|source| Error::ParseConfig {
+            path: index_path.clone(),
+            source,
+        })?;
+    let offset = match index.get(id) {
+        Some(x) => *x,
+        None => return Ok(None),
+    };
+    let read_err = |source| Error::ReadInput {
+        path: dump_path.clone(),
+        source,
+    };
+    let mut file = std::fs::File::open(&dump_path).map_err(read_err)?;
+    file.seek(SeekFrom::Start(offset)).map_err(read_err)?;
+    let mut records = serde_json::Deserializer::from_reader(file.by_ref()).into_iter();
+    match records.next() {
+        Some(record) => Ok(Some(record.map_err(parse_err)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexes_records() {
+        let vars: Vec<SingleTile> = [
+            r#"{ "id": "t_wall", "fg": 1 }"#,
+            r#"{ "id": "t_floor", "fg": [2, 3], "rotates": true }"#,
+        ]
+        .iter()
+        .map(|x| serde_json::from_str(x).unwrap())
+        .collect();
+        let refs: Vec<&SingleTile> = vars.iter().collect();
+        let (dump, index) = render_dump(&refs).unwrap();
+        assert_eq!(dump, serde_json::to_string_pretty(&refs).unwrap());
+
+        let offset = index["t_floor"] as usize;
+        let record: SingleTile = serde_json::Deserializer::from_str(&dump[offset..])
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.id, vars[1].id);
+        assert_eq!(record.fg, vars[1].fg);
+    }
+}
@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub const EXIT_OK: i32 = 0;
+/// Exit code for a successful run that found problems in its input.
+pub const EXIT_PROBLEMS_FOUND: i32 = 1;
+/// Exit code for problems with the tilesets or arguments the user supplied.
+pub const EXIT_BAD_INPUT: i32 = 2;
+/// Exit code for failures unrelated to the input, e.g. unwritable output.
+pub const EXIT_INTERNAL: i32 = 3;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("tileset directory '{}' does not exist or is not a directory", .0.display())]
+    NoTilesetDir(PathBuf),
+    #[error("'{}' does not exist or is not a file", .0.display())]
+    NoInputFile(PathBuf),
+    #[error("failed to read '{}': {source}", path.display())]
+    ReadInput {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse '{}': {source}", path.display())]
+    ParseConfig {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("failed to decode image '{}': {source}", path.display())]
+    DecodeImage {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    #[error("failed to parse '{}': {source}", path.display())]
+    ParseToml {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid tileset '{}': {message}", path.display())]
+    BadTileset { path: PathBuf, message: String },
+    #[error("invalid index mapping '{}': {message}", path.display())]
+    BadRemap { path: PathBuf, message: String },
+    #[error("invalid id mapping '{}': {message}", path.display())]
+    BadIdMapping { path: PathBuf, message: String },
+    #[error("invalid archive '{}': {message}", path.display())]
+    BadArchive { path: PathBuf, message: String },
+    #[error("invalid history '{}': {message}", path.display())]
+    BadHistory { path: PathBuf, message: String },
+    #[error("git failed in '{}': {message}", path.display())]
+    Git { path: PathBuf, message: String },
+    #[error("environment variable {0} is not set")]
+    MissingEnv(&'static str),
+    #[error("sprite {0} is outside all atlas ranges")]
+    SpriteNotFound(u32),
+    #[error("failed to write '{}': {source}", path.display())]
+    WriteOutput {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to save image '{}': {source}", path.display())]
+    SaveImage {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    #[error("failed to serialize output: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("output directory '{}' is not writable: {source}", path.display())]
+    OutputNotWritable {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("directory '{}' is not empty; pass a new or empty one", .0.display())]
+    DirNotEmpty(PathBuf),
+    #[error("timed out waiting for lock '{}'; delete it if no other run is active", .0.display())]
+    LockTimeout(PathBuf),
+    #[error("failed to start worker threads: {0}")]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NoTilesetDir(_)
+            | Error::NoInputFile(_)
+            | Error::ReadInput { .. }
+            | Error::ParseConfig { .. }
+            | Error::ParseToml { .. }
+            | Error::DecodeImage { .. }
+            | Error::BadTileset { .. }
+            | Error::BadRemap { .. }
+            | Error::BadIdMapping { .. }
+            | Error::BadArchive { .. }
+            | Error::BadHistory { .. }
+            | Error::Git { .. }
+            | Error::MissingEnv(_)
+            | Error::SpriteNotFound(_)
+            | Error::DirNotEmpty(_) => EXIT_BAD_INPUT,
+            Error::WriteOutput { .. }
+            | Error::SaveImage { .. }
+            | Error::OutputNotWritable { .. }
+            | Error::Serialize(_)
+            | Error::LockTimeout(_)
+            | Error::ThreadPool(_) => EXIT_INTERNAL,
+        }
+    }
+}
+
+pub fn write_output<C: AsRef<[u8]>>(path: &Path, contents: C) -> Result<()> {
+    std::fs::write(path, contents).map_err(|source| Error::WriteOutput {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+pub fn create_output_dir(path: &Path) -> Result<()> {
+    std::fs::create_dir_all(path).map_err(|source| Error::WriteOutput {
+        path: path.to_owned(),
+        source,
+    })
+}
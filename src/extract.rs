@@ -0,0 +1,124 @@
+use crate::atlas::{save_png, save_tile_as, HashOptions, TileAtlas};
+use crate::error::{create_output_dir, write_output, Error, Result};
+use crate::file_name::sanitize;
+use crate::output::dump_meta;
+use crate::progress::Progress;
+use crate::tileset::Tileset;
+use crate::warnings::Code;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Reads one id per line; `path` is only used in errors.
+fn read_ids<R: BufRead>(reader: R, path: &Path) -> Result<Vec<String>> {
+    let read_err = |source| Error::ReadInput {
+        path: path.to_owned(),
+        source,
+    };
+
+    let mut ret = vec![];
+
+    for line in reader.lines() {
+        let line = line.map_err(read_err)?;
+        // Editors on Windows like to add a byte order mark and CRLF line ends
+        let line = line.trim_start_matches('\u{feff}').trim_end_matches('\r');
+        ret.push(line.to_string());
+    }
+
+    Ok(ret)
+}
+
+pub fn load_ids_file(base_path: &Path) -> Result<Vec<String>> {
+    if !base_path.is_file() {
+        return Err(Error::NoInputFile(base_path.to_owned()));
+    }
+
+    let file = File::open(base_path).map_err(|source| Error::ReadInput {
+        path: base_path.to_owned(),
+        source,
+    })?;
+    read_ids(BufReader::new(file), base_path)
+}
+
+/// Reads ids from a file, or from standard input if `source` is `-`.
+pub fn load_ids_from(source: &str) -> Result<Vec<String>> {
+    if source == "-" {
+        read_ids(std::io::stdin().lock(), Path::new("<stdin>"))
+    } else {
+        load_ids_file(Path::new(source))
+    }
+}
+
+/// Saves sprite `tile_id` as `path`.
+fn save_sprite(atlases: &[TileAtlas], tile_id: u32, path: &Path) -> Result<()> {
+    let atlas = atlases
+        .iter()
+        .find(|x| x.in_bounds(tile_id))
+        .ok_or(Error::SpriteNotFound(tile_id))?;
+    save_png(&atlas.get_sprite(tile_id).to_image(), path)
+}
+
+/// Writes the hashed definition of each id into `<id>/<id>.json`, its
+/// sprites into `<id>/<id>_<fg|bg>_<n>.png`, numbered in order across
+/// variations, and every sprite into `out_dir` named by its hash.
+pub fn extract_tiles(
+    ts: &Tileset,
+    ids: Option<&[String]>,
+    out_dir: &Path,
+    opts: &HashOptions,
+) -> Result<()> {
+    let (vars, atlases) = ts.generate_variations(false, opts)?;
+    let (vars_hashed, _) = ts.generate_variations(true, opts)?;
+    ts.dump_sprites(&atlases)?;
+
+    let vars_hm: HashMap<&str, usize> = vars
+        .iter()
+        .enumerate()
+        .map(|x| (x.1.id.0[0].as_str(), x.0))
+        .collect();
+
+    // Variations only hold ids passing the filter
+    let all_ids: Vec<String>;
+    let ids = match ids {
+        Some(ids) => ids,
+        None => {
+            all_ids = vars.iter().map(|x| x.id.0[0].clone()).collect();
+            &all_ids
+        }
+    };
+
+    let progress = Progress::new("Extracting tiles", ids.len());
+    for id in ids {
+        progress.inc();
+        if let Some(&idx) = vars_hm.get(id.as_str()) {
+            let stem = sanitize(id);
+            let this_tile_dir: PathBuf = out_dir.join(&stem);
+            create_output_dir(&this_tile_dir)?;
+
+            let out_json = this_tile_dir.join(stem.clone() + ".json");
+
+            let tile_hashed = &vars_hashed[idx];
+            let out_str = serde_json::to_string_pretty(tile_hashed)?;
+            write_output(&out_json, out_str)?;
+
+            let variation = &vars[idx];
+            for (role, list) in [("fg", variation.fg()), ("bg", variation.bg())] {
+                let sprites = list.iter().flat_map(|x| x.indices());
+                for (k, tile_id) in sprites.enumerate() {
+                    save_tile_as(&atlases, tile_id, out_dir, opts)?;
+                    let out_png = this_tile_dir.join(format!("{}_{}_{}.png", stem, role, k));
+                    save_sprite(&atlases, tile_id, &out_png)?;
+                }
+            }
+        } else {
+            ts.warn(
+                Code::UnknownId,
+                None,
+                format!("failed to find tile with id {}", id),
+            );
+        }
+    }
+
+    dump_meta(opts, out_dir)
+}
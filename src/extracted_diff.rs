@@ -0,0 +1,183 @@
+use crate::atlas::HashBits;
+use crate::error::{Error, Result};
+use crate::field_diff::{field_changes, FieldChange};
+use crate::hash_algo::HashAlgo;
+use crate::id_order::IdOrder;
+use crate::raw_config::read_json;
+use crate::tileset::SingleTile;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Definitions of an `extracted` directory by id, and the hash mode and
+/// algorithm its sprites were hashed with.
+struct Extracted {
+    tiles: BTreeMap<String, SingleTile>,
+    hash_mode: Option<String>,
+    /// Directories extracted before the algorithm could be chosen used the
+    /// legacy one
+    hash_algo: String,
+    /// 32 for directories extracted before hashes could be widened
+    hash_bits: u64,
+}
+
+/// Reads the `<id>/<id>.json` definitions and `meta.json` written by extract.
+fn load_extracted(dir: &Path) -> Result<Extracted> {
+    if !dir.is_dir() {
+        return Err(Error::NoTilesetDir(dir.to_owned()));
+    }
+    let read_err = |source| Error::ReadInput {
+        path: dir.to_owned(),
+        source,
+    };
+    let mut tiles = BTreeMap::new();
+    for entry in std::fs::read_dir(dir).map_err(read_err)? {
+        let path = entry.map_err(read_err)?.path();
+        let name = match path.file_name() {
+            Some(name) if path.is_dir() => name.to_owned(),
+            _ => continue,
+        };
+        let json_path = path.join(&name).with_extension("json");
+        if !json_path.is_file() {
+            continue;
+        }
+        let tile: SingleTile =
+            serde_json::from_value(read_json(&json_path)?).map_err(|source| {
+                Error::ParseConfig {
+                    path: json_path.clone(),
+                    source,
+                }
+            })?;
+        tiles.insert(tile.id.0[0].clone(), tile);
+    }
+
+    let meta_path = dir.join("meta.json");
+    let meta = if meta_path.is_file() {
+        read_json(&meta_path)?
+    } else {
+        Value::Null
+    };
+    let field = |key: &str| meta.get(key).and_then(Value::as_str).map(str::to_string);
+    Ok(Extracted {
+        tiles,
+        hash_mode: field("hash_mode"),
+        hash_algo: field("hash_algo").unwrap_or_else(|| HashAlgo::Legacy.name().to_string()),
+        hash_bits: meta.get("hash_bits").and_then(Value::as_u64).unwrap_or(32),
+    })
+}
+
+/// Differences between two extracted directories, ids in natural order.
+#[derive(Debug, Default)]
+pub struct ExtractedDiff {
+    pub only_in_a: Vec<String>,
+    pub only_in_b: Vec<String>,
+    pub changed: Vec<(String, Vec<FieldChange>)>,
+}
+
+impl ExtractedDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_in_a.is_empty() && self.only_in_b.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn diff_tiles(
+    a: &BTreeMap<String, SingleTile>,
+    b: &BTreeMap<String, SingleTile>,
+    bits: HashBits,
+) -> ExtractedDiff {
+    let sorted = |ids: Vec<&String>| {
+        let mut ids: Vec<&str> = ids.into_iter().map(String::as_str).collect();
+        IdOrder::Natural.sort(&mut ids);
+        ids.into_iter().map(str::to_string).collect::<Vec<_>>()
+    };
+    let changed = sorted(
+        a.iter()
+            .filter(|(id, tile)| b.get(*id).is_some_and(|x| x != *tile))
+            .map(|x| x.0)
+            .collect(),
+    );
+    ExtractedDiff {
+        only_in_a: sorted(a.keys().filter(|x| !b.contains_key(*x)).collect()),
+        only_in_b: sorted(b.keys().filter(|x| !a.contains_key(*x)).collect()),
+        changed: changed
+            .into_iter()
+            .map(|id| {
+                let changes = field_changes(&a[&id], &b[&id], bits);
+                (id, changes)
+            })
+            .collect(),
+    }
+}
+
+/// Compares two directories written by extract, without the tilesets they
+/// came from. Sprites are compared by the hashes in the definitions, so both
+/// must have been extracted with the same hash mode and algorithm.
+pub fn diff_extracted(a: &Path, b: &Path) -> Result<ExtractedDiff> {
+    let extracted_a = load_extracted(a)?;
+    let extracted_b = load_extracted(b)?;
+    if extracted_a.hash_mode != extracted_b.hash_mode {
+        let mode = |x: &Option<String>| x.clone().unwrap_or_else(|| "unknown".to_string());
+        return Err(Error::BadTileset {
+            path: b.to_owned(),
+            message: format!(
+                "extracted with hash mode '{}', but '{}' with '{}'",
+                mode(&extracted_b.hash_mode),
+                a.display(),
+                mode(&extracted_a.hash_mode)
+            ),
+        });
+    }
+    if extracted_a.hash_algo != extracted_b.hash_algo {
+        return Err(Error::BadTileset {
+            path: b.to_owned(),
+            message: format!(
+                "extracted with hash algorithm '{}', but '{}' with '{}'",
+                extracted_b.hash_algo,
+                a.display(),
+                extracted_a.hash_algo
+            ),
+        });
+    }
+    if extracted_a.hash_bits != extracted_b.hash_bits {
+        return Err(Error::BadTileset {
+            path: b.to_owned(),
+            message: format!(
+                "extracted with {}-bit hashes, but '{}' with {}-bit ones",
+                extracted_b.hash_bits,
+                a.display(),
+                extracted_a.hash_bits
+            ),
+        });
+    }
+    let bits = match extracted_a.hash_bits {
+        64 => HashBits::Bits64,
+        _ => HashBits::Bits32,
+    };
+    Ok(diff_tiles(&extracted_a.tiles, &extracted_b.tiles, bits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiles(defs: &[(&str, u32)]) -> BTreeMap<String, SingleTile> {
+        defs.iter()
+            .map(|(id, fg)| {
+                let json = format!(r#"{{ "id": "{}", "fg": {} }}"#, id, fg);
+                (id.to_string(), serde_json::from_str(&json).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diffs_definitions() {
+        let a = tiles(&[("t_wall_10", 1), ("t_wall_2", 1), ("t_floor", 2)]);
+        let b = tiles(&[("t_wall_10", 3), ("t_wall_2", 4), ("t_grass", 5)]);
+        let diff = diff_tiles(&a, &b, HashBits::Bits32);
+        assert_eq!(diff.only_in_a, ["t_floor"]);
+        assert_eq!(diff.only_in_b, ["t_grass"]);
+        let changed: Vec<&str> = diff.changed.iter().map(|x| x.0.as_str()).collect();
+        assert_eq!(changed, ["t_wall_2", "t_wall_10"]);
+        assert_eq!(diff.changed[0].1[0].field, "fg");
+    }
+}
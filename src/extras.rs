@@ -0,0 +1,75 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+/// Fields of a `tile_config.json` object this version of the tool doesn't
+/// know, kept as opaque JSON values. Comment fields (`//`, `//2`, ...) are
+/// dropped, since they never affect the game.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct Extras(pub BTreeMap<String, Value>);
+
+impl<'de> Deserialize<'de> for Extras {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = BTreeMap::<String, Value>::deserialize(deserializer)?;
+        fields.retain(|k, _| !k.starts_with("//"));
+        Ok(Extras(fields))
+    }
+}
+
+impl Extras {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(|x| x.as_str())
+    }
+
+    /// Describes how the fields differ from `a` to `b`,
+    /// e.g. `season added`, one entry per field.
+    pub fn describe_changes(a: &Extras, b: &Extras) -> Vec<String> {
+        let mut ret = vec![];
+        for (name, value) in &a.0 {
+            match b.0.get(name) {
+                None => ret.push(format!("{} removed", name)),
+                Some(other) if other != value => ret.push(format!("{} changed", name)),
+                _ => {}
+            }
+        }
+        for name in b.0.keys() {
+            if !a.0.contains_key(name) {
+                ret.push(format!("{} added", name));
+            }
+        }
+        ret
+    }
+
+    /// Values in compact JSON form, which gives them a total order.
+    fn canonical(&self) -> Vec<(&str, String)> {
+        self.0
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.to_string()))
+            .collect()
+    }
+}
+
+impl PartialOrd for Extras {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Extras {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.canonical().cmp(&other.canonical())
+    }
+}
+
+impl Hash for Extras {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical().hash(state);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::atlas::HashBits;
+use crate::single_or_vec::SingleOrVec;
+use crate::sprite_id_with_weight::SpriteIdWithWeight;
+use crate::sprite_tag::full_tag;
+use crate::tileset::SingleTile;
+use crate::variation_diff::{edit_script, EditSummary};
+use serde::Serialize;
+
+/// Value absent on one side of a change, e.g. an unknown field that was added.
+const ABSENT: &str = "-";
+
+/// One field of a tile that differs between tileset A and tileset B, with
+/// both values rendered as text.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+impl FieldChange {
+    fn new<S: ToString, T: ToString>(field: &str, before: S, after: T) -> Self {
+        FieldChange {
+            field: field.to_string(),
+            before: before.to_string(),
+            after: after.to_string(),
+        }
+    }
+}
+
+/// Variations as `[tag xweight, tag+tag]`: sprites by base32 tag (as accepted
+/// by find-sprite), joined with `+` for animation frames.
+fn render_variations(list: &[SpriteIdWithWeight], bits: HashBits) -> String {
+    let items: Vec<String> = list.iter().map(|x| render_variation(x, bits)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// A sprite list as its variations, or `-` if the field is absent.
+fn render_list(list: &Option<SingleOrVec<SpriteIdWithWeight>>, bits: HashBits) -> String {
+    list.as_ref()
+        .map_or(ABSENT.to_string(), |x| render_variations(&x.0, bits))
+}
+
+fn render_variation(spidw: &SpriteIdWithWeight, bits: HashBits) -> String {
+    let sprites: Vec<String> = spidw.id.0.iter().map(|x| full_tag(*x, bits)).collect();
+    match spidw.weight {
+        Some(weight) => format!("{} x{}", sprites.join("+"), weight),
+        None => sprites.join("+"),
+    }
+}
+
+fn render_weights(list: &[SpriteIdWithWeight]) -> String {
+    let weights: Vec<String> = list
+        .iter()
+        .map(|x| x.weight.map_or(ABSENT.to_string(), |w| w.to_string()))
+        .collect();
+    weights.join(", ")
+}
+
+/// Frame count and total duration of an animated tile: each fg variation is
+/// a frame, shown for `weight` ticks (1 if unset).
+fn animation(tile: &SingleTile) -> Option<(usize, u64)> {
+    if !tile.animated {
+        return None;
+    }
+    let frames = tile.fg().len();
+    let length = tile.fg().iter().map(|x| x.weight.unwrap_or(1) as u64).sum();
+    Some((frames, length))
+}
+
+fn render_animation(animation: Option<(usize, u64)>) -> String {
+    match animation {
+        Some((frames, length)) => format!("{} frame(s), {} tick(s)", frames, length),
+        None => ABSENT.to_string(),
+    }
+}
+
+/// Differences between the frames of two animations: the same frames in
+/// another order as `frame order`, otherwise a `frames` count change and a
+/// `frame N` entry (counted from 1) for each frame both have that differs.
+fn frame_changes(
+    a: &[SpriteIdWithWeight],
+    b: &[SpriteIdWithWeight],
+    bits: HashBits,
+) -> Vec<FieldChange> {
+    let (mut sorted_a, mut sorted_b) = (a.to_vec(), b.to_vec());
+    sorted_a.sort();
+    sorted_b.sort();
+    if sorted_a == sorted_b {
+        let render = |x: &[SpriteIdWithWeight]| {
+            let items: Vec<String> = x.iter().map(|x| render_variation(x, bits)).collect();
+            items.join(", ")
+        };
+        return vec![FieldChange::new("frame order", render(a), render(b))];
+    }
+
+    let mut ret = vec![];
+    if a.len() != b.len() {
+        let after = if b.len() > a.len() {
+            format!("{} ({} added)", b.len(), b.len() - a.len())
+        } else {
+            format!("{} ({} removed)", b.len(), a.len() - b.len())
+        };
+        ret.push(FieldChange::new("frames", a.len(), after));
+    }
+    for (k, (x, y)) in a.iter().zip(b).enumerate() {
+        if x != y {
+            ret.push(FieldChange::new(
+                &format!("frame {}", k + 1),
+                render_variation(x, bits),
+                render_variation(y, bits),
+            ));
+        }
+    }
+    ret
+}
+
+/// Fields that differ between two hashed definitions of the same id. Sprite
+/// lists whose variations only changed weight are reported as `fg weights`
+/// or `bg weights` rather than as a whole new list, and the foreground of
+/// tiles animated on both sides frame by frame. An absent list and an empty
+/// one differ. Sprites are tagged as hashes of `bits` bits.
+pub fn field_changes(a: &SingleTile, b: &SingleTile, bits: HashBits) -> Vec<FieldChange> {
+    let mut ret = vec![];
+    for (role, oa, ob) in [("fg", &a.fg, &b.fg), ("bg", &a.bg, &b.bg)] {
+        let la = oa.as_ref().map_or(&[][..], |x| &x.0);
+        let lb = ob.as_ref().map_or(&[][..], |x| &x.0);
+        let summary = EditSummary::new(&edit_script(la, lb));
+        let frames = role == "fg" && a.animated && b.animated;
+        if frames && (summary.added > 0 || summary.removed > 0) {
+            ret.extend(frame_changes(la, lb, bits));
+        } else if summary.added > 0 || summary.removed > 0 || oa.is_some() != ob.is_some() {
+            ret.push(FieldChange::new(
+                role,
+                render_list(oa, bits),
+                render_list(ob, bits),
+            ));
+        } else if summary.reweighted > 0 {
+            ret.push(FieldChange::new(
+                &format!("{} weights", role),
+                render_weights(la),
+                render_weights(lb),
+            ));
+        }
+    }
+
+    let rotates = |x: &SingleTile| x.rotates.map_or(ABSENT.to_string(), |r| r.to_string());
+    if a.rotates != b.rotates {
+        ret.push(FieldChange::new("rotates", rotates(a), rotates(b)));
+    }
+    if a.multitile != b.multitile {
+        ret.push(FieldChange::new("multitile", a.multitile, b.multitile));
+    }
+    if a.animated != b.animated {
+        ret.push(FieldChange::new("animated", a.animated, b.animated));
+    }
+    let (anim_a, anim_b) = (animation(a), animation(b));
+    if anim_a != anim_b {
+        let mut after = render_animation(anim_b);
+        if let (Some((_, la)), Some((_, lb))) = (anim_a, anim_b) {
+            if lb < la {
+                after += " (shorter)";
+            } else if lb > la {
+                after += " (longer)";
+            }
+        }
+        ret.push(FieldChange::new(
+            "animation",
+            render_animation(anim_a),
+            after,
+        ));
+    }
+    if a.height_3d != b.height_3d {
+        ret.push(FieldChange::new("height_3d", a.height_3d, b.height_3d));
+    }
+
+    let names = a
+        .extra
+        .names()
+        .chain(b.extra.names().filter(|x| !a.extra.0.contains_key(*x)));
+    for name in names {
+        let before = a.extra.0.get(name);
+        let after = b.extra.0.get(name);
+        if before != after {
+            let render =
+                |x: Option<&serde_json::Value>| x.map_or(ABSENT.to_string(), |v| v.to_string());
+            ret.push(FieldChange::new(name, render(before), render(after)));
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animated(frames: &[u32]) -> SingleTile {
+        let frames: Vec<String> = frames
+            .iter()
+            .map(|x| format!(r#"{{ "sprite": {}, "weight": 2 }}"#, x))
+            .collect();
+        let json = format!(
+            r#"{{ "id": "fire", "fg": [{}], "animated": true }}"#,
+            frames.join(", ")
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn fields(a: &[u32], b: &[u32]) -> Vec<String> {
+        field_changes(&animated(a), &animated(b), HashBits::Bits32)
+            .into_iter()
+            .map(|x| x.field)
+            .collect()
+    }
+
+    #[test]
+    fn diffs_animations_by_frame() {
+        assert_eq!(fields(&[1, 2, 3], &[3, 1, 2]), ["frame order"]);
+        assert_eq!(
+            fields(&[1, 2], &[1, 5, 3]),
+            ["frames", "frame 2", "animation"]
+        );
+        assert_eq!(fields(&[1, 2, 3], &[1, 2]), ["frames", "animation"]);
+    }
+
+    #[test]
+    fn absent_list_differs_from_empty() {
+        let absent: SingleTile = serde_json::from_str(r#"{ "id": "t_floor", "fg": 1 }"#).unwrap();
+        let empty: SingleTile =
+            serde_json::from_str(r#"{ "id": "t_floor", "fg": 1, "bg": [] }"#).unwrap();
+        assert_eq!(absent.bg, None);
+        assert_eq!(empty.bg, Some(SingleOrVec(vec![])));
+        assert_eq!(
+            field_changes(&absent, &empty, HashBits::Bits32),
+            [FieldChange::new("bg", ABSENT, "[]")]
+        );
+        assert_eq!(
+            serde_json::to_string(&absent).unwrap(),
+            r#"{"id":["t_floor"],"fg":[{"id":[1],"weight":null}],"rotates":null,"multitile":false,"animated":false,"height_3d":0}"#
+        );
+    }
+}
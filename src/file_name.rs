@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+
+/// Longest file name stem produced, in bytes. Most file systems allow 255
+/// bytes per name, and CJK ids take 3 bytes and emoji 4 bytes per character,
+/// so this leaves room for suffixes and extensions.
+const MAX_STEM_BYTES: usize = 200;
+
+/// File name stem for a tile id, safe on every common file system.
+///
+/// Non-ASCII characters, e.g. CJK or emoji, are kept as they are. Path
+/// separators, characters reserved on Windows and control characters become
+/// `_`, trailing dots and spaces are dropped, and overlong ids are cut at a
+/// character boundary.
+pub fn sanitize(id: &str) -> String {
+    let mut ret = String::with_capacity(id.len());
+    for c in id.chars() {
+        let c = match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        };
+        if ret.len() + c.len_utf8() > MAX_STEM_BYTES {
+            break;
+        }
+        ret.push(c);
+    }
+    let trimmed = ret.trim_end_matches(['.', ' ']).len();
+    ret.truncate(trimmed);
+    if ret.is_empty() {
+        ret.push('_');
+    }
+    ret
+}
+
+/// `base`, or `base_2`, `base_3`, ... if taken, marking the result as taken.
+pub fn unique_name(base: &str, taken: &mut HashSet<String>) -> String {
+    let mut name = base.to_string();
+    let mut n = 1;
+    while taken.contains(&name) {
+        n += 1;
+        name = format!("{}_{}", base, n);
+    }
+    taken.insert(name.clone());
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_cjk_and_emoji() {
+        assert_eq!(sanitize("t_墙壁"), "t_墙壁");
+        assert_eq!(sanitize("overlay_🐉_wings"), "overlay_🐉_wings");
+        assert_eq!(sanitize("t_👨‍👩‍👧"), "t_👨‍👩‍👧");
+    }
+
+    #[test]
+    fn replaces_unsafe_characters() {
+        assert_eq!(sanitize("a/b\\c:d"), "a_b_c_d");
+        assert_eq!(sanitize("门?*\"<>|"), "门______");
+        assert_eq!(sanitize("t\n\t"), "t__");
+    }
+
+    #[test]
+    fn never_empty_or_a_directory_reference() {
+        assert_eq!(sanitize(""), "_");
+        assert_eq!(sanitize("."), "_");
+        assert_eq!(sanitize(".."), "_");
+        assert_eq!(sanitize("t_wall. "), "t_wall");
+    }
+
+    #[test]
+    fn truncates_at_char_boundary() {
+        let id = "龙".repeat(100);
+        let stem = sanitize(&id);
+        assert!(stem.len() <= MAX_STEM_BYTES);
+        assert_eq!(stem, "龙".repeat(MAX_STEM_BYTES / 3));
+
+        let id = "🐉".repeat(100);
+        assert_eq!(sanitize(&id), "🐉".repeat(MAX_STEM_BYTES / 4));
+    }
+}
@@ -0,0 +1,53 @@
+use image::{GenericImageView, RgbaImage, SubImage};
+
+/// Largest per-channel difference, out of 255, within `fuzz` percent.
+fn max_channel_diff(fuzz: f32) -> u8 {
+    (fuzz.clamp(0.0, 100.0) / 100.0 * 255.0).floor() as u8
+}
+
+/// Whether two sprites of the same size are equal but for re-save noise:
+/// every channel of every pixel within `fuzz` percent, except for at most
+/// `max_pixels` pixels.
+pub fn fuzzy_equal(
+    a: &SubImage<&RgbaImage>,
+    b: &SubImage<&RgbaImage>,
+    fuzz: f32,
+    max_pixels: usize,
+) -> bool {
+    if a.dimensions() != b.dimensions() {
+        return false;
+    }
+    let max_diff = max_channel_diff(fuzz);
+    let differing = a
+        .pixels()
+        .zip(b.pixels())
+        .filter(|((_, _, pa), (_, _, pb))| {
+            pa.0.iter()
+                .zip(pb.0.iter())
+                .any(|(ca, cb)| ca.abs_diff(*cb) > max_diff)
+        })
+        .take(max_pixels + 1)
+        .count();
+    differing <= max_pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn tolerates_small_differences() {
+        let a = RgbaImage::from_pixel(4, 4, Rgba([100, 100, 100, 255]));
+        let mut b = RgbaImage::from_pixel(4, 4, Rgba([102, 99, 100, 255]));
+        fn view(img: &RgbaImage) -> SubImage<&RgbaImage> {
+            img.view(0, 0, 4, 4)
+        }
+        assert!(!fuzzy_equal(&view(&a), &view(&b), 0.0, 0));
+        assert!(fuzzy_equal(&view(&a), &view(&b), 1.0, 0));
+
+        b.put_pixel(0, 0, Rgba([0, 0, 0, 255]));
+        assert!(!fuzzy_equal(&view(&a), &view(&b), 1.0, 0));
+        assert!(fuzzy_equal(&view(&a), &view(&b), 1.0, 1));
+    }
+}
@@ -0,0 +1,77 @@
+use crate::archive::Archive;
+use crate::error::{create_output_dir, Error, Result};
+use crate::file_name::sanitize;
+use crate::workspace::user_cache_dir;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Revision standing for the working tree rather than a commit, so that
+/// uncommitted changes can be compared against a commit.
+pub const WORKTREE: &str = ".";
+
+/// Runs git in `repo`, returning what it printed.
+fn git(repo: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let failed = |message: String| Error::Git {
+        path: repo.to_owned(),
+        message,
+    };
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .map_err(|e| failed(format!("could not run git: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(failed(stderr.trim().to_string()));
+    }
+    Ok(output.stdout)
+}
+
+/// Full commit id a revision such as a branch, tag or `HEAD~2` names.
+pub fn resolve(repo: &Path, rev: &str) -> Result<String> {
+    let commit = format!("{}^{{commit}}", rev);
+    let out =
+        git(repo, &["rev-parse", "--verify", "--quiet", &commit]).map_err(|_| Error::Git {
+            path: repo.to_owned(),
+            message: format!("'{}' does not name a commit", rev),
+        })?;
+    Ok(String::from_utf8_lossy(&out).trim().to_string())
+}
+
+/// The tileset at `path` in the working tree of the repository `repo`, as
+/// it is on disk.
+pub fn worktree_dir(repo: &Path, path: &str) -> PathBuf {
+    repo.join(path.trim_matches('/'))
+}
+
+/// Writes the tileset at `path` in the repository `repo`, as of revision
+/// `rev`, into a directory of the user cache named after the path and
+/// commit, replacing what an earlier run wrote there, and returns that
+/// directory. Files are read from the repository objects, so the working
+/// tree and index are left alone.
+pub fn export_to_cache(repo: &Path, rev: &str, path: &str) -> Result<PathBuf> {
+    let commit = resolve(repo, rev)?;
+    let path = path.trim_matches('/');
+    // `<commit>:<path>` is the tree at `path` from the repository root, so
+    // the archive holds the tileset at its top level
+    let spec = format!("{}:{}", commit, path);
+    let zip = git(repo, &["archive", "--format=zip", &spec])?;
+    let archive = Archive::parse(Path::new(&format!("{}:{}", rev, path)), zip)?;
+
+    let name = Path::new(path)
+        .file_name()
+        .map_or("tileset".into(), |x| x.to_string_lossy());
+    let dir = user_cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("revisions")
+        .join(format!(
+            "{}-{}",
+            sanitize(&name),
+            &commit[..12.min(commit.len())]
+        ));
+    let _ = std::fs::remove_dir_all(&dir);
+    create_output_dir(&dir)?;
+    archive.unpack_tileset(&dir)?;
+    Ok(dir)
+}
@@ -0,0 +1,164 @@
+use crate::compare::ComparisonResult;
+use crate::error::{Error, Result};
+use crate::lint_file::Lint;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Most ids listed per section of the step summary; the rest are counted.
+const SUMMARY_MAX_IDS: usize = 50;
+
+/// Inputs of the action, which the runner passes as `INPUT_<NAME>`
+/// environment variables.
+pub struct ActionInputs {
+    pub tileset: PathBuf,
+    /// Tileset to compare against, e.g. the one on the base branch
+    pub baseline: Option<PathBuf>,
+}
+
+fn input(name: &'static str) -> Option<String> {
+    std::env::var(name).ok().filter(|x| !x.trim().is_empty())
+}
+
+impl ActionInputs {
+    pub fn from_env() -> Result<ActionInputs> {
+        Ok(ActionInputs {
+            tileset: input("INPUT_TILESET")
+                .map(PathBuf::from)
+                .ok_or(Error::MissingEnv("INPUT_TILESET"))?,
+            baseline: input("INPUT_BASELINE").map(PathBuf::from),
+        })
+    }
+}
+
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Workflow command annotating the line of a lint, e.g.
+/// `::warning file=gfx/tile_config.json,line=12,title=W003 duplicate-id::...`
+pub fn annotation(lint: &Lint) -> String {
+    let title = format!("{} {}", lint.warning.code, lint.warning.code.name());
+    format!(
+        "::warning file={},line={},title={}::{}",
+        escape_property(&lint.path.to_string_lossy()),
+        lint.line,
+        escape_property(&title),
+        escape_data(&lint.warning.message)
+    )
+}
+
+fn escape_cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+fn id_list(title: &str, ids: &[String]) -> String {
+    if ids.is_empty() {
+        return String::new();
+    }
+    let mut ret = format!("<details><summary>{} ({})</summary>\n\n", title, ids.len());
+    for id in ids.iter().take(SUMMARY_MAX_IDS) {
+        ret += &format!("- `{}`\n", id);
+    }
+    if ids.len() > SUMMARY_MAX_IDS {
+        ret += &format!("- and {} more\n", ids.len() - SUMMARY_MAX_IDS);
+    }
+    ret + "\n</details>\n\n"
+}
+
+/// Markdown summary of the lints of a tileset and of its comparison with a
+/// baseline, if any, for the job summary page.
+pub fn render_summary(tileset: &Path, lints: &[Lint], result: Option<&ComparisonResult>) -> String {
+    let mut md = format!("## Tileset check: `{}`\n\n", tileset.display());
+    if lints.is_empty() {
+        md += "No lints.\n\n";
+    } else {
+        md += &format!("**{} lint(s)**\n\n", lints.len());
+        md += "| Line | Code | Message |\n|---:|---|---|\n";
+        for lint in lints {
+            md += &format!(
+                "| {} | {} {} | {} |\n",
+                lint.line,
+                lint.warning.code,
+                lint.warning.code.name(),
+                escape_cell(&lint.warning.message)
+            );
+        }
+        md += "\n";
+    }
+
+    if let Some(result) = result {
+        let changed = result.changed.as_deref().unwrap_or_default();
+        md += &format!("### Comparison with baseline: {}\n\n", result.status());
+        md += "| Added | Removed | Changed |\n|---:|---:|---:|\n";
+        md += &format!(
+            "| {} | {} | {} |\n\n",
+            result.only_in_b.len(),
+            result.only_in_a.len(),
+            changed.len()
+        );
+        md += &id_list("Added", &result.only_in_b);
+        md += &id_list("Removed", &result.only_in_a);
+        md += &id_list("Changed", changed);
+    }
+    md
+}
+
+/// `name=value` pairs for downstream steps: lint count, and comparison
+/// status and counts if compared.
+pub fn outputs(lints: &[Lint], result: Option<&ComparisonResult>) -> String {
+    let mut ret = format!("lints={}\n", lints.len());
+    if let Some(result) = result {
+        ret += &format!("status={}\n", result.status());
+        ret += &format!("added={}\n", result.only_in_b.len());
+        ret += &format!("removed={}\n", result.only_in_a.len());
+        if let Some(changed) = &result.changed {
+            ret += &format!("changed={}\n", changed.len());
+        }
+    }
+    ret
+}
+
+/// Appends to the file named by the environment variable `var`, the way
+/// the runner collects `GITHUB_STEP_SUMMARY` and `GITHUB_OUTPUT`. Returns
+/// false if the variable is not set.
+pub fn append_to_env_file(var: &str, contents: &str) -> Result<bool> {
+    let path = match std::env::var_os(var) {
+        Some(x) if !x.is_empty() => PathBuf::from(x),
+        _ => return Ok(false),
+    };
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|source| Error::WriteOutput { path, source })?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warnings::{Code, Warning};
+
+    #[test]
+    fn escapes_annotations() {
+        let lint = Lint {
+            path: PathBuf::from("gfx/a,b/tile_config.json"),
+            line: 12,
+            warning: Warning::new(Code::DuplicateId, "duplicate tile id '50%'\nagain"),
+        };
+        assert_eq!(
+            annotation(&lint),
+            "::warning file=gfx/a%2Cb/tile_config.json,line=12,title=W003 duplicate-id\
+::duplicate tile id '50%25'%0Aagain"
+        );
+        let md = render_summary(Path::new("gfx"), &[lint], None);
+        assert!(md.contains("| 12 | W003 duplicate-id | duplicate tile id '50%' again |"));
+    }
+}
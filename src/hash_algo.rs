@@ -0,0 +1,364 @@
+use clap::ArgEnum;
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+/// Algorithm turning sprite pixels into sprite hashes. Hashes, and so dumps,
+/// extracted directories and reports, are only comparable between runs
+/// using the same algorithm.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashAlgo {
+    #[default]
+    Xxh64,
+    Blake3,
+    Fnv,
+    /// Rust's `DefaultHasher`, as used before the algorithm could be chosen.
+    /// May change between Rust releases.
+    Legacy,
+}
+
+impl HashAlgo {
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgo::Xxh64 => "xxh64",
+            HashAlgo::Blake3 => "blake3",
+            HashAlgo::Fnv => "fnv",
+            HashAlgo::Legacy => "legacy",
+        }
+    }
+
+    pub fn hasher(self) -> Box<dyn Hasher> {
+        let digest: fn(&[u8]) -> u64 = match self {
+            HashAlgo::Xxh64 => |bytes: &[u8]| xxh64(bytes, 0),
+            HashAlgo::Blake3 => |bytes: &[u8]| {
+                let digest = blake3(bytes);
+                u64::from_le_bytes(digest[..8].try_into().unwrap_or_default())
+            },
+            HashAlgo::Fnv => fnv1a64,
+            HashAlgo::Legacy => return Box::new(DefaultHasher::new()),
+        };
+        Box::new(StableHasher {
+            digest,
+            bytes: vec![],
+        })
+    }
+}
+
+/// Collects what is hashed as little-endian bytes, with `usize` widened to
+/// 64 bits, so that hashes are the same on every platform, and hashes them
+/// all at once with one of the stable algorithms.
+struct StableHasher {
+    digest: fn(&[u8]) -> u64,
+    bytes: Vec<u8>,
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        (self.digest)(&self.bytes)
+    }
+}
+
+/// 64-bit FNV-1a.
+pub fn fnv1a64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+const XXH_P1: u64 = 0x9e37_79b1_85eb_ca87;
+const XXH_P2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const XXH_P3: u64 = 0x1656_67b1_9e37_79f9;
+const XXH_P4: u64 = 0x85eb_ca77_c2b2_ae63;
+const XXH_P5: u64 = 0x27d4_eb2f_1656_67c5;
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap_or_default())
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap_or_default())
+}
+
+fn xxh_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(XXH_P2))
+        .rotate_left(31)
+        .wrapping_mul(XXH_P1)
+}
+
+fn xxh_merge(acc: u64, val: u64) -> u64 {
+    (acc ^ xxh_round(0, val))
+        .wrapping_mul(XXH_P1)
+        .wrapping_add(XXH_P4)
+}
+
+/// XXH64 of `bytes`.
+pub fn xxh64(bytes: &[u8], seed: u64) -> u64 {
+    let mut rest = bytes;
+    let mut hash = if bytes.len() >= 32 {
+        let mut v = [
+            seed.wrapping_add(XXH_P1).wrapping_add(XXH_P2),
+            seed.wrapping_add(XXH_P2),
+            seed,
+            seed.wrapping_sub(XXH_P1),
+        ];
+        while rest.len() >= 32 {
+            for (k, acc) in v.iter_mut().enumerate() {
+                *acc = xxh_round(*acc, read_u64(&rest[k * 8..]));
+            }
+            rest = &rest[32..];
+        }
+        let hash = v[0]
+            .rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18));
+        v.iter().fold(hash, |hash, &acc| xxh_merge(hash, acc))
+    } else {
+        seed.wrapping_add(XXH_P5)
+    };
+    hash = hash.wrapping_add(bytes.len() as u64);
+
+    while rest.len() >= 8 {
+        hash ^= xxh_round(0, read_u64(rest));
+        hash = hash
+            .rotate_left(27)
+            .wrapping_mul(XXH_P1)
+            .wrapping_add(XXH_P4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        hash ^= (read_u32(rest) as u64).wrapping_mul(XXH_P1);
+        hash = hash
+            .rotate_left(23)
+            .wrapping_mul(XXH_P2)
+            .wrapping_add(XXH_P3);
+        rest = &rest[4..];
+    }
+    for &b in rest {
+        hash ^= (b as u64).wrapping_mul(XXH_P5);
+        hash = hash.rotate_left(11).wrapping_mul(XXH_P1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(XXH_P2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(XXH_P3);
+    hash ^ (hash >> 32)
+}
+
+const BLAKE3_IV: [u32; 8] = [
+    0x6a09_e667,
+    0xbb67_ae85,
+    0x3c6e_f372,
+    0xa54f_f53a,
+    0x510e_527f,
+    0x9b05_688c,
+    0x1f83_d9ab,
+    0x5be0_cd19,
+];
+const BLAKE3_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+const BLAKE3_CHUNK_LEN: usize = 1024;
+const BLAKE3_BLOCK_LEN: usize = 64;
+const CHUNK_START: u32 = 1;
+const CHUNK_END: u32 = 2;
+const PARENT: u32 = 4;
+const ROOT: u32 = 8;
+
+#[allow(clippy::many_single_char_names)]
+fn blake3_g(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
+    s[a] = s[a].wrapping_add(s[b]).wrapping_add(mx);
+    s[d] = (s[d] ^ s[a]).rotate_right(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_right(12);
+    s[a] = s[a].wrapping_add(s[b]).wrapping_add(my);
+    s[d] = (s[d] ^ s[a]).rotate_right(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_right(7);
+}
+
+/// The BLAKE3 compression function, returning the new chaining value.
+fn blake3_compress(cv: &[u32; 8], block: &[u8], counter: u64, flags: u32) -> [u32; 8] {
+    let mut padded = [0; BLAKE3_BLOCK_LEN];
+    padded[..block.len()].copy_from_slice(block);
+    let mut m = [0; 16];
+    for (k, word) in m.iter_mut().enumerate() {
+        *word = read_u32(&padded[k * 4..]);
+    }
+    let mut s = [0; 16];
+    s[..8].copy_from_slice(cv);
+    s[8..12].copy_from_slice(&BLAKE3_IV[..4]);
+    s[12] = counter as u32;
+    s[13] = (counter >> 32) as u32;
+    s[14] = block.len() as u32;
+    s[15] = flags;
+    for round in 0..7 {
+        if round > 0 {
+            let prev = m;
+            for (k, word) in m.iter_mut().enumerate() {
+                *word = prev[BLAKE3_PERMUTATION[k]];
+            }
+        }
+        blake3_g(&mut s, 0, 4, 8, 12, m[0], m[1]);
+        blake3_g(&mut s, 1, 5, 9, 13, m[2], m[3]);
+        blake3_g(&mut s, 2, 6, 10, 14, m[4], m[5]);
+        blake3_g(&mut s, 3, 7, 11, 15, m[6], m[7]);
+        blake3_g(&mut s, 0, 5, 10, 15, m[8], m[9]);
+        blake3_g(&mut s, 1, 6, 11, 12, m[10], m[11]);
+        blake3_g(&mut s, 2, 7, 8, 13, m[12], m[13]);
+        blake3_g(&mut s, 3, 4, 9, 14, m[14], m[15]);
+    }
+    let mut ret = [0; 8];
+    for (k, word) in ret.iter_mut().enumerate() {
+        *word = s[k] ^ s[k + 8];
+    }
+    ret
+}
+
+/// Chaining value of a subtree of whole chunks, starting at chunk `first`.
+fn blake3_subtree(bytes: &[u8], first: u64, root: bool) -> [u32; 8] {
+    let root_flag = if root { ROOT } else { 0 };
+    if bytes.len() <= BLAKE3_CHUNK_LEN {
+        let blocks: Vec<&[u8]> = if bytes.is_empty() {
+            vec![bytes]
+        } else {
+            bytes.chunks(BLAKE3_BLOCK_LEN).collect()
+        };
+        let mut cv = BLAKE3_IV;
+        for (k, block) in blocks.iter().enumerate() {
+            let mut flags = 0;
+            if k == 0 {
+                flags |= CHUNK_START;
+            }
+            if k == blocks.len() - 1 {
+                flags |= CHUNK_END | root_flag;
+            }
+            cv = blake3_compress(&cv, block, first, flags);
+        }
+        return cv;
+    }
+    // The left subtree holds the largest power of 2 of chunks leaving any
+    // for the right one
+    let chunks = bytes.len().div_ceil(BLAKE3_CHUNK_LEN);
+    let left_chunks = 1 << (usize::BITS - 1 - (chunks - 1).leading_zeros());
+    let (left, right) = bytes.split_at(left_chunks * BLAKE3_CHUNK_LEN);
+    let mut block = [0; BLAKE3_BLOCK_LEN];
+    for (k, word) in blake3_subtree(left, first, false)
+        .iter()
+        .chain(&blake3_subtree(right, first + left_chunks as u64, false))
+        .enumerate()
+    {
+        block[k * 4..k * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    blake3_compress(&BLAKE3_IV, &block, 0, PARENT | root_flag)
+}
+
+/// 32-byte BLAKE3 digest of `bytes`.
+pub fn blake3(bytes: &[u8]) -> [u8; 32] {
+    let mut ret = [0; 32];
+    for (k, word) in blake3_subtree(bytes, 0, true).iter().enumerate() {
+        ret[k * 4..k * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|x| format!("{:02x}", x)).collect()
+    }
+
+    #[test]
+    fn matches_reference_digests() {
+        assert_eq!(fnv1a64(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a64(b"foobar"), 0x8594_4171_f739_67e8);
+
+        assert_eq!(xxh64(b"", 0), 0xef46_db37_51d8_e999);
+        assert_eq!(xxh64(b"abc", 0), 0x44bc_2cf5_ad77_0999);
+        assert_eq!(
+            xxh64(b"Nobody inspects the spammish repetition", 0),
+            0xfbce_a83c_8a37_8bf1
+        );
+
+        assert_eq!(
+            hex(&blake3(b"")),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+        assert_eq!(
+            hex(&blake3(b"abc")),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+    }
+
+    #[test]
+    fn matches_xxh64_reference_vectors() {
+        assert_eq!(xxh64(b"a", 0), 0xd24e_c4f1_a98c_6e5b);
+        assert_eq!(xxh64(b"xxhash", 20141025), 0xb559_b98d_844e_0635);
+    }
+
+    /// Vectors of the BLAKE3 reference, whose inputs repeat the bytes 0 to
+    /// 250. Inputs over one 1024-byte chunk go through the chunk tree.
+    #[test]
+    fn matches_blake3_test_vectors() {
+        let vectors = [
+            (
+                1,
+                "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213",
+            ),
+            (
+                1024,
+                "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+            ),
+            (
+                1025,
+                "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+            ),
+            (
+                2048,
+                "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a",
+            ),
+            (
+                3072,
+                "b98cb0ff3623be03326b373de6b9095218513e64f1ee2edd2525c7ad1e5cffd2",
+            ),
+            (
+                31744,
+                "62b6960e1a44bcc1eb1a611a8d6235b6b4b78f32e7abc4fb4c6cdcce94895c47",
+            ),
+            (
+                102400,
+                "bc3e3d41a1146b069abffad3c0d44860cf664390afce4d9661f7902e7943e085",
+            ),
+        ];
+        for (len, digest) in vectors {
+            let input: Vec<u8> = (0..len).map(|x| (x % 251) as u8).collect();
+            assert_eq!(hex(&blake3(&input)), digest, "{} bytes", len);
+        }
+    }
+}
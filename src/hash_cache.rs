@@ -0,0 +1,169 @@
+use crate::atlas::{HashOptions, TileAtlas, HASH_VERSION};
+use crate::error::{create_output_dir, write_output, Error, Result};
+use crate::hash_algo::{xxh64, HashAlgo};
+use crate::tileset::{TilesNew, Tileset};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Directory, in the working directory, of the sprite hash cache.
+pub const CACHE_DIR_NAME: &str = ".tsc-cache";
+
+/// Sprite hashes of every sprite of a sheet, with the geometry of its atlas,
+/// so that an atlas can be set up without decoding the sheet.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CachedSheet {
+    pub sprite_w: u32,
+    pub sprite_h: u32,
+    pub offset_x: i32,
+    pub offset_y: i32,
+    pub tile_w: u32,
+    pub tile_h: u32,
+    pub tiles_x: u32,
+    pub tiles_y: u32,
+    pub evenly_divided: bool,
+    /// Hash of every sprite, in index order
+    pub hashes: Vec<u64>,
+}
+
+impl CachedSheet {
+    /// Entry for an atlas holding its whole sheet, given the hash of every
+    /// sprite in it.
+    pub fn new(atlas: &TileAtlas, hashes: &HashMap<u32, u64>) -> CachedSheet {
+        CachedSheet {
+            sprite_w: atlas.sprite_w,
+            sprite_h: atlas.sprite_h,
+            offset_x: atlas.offset_x,
+            offset_y: atlas.offset_y,
+            tile_w: atlas.tile_w,
+            tile_h: atlas.tile_h,
+            tiles_x: atlas.tiles_x,
+            tiles_y: atlas.tiles_y,
+            evenly_divided: atlas.is_evenly_divided(),
+            hashes: (atlas.tiles_start..atlas.tiles_end)
+                .map(|x| hashes[&x])
+                .collect(),
+        }
+    }
+
+    /// Atlas of the sheet with its sprites numbered from `tiles_start`,
+    /// holding none of them until `Tileset::load_sprites` brings them in.
+    pub fn atlas(&self, file: &str, tiles_start: u32) -> TileAtlas {
+        TileAtlas {
+            file: file.to_string(),
+            img: Default::default(),
+            sprite_w: self.sprite_w,
+            sprite_h: self.sprite_h,
+            offset_x: self.offset_x,
+            offset_y: self.offset_y,
+            tile_w: self.tile_w,
+            tile_h: self.tile_h,
+            tiles_x: self.tiles_x,
+            tiles_y: self.tiles_y,
+            tiles_start,
+            tiles_end: tiles_start + self.tiles_x * self.tiles_y,
+            slots: Some(HashMap::new()),
+        }
+    }
+
+    /// Hashes of the sprites, by index of an atlas starting at `tiles_start`.
+    pub fn hashes_from(&self, tiles_start: u32) -> impl Iterator<Item = (u32, u64)> + '_ {
+        (tiles_start..).zip(self.hashes.iter().copied())
+    }
+}
+
+/// Sprite hashes of sheets already hashed by earlier runs, one JSON file per
+/// sheet named after a key covering the sheet image, the sprite geometry
+/// and the hash options. Entries are never invalidated, only missed, so the
+/// directory can be deleted at any time.
+pub struct HashCache {
+    dir: PathBuf,
+}
+
+impl HashCache {
+    /// The cache in `dir`, created if missing. `None` for the legacy hash
+    /// algorithm, whose hashes may change with the Rust release the tool is
+    /// built with.
+    pub fn open(dir: &Path, opts: &HashOptions) -> Result<Option<HashCache>> {
+        if opts.hash_algo == HashAlgo::Legacy {
+            return Ok(None);
+        }
+        create_output_dir(dir)?;
+        Ok(Some(HashCache {
+            dir: dir.to_owned(),
+        }))
+    }
+
+    /// Key of a sheet of a tileset, reading its image.
+    pub fn key(&self, ts: &Tileset, sheet: &TilesNew, opts: &HashOptions) -> Result<u64> {
+        let path = ts.sheet_path(sheet);
+        let bytes = std::fs::read(&path).map_err(|source| Error::ReadInput { path, source })?;
+        let tile_info = ts.tile_info();
+        let description = format!(
+            "{} {} {} {} {:?} {}x{} {:?}x{:?} {:?},{:?} {:?} {:?} {:016x}",
+            HASH_VERSION,
+            opts.hash_algo.name(),
+            opts.hash_bits.bits(),
+            opts.hash_mode(),
+            opts.alpha_threshold,
+            tile_info.width,
+            tile_info.height,
+            sheet.sprite_width,
+            sheet.sprite_height,
+            sheet.sprite_offset_x,
+            sheet.sprite_offset_y,
+            ts.upscale,
+            ts.downscale,
+            xxh64(&bytes, 0)
+        );
+        Ok(xxh64(description.as_bytes(), 0))
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", key))
+    }
+
+    /// The entry of a key, if an earlier run wrote a readable one.
+    pub fn get(&self, key: u64) -> Option<CachedSheet> {
+        let text = std::fs::read_to_string(self.path(key)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn put(&self, key: u64, sheet: &CachedSheet) -> Result<()> {
+        write_output(&self.path(key), serde_json::to_string(sheet)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_sheets() {
+        let dir = std::env::temp_dir().join(format!("tileset-hash-cache-{}", std::process::id()));
+        let opts = HashOptions {
+            hash_algo: HashAlgo::Xxh64,
+            ..Default::default()
+        };
+        let cache = HashCache::open(&dir, &opts).unwrap().unwrap();
+        let atlas = TileAtlas::from_pixels(&[[0; 4]; 3], 5);
+        let hashes = HashMap::from([(5, 50), (6, 60), (7, 70), (9, 90)]);
+        let sheet = CachedSheet::new(&atlas, &hashes);
+        assert_eq!(sheet.hashes, [50, 60, 70]);
+
+        assert!(cache.get(1).is_none());
+        cache.put(1, &sheet).unwrap();
+        let cached = cache.get(1).unwrap();
+        assert_eq!(cached, sheet);
+        let moved = cached.atlas("main.png", 0);
+        assert_eq!((moved.tiles_end, moved.has_sprite(0)), (3, false));
+        assert_eq!(cached.hashes_from(0).last(), Some((2, 70)));
+
+        let legacy = HashOptions {
+            hash_algo: HashAlgo::Legacy,
+            ..Default::default()
+        };
+        assert!(HashCache::open(&dir, &legacy).unwrap().is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::atlas::{save_png, sprites_by_hash, HashOptions};
+use crate::compare::ComparisonResult;
+use crate::error::{create_output_dir, Result};
+use crate::file_name::sanitize;
+use image::{GenericImageView, Rgba, RgbaImage, SubImage};
+use std::path::Path;
+
+/// Lowest intensity of a changed pixel, so off-by-one edits stay visible.
+const MIN_HEAT: u32 = 64;
+
+fn heat(delta: u8) -> u8 {
+    if delta == 0 {
+        0
+    } else {
+        (MIN_HEAT + delta as u32 * (255 - MIN_HEAT) / 255) as u8
+    }
+}
+
+/// Per-pixel change between two sprites of the same size: red for the
+/// largest color channel change, blue for the alpha change, black if unchanged.
+pub fn heatmap(a: &SubImage<&RgbaImage>, b: &SubImage<&RgbaImage>) -> RgbaImage {
+    let (w, h) = a.dimensions();
+    RgbaImage::from_fn(w, h, |x, y| {
+        let pa = a.get_pixel(x, y).0;
+        let pb = b.get_pixel(x, y).0;
+        let color = (0..3).map(|c| pa[c].abs_diff(pb[c])).max().unwrap_or(0);
+        let alpha = pa[3].abs_diff(pb[3]);
+        Rgba([heat(color), 0, heat(alpha), 255])
+    })
+}
+
+/// Color marking changed pixels in a diff image.
+const DIFF_COLOR: Rgba<u8> = Rgba([255, 0, 255, 255]);
+/// Share of the original alpha unchanged pixels keep in a diff image.
+const FADE: u32 = 4;
+
+/// The new sprite faded out, with every changed pixel in magenta, to show
+/// at a glance what moved.
+pub fn pixel_diff(a: &SubImage<&RgbaImage>, b: &SubImage<&RgbaImage>) -> RgbaImage {
+    let (w, h) = a.dimensions();
+    RgbaImage::from_fn(w, h, |x, y| {
+        let pa = a.get_pixel(x, y);
+        let mut pb = b.get_pixel(x, y);
+        if pa != pb {
+            DIFF_COLOR
+        } else {
+            pb.0[3] = (pb.0[3] as u32 / FADE) as u8;
+            pb
+        }
+    })
+}
+
+/// A sprite that changed in place: same id, role, variation and position,
+/// but different pixels of the same size.
+pub struct InPlaceChange<'a> {
+    pub id: &'a str,
+    /// `fg` or `bg`
+    pub role: &'static str,
+    pub variation: usize,
+    /// Index of the sprite within the variation, e.g. the animation frame
+    pub position: usize,
+    pub a: SubImage<&'a RgbaImage>,
+    pub b: SubImage<&'a RgbaImage>,
+}
+
+/// Every sprite of the changed ids that changed in place.
+pub fn changed_in_place<'a>(
+    result: &'a ComparisonResult,
+    opts: &HashOptions,
+) -> Vec<InPlaceChange<'a>> {
+    let sprites_a = sprites_by_hash(&result.atlases_a, opts);
+    let sprites_b = sprites_by_hash(&result.atlases_b, opts);
+
+    let mut ret = vec![];
+    for (a, b) in result.changed_pairs() {
+        for (role, la, lb) in [("fg", a.fg(), b.fg()), ("bg", a.bg(), b.bg())] {
+            for (var, (va, vb)) in la.iter().zip(lb).enumerate() {
+                for (pos, (ha, hb)) in va.id.0.iter().zip(vb.id.0.iter()).enumerate() {
+                    if ha == hb {
+                        continue;
+                    }
+                    let (sa, sb) = match (sprites_a.get(ha), sprites_b.get(hb)) {
+                        (Some(sa), Some(sb)) => (sa.0.get_sprite(sa.1), sb.0.get_sprite(sb.1)),
+                        _ => continue,
+                    };
+                    if sa.dimensions() != sb.dimensions() {
+                        continue;
+                    }
+                    ret.push(InPlaceChange {
+                        id: &a.id.0[0],
+                        role,
+                        variation: var,
+                        position: pos,
+                        a: sa,
+                        b: sb,
+                    });
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// Writes a heatmap for every sprite that changed in place, named
+/// `<id>_<fg|bg>_<variation>_<sprite>.png`, along with the sprite before and
+/// after and a pixel diff, with `_before`, `_after` and `_diff` added.
+/// Returns the number of heatmaps written.
+pub fn write_heatmaps(
+    result: &ComparisonResult,
+    opts: &HashOptions,
+    out_dir: &Path,
+) -> Result<usize> {
+    create_output_dir(out_dir)?;
+    let changes = changed_in_place(result, opts);
+    for x in &changes {
+        let name = format!(
+            "{}_{}_{}_{}",
+            sanitize(x.id),
+            x.role,
+            x.variation,
+            x.position
+        );
+        let path = |suffix: &str| out_dir.join(format!("{}{}.png", name, suffix));
+        save_png(&heatmap(&x.a, &x.b), &path(""))?;
+        save_png(&x.a.to_image(), &path("_before"))?;
+        save_png(&x.b.to_image(), &path("_after"))?;
+        save_png(&pixel_diff(&x.a, &x.b), &path("_diff"))?;
+    }
+    Ok(changes.len())
+}
@@ -0,0 +1,202 @@
+use crate::atlas::{save_png, HashOptions, TileAtlas};
+use crate::compare::ComparisonResult;
+use crate::error::{create_output_dir, write_output, Error, Result};
+use crate::tileset::{SingleTile, Tileset};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Tint of the sprites of changed ids.
+const CHANGED_TINT: [u8; 3] = [255, 0, 255];
+/// Tint of the sprites of ids only in B.
+const ADDED_TINT: [u8; 3] = [0, 255, 0];
+
+/// Suffix of the tileset name and of the name shown in the game's options.
+const NAME_SUFFIX: &str = "_changes";
+const VIEW_SUFFIX: &str = " (changes highlighted)";
+
+/// What a highlighted copy of a tileset marks.
+#[derive(Debug, Default)]
+pub struct HighlightSummary {
+    pub sprites: usize,
+    /// Changed and added ids all of whose sprites unchanged ids use too
+    pub unmarked: Vec<String>,
+}
+
+/// Picks the tint of every sprite used by a changed or added id. Sprites
+/// that unchanged ids use too are left alone, so that everything tinted in
+/// game did change.
+fn sprite_tints(
+    vars: &[SingleTile],
+    changed: &HashSet<&str>,
+    added: &HashSet<&str>,
+) -> (HashMap<u32, [u8; 3]>, Vec<String>) {
+    let sprites =
+        |var: &SingleTile| -> Vec<u32> { var.variations().flat_map(|x| x.indices()).collect() };
+    let tint_of = |id: &str| {
+        if changed.contains(id) {
+            Some(CHANGED_TINT)
+        } else if added.contains(id) {
+            Some(ADDED_TINT)
+        } else {
+            None
+        }
+    };
+
+    let mut shared = HashSet::new();
+    for var in vars.iter().filter(|x| tint_of(&x.id.0[0]).is_none()) {
+        shared.extend(sprites(var));
+    }
+    let mut tints = HashMap::new();
+    let mut unmarked = vec![];
+    for var in vars {
+        let tint = match tint_of(&var.id.0[0]) {
+            Some(x) => x,
+            None => continue,
+        };
+        let own: Vec<u32> = sprites(var)
+            .into_iter()
+            .filter(|x| !shared.contains(x))
+            .collect();
+        if own.is_empty() {
+            unmarked.push(var.id.0[0].clone());
+        }
+        for sprite in own {
+            // Changed wins over added for sprites both use
+            tints.entry(sprite).or_insert(tint);
+        }
+    }
+    (tints, unmarked)
+}
+
+/// Blends the visible pixels of a sprite half way towards `tint`.
+fn tint_sprite(atlas: &mut TileAtlas, sprite: u32, tint: [u8; 3]) {
+    let within = sprite - atlas.tiles_start;
+    let x0 = within % atlas.tiles_x * atlas.sprite_w;
+    let y0 = within / atlas.tiles_x * atlas.sprite_h;
+    for y in y0..y0 + atlas.sprite_h {
+        for x in x0..x0 + atlas.sprite_w {
+            let px = atlas.img.get_pixel_mut(x, y);
+            if px.0[3] != 0 {
+                for (c, t) in px.0.iter_mut().zip(tint) {
+                    *c = ((*c as u16 + t as u16) / 2) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Renames a `tileset.txt` so the copy shows up next to the original in
+/// the game's options, or writes a minimal one.
+fn renamed_tileset_txt(ts: &Tileset, fallback_name: &str) -> Result<String> {
+    let path = ts.base_path.join("tileset.txt");
+    if !path.is_file() {
+        return Ok(format!(
+            "NAME: {0}{1}\nVIEW: {0}{2}\nJSON: tile_config.json\n",
+            fallback_name, NAME_SUFFIX, VIEW_SUFFIX
+        ));
+    }
+    let data = std::fs::read_to_string(&path).map_err(|source| Error::ReadInput {
+        path: path.clone(),
+        source,
+    })?;
+    let mut ret = String::new();
+    for line in data.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with("NAME:") {
+            ret += &format!("{}{}\n", line.trim_end(), NAME_SUFFIX);
+        } else if line.starts_with("VIEW:") {
+            ret += &format!("{}{}\n", line.trim_end(), VIEW_SUFFIX);
+        } else {
+            ret += line;
+            ret += "\n";
+        }
+    }
+    Ok(ret)
+}
+
+fn copy_file(from: &Path, to: &Path) -> Result<()> {
+    std::fs::copy(from, to)
+        .map(|_| ())
+        .map_err(|source| Error::WriteOutput {
+            path: to.to_owned(),
+            source,
+        })
+}
+
+/// Writes a copy of tileset B into `dir`, installable in the game's gfx
+/// folder, with the sprites of changed ids tinted magenta and those of ids
+/// only in B tinted green, for reviewing a tileset update in game.
+pub fn write_highlight_tileset(
+    result: &ComparisonResult,
+    ts_b: &Tileset,
+    dir: &Path,
+    opts: &HashOptions,
+) -> Result<HighlightSummary> {
+    // Fresh, so that the copy has the original resolution and every id
+    let ts = Tileset::load(&ts_b.base_path)?;
+    let mut atlases = ts.load_atlases()?;
+    let vars = ts.expand_variations(&atlases, false, opts);
+    let changed: HashSet<&str> = result
+        .changed
+        .iter()
+        .flatten()
+        .map(|x| x.as_str())
+        .collect();
+    let added: HashSet<&str> = result.only_in_b.iter().map(|x| x.as_str()).collect();
+    let (tints, unmarked) = sprite_tints(&vars, &changed, &added);
+
+    for (&sprite, &tint) in &tints {
+        if let Some(atlas) = atlases.iter_mut().find(|x| x.in_bounds(sprite)) {
+            tint_sprite(atlas, sprite, tint);
+        }
+    }
+
+    create_output_dir(dir)?;
+    for atlas in &atlases {
+        let path = dir.join(&atlas.file);
+        if let Some(parent) = path.parent() {
+            create_output_dir(parent)?;
+        }
+        save_png(&atlas.img, &path)?;
+    }
+    for file in ["tile_config.json", "layering.json"] {
+        let from = ts.base_path.join(file);
+        if from.is_file() {
+            copy_file(&from, &dir.join(file))?;
+        }
+    }
+    let name = ts
+        .base_path
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "tileset".to_string());
+    write_output(&dir.join("tileset.txt"), renamed_tileset_txt(&ts, &name)?)?;
+
+    Ok(HighlightSummary {
+        sprites: tints.len(),
+        unmarked,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_shared_sprites_alone() {
+        let vars: Vec<SingleTile> = [
+            r#"{ "id": "t_wall", "fg": 1, "bg": 0 }"#,
+            r#"{ "id": "t_floor", "fg": 0 }"#,
+            r#"{ "id": "t_door", "fg": 0 }"#,
+            r#"{ "id": "t_new", "fg": [{ "sprite": 1, "weight": 1 }, { "sprite": 2, "weight": 1 }] }"#,
+        ]
+        .iter()
+        .map(|x| serde_json::from_str(x).unwrap())
+        .collect();
+        let changed = HashSet::from(["t_wall", "t_door"]);
+        let added = HashSet::from(["t_new"]);
+        let (tints, unmarked) = sprite_tints(&vars, &changed, &added);
+        assert_eq!(tints, HashMap::from([(1, CHANGED_TINT), (2, ADDED_TINT)]));
+        assert_eq!(unmarked, ["t_door"]);
+    }
+}
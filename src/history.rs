@@ -0,0 +1,212 @@
+use crate::badge::escape_xml;
+use crate::compare::ComparisonResult;
+use crate::coverage::Coverage;
+use crate::error::{Error, Result};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HEADER: &str = "time,metric,value";
+
+const CHART_WIDTH: u32 = 480;
+const CHART_HEIGHT: u32 = 120;
+/// Space above each plot for its title
+const TITLE_HEIGHT: u32 = 24;
+const MARGIN: u32 = 10;
+
+/// One metric of one run: seconds since the Unix epoch, name and value.
+#[derive(Debug, PartialEq)]
+pub struct Sample {
+    pub time: u64,
+    pub metric: String,
+    pub value: f64,
+}
+
+/// Summary metrics of a comparison: tile counts and diff counts. The number
+/// of changed ids is left out when duplicates prevented the diff.
+pub fn comparison_metrics(result: &ComparisonResult) -> Vec<(String, f64)> {
+    let mut ret = vec![
+        ("tiles A".to_string(), result.variations_a.len() as f64),
+        ("tiles B".to_string(), result.variations_b.len() as f64),
+        ("only in A".to_string(), result.only_in_a.len() as f64),
+        ("only in B".to_string(), result.only_in_b.len() as f64),
+    ];
+    if let Some(changed) = &result.changed {
+        ret.push(("changed".to_string(), changed.len() as f64));
+    }
+    ret
+}
+
+/// Percentage of game objects with a tile of their own, per category and
+/// overall.
+pub fn coverage_metrics(coverage: &Coverage) -> Vec<(String, f64)> {
+    let percent = |covered: usize, total: usize| {
+        if total == 0 {
+            100.0
+        } else {
+            100.0 * covered as f64 / total as f64
+        }
+    };
+    let mut ret: Vec<(String, f64)> = coverage
+        .categories
+        .iter()
+        .map(|(category, stats)| {
+            let name = format!("{} coverage %", category);
+            (name, percent(stats.covered, stats.total()))
+        })
+        .collect();
+    let covered = coverage.categories.values().map(|x| x.covered).sum();
+    let total = coverage.categories.values().map(|x| x.total()).sum();
+    ret.push(("coverage %".to_string(), percent(covered, total)));
+    ret.push(("stale ids".to_string(), coverage.stale.len() as f64));
+    ret
+}
+
+/// Appends the metrics of a run to the CSV history at `path`, creating it
+/// with a header if needed.
+pub fn record_history(path: &Path, metrics: &[(String, f64)]) -> Result<()> {
+    let write_err = |source| Error::WriteOutput {
+        path: path.to_owned(),
+        source,
+    };
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs());
+    let is_new = std::fs::metadata(path).map_or(true, |x| x.len() == 0);
+    let mut lines = String::new();
+    if is_new {
+        lines += HEADER;
+        lines += "\n";
+    }
+    for (metric, value) in metrics {
+        lines += &format!("{},{},{}\n", time, metric, value);
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .map_err(write_err)
+}
+
+fn parse_history(text: &str, path: &Path) -> Result<Vec<Sample>> {
+    let bad = |line: usize, message: &str| Error::BadHistory {
+        path: path.to_owned(),
+        message: format!("line {}: {}", line, message),
+    };
+    let mut ret = vec![];
+    for (k, line) in text.lines().enumerate().filter(|(_, x)| !x.is_empty()) {
+        if k == 0 && line == HEADER {
+            continue;
+        }
+        // Metric names may contain commas, values and times can't
+        let (time, rest) = line
+            .split_once(',')
+            .ok_or_else(|| bad(k + 1, "expected time,metric,value"))?;
+        let (metric, value) = rest
+            .rsplit_once(',')
+            .ok_or_else(|| bad(k + 1, "expected time,metric,value"))?;
+        ret.push(Sample {
+            time: time.parse().map_err(|_| bad(k + 1, "invalid time"))?,
+            metric: metric.to_string(),
+            value: value.parse().map_err(|_| bad(k + 1, "invalid value"))?,
+        });
+    }
+    Ok(ret)
+}
+
+/// Reads every sample of the CSV history at `path`.
+pub fn read_history(path: &Path) -> Result<Vec<Sample>> {
+    let text = std::fs::read_to_string(path).map_err(|source| Error::ReadInput {
+        path: path.to_owned(),
+        source,
+    })?;
+    parse_history(&text, path)
+}
+
+/// Samples grouped by metric, each in recording order.
+pub fn by_metric(samples: &[Sample]) -> BTreeMap<&str, Vec<&Sample>> {
+    let mut ret: BTreeMap<&str, Vec<&Sample>> = BTreeMap::new();
+    for sample in samples {
+        ret.entry(&sample.metric).or_default().push(sample);
+    }
+    ret
+}
+
+/// Renders one line chart per metric, stacked vertically, each spanning the
+/// range of its own values with runs evenly spaced.
+pub fn render_trends(samples: &[Sample]) -> String {
+    let metrics = by_metric(samples);
+    let chart_h = TITLE_HEIGHT + CHART_HEIGHT + MARGIN;
+    let total_h = chart_h * metrics.len() as u32 + MARGIN;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+font-family=\"Verdana,Geneva,DejaVu Sans,sans-serif\" font-size=\"11\">\n",
+        CHART_WIDTH + 2 * MARGIN,
+        total_h
+    );
+    for (k, (metric, samples)) in metrics.iter().enumerate() {
+        let top = MARGIN + chart_h * k as u32;
+        let values: Vec<f64> = samples.iter().map(|x| x.value).collect();
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let latest = values[values.len() - 1];
+        svg += &format!(
+            "<text x=\"{}\" y=\"{}\">{}: {} (min {}, max {})</text>\n",
+            MARGIN,
+            top + 14,
+            escape_xml(metric),
+            latest,
+            min,
+            max
+        );
+        let plot_top = top + TITLE_HEIGHT;
+        svg += &format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#f6f6f6\" stroke=\"#ccc\"/>\n",
+            MARGIN, plot_top, CHART_WIDTH, CHART_HEIGHT
+        );
+        let points: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(n, value)| {
+                let x = if values.len() > 1 {
+                    n as f64 * CHART_WIDTH as f64 / (values.len() - 1) as f64
+                } else {
+                    CHART_WIDTH as f64 / 2.0
+                };
+                let y = if max > min {
+                    (max - value) / (max - min) * CHART_HEIGHT as f64
+                } else {
+                    CHART_HEIGHT as f64 / 2.0
+                };
+                format!("{:.1},{:.1}", x + MARGIN as f64, y + plot_top as f64)
+            })
+            .collect();
+        svg += &format!(
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"#4c1\" stroke-width=\"2\"/>\n",
+            points.join(" ")
+        );
+    }
+    svg += "</svg>\n";
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_charts_history() {
+        let text = "time,metric,value\n1,changed,4\n1,tiles A,10\n2,changed,2\n";
+        let samples = parse_history(text, Path::new("history.csv")).unwrap();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(by_metric(&samples)["changed"].len(), 2);
+
+        let svg = render_trends(&samples);
+        assert!(svg.contains("changed: 2 (min 2, max 4)"));
+        assert!(svg.contains("points=\"10.0,34.0 490.0,154.0\""));
+
+        assert!(parse_history("1,changed,many\n", Path::new("history.csv")).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use crate::atlas::{HashBits, HashOptions, TileAtlas};
+use crate::badge::escape_xml;
+use crate::compare::ComparisonResult;
+use crate::error::{write_output, Result};
+use crate::field_diff::{field_changes, FieldChange};
+use crate::messages::{Lang, Msg};
+use crate::review::ReviewLog;
+use crate::sprite_id_with_weight::SpriteIdWithWeight;
+use crate::tileset::{SingleTile, Tileset};
+use image::png::PngEncoder;
+use image::{ColorType, GenericImageView};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+const STYLE: &str = "
+body { font-family: sans-serif; background: #2b2b2b; color: #ddd; }
+table { border-collapse: collapse; }
+td, th { border: 1px solid #555; padding: 4px 8px; vertical-align: top; }
+img { image-rendering: pixelated; min-width: 32px; background: #444; margin: 1px; }
+.role { color: #999; font-size: small; }
+.weight { color: #999; font-size: small; }
+.field { color: #999; }
+.note { color: #999; font-size: small; }
+";
+
+/// Everything needed to render the sprites of one side of a comparison.
+pub struct ReportSide<'a> {
+    pub ts: &'a Tileset,
+    pub atlases: &'a [TileAtlas],
+    /// Tiles with sprite indices (not hashes), by id
+    pub tiles: HashMap<&'a str, &'a SingleTile>,
+}
+
+impl<'a> ReportSide<'a> {
+    pub fn new(ts: &'a Tileset, atlases: &'a [TileAtlas], vars: &'a [SingleTile]) -> Self {
+        ReportSide {
+            ts,
+            atlases,
+            tiles: vars.iter().map(|x| (x.id.0[0].as_str(), x)).collect(),
+        }
+    }
+}
+
+/// Data URIs of sprites, encoded once per sprite index.
+struct SpriteCache<'a> {
+    side: &'a ReportSide<'a>,
+    uris: HashMap<u32, String>,
+}
+
+impl<'a> SpriteCache<'a> {
+    fn uri(&mut self, sprite: u32) -> String {
+        if let Some(uri) = self.uris.get(&sprite) {
+            return uri.clone();
+        }
+        let uri = match self.side.atlases.iter().find(|x| x.in_bounds(sprite)) {
+            Some(atlas) => {
+                let subimg = atlas.get_sprite(sprite);
+                let raw = subimg.to_image();
+                let mut png: Vec<u8> = vec![];
+                PngEncoder::new(&mut png)
+                    .encode(&raw, subimg.width(), subimg.height(), ColorType::Rgba8)
+                    .map(|_| format!("data:image/png;base64,{}", base64::encode(&png)))
+                    .unwrap_or_default()
+            }
+            None => String::new(),
+        };
+        self.uris.insert(sprite, uri.clone());
+        uri
+    }
+
+    fn render_list(&mut self, role: &str, list: &[SpriteIdWithWeight]) -> String {
+        if list.is_empty() {
+            return String::new();
+        }
+        let mut ret = format!("<div><span class=\"role\">{}</span> ", role);
+        for spidw in list {
+            for sprite in spidw.indices() {
+                let _ = write!(
+                    ret,
+                    "<img src=\"{}\" title=\"sprite {}\" alt=\"sprite {}\">",
+                    self.uri(sprite),
+                    sprite,
+                    sprite
+                );
+            }
+            if let Some(weight) = spidw.weight {
+                let _ = write!(ret, "<span class=\"weight\">&times;{}</span> ", weight);
+            }
+        }
+        ret += "</div>";
+        ret
+    }
+
+    fn render_tile(&mut self, id: &str) -> String {
+        match self.side.tiles.get(id) {
+            Some(tile) => self.render_list("fg", tile.fg()) + &self.render_list("bg", tile.bg()),
+            None => "&mdash;".to_string(),
+        }
+    }
+}
+
+fn render_changes(changes: &[FieldChange]) -> String {
+    let mut ret = String::new();
+    for change in changes {
+        let _ = write!(
+            ret,
+            "<div><span class=\"field\">{}</span> {} &rarr; {}</div>",
+            escape_xml(&change.field),
+            escape_xml(&change.before),
+            escape_xml(&change.after)
+        );
+    }
+    ret
+}
+
+/// What every section of a report shares.
+struct Page<'a> {
+    lang: Lang,
+    review: &'a ReviewLog,
+}
+
+impl Page<'_> {
+    fn render_review(&self, id: &str) -> String {
+        match self.review.get(id) {
+            Some(entry) => {
+                let mut ret = escape_xml(self.lang.text(entry.decision.message()));
+                if !entry.note.is_empty() {
+                    let _ = write!(ret, "<div class=\"note\">{}</div>", escape_xml(&entry.note));
+                }
+                ret
+            }
+            None => String::new(),
+        }
+    }
+}
+
+/// Renders a table of ids with the sprites from both sides, plus a column
+/// of changed fields if `changes` is given and one of review decisions if
+/// there are any.
+fn render_section(
+    out: &mut String,
+    page: &Page,
+    title: Msg,
+    ids: &[String],
+    changes: Option<&HashMap<&str, Vec<FieldChange>>>,
+    a: &mut SpriteCache,
+    b: &mut SpriteCache,
+) {
+    let text = |msg| escape_xml(page.lang.text(msg));
+    let _ = writeln!(out, "<h2>{} ({})</h2>", text(title), ids.len());
+    if ids.is_empty() {
+        return;
+    }
+    let _ = write!(
+        out,
+        "<table>\n<tr><th>{}</th><th>{}</th><th>{}</th>",
+        text(Msg::ColumnId),
+        escape_xml(&a.side.ts.base_path.to_string_lossy()),
+        escape_xml(&b.side.ts.base_path.to_string_lossy())
+    );
+    if changes.is_some() {
+        let _ = write!(out, "<th>{}</th>", text(Msg::ColumnChanges));
+    }
+    let reviewed = !page.review.decisions.is_empty();
+    if reviewed {
+        let _ = write!(out, "<th>{}</th>", text(Msg::ColumnReview));
+    }
+    out.push_str("</tr>\n");
+    for id in ids {
+        let _ = write!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td>{}</td>",
+            escape_xml(id),
+            a.render_tile(id),
+            b.render_tile(id)
+        );
+        if let Some(changes) = changes {
+            let cell = changes.get(id.as_str()).map(|x| render_changes(x));
+            let _ = write!(out, "<td>{}</td>", cell.unwrap_or_default());
+        }
+        if reviewed {
+            let _ = write!(out, "<td>{}</td>", page.render_review(id));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+}
+
+/// Writes a self-contained HTML page showing the fg/bg sprites of every
+/// changed, removed and added id from both tilesets side by side, along
+/// with the decisions of `review`, in the given language.
+pub fn write_html_report(
+    path: &Path,
+    a: &ReportSide,
+    b: &ReportSide,
+    result: &ComparisonResult,
+    review: &ReviewLog,
+    lang: Lang,
+    bits: HashBits,
+) -> Result<()> {
+    let page = Page { lang, review };
+    let mut cache_a = SpriteCache {
+        side: a,
+        uris: HashMap::new(),
+    };
+    let mut cache_b = SpriteCache {
+        side: b,
+        uris: HashMap::new(),
+    };
+
+    let mut out = String::new();
+    let _ = writeln!(out, "<!DOCTYPE html>\n<html lang=\"{}\">", lang.code());
+    out.push_str("<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(
+        out,
+        "<title>{}</title>",
+        escape_xml(lang.text(Msg::ReportTitle))
+    );
+    let _ = writeln!(out, "<style>{}</style>\n</head>\n<body>", STYLE);
+    let _ = writeln!(
+        out,
+        "<h1>{} &rarr; {}</h1>",
+        escape_xml(&a.ts.base_path.to_string_lossy()),
+        escape_xml(&b.ts.base_path.to_string_lossy())
+    );
+
+    match &result.changed {
+        Some(changed) => {
+            let changes: HashMap<&str, Vec<FieldChange>> = result
+                .changed_pairs()
+                .into_iter()
+                .map(|(x, y)| (x.id.0[0].as_str(), field_changes(x, y, bits)))
+                .collect();
+            render_section(
+                &mut out,
+                &page,
+                Msg::Changed,
+                changed,
+                Some(&changes),
+                &mut cache_a,
+                &mut cache_b,
+            )
+        }
+        None => {
+            let _ = writeln!(
+                out,
+                "<h2>{}</h2>\n<p>{}</p>",
+                escape_xml(lang.text(Msg::Changed)),
+                escape_xml(lang.text(Msg::NotCompared))
+            );
+        }
+    }
+    render_section(
+        &mut out,
+        &page,
+        Msg::OnlyInA,
+        &result.only_in_a,
+        None,
+        &mut cache_a,
+        &mut cache_b,
+    );
+    render_section(
+        &mut out,
+        &page,
+        Msg::OnlyInB,
+        &result.only_in_b,
+        None,
+        &mut cache_a,
+        &mut cache_b,
+    );
+
+    out.push_str("</body>\n</html>\n");
+    write_output(path, out)
+}
+
+/// Writes the HTML report for a comparison of `ts_a` against `ts_b`,
+/// including the decisions of a review in A's output directory.
+pub fn write_comparison_report(
+    path: &Path,
+    ts_a: &Tileset,
+    ts_b: &Tileset,
+    result: &ComparisonResult,
+    opts: &HashOptions,
+    lang: Lang,
+) -> Result<()> {
+    let raw_a = ts_a.expand_variations(&result.atlases_a, false, opts);
+    let raw_b = ts_b.expand_variations(&result.atlases_b, false, opts);
+    write_html_report(
+        path,
+        &ReportSide::new(ts_a, &result.atlases_a, &raw_a),
+        &ReportSide::new(ts_b, &result.atlases_b, &raw_b),
+        result,
+        &ReviewLog::load(&ts_a.out_path)?,
+        lang,
+        opts.hash_bits,
+    )
+}
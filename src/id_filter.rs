@@ -0,0 +1,327 @@
+use std::str::FromStr;
+
+/// Restricts a run to the tile ids matching a pattern. Patterns must match
+/// the whole id, including ids generated from additional_tiles.
+#[derive(Clone, Debug)]
+pub enum IdFilter {
+    /// `*` matches any run of characters and `?` any single character
+    Glob(String),
+    /// Regular expression, written with a `re:` prefix
+    Regex(Regex),
+}
+
+impl IdFilter {
+    pub fn matches(&self, id: &str) -> bool {
+        match self {
+            IdFilter::Glob(pattern) => glob_match(pattern, id),
+            IdFilter::Regex(regex) => regex.is_match(id),
+        }
+    }
+}
+
+impl FromStr for IdFilter {
+    type Err = String;
+
+    /// Parses `re:<regex>` as a regular expression, anything else as a glob.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("re:") {
+            Some(source) => Regex::new(source).map(IdFilter::Regex),
+            None => Ok(IdFilter::Glob(s.to_string())),
+        }
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any run of
+/// characters and `?` any single character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star_pi, star_ti)) = star {
+            pi = star_pi + 1;
+            ti = star_ti + 1;
+            star = Some((star_pi, star_ti + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|x| *x == '*')
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+    Start,
+    End,
+    /// Alternatives, each a sequence
+    Group(Vec<Vec<Node>>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+/// Regular expression supporting literals, `.`, character classes (`[a-z]`,
+/// `[^_]`, `\d`, `\w`, `\s`), groups, alternation and the `*`, `+` and `?`
+/// quantifiers, which is all tile ids need. Matches whole strings.
+#[derive(Clone, Debug)]
+pub struct Regex {
+    alternatives: Vec<Vec<Node>>,
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')];
+const SPACE: &[(char, char)] = &[(' ', ' '), ('\t', '\r')];
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut ret = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            ret.push(self.sequence()?);
+        }
+        Ok(ret)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut ret = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let mut node = self.atom()?;
+            while let Some(q) = self.peek() {
+                let (min, max) = match q {
+                    '*' => (0, None),
+                    '+' => (1, None),
+                    '?' => (0, Some(1)),
+                    _ => break,
+                };
+                self.pos += 1;
+                node = Node::Repeat {
+                    node: Box::new(node),
+                    min,
+                    max,
+                };
+            }
+            ret.push(node);
+        }
+        Ok(ret)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some('(') => {
+                if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                }
+                let alternatives = self.alternatives()?;
+                match self.next() {
+                    Some(')') => Ok(Node::Group(alternatives)),
+                    _ => Err("unclosed '('".to_string()),
+                }
+            }
+            Some('[') => self.class(),
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('\\') => self.escape(),
+            Some(c @ ('*' | '+' | '?')) => Err(format!("'{}' has nothing to repeat", c)),
+            Some(c) => Ok(Node::Char(c)),
+            None => Err("unexpected end of pattern".to_string()),
+        }
+    }
+
+    fn escape(&mut self) -> Result<Node, String> {
+        let class = |negated: bool, ranges: &[(char, char)]| Node::Class {
+            negated,
+            ranges: ranges.to_vec(),
+        };
+        match self.next() {
+            Some('d') => Ok(class(false, DIGIT)),
+            Some('D') => Ok(class(true, DIGIT)),
+            Some('w') => Ok(class(false, WORD)),
+            Some('W') => Ok(class(true, WORD)),
+            Some('s') => Ok(class(false, SPACE)),
+            Some('S') => Ok(class(true, SPACE)),
+            Some(c) => Ok(Node::Char(c)),
+            None => Err("pattern ends with '\\'".to_string()),
+        }
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = vec![];
+        let mut first = true;
+        loop {
+            let c = match self.next() {
+                Some(']') if !first => break,
+                Some('\\') => match self.escape()? {
+                    Node::Class { ranges: r, .. } => {
+                        ranges.extend(r);
+                        first = false;
+                        continue;
+                    }
+                    Node::Char(c) => c,
+                    _ => unreachable!(),
+                },
+                Some(c) => c,
+                None => return Err("unclosed '['".to_string()),
+            };
+            first = false;
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|x| *x != ']') {
+                self.pos += 1;
+                let end = self.next().unwrap_or(c);
+                if end < c {
+                    return Err(format!("invalid range '{}-{}'", c, end));
+                }
+                ranges.push((c, end));
+            } else {
+                ranges.push((c, c));
+            }
+        }
+        Ok(Node::Class { negated, ranges })
+    }
+}
+
+fn match_seq(nodes: &[Node], text: &[char], pos: usize, k: &mut dyn FnMut(usize) -> bool) -> bool {
+    match nodes.split_first() {
+        None => k(pos),
+        Some((first, rest)) => match_node(first, text, pos, &mut |p| match_seq(rest, text, p, k)),
+    }
+}
+
+fn match_node(node: &Node, text: &[char], pos: usize, k: &mut dyn FnMut(usize) -> bool) -> bool {
+    match node {
+        Node::Char(c) => text.get(pos) == Some(c) && k(pos + 1),
+        Node::Any => pos < text.len() && k(pos + 1),
+        Node::Class { negated, ranges } => match text.get(pos) {
+            Some(c) => {
+                let inside = ranges.iter().any(|(lo, hi)| lo <= c && c <= hi);
+                inside != *negated && k(pos + 1)
+            }
+            None => false,
+        },
+        Node::Start => pos == 0 && k(pos),
+        Node::End => pos == text.len() && k(pos),
+        Node::Group(alternatives) => alternatives.iter().any(|seq| match_seq(seq, text, pos, k)),
+        Node::Repeat { node, min, max } => match_repeat(node, *min, *max, 0, text, pos, k),
+    }
+}
+
+/// Greedily matches `node` repeatedly, backtracking to fewer repetitions.
+fn match_repeat(
+    node: &Node,
+    min: usize,
+    max: Option<usize>,
+    count: usize,
+    text: &[char],
+    pos: usize,
+    k: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    if max.is_none_or(|max| count < max) {
+        // Empty repetitions past the minimum would loop forever
+        let mut more = |p: usize| {
+            (p != pos || count < min) && match_repeat(node, min, max, count + 1, text, p, k)
+        };
+        if match_node(node, text, pos, &mut more) {
+            return true;
+        }
+    }
+    count >= min && k(pos)
+}
+
+impl Regex {
+    pub fn new(source: &str) -> Result<Regex, String> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            pos: 0,
+        };
+        let alternatives = parser.alternatives()?;
+        if parser.pos < parser.chars.len() {
+            return Err("unmatched ')'".to_string());
+        }
+        Ok(Regex { alternatives })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let root = Node::Group(self.alternatives.clone());
+        match_node(&root, &text, 0, &mut |p| p == text.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, id: &str) -> bool {
+        pattern.parse::<IdFilter>().unwrap().matches(id)
+    }
+
+    #[test]
+    fn glob_matches_multibyte_characters() {
+        assert!(glob_match("t_墙*", "t_墙_center"));
+        assert!(glob_match("t_?_wall", "t_🐉_wall"));
+        assert!(!glob_match("t_?_wall", "t_🐉🐉_wall"));
+        assert!(glob_match("*🐉", "overlay_🐉"));
+    }
+
+    #[test]
+    fn regex_matches_whole_ids() {
+        assert!(matches("re:overlay_worn_.*", "overlay_worn_boots"));
+        assert!(!matches("re:overlay_worn_.*", "overlay_female_worn_boots"));
+        assert!(matches("re:t_wall(_(center|edge))?", "t_wall_edge"));
+        assert!(!matches("re:t_wall(_(center|edge))?", "t_wall_corner"));
+        assert!(matches("re:t_[a-z]+_\\d+", "t_wall_10"));
+        assert!(!matches("re:t_[^w]\\w*", "t_wall"));
+        assert!(matches("re:(a*)+b", "aab"));
+        assert!(matches("re:(a*)+", ""));
+    }
+
+    #[test]
+    fn regex_errors() {
+        for bad in [
+            "re:(t_wall",
+            "re:t_wall)",
+            "re:*",
+            "re:[a-",
+            "re:[z-a]",
+            "re:\\",
+        ] {
+            assert!(bad.parse::<IdFilter>().is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::cmp::Ordering;
+
+/// Ordering used for ids in every sorted output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IdOrder {
+    /// Digit runs compare by numeric value and letters ignore case,
+    /// so `t_wall_2` comes before `t_wall_10`.
+    #[default]
+    Natural,
+    /// Plain byte-wise string comparison.
+    Bytewise,
+}
+
+impl IdOrder {
+    pub fn cmp(self, a: &str, b: &str) -> Ordering {
+        match self {
+            IdOrder::Natural => natural_cmp(a, b),
+            IdOrder::Bytewise => a.cmp(b),
+        }
+    }
+
+    pub fn sort(self, ids: &mut [&str]) {
+        ids.sort_by(|a, b| self.cmp(a, b));
+    }
+}
+
+fn split_chunks(s: &str) -> Vec<&str> {
+    let mut ret = vec![];
+    let mut start = 0;
+    let mut prev_digit = None;
+    for (pos, c) in s.char_indices() {
+        let is_digit = c.is_ascii_digit();
+        if prev_digit.is_some() && prev_digit != Some(is_digit) {
+            ret.push(&s[start..pos]);
+            start = pos;
+        }
+        prev_digit = Some(is_digit);
+    }
+    if start < s.len() {
+        ret.push(&s[start..]);
+    }
+    ret
+}
+
+fn cmp_numeric(a: &str, b: &str) -> Ordering {
+    let a_trimmed = a.trim_start_matches('0');
+    let b_trimmed = b.trim_start_matches('0');
+    a_trimmed
+        .len()
+        .cmp(&b_trimmed.len())
+        .then_with(|| a_trimmed.cmp(b_trimmed))
+}
+
+fn cmp_text(a: &str, b: &str) -> Ordering {
+    let a_lower = a.chars().flat_map(char::to_lowercase);
+    let b_lower = b.chars().flat_map(char::to_lowercase);
+    a_lower.cmp(b_lower)
+}
+
+/// Compares strings chunk by chunk, where a chunk is a run of ASCII digits
+/// or a run of anything else. Ties (e.g. `a01` vs `a1`, `Wall` vs `wall`)
+/// are broken byte-wise so the order stays total and deterministic.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let a_chunks = split_chunks(a);
+    let b_chunks = split_chunks(b);
+    for (ca, cb) in a_chunks.iter().zip(b_chunks.iter()) {
+        let a_num = ca.as_bytes()[0].is_ascii_digit();
+        let b_num = cb.as_bytes()[0].is_ascii_digit();
+        let ord = if a_num && b_num {
+            cmp_numeric(ca, cb)
+        } else {
+            cmp_text(ca, cb)
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    a_chunks.len().cmp(&b_chunks.len()).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_order_with_cjk_and_emoji() {
+        let mut ids = vec!["t_墙_10", "t_🐉", "t_墙_2", "t_wall", "t_门", "t_墙"];
+        IdOrder::Natural.sort(&mut ids);
+        assert_eq!(ids, ["t_wall", "t_墙", "t_墙_2", "t_墙_10", "t_门", "t_🐉"]);
+    }
+
+    #[test]
+    fn digits_after_multibyte_characters() {
+        assert_eq!(natural_cmp("龙9", "龙10"), Ordering::Less);
+        assert_eq!(natural_cmp("🐉_02", "🐉_2"), Ordering::Less);
+        assert_eq!(split_chunks("墙12门3"), ["墙", "12", "门", "3"]);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::atlas::{HashOptions, HASH_VERSION};
+use crate::compare::ComparisonResult;
+use crate::error::{write_output, Result};
+use crate::output::TileChanges;
+use crate::tileset::Tileset;
+use crate::warnings::{self, Warning};
+use serde::Serialize;
+use std::path::Path;
+
+/// Version of the report.json layout, bumped on incompatible changes.
+pub const REPORT_FORMAT_VERSION: u32 = 1;
+
+/// Everything a comparison found, for tools that would otherwise parse the
+/// text outputs.
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    /// Version of this document's layout
+    format_version: u32,
+    tool_version: &'static str,
+    hash_version: u32,
+    /// `identical`, `different` or `unknown`
+    status: &'static str,
+    hash_mode: &'static str,
+    hash_algo: &'static str,
+    hash_bits: u32,
+    /// Ids only in tileset B
+    added: &'a [String],
+    /// Ids only in tileset A
+    removed: &'a [String],
+    /// Changed ids with their field changes, `null` if duplicates prevented
+    /// the diff
+    changed: Option<Vec<TileChanges<'a>>>,
+    similar: &'a [String],
+    duplicates_a: &'a [String],
+    duplicates_b: &'a [String],
+    ascii_changes: &'a [String],
+    ordering_changes: &'a [String],
+    subtile_changes: &'a [String],
+    /// Warnings printed during the run
+    warnings: Vec<Warning>,
+}
+
+/// Writes the whole comparison, with per-field changes and source lines of
+/// every changed id and the warnings printed so far, as one JSON document.
+pub fn write_json_report(
+    path: &Path,
+    result: &ComparisonResult,
+    ts1: &Tileset,
+    ts2: &Tileset,
+    opts: &HashOptions,
+) -> Result<()> {
+    let changed = result.changed.as_ref().map(|_| {
+        result
+            .changed_pairs()
+            .into_iter()
+            .map(|(a, b)| TileChanges::new(a, b, ts1, ts2, opts.hash_bits))
+            .collect()
+    });
+    let report = JsonReport {
+        format_version: REPORT_FORMAT_VERSION,
+        tool_version: env!("CARGO_PKG_VERSION"),
+        hash_version: HASH_VERSION,
+        status: result.status(),
+        hash_mode: opts.hash_mode(),
+        hash_algo: opts.hash_algo.name(),
+        hash_bits: opts.hash_bits.bits(),
+        added: &result.only_in_b,
+        removed: &result.only_in_a,
+        changed,
+        similar: &result.similar,
+        duplicates_a: &result.duplicates_a,
+        duplicates_b: &result.duplicates_b,
+        ascii_changes: &result.ascii_changes,
+        ordering_changes: &result.ordering_changes,
+        subtile_changes: &result.subtile_changes,
+        warnings: warnings::emitted(),
+    };
+    let dump = serde_json::to_string_pretty(&report)?;
+    write_output(path, dump)
+}
@@ -0,0 +1,80 @@
+#![feature(slice_partition_dedup)]
+
+pub mod archive;
+pub mod artifacts;
+pub mod atlas;
+pub mod badge;
+pub mod bundle;
+pub mod collision;
+pub mod color_search;
+pub mod compare;
+pub mod comparison_sheet;
+pub mod compose;
+pub mod coverage;
+pub mod decompose;
+pub mod dedupe;
+pub mod dump_index;
+pub mod error;
+pub mod extract;
+pub mod extracted_diff;
+pub mod extras;
+pub mod field_diff;
+pub mod file_name;
+pub mod fuzz;
+pub mod git_rev;
+pub mod github;
+pub mod hash_algo;
+pub mod hash_cache;
+pub mod heatmap;
+pub mod highlight;
+pub mod history;
+pub mod html_report;
+pub mod id_filter;
+pub mod id_order;
+pub mod json_report;
+pub mod lint_file;
+pub mod log;
+pub mod markdown_report;
+pub mod merge;
+pub mod messages;
+pub mod multitile;
+pub mod null_sprite;
+pub mod output;
+pub mod parallel;
+pub mod perceptual;
+pub mod progress;
+pub mod property_matrix;
+pub mod provenance;
+pub mod raw_config;
+pub mod remap;
+pub mod rename;
+pub mod repack;
+pub mod report;
+pub mod reslice;
+pub mod review;
+pub mod selftest;
+pub mod single_or_vec;
+pub mod source_diff;
+pub mod source_map;
+pub mod sprite_id_with_weight;
+pub mod sprite_store;
+pub mod sprite_tag;
+pub mod stats;
+pub mod three_way;
+pub mod tileset;
+pub mod tileset_config;
+pub mod tool_config;
+pub mod transform;
+pub mod unused;
+pub mod validate;
+pub mod variation_diff;
+pub mod version_info;
+pub mod warnings;
+pub mod watch;
+pub mod what_if;
+pub mod workspace;
+
+pub use atlas::{HashOptions, TileAtlas};
+pub use compare::{compare, CompareOptions, ComparisonResult};
+pub use error::{Error, Result};
+pub use tileset::{SingleTile, Tileset};
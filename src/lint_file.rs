@@ -0,0 +1,48 @@
+use crate::atlas::HashOptions;
+use crate::error::Result;
+use crate::tileset::Tileset;
+use crate::validate::lint_config;
+use crate::warnings::Warning;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A problem in a tile_config.json, with the line it was found on.
+pub struct Lint {
+    pub path: PathBuf,
+    /// 1-based; 1 for problems without a location, e.g. unknown top-level
+    /// fields
+    pub line: usize,
+    pub warning: Warning,
+}
+
+/// Compact `file:line: code message` format, as understood by editors and
+/// pre-commit.
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} {}",
+            self.path.display(),
+            self.line,
+            self.warning.code,
+            self.warning.message
+        )
+    }
+}
+
+/// Runs the lints that need no images on a single tile_config.json. Unknown
+/// fields are reported as lints rather than refused. Suppressions from the
+/// command line and from the tileset's config apply.
+pub fn lint_file(path: &Path) -> Result<Vec<Lint>> {
+    let ts = Tileset::load_config_file(path, true)?;
+    let vars = ts.expand_variations(&[], false, &HashOptions::default());
+    Ok(lint_config(&ts, &vars)
+        .into_iter()
+        .filter(|x| !ts.allows(x.warning.code, x.sheet))
+        .map(|x| Lint {
+            path: path.to_owned(),
+            line: x.line.unwrap_or(1),
+            warning: x.warning,
+        })
+        .collect())
+}
@@ -0,0 +1,97 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much is printed besides results, warnings and errors, which are
+/// always printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Nothing else, for scripts
+    Quiet = 0,
+    /// What the tool is doing and what it wrote
+    Normal = 1,
+    /// Also every atlas as it's loaded
+    Verbose = 2,
+    /// Also timings and hash cache lookups
+    Debug = 3,
+}
+
+impl Verbosity {
+    /// Level given by `-q` and the number of `-v` flags.
+    pub fn from_flags(quiet: bool, verbose: u64) -> Verbosity {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            _ => Verbosity::Debug,
+        }
+    }
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+/// Whether messages of the given level are printed.
+pub fn enabled(verbosity: Verbosity) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= verbosity as u8
+}
+
+/// Prints a line to stdout unless `-q` was given.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Verbosity::Normal) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Prints a line to stdout with `-v` or `-vv`.
+#[macro_export]
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Verbosity::Verbose) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Prints a line to stdout with `-vv`.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Verbosity::Debug) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Prints a warning without a code to stderr, prefixed like coded ones.
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        eprintln!("WARN: {}", format_args!($($arg)*))
+    };
+}
+
+/// Prints an error to stderr.
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        eprintln!("ERROR: {}", format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_verbose_flags() {
+        assert_eq!(Verbosity::from_flags(true, 2), Verbosity::Quiet);
+        assert_eq!(Verbosity::from_flags(false, 0), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(false, 1), Verbosity::Verbose);
+        assert_eq!(Verbosity::from_flags(false, 5), Verbosity::Debug);
+    }
+}
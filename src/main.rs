@@ -1,530 +1,1882 @@
-#![feature(slice_partition_dedup)]
-
-mod single_or_vec;
-mod sprite_id_with_weight;
-
-use single_or_vec::SingleOrVec;
-use sprite_id_with_weight::SpriteIdWithWeight;
-
-use clap::{Parser, Subcommand};
-use image::io::Reader as ImageReader;
-use image::{DynamicImage, GenericImageView, ImageFormat, RgbaImage, SubImage};
-use serde::{Deserialize, Serialize};
-use std::collections::hash_map::DefaultHasher;
-use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::hash::{Hash, Hasher};
-use std::io::{BufRead, BufReader};
+use tileset_comparator::archive::{is_archive, unpack_to_cache};
+use tileset_comparator::artifacts::{create_dated_dir, write_problems};
+use tileset_comparator::atlas::{hash_all_sprites, hash_sprite_indices};
+use tileset_comparator::bundle::{apply_bundle, bundle_report, load_bundle};
+use tileset_comparator::color_search::{self, Color};
+use tileset_comparator::compare::{self, compare};
+use tileset_comparator::compose::compose;
+use tileset_comparator::coverage::{coverage, GameData};
+use tileset_comparator::decompose::decompose;
+use tileset_comparator::dedupe::duplicate_sprites;
+use tileset_comparator::dump_index::read_dumped;
+use tileset_comparator::error::{
+    create_output_dir, write_output, Error, Result, EXIT_INTERNAL, EXIT_OK, EXIT_PROBLEMS_FOUND,
+};
+use tileset_comparator::extract::{extract_tiles, load_ids_file, load_ids_from};
+use tileset_comparator::extracted_diff::diff_extracted;
+use tileset_comparator::field_diff::{field_changes, FieldChange};
+use tileset_comparator::github::{self, ActionInputs};
+use tileset_comparator::hash_algo::HashAlgo;
+use tileset_comparator::history::{
+    by_metric, coverage_metrics, read_history, record_history, render_trends,
+};
+use tileset_comparator::html_report::ReportSide;
+use tileset_comparator::id_filter::IdFilter;
+use tileset_comparator::id_order::IdOrder;
+use tileset_comparator::lint_file::lint_file;
+use tileset_comparator::log::{self, Verbosity};
+use tileset_comparator::merge::merge;
+use tileset_comparator::messages::Lang;
+use tileset_comparator::parallel;
+use tileset_comparator::progress;
+use tileset_comparator::remap::{load_remap, verify_remap};
+use tileset_comparator::rename::{load_id_mapping, rename};
+use tileset_comparator::repack::repack;
+use tileset_comparator::report::{Audience, Reporter};
+use tileset_comparator::reslice::{reslice, SpriteSize};
+use tileset_comparator::review::{self, Answer, ReviewLog, REVIEW_FILE_NAME};
+use tileset_comparator::selftest::selftest;
+use tileset_comparator::source_diff::diff_sources;
+use tileset_comparator::stats::tileset_stats;
+use tileset_comparator::three_way::compare3;
+use tileset_comparator::tool_config::{HashMode, ToolConfig};
+use tileset_comparator::unused::{dump_unused, unused_sprites};
+use tileset_comparator::version_info::version_info;
+use tileset_comparator::warnings::{self, Code};
+use tileset_comparator::watch;
+use tileset_comparator::what_if::{replace_sheets, SheetReplacement, WhatIf};
+use tileset_comparator::workspace::{comparison_dir, lock_dirs, WorkspaceOptions};
+use tileset_comparator::{error, info, warning};
+use tileset_comparator::{
+    git_rev, sprite_tag, validate, CompareOptions, HashOptions, SingleTile, Tileset,
+};
+
+use clap::{ArgMatches, CommandFactory, ErrorKind, FromArgMatches, Parser, Subcommand};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields)]
-struct TilesetTileInfo {
-    #[serde(default = "default_pixelscale")]
-    pixelscale: f32,
-    #[serde(default = "default_retract_dist_min")]
-    retract_dist_min: f32,
-    #[serde(default = "default_retract_dist_max")]
-    retract_dist_max: f32,
-    #[serde(default)]
-    iso: bool,
-    width: u32,
-    height: u32,
+use std::time::Duration;
+
+/// Ids of all tiles referencing each sprite index through fg or bg.
+fn sprite_users(vars: &[SingleTile]) -> HashMap<u32, Vec<&str>> {
+    let mut ret: HashMap<u32, Vec<&str>> = HashMap::new();
+    for var in vars {
+        for spidw in var.variations() {
+            for sprite in spidw.indices() {
+                let users = ret.entry(sprite).or_default();
+                if users.last() != Some(&var.id.0[0].as_str()) {
+                    users.push(var.id.0[0].as_str());
+                }
+            }
+        }
+    }
+    ret
 }
 
-fn default_pixelscale() -> f32 {
-    1.0
-}
+fn find_sprite(ts: &Tileset, query: &str, opts: &HashOptions) -> Result<()> {
+    let (vars, atlases) = ts.generate_variations(false, opts)?;
+    let hashes = hash_all_sprites(&atlases, opts);
+    let tag_len = sprite_tag::unique_tag_len(hashes.iter().map(|x| &x.1), opts.hash_bits);
+    let matches = sprite_tag::resolve(query, hashes.iter().map(|x| &x.1), opts.hash_bits);
 
-fn default_retract_dist_min() -> f32 {
-    -1.0
-}
+    if matches.is_empty() {
+        println!("No sprite matches '{}'.", query);
+        return Ok(());
+    }
+    if matches.len() > 1 {
+        println!("'{}' is ambiguous, {} sprites match:", query, matches.len());
+    }
 
-fn default_retract_dist_max() -> f32 {
-    1.0
-}
+    let users = sprite_users(&vars);
+    for hash in matches {
+        println!(
+            "Sprite {}:",
+            sprite_tag::short_tag(hash, tag_len, opts.hash_bits)
+        );
+        for (idx, _) in hashes.iter().filter(|x| x.1 == hash) {
+            let used_by = users.get(idx).map(|x| x.join(", ")).unwrap_or_default();
+            println!("  index {:6}  used by: {}", idx, used_by);
+        }
+    }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields)]
-struct OverlayOrderElem {
-    id: SingleOrVec<String>,
-    order: i32,
+    Ok(())
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, Ord, PartialOrd, Eq, PartialEq, Hash)]
-#[serde(deny_unknown_fields)]
-struct SingleTile {
-    id: SingleOrVec<String>,
-    #[serde(default)]
-    fg: SingleOrVec<SpriteIdWithWeight>,
-    #[serde(default)]
-    bg: SingleOrVec<SpriteIdWithWeight>,
-    rotates: Option<bool>,
-    #[serde(default)]
-    multitile: bool,
-    #[serde(default)]
-    animated: bool,
-    #[serde(default)]
-    height_3d: i32,
-}
+fn find_color(ts: &Tileset, color: Color, tolerance: u8) -> Result<()> {
+    let (vars, atlases) = ts.generate_variations(false, &HashOptions::default())?;
+    let matches = color_search::find_color(&atlases, color, tolerance);
+
+    if matches.is_empty() {
+        println!("No sprite contains the color.");
+        return Ok(());
+    }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields)]
-struct CompositeTile {
-    #[serde(flatten)]
-    base: SingleTile,
-    #[serde(default)]
-    additional_tiles: Vec<SingleTile>,
-    // Comments
-    #[serde(default, rename = "//")]
-    _comment: String,
+    let users = sprite_users(&vars);
+    for (idx, count) in &matches {
+        let used_by = users.get(idx).map(|x| x.join(", ")).unwrap_or_default();
+        println!(
+            "  index {:6}  pixels: {:5}  used by: {}",
+            idx, count, used_by
+        );
+    }
+    println!("Found {} sprite(s).", matches.len());
+
+    Ok(())
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields)]
-struct SingleAscii {
-    offset: i32,
-    bold: bool,
-    color: String,
+fn find_duplicates(ts: &Tileset, opts: &HashOptions) -> Result<usize> {
+    let (vars, atlases) = ts.generate_variations(false, opts)?;
+    let groups = duplicate_sprites(&atlases, opts);
+
+    let users = sprite_users(&vars);
+    for group in &groups {
+        println!("{} identical sprites:", group.len());
+        for idx in group {
+            let used_by = users.get(idx).map(|x| x.join(", ")).unwrap_or_default();
+            println!("  index {:6}  used by: {}", idx, used_by);
+        }
+    }
+
+    Ok(groups.len())
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields)]
-struct TilesNew {
-    file: String,
-    sprite_width: Option<u32>,
-    sprite_height: Option<u32>,
-    sprite_offset_x: Option<i32>,
-    sprite_offset_y: Option<i32>,
-    tiles: Vec<CompositeTile>,
-    #[serde(default)]
-    ascii: Vec<SingleAscii>,
-    // Comments
-    #[serde(default, rename = "//")]
-    _comment: String,
+#[derive(Parser)]
+#[clap(version, arg_required_else_help = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Commands>,
+    /// Print the version along with the supported config layouts, output
+    /// format versions, sprite hash version and enabled features
+    #[clap(long)]
+    version_info: bool,
+    /// Suppress warnings with the given code or name (e.g. W001 or atlas-indivisible)
+    #[clap(long, global = true, use_value_delimiter = true)]
+    allow: Vec<Code>,
+    /// Accept tile_config.json fields unknown to this tool, comparing them as
+    /// opaque values
+    #[clap(long, global = true)]
+    compat: bool,
+    /// Worker threads for hashing sprites, 0 for one per CPU core.
+    /// Outputs are the same for any value.
+    #[clap(long, global = true, default_value = "0")]
+    jobs: usize,
+    /// Language of the verdict, HTML report and badge; id lists and JSON
+    /// outputs are the same in every language
+    #[clap(long, global = true, arg_enum, default_value = "en")]
+    lang: Lang,
+    /// Read default options from this file instead of from
+    /// tileset-comparator.toml in the working directory
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+    /// Write generated files into per-tileset subfolders of this directory
+    /// instead of, for compare and review, comparison-<A>-vs-<B>/ in the
+    /// working directory, or else the tileset directories; `all` creates its
+    /// dated directory here instead of in --artifacts
+    #[clap(long, global = true)]
+    output_dir: Option<PathBuf>,
+    /// Print only results, warnings and errors
+    #[clap(short, long, global = true)]
+    quiet: bool,
+    /// Also print every atlas as it's loaded; twice to also print timings
+    /// and hash cache lookups
+    #[clap(
+        short,
+        long,
+        global = true,
+        parse(from_occurrences),
+        conflicts_with = "quiet"
+    )]
+    verbose: u64,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields)]
-struct Tileset {
-    #[serde(skip_deserializing)]
-    base_path: PathBuf,
-    tile_info: Vec<TilesetTileInfo>,
-    #[serde(rename = "tiles-new")]
-    tiles_new: Vec<TilesNew>,
-    #[serde(default)]
-    overlay_ordering: Vec<OverlayOrderElem>,
+// Parsed once, so the size of the compare options doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+enum Commands {
+    /// Compare two tilesets by sprite content and report the ids that were
+    /// added, removed or changed
+    Compare {
+        /// Tileset A, or a revision of the repository given with --git, or
+        /// `.` for its working tree
+        a: String,
+        /// Tileset B, or a revision of the repository given with --git, or
+        /// `.` for its working tree
+        b: String,
+        /// Read both tilesets from this git repository as of revisions A and
+        /// B, e.g. `main` and `HEAD`, without checking them out. `.` as A or
+        /// B stands for the working tree, e.g. `main .` compares uncommitted
+        /// changes against main
+        #[clap(long, requires = "path", conflicts_with = "watch")]
+        git: Option<String>,
+        /// Directory of the tileset in the repository given with --git
+        #[clap(long, requires = "git")]
+        path: Option<String>,
+        #[clap(flatten)]
+        hash_opts: HashOptions,
+        /// Write an SVG badge summarizing the number of changed tiles
+        #[clap(long)]
+        badge: Option<String>,
+        /// Write a Markdown summary with tables of added, removed and changed
+        /// ids, for pasting into a pull request comment
+        #[clap(long)]
+        markdown: Option<String>,
+        /// Show sprites in the Markdown summary, linked through this
+        /// template, e.g. `https://example.org/run/12/{side}/sprites/{sprite}.png`
+        /// with `{side}` a or b, for sprites dumped by this run with
+        /// --dump-sprites and uploaded
+        #[clap(long, requires = "markdown")]
+        markdown_images: Option<String>,
+        /// Append tile counts and diff counts to this CSV history
+        #[clap(long)]
+        history: Option<String>,
+        /// List every changed id in different.txt, even those of entries
+        /// with many ids that all changed the same way
+        #[clap(long)]
+        expand_groups: bool,
+        /// Write an HTML report showing the sprites of every changed id from both tilesets
+        #[clap(long)]
+        html: Option<String>,
+        /// Write a heatmap of per-pixel changes for every sprite changed in place
+        /// into this directory, along with the sprite before and after and a
+        /// diff image marking changed pixels in magenta
+        #[clap(long)]
+        heatmaps: Option<String>,
+        /// Write one PNG with the id, tileset A art and tileset B art of every
+        /// changed id side by side
+        #[clap(long)]
+        sheet: Option<String>,
+        /// Write a copy of tileset B into this directory with the sprites of
+        /// changed ids tinted magenta and of added ids green; put it in the
+        /// game's gfx folder to review the changes in game
+        #[clap(long)]
+        highlight_tileset: Option<String>,
+        /// Write every sprite of both tilesets into `sprites/` of their
+        /// output directories, named by index. Existing directories are
+        /// written into, never emptied.
+        #[clap(long)]
+        dump_sprites: bool,
+        /// With --dump-sprites, write each distinct sprite of both tilesets
+        /// once into `sprite-store/` in A's output directory, with both
+        /// `sprites/` directories hard linked to it
+        #[clap(long, requires = "dump-sprites")]
+        shared_sprites: bool,
+        /// Sort ids in outputs byte-wise instead of naturally (`t_wall_10` before `t_wall_2`)
+        #[clap(long)]
+        bytewise_sort: bool,
+        /// Treat ids as changed only if the sprites they draw changed,
+        /// ignoring weights and every other field, e.g. for generated
+        /// tilesets; overlay ordering, subtiles and properties are not diffed
+        #[clap(long)]
+        pixels_only: bool,
+        /// Decode and hash one sheet at a time, keeping only the sprites of
+        /// ids that differ, for tilesets too large to hold decoded at once.
+        /// Slower, and --dump-sprites only writes the sprites of those ids.
+        #[clap(long, conflicts_with_all = &["highlight-tileset", "shared-sprites"])]
+        low_memory: bool,
+        /// Keep sprite hashes of every sheet in .tsc-cache/ of the working
+        /// directory, and skip decoding sheets hashed by an earlier run.
+        /// Needs a --hash-algo other than legacy. Like --low-memory,
+        /// --dump-sprites only writes the sprites of ids that differ.
+        #[clap(long, conflicts_with_all = &["highlight-tileset", "shared-sprites"])]
+        hash_cache: bool,
+        /// Only compare ids matching this glob, or this regex if prefixed
+        /// with `re:`. Sprites of other ids are not hashed.
+        #[clap(long)]
+        filter: Option<IdFilter>,
+        /// Who the outputs are for: artists get pictures, CI gets id lists and
+        /// an exit status, maintainers get everything
+        #[clap(long, arg_enum, default_value = "maintainer")]
+        audience: Audience,
+        /// If tile sizes differ by a whole factor, e.g. 32x32 and 64x64
+        /// variants of one tileset, shrink the larger sprites with
+        /// nearest-neighbor sampling to compare content
+        #[clap(long)]
+        normalize_resolution: bool,
+        /// Also run the comparison on a single thread, and exit with status 3
+        /// if any output differs from the parallel run
+        #[clap(long)]
+        self_check: bool,
+        /// Compare in memory and print the verdict instead of writing any
+        /// file, for read-only tileset directories
+        #[clap(
+            long,
+            conflicts_with_all = &[
+                "badge",
+                "markdown",
+                "history",
+                "html",
+                "heatmaps",
+                "sheet",
+                "highlight-tileset",
+                "workspace"
+            ]
+        )]
+        read_only: bool,
+        /// Exit with status 1 unless the tilesets are identical, for CI
+        /// gating with any audience. Errors exit with 2, or 3 for failures
+        /// unrelated to the input.
+        #[clap(long)]
+        check: bool,
+        /// Keep running, and compare again whenever tile_config.json,
+        /// comparator.toml or a sheet image of either tileset changes
+        #[clap(long)]
+        watch: bool,
+        #[clap(flatten)]
+        workspace: WorkspaceOptions,
+    },
+    /// Classify every id of two forks of a base tileset as changed in ours,
+    /// in theirs, in both, or conflicting
+    Compare3 {
+        base: String,
+        ours: String,
+        theirs: String,
+        #[clap(flatten)]
+        hash_opts: HashOptions,
+        /// Sort ids in outputs byte-wise instead of naturally
+        #[clap(long)]
+        bytewise_sort: bool,
+    },
+    /// Walk the changed, removed and added ids of a comparison, previewing
+    /// their sprites before and after, and record an accept, reject or
+    /// needs-work decision and a note for each into review.json in A's
+    /// output directory, which the HTML report of later comparisons shows
+    Review {
+        a: String,
+        b: String,
+        #[clap(flatten)]
+        hash_opts: HashOptions,
+        /// Ask again about ids decided in an earlier review
+        #[clap(long)]
+        redo: bool,
+        /// Don't draw sprites in the terminal, e.g. if it lacks 24-bit color
+        #[clap(long)]
+        no_preview: bool,
+        #[clap(flatten)]
+        workspace: WorkspaceOptions,
+    },
+    /// Write the hashed definitions and sprites of the given ids into the
+    /// output directory, one subdirectory per id
+    Extract {
+        tileset: String,
+        /// File with one id per line; if omitted along with --id and
+        /// --ids-from, every id passing --filter
+        #[clap(required_unless_present_any = &["filter", "id", "ids-from", "all"])]
+        ids_file: Option<String>,
+        /// Extract every id of the tileset, or every id passing --filter
+        #[clap(long, conflicts_with_all = &["ids-file", "id", "ids-from"])]
+        all: bool,
+        /// Id to extract, may be repeated
+        #[clap(long)]
+        id: Vec<String>,
+        /// Read ids one per line from this file, or from standard input if `-`
+        #[clap(long)]
+        ids_from: Option<String>,
+        /// Only extract ids matching this glob, or this regex if prefixed
+        /// with `re:`
+        #[clap(long)]
+        filter: Option<IdFilter>,
+        #[clap(flatten)]
+        hash_opts: HashOptions,
+        #[clap(flatten)]
+        workspace: WorkspaceOptions,
+    },
+    /// List sprite indices with the given hash, either as a decimal number of
+    /// at least 10 digits or as a (short) base32 tag, and the tiles using them
+    FindSprite {
+        tileset: String,
+        hash: String,
+        #[clap(flatten)]
+        hash_opts: HashOptions,
+    },
+    /// List sprite indices containing a color, e.g. magic pink placeholder
+    /// pixels, and the tiles using them
+    FindColor {
+        tileset: String,
+        /// `#rrggbb`
+        color: Color,
+        /// Largest difference per channel still counted as the color
+        #[clap(long, default_value = "0")]
+        tolerance: u8,
+    },
+    /// List groups of identical sprites at different indices and the tiles
+    /// using each copy. Fully transparent sprites are not listed.
+    Dedupe {
+        tileset: String,
+        #[clap(flatten)]
+        hash_opts: HashOptions,
+    },
+    /// Check that an old->new sprite index mapping, e.g. from a sheet
+    /// repacking script, maps every sprite to identical art
+    VerifyRemap {
+        old: String,
+        new: String,
+        /// JSON object of old index to new index, e.g. {"12": 40}
+        #[clap(long)]
+        map: String,
+        #[clap(flatten)]
+        hash_opts: HashOptions,
+    },
+    /// Cross-reference the terrain, furniture, items and monsters of the
+    /// game's data/json with the tileset: which have tiles, which fall back
+    /// to another tile through looks_like, which are drawn as ASCII, and
+    /// which tileset ids the game no longer has
+    Coverage {
+        tileset: String,
+        game_data: String,
+        /// Append coverage percentages to this CSV history
+        #[clap(long)]
+        history: Option<String>,
+    },
+    /// Summarize the metrics recorded with --history, and optionally chart
+    /// each over time as SVG
+    History {
+        file: String,
+        #[clap(long)]
+        svg: Option<String>,
+    },
+    /// List the ids whose appearance would change if sheet images were
+    /// replaced, without modifying anything
+    WhatIf {
+        tileset: String,
+        /// `<sheet file>=<new image>`, may be repeated
+        #[clap(long = "replace-sheet", required = true)]
+        replacements: Vec<SheetReplacement>,
+        /// Print only a JSON object with the replaced sheets and the changed ids
+        #[clap(long)]
+        json: bool,
+        #[clap(flatten)]
+        hash_opts: HashOptions,
+    },
+    /// Print size metrics of a tileset: tiles-new entries, ids, multitile
+    /// and animated ids, and per atlas its sprites, how many are filled and
+    /// how many no tile references
+    Stats {
+        tileset: String,
+        /// Print only the metrics as a JSON object
+        #[clap(long)]
+        json: bool,
+    },
+    /// List sprite indices, per atlas, that no tile references
+    Unused {
+        tileset: String,
+        /// Also write every unused sprite into this directory as <index>.png
+        #[clap(long)]
+        thumbnails: Option<String>,
+    },
+    /// Check a single tileset for duplicate ids, out-of-range sprites,
+    /// unevenly divided atlases, empty entries and unused files
+    Validate {
+        tileset: String,
+        #[clap(flatten)]
+        hash_opts: HashOptions,
+    },
+    /// Run the lints that need no images on a single tile_config.json,
+    /// printing `file:line: code message` lines, e.g. for pre-commit hooks
+    LintFile { file: String },
+    /// Report what a contribution bundle, a folder of PNGs named after tile
+    /// ids, would change in a tileset: updated and new ids and overwritten
+    /// sprites. With --apply, merge it in, each PNG becoming the only
+    /// foreground sprite of its id.
+    ImportBundle {
+        tileset: String,
+        bundle: String,
+        #[clap(long)]
+        apply: bool,
+        /// Seconds to wait for another invocation's lock on the tileset before giving up
+        #[clap(long, default_value = "600")]
+        lock_timeout: u64,
+    },
+    /// Copy tile entries from tileset B into tileset A, appending their
+    /// sprites to A's atlases and rewriting A's tile_config.json
+    Merge {
+        a: String,
+        b: String,
+        /// Id or glob pattern (`*`, `?`) of entries to copy, may be repeated
+        #[clap(long = "id")]
+        ids: Vec<String>,
+        /// File with one id or glob pattern per line
+        #[clap(long)]
+        ids_file: Option<String>,
+        #[clap(flatten)]
+        hash_opts: HashOptions,
+        /// Seconds to wait for another invocation's lock on A before giving up
+        #[clap(long, default_value = "600")]
+        lock_timeout: u64,
+    },
+    /// Rename ids in tile entries and overlay_ordering of tile_config.json
+    /// following an old->new mapping, e.g. after the game renamed them
+    Rename {
+        tileset: String,
+        /// JSON object of old to new ids, or CSV lines of `old,new` unless
+        /// the file name ends in `.json`
+        mapping: String,
+        /// Write a renamed copy of the tileset into this directory instead
+        /// of rewriting it in place
+        #[clap(long)]
+        output: Option<String>,
+        /// Seconds to wait for another invocation's lock on the tileset
+        /// before giving up
+        #[clap(long, default_value = "600")]
+        lock_timeout: u64,
+    },
+    /// Rebuild every sheet from only the sprites tile entries reference,
+    /// removing unused slots and rewriting sprite indices to match
+    Repack {
+        tileset: String,
+        /// Seconds to wait for another invocation's lock on the tileset
+        /// before giving up
+        #[clap(long, default_value = "600")]
+        lock_timeout: u64,
+    },
+    /// Re-cut every sheet into sprites of a new size, padding or cropping
+    /// sprites around their bottom center and adjusting sprite offsets so
+    /// they are drawn where they were, and renumber sprites of later sheets
+    Reslice {
+        tileset: String,
+        /// New sprite size, e.g. 32x32
+        size: SpriteSize,
+        /// Seconds to wait for another invocation's lock on the tileset
+        /// before giving up
+        #[clap(long, default_value = "600")]
+        lock_timeout: u64,
+    },
+    /// Build tile_config.json and packed atlases from a compose.py-style
+    /// source tree of individual sprites and tile entries
+    Compose { source: String, output: String },
+    /// Explode a packed tileset into a compose.py-style source tree of
+    /// individual sprites and tile entries
+    Decompose { tileset: String, output: String },
+    /// List files renamed, moved, edited, added or removed between two
+    /// compose.py-style source trees, including moved folders
+    CompareSources { a: String, b: String },
+    /// Diff two directories written by extract, without the tilesets they
+    /// were extracted from
+    CompareExtracted { a: String, b: String },
+    /// Print the dumped definition of one id from an output directory
+    /// holding dump.json
+    Lookup { dir: String, id: String },
+    /// Describe a warning code and typical fixes, or list all codes
+    Explain { code: Option<Code> },
+    /// Run validate, stats, unused, dedupe, coverage, compare, extract,
+    /// decompose, compose, merge, rename, repack and reslice on a built-in
+    /// miniature tileset and check every output against the expected one,
+    /// to verify this build produces correct results
+    Selftest {
+        /// Keep the fixture tilesets and outputs in this directory, which
+        /// must be new or empty, instead of a temporary one
+        #[clap(long)]
+        dir: Option<String>,
+    },
+    /// Validate a tileset, count its statistics and coverage, compare it
+    /// against a baseline, and write every report into a directory named
+    /// after today's date
+    All {
+        tileset: String,
+        /// Tileset to compare against, e.g. the last release
+        #[clap(long)]
+        baseline: Option<String>,
+        /// Game data directory to report the tileset's coverage of
+        #[clap(long)]
+        game_data: Option<String>,
+        /// Directory to create the dated artifacts directory in
+        #[clap(long, default_value = "artifacts")]
+        artifacts: String,
+        #[clap(flatten)]
+        hash_opts: HashOptions,
+        /// Sort ids in outputs byte-wise instead of naturally
+        #[clap(long)]
+        bytewise_sort: bool,
+        /// Who the outputs are for: artist, maintainer or ci
+        #[clap(long, arg_enum, default_value = "maintainer")]
+        audience: Audience,
+    },
+    /// Check a tileset as a GitHub Actions step: reads the tileset from
+    /// INPUT_TILESET and an optional baseline to compare against from
+    /// INPUT_BASELINE, annotates lints, writes a Markdown summary to
+    /// GITHUB_STEP_SUMMARY and sets lint and comparison counts as step
+    /// outputs
+    Ci,
 }
 
-fn load_tileset(base_path: &Path) -> Option<Tileset> {
-    assert!(base_path.exists());
-    assert!(base_path.is_dir());
+/// Runs the selected command, returning the process exit code on success.
+fn run(cli: &Cli) -> Result<i32> {
+    warnings::allow(&cli.allow);
+    parallel::set_jobs(cli.jobs)?;
+    let verbosity = Verbosity::from_flags(cli.quiet, cli.verbose);
+    log::set_verbosity(verbosity);
+    if verbosity == Verbosity::Quiet {
+        progress::disable();
+    }
 
-    let base_tile_config = base_path.join("tile_config.json");
+    let load = |path: &Path| {
+        if cli.compat {
+            Tileset::load_compat(path)
+        } else {
+            Tileset::load(path)
+        }
+    };
+    // Compare and extract also take zipped tilesets, unpacked first
+    let load_input = |path: &Path| {
+        if !is_archive(path) {
+            return load(path);
+        }
+        let dir = unpack_to_cache(path)?;
+        info!(
+            "NOTE: unpacked '{}' into {}",
+            path.display(),
+            dir.to_string_lossy()
+        );
+        load(&dir)
+    };
 
-    assert!(base_tile_config.exists());
+    if cli.version_info {
+        println!("{}", version_info());
+        return Ok(EXIT_OK);
+    }
+    let command = match &cli.command {
+        Some(command) => command,
+        None => Cli::command()
+            .error(ErrorKind::MissingSubcommand, "a subcommand is required")
+            .exit(),
+    };
 
-    let tile_config_data = std::fs::read_to_string(base_tile_config).unwrap();
+    match command {
+        Commands::Compare {
+            a,
+            b,
+            git,
+            path,
+            hash_opts,
+            badge,
+            markdown,
+            markdown_images,
+            history,
+            expand_groups,
+            html,
+            heatmaps,
+            sheet,
+            highlight_tileset,
+            dump_sprites,
+            shared_sprites,
+            bytewise_sort,
+            pixels_only,
+            low_memory,
+            hash_cache,
+            filter,
+            audience,
+            normalize_resolution,
+            self_check,
+            read_only,
+            check,
+            watch,
+            workspace,
+        } => {
+            let reporter = Reporter {
+                audience: *audience,
+                html: html.as_ref().map(PathBuf::from),
+                heatmaps: heatmaps.as_ref().map(PathBuf::from),
+                sheet: sheet.as_ref().map(PathBuf::from),
+                badge: badge.as_ref().map(PathBuf::from),
+                markdown: markdown.as_ref().map(PathBuf::from),
+                markdown_images: markdown_images.clone(),
+                highlight: highlight_tileset.as_ref().map(PathBuf::from),
+                dump_sprites: *dump_sprites,
+                shared_sprites: *shared_sprites,
+                history: history.as_ref().map(PathBuf::from),
+                expand_groups: *expand_groups,
+                read_only: *read_only,
+                check: *check,
+                lang: cli.lang,
+            };
+            if *audience == Audience::Ci {
+                progress::disable();
+            }
+            reporter.say("Tileset comparison mode.");
+            // Not a clap requirement, since dump_sprites may come from the config
+            if reporter.markdown_images.is_some() && !reporter.dump_sprites {
+                warning!("--markdown-images is given without --dump-sprites, so the sprites it links to are not written");
+            }
 
-    let mut tileset: Tileset = serde_json::from_str(&tile_config_data).unwrap();
-    tileset.base_path = base_path.to_owned();
+            // Revisions are exported to the cache, and loaded from there
+            let load_side = |side: &str| match (git, path) {
+                (Some(repo), Some(path)) if side == git_rev::WORKTREE => {
+                    load(&git_rev::worktree_dir(Path::new(repo), path))
+                }
+                (Some(repo), Some(path)) => {
+                    let dir = git_rev::export_to_cache(Path::new(repo), side, path)?;
+                    reporter.say(format!(
+                        "NOTE: exported '{}' at {} into {}",
+                        path,
+                        side,
+                        dir.to_string_lossy()
+                    ));
+                    load(&dir)
+                }
+                _ => load_input(Path::new(side)),
+            };
+            let compare_once = || -> Result<i32> {
+                reporter.say(format!("Loading tileset A:  {}", a));
+                let mut tiles_a = load_side(a)?;
+
+                reporter.say(format!("Loading tileset B: {}", b));
+                let mut tiles_b = load_side(b)?;
+                tiles_a.filter = filter.clone();
+                tiles_b.filter = filter.clone();
+                let resized = *normalize_resolution
+                    && match compare::match_resolutions(&mut tiles_a, &mut tiles_b) {
+                        Some(note) => {
+                            reporter.say(note);
+                            tiles_a.downscale.is_some() || tiles_b.downscale.is_some()
+                        }
+                        None => false,
+                    };
+                // Pixelscales of variants of different resolutions differ too
+                if !resized {
+                    if let Some(note) = compare::match_pixelscales(&mut tiles_a, &mut tiles_b) {
+                        reporter.say(note);
+                    }
+                }
 
-    Some(tileset)
-}
+                // Held until the outputs are written
+                let _ws = if *read_only {
+                    None
+                } else {
+                    // Named after the working tree rather than the current directory
+                    let name = |x: &String| match git {
+                        Some(_) if x == git_rev::WORKTREE => "worktree".to_string(),
+                        _ => x.clone(),
+                    };
+                    let output_dir = cli
+                        .output_dir
+                        .clone()
+                        .unwrap_or_else(|| comparison_dir(&name(a), &name(b)));
+                    if cli.output_dir.is_none() {
+                        reporter.say(format!("Writing outputs to {}", output_dir.display()));
+                    }
+                    let ws = workspace
+                        .prepare(&[&tiles_a.base_path, &tiles_b.base_path], Some(&output_dir))?;
+                    tiles_a.out_path = ws.dirs[0].clone();
+                    tiles_b.out_path = ws.dirs[1].clone();
+                    Some(ws)
+                };
+
+                let opts = CompareOptions {
+                    hash: *hash_opts,
+                    order: if *bytewise_sort {
+                        IdOrder::Bytewise
+                    } else {
+                        IdOrder::Natural
+                    },
+                    pixels_only: *pixels_only,
+                    low_memory: *low_memory,
+                    hash_cache: *hash_cache && hash_opts.hash_algo != HashAlgo::Legacy,
+                };
+                if *hash_cache && !opts.hash_cache {
+                    info!("NOTE: legacy sprite hashes may change between builds, so they are not cached; choose another --hash-algo.");
+                }
+                if let Some(note) = compare::collision_note(&opts) {
+                    reporter.say(note);
+                }
 
-struct TileAtlas {
-    img: RgbaImage,
-    sprite_w: u32,
-    sprite_h: u32,
-    tiles_x: u32,
-    tiles_y: u32,
-    tiles_start: u32,
-    tiles_end: u32,
-}
+                // Known without decoding anything, so reported before the slow part
+                let early = compare::early_diff(&tiles_a, &tiles_b, &opts);
+                reporter.say(format!(
+                    "From tile configs: {} id(s) only in A, {} only in B, {} with changed properties.",
+                    early.only_in_a.len(),
+                    early.only_in_b.len(),
+                    early.changed_properties.len()
+                ));
+                tiles_a.priority_sheets = early.sheets_a;
+                tiles_b.priority_sheets = early.sheets_b;
+
+                reporter.say("Running comparison...");
+
+                let mut exit_code = EXIT_OK;
+                let result = if *self_check {
+                    let (result, differing) = compare::self_check(&tiles_a, &tiles_b, &opts)?;
+                    if differing.is_empty() {
+                        reporter.say("Self-check passed: outputs do not depend on thread count.");
+                    } else {
+                        error!(
+                            "self-check failed, single- and multi-threaded runs differ in: {}",
+                            differing.join(", ")
+                        );
+                        exit_code = EXIT_INTERNAL;
+                    }
+                    result
+                } else {
+                    compare(&tiles_a, &tiles_b, &opts)?
+                };
+                if !result.similar.is_empty() {
+                    reporter.say(format!(
+                        "{} changed tile(s) differ only by similar sprites and are treated as equal.",
+                        result.similar.len()
+                    ));
+                }
 
-impl TileAtlas {
-    pub fn tiles_total(&self) -> u32 {
-        self.tiles_x * self.tiles_y
-    }
+                if !result.ascii_changes.is_empty() {
+                    reporter.say(format!(
+                        "{} fallback glyph set(s) changed.",
+                        result.ascii_changes.len()
+                    ));
+                }
+                if !result.ordering_changes.is_empty() {
+                    reporter.say(format!(
+                        "{} overlay ordering id(s) changed.",
+                        result.ordering_changes.len()
+                    ));
+                }
+                if !result.subtile_changes.is_empty() {
+                    reporter.say(format!(
+                        "{} multitile(s) gained or lost subtiles.",
+                        result.subtile_changes.len()
+                    ));
+                }
 
-    pub fn in_bounds(&self, tile_id: u32) -> bool {
-        tile_id >= self.tiles_start && tile_id < self.tiles_end
-    }
+                reporter.write(&result, &tiles_a, &tiles_b, &opts)?;
+                if exit_code != EXIT_OK {
+                    return Ok(exit_code);
+                }
+                Ok(reporter.exit_code(&result))
+            };
+            if !*watch {
+                return compare_once();
+            }
 
-    fn get_sprite(&self, tile_id: u32) -> SubImage<&RgbaImage> {
-        let id_within_atlas = tile_id - self.tiles_start;
-        let within_x = id_within_atlas % self.tiles_x;
-        let within_y = id_within_atlas / self.tiles_x;
-        self.img.view(
-            within_x * self.sprite_w,
-            within_y * self.sprite_h,
-            self.sprite_w,
-            self.sprite_h,
-        )
-    }
+            loop {
+                // Errors, e.g. from a half-saved config, don't end watching
+                if let Err(e) = compare_once() {
+                    error!("{}", e);
+                }
+                warnings::clear_emitted();
+                // Sheets may have been added or removed
+                let files: Vec<PathBuf> = [a, b]
+                    .iter()
+                    .flat_map(|x| watch::watched_files(Path::new(x)))
+                    .collect();
+                reporter.say("Watching for changes, press Ctrl+C to stop.");
+                watch::wait_for_change(&files);
+                reporter.say("Change detected, comparing again.");
+            }
+        }
+        Commands::Compare3 {
+            base,
+            ours,
+            theirs,
+            hash_opts,
+            bytewise_sort,
+        } => {
+            info!("Three-way comparison mode.");
+
+            info!("Loading base:   {}", base);
+            let tiles_base = load(Path::new(base))?;
+            info!("Loading ours:   {}", ours);
+            let tiles_ours = load(Path::new(ours))?;
+            info!("Loading theirs: {}", theirs);
+            let tiles_theirs = load(Path::new(theirs))?;
+
+            let opts = CompareOptions {
+                hash: *hash_opts,
+                order: if *bytewise_sort {
+                    IdOrder::Bytewise
+                } else {
+                    IdOrder::Natural
+                },
+                pixels_only: false,
+                low_memory: false,
+                hash_cache: false,
+            };
+            let result = compare3(&tiles_base, &tiles_ours, &tiles_theirs, &opts)?;
+            for (title, ids) in [
+                ("Changed in ours", &result.ours),
+                ("Changed in theirs", &result.theirs),
+                ("Changed in both, identically", &result.both),
+                ("Conflicts", &result.conflicts),
+            ] {
+                if !ids.is_empty() {
+                    println!("{} ({}):", title, ids.len());
+                    for id in ids {
+                        println!("  {}", id);
+                    }
+                }
+            }
+            println!("Unchanged: {}", result.unchanged);
+            if !result.conflicts.is_empty() {
+                return Ok(EXIT_PROBLEMS_FOUND);
+            }
+        }
+        Commands::Review {
+            a,
+            b,
+            hash_opts,
+            redo,
+            no_preview,
+            workspace,
+        } => {
+            info!("Tileset review mode.");
+
+            info!("Loading tileset A:  {}", a);
+            let mut tiles_a = load(Path::new(a))?;
+            info!("Loading tileset B: {}", b);
+            let mut tiles_b = load(Path::new(b))?;
+            if let Some(note) = compare::match_pixelscales(&mut tiles_a, &mut tiles_b) {
+                info!("{}", note);
+            }
+            let output_dir = cli
+                .output_dir
+                .clone()
+                .unwrap_or_else(|| comparison_dir(a, b));
+            let ws =
+                workspace.prepare(&[&tiles_a.base_path, &tiles_b.base_path], Some(&output_dir))?;
+            tiles_a.out_path = ws.dirs[0].clone();
+            tiles_b.out_path = ws.dirs[1].clone();
+
+            info!("Running comparison...");
+            let opts = CompareOptions {
+                hash: *hash_opts,
+                order: IdOrder::Natural,
+                pixels_only: false,
+                low_memory: false,
+                hash_cache: false,
+            };
+            if let Some(note) = compare::collision_note(&opts) {
+                info!("{}", note);
+            }
+            let result = compare(&tiles_a, &tiles_b, &opts)?;
+            let raw_a = tiles_a.expand_variations(&result.atlases_a, false, &opts.hash);
+            let raw_b = tiles_b.expand_variations(&result.atlases_b, false, &opts.hash);
+            let side_a = ReportSide::new(&tiles_a, &result.atlases_a, &raw_a);
+            let side_b = ReportSide::new(&tiles_b, &result.atlases_b, &raw_b);
+            let changes: HashMap<&str, Vec<FieldChange>> = result
+                .changed_pairs()
+                .into_iter()
+                .map(|(x, y)| (x.id.0[0].as_str(), field_changes(x, y, opts.hash.hash_bits)))
+                .collect();
+
+            let mut log = ReviewLog::load(&tiles_a.out_path)?;
+            let pending: Vec<(&str, &String)> = [
+                ("changed", result.changed.as_deref().unwrap_or_default()),
+                ("removed", &result.only_in_a[..]),
+                ("added", &result.only_in_b[..]),
+            ]
+            .into_iter()
+            .flat_map(|(kind, ids)| ids.iter().map(move |id| (kind, id)))
+            .filter(|(_, id)| *redo || log.get(id).is_none())
+            .collect();
+            println!(
+                "{} id(s) to review, {} decided earlier.",
+                pending.len(),
+                log.decisions.len()
+            );
 
-    pub fn get_sprite_hash(&self, tile_id: u32) -> u32 {
-        if !self.in_bounds(tile_id) {
-            eprintln!(
-                "WARNING: tile {} outside active atlas range {}..{}",
-                tile_id, self.tiles_start, self.tiles_end
+            let stdin = std::io::stdin();
+            let mut input = stdin.lock();
+            let mut stdout = std::io::stdout();
+            let mut reviewed = 0;
+            for (k, (kind, id)) in pending.iter().enumerate() {
+                println!("\n[{}/{}] {}: {}", k + 1, pending.len(), kind, id);
+                for change in changes.get(id.as_str()).into_iter().flatten() {
+                    println!("  {}: {} -> {}", change.field, change.before, change.after);
+                }
+                if !*no_preview {
+                    print!("{}", review::preview(&side_a, &side_b, id));
+                }
+                let read_err = |source| Error::ReadInput {
+                    path: PathBuf::from("<stdin>"),
+                    source,
+                };
+                match review::ask(&mut input, &mut stdout).map_err(read_err)? {
+                    Answer::Decided(entry) => {
+                        log.record(id, entry);
+                        // Saved as we go, so quitting keeps what was decided
+                        log.save()?;
+                        reviewed += 1;
+                    }
+                    Answer::Skip => {}
+                    Answer::Quit => break,
+                }
+            }
+            println!(
+                "\nRecorded {} decision(s) into {}",
+                reviewed,
+                tiles_a.out_path.join(REVIEW_FILE_NAME).display()
             );
-            return 0;
         }
+        Commands::Extract {
+            tileset,
+            ids_file,
+            id,
+            ids_from,
+            all: _,
+            filter,
+            hash_opts,
+            workspace,
+        } => {
+            info!("Tile extraction mode.");
+
+            info!("Loading tileset:  {}", tileset);
+            let mut tiles = load_input(Path::new(tileset))?;
+            tiles.filter = filter.clone();
+
+            let ids = if ids_file.is_some() || ids_from.is_some() || !id.is_empty() {
+                let mut ids = vec![];
+                if let Some(ids_file) = ids_file {
+                    info!("Loading ids file: {}", ids_file);
+                    ids.extend(load_ids_file(Path::new(ids_file))?);
+                }
+                if let Some(source) = ids_from {
+                    info!("Loading ids from: {}", source);
+                    ids.extend(load_ids_from(source)?);
+                }
+                ids.extend(id.iter().cloned());
+                Some(ids)
+            } else {
+                None
+            };
 
-        let subimg = self.get_sprite(tile_id);
+            let ws = workspace.prepare(&[&tiles.base_path], cli.output_dir.as_deref())?;
+            tiles.out_path = ws.dirs[0].clone();
 
-        let mut hasher = DefaultHasher::new();
-        self.sprite_w.hash(&mut hasher);
-        self.sprite_h.hash(&mut hasher);
+            info!("Extracting...");
 
-        for px in subimg.pixels() {
-            px.hash(&mut hasher);
+            extract_tiles(
+                &tiles,
+                ids.as_deref(),
+                &tiles.out_path.join("extracted"),
+                hash_opts,
+            )?;
         }
+        Commands::Merge {
+            a,
+            b,
+            ids,
+            ids_file,
+            hash_opts,
+            lock_timeout,
+        } => {
+            info!("Tileset merge mode.");
+
+            info!("Loading tileset A:  {}", a);
+            let tiles_a = load(Path::new(a))?;
+
+            info!("Loading tileset B: {}", b);
+            let tiles_b = load(Path::new(b))?;
+
+            let mut patterns = ids.clone();
+            if let Some(ids_file) = ids_file {
+                info!("Loading ids file: {}", ids_file);
+                patterns.extend(load_ids_file(Path::new(ids_file))?);
+            }
 
-        // Intended narrowing conversion
-        hasher.finish() as u32
-    }
+            let _ws = lock_dirs(&[&tiles_a.base_path], Duration::from_secs(*lock_timeout))?;
 
-    pub fn dump_sprites_to_dir(&self, base_path: &Path) {
-        for tile_id in self.tiles_start..self.tiles_end {
-            let sprite_path = base_path.join(format!("{}.png", tile_id));
-            let subimg = self.get_sprite(tile_id);
-            subimg
-                .to_image()
-                .save_with_format(&sprite_path, ImageFormat::Png)
-                .unwrap();
+            info!("Merging...");
+            let summary = merge(&tiles_a, &tiles_b, &patterns, hash_opts)?;
+            info!(
+                "Merged {} id(s), {} replacing existing definitions.",
+                summary.merged.len(),
+                summary.replaced.len()
+            );
+            info!(
+                "Copied {} sprite(s), reused {} identical sprite(s).",
+                summary.sprites_copied, summary.sprites_reused
+            );
         }
-    }
-}
+        Commands::Rename {
+            tileset,
+            mapping,
+            output,
+            lock_timeout,
+        } => {
+            info!("Id rename mode.");
+
+            info!("Loading tileset:  {}", tileset);
+            let tiles = load(Path::new(tileset))?;
+
+            info!("Loading mapping: {}", mapping);
+            let map = load_id_mapping(Path::new(mapping))?;
+
+            let _ws = match output {
+                Some(_) => None,
+                None => Some(lock_dirs(
+                    &[&tiles.base_path],
+                    Duration::from_secs(*lock_timeout),
+                )?),
+            };
 
-fn get_sprite_hash(atlases: &[TileAtlas], tile_id: u32) -> u32 {
-    for atlas in atlases {
-        if atlas.in_bounds(tile_id) {
-            return atlas.get_sprite_hash(tile_id);
+            info!("Renaming...");
+            let summary = rename(&tiles, &map, output.as_deref().map(Path::new))?;
+            for id in &summary.missing {
+                tiles.warn(
+                    Code::UnknownId,
+                    None,
+                    format!("failed to find tile with id {}", id),
+                );
+            }
+            info!(
+                "Renamed {} id(s) in tile entries and {} in overlay_ordering.",
+                summary.renamed, summary.ordering
+            );
         }
-    }
-    eprintln!("WARNING: tile {} outside all atlas ranges", tile_id);
-    return 0;
-}
+        Commands::Repack {
+            tileset,
+            lock_timeout,
+        } => {
+            info!("Tileset repack mode.");
+
+            info!("Loading tileset:  {}", tileset);
+            let tiles = load(Path::new(tileset))?;
+
+            let _ws = lock_dirs(&[&tiles.base_path], Duration::from_secs(*lock_timeout))?;
 
-fn hash_sprites(ids: &mut SingleOrVec<SpriteIdWithWeight>, atlases: &[TileAtlas]) {
-    for spidw in &mut ids.0 {
-        for id in &mut spidw.id.0 {
-            *id = get_sprite_hash(atlases, *id);
+            info!("Repacking...");
+            let summary = repack(&tiles)?;
+            for file in &summary.sheets {
+                info!("Repacked '{}'.", file);
+            }
+            for file in &summary.dropped {
+                info!("Removed '{}', which had no tiles or used sprites.", file);
+            }
+            info!(
+                "Repacked {} sheet(s), removed {} unused sprite slot(s).",
+                summary.sheets.len(),
+                summary.removed
+            );
         }
-    }
-}
+        Commands::Reslice {
+            tileset,
+            size,
+            lock_timeout,
+        } => {
+            info!("Tileset reslice mode.");
+
+            info!("Loading tileset:  {}", tileset);
+            let tiles = load(Path::new(tileset))?;
+
+            let _ws = lock_dirs(&[&tiles.base_path], Duration::from_secs(*lock_timeout))?;
+
+            info!("Reslicing into {}x{} sprites...", size.width, size.height);
+            let summary = reslice(&tiles, size.width, size.height)?;
+            for file in &summary.sheets {
+                info!("Resliced '{}'.", file);
+            }
+            info!(
+                "Resliced {} sheet(s), renumbered {} sprite(s) of later sheets.",
+                summary.sheets.len(),
+                summary.moved
+            );
+            if !summary.cropped.is_empty() {
+                let cropped: Vec<String> = summary.cropped.iter().map(|x| x.to_string()).collect();
+                warning!(
+                    "{} sprite(s) lost pixels to cropping: {}",
+                    cropped.len(),
+                    cropped.join(", ")
+                );
+            }
+        }
+        Commands::ImportBundle {
+            tileset,
+            bundle,
+            apply,
+            lock_timeout,
+        } => {
+            info!("Bundle import mode.");
+
+            info!("Loading tileset:  {}", tileset);
+            let tiles = load(Path::new(tileset))?;
+
+            info!("Loading bundle: {}", bundle);
+            let sprites = load_bundle(Path::new(bundle))?;
+
+            let report = bundle_report(&tiles, &sprites)?;
+            for (title, ids) in [
+                ("Updated", &report.updated),
+                ("New", &report.new),
+                ("Unchanged", &report.unchanged),
+            ] {
+                println!("{} ({}):", title, ids.len());
+                for id in ids {
+                    println!("  {}", id);
+                }
+            }
+            println!("Overwritten sprites ({}):", report.overwritten.len());
+            for cell in &report.overwritten {
+                let mut line = format!(
+                    "  {} {} ({}, {}) by {}",
+                    cell.sheet, cell.sprite, cell.cell.0, cell.cell.1, cell.id
+                );
+                if !cell.shared_with.is_empty() {
+                    line += &format!(", still used by {}", cell.shared_with.join(", "));
+                }
+                println!("{}", line);
+            }
 
-fn save_tile_as(atlases: &[TileAtlas], tile_id: u32, out_dir: &Path) {
-    for atlas in atlases {
-        if atlas.in_bounds(tile_id) {
-            let tile_hash = atlas.get_sprite_hash(tile_id);
-            let path = out_dir.join(format!("{:010}.png", tile_hash));
-            let subimg = atlas.get_sprite(tile_id);
-            subimg
-                .to_image()
-                .save_with_format(path, ImageFormat::Png)
-                .unwrap();
-            return;
+            if *apply {
+                let _ws = lock_dirs(&[&tiles.base_path], Duration::from_secs(*lock_timeout))?;
+                info!("Merging...");
+                let summary = apply_bundle(&tiles, &sprites, &report, Path::new(bundle))?;
+                info!(
+                    "Merged {} id(s), {} replacing existing definitions.",
+                    summary.merged.len(),
+                    summary.replaced.len()
+                );
+                info!(
+                    "Copied {} sprite(s), reused {} identical sprite(s).",
+                    summary.sprites_copied, summary.sprites_reused
+                );
+            }
         }
-    }
-    panic!("Failed to save tile with id {}: tile not found.", tile_id);
-}
+        Commands::FindSprite {
+            tileset,
+            hash,
+            hash_opts,
+        } => {
+            info!("Sprite search mode.");
 
-impl Tileset {
-    pub fn generate_variations(&self, do_hash: bool, do_dump: bool) -> (Vec<SingleTile>, Vec<TileAtlas>) {
-        let mut ret = Vec::with_capacity(self.tiles_new.len());
+            info!("Loading tileset:  {}", tileset);
+            let tiles = load(Path::new(tileset))?;
 
-        let sprites_path = self.base_path.join("sprites");
-        let _ = std::fs::remove_dir_all(&sprites_path);
-        std::fs::create_dir(&sprites_path).unwrap();
+            find_sprite(&tiles, hash, hash_opts)?;
+        }
+        Commands::FindColor {
+            tileset,
+            color,
+            tolerance,
+        } => {
+            info!("Color search mode.");
 
-        let mut tiles_start: u32 = 0;
+            info!("Loading tileset:  {}", tileset);
+            let tiles = load(Path::new(tileset))?;
 
-        let mut atlases: Vec<TileAtlas> = vec![];
+            find_color(&tiles, *color, *tolerance)?;
+        }
+        Commands::Dedupe { tileset, hash_opts } => {
+            info!("Duplicate sprite search mode.");
 
-        for tiles_new in &self.tiles_new {
-            let img_path = self.base_path.join(&tiles_new.file);
-            let img_raw: DynamicImage = ImageReader::open(&img_path).unwrap().decode().unwrap();
-            let img: RgbaImage = img_raw.to_rgba8();
-            let sprite_w = tiles_new.sprite_width.unwrap_or(self.tile_info[0].width);
-            let sprite_h = tiles_new.sprite_height.unwrap_or(self.tile_info[0].height);
+            info!("Loading tileset:  {}", tileset);
+            let tiles = load(Path::new(tileset))?;
 
-            if img.width() % sprite_w != 0 || img.height() % sprite_h != 0 {
-                eprint!(
-                    "WARNING: image '{}' cannot be properly divided into sprites of size {}x{}",
-                    img_path.to_string_lossy(),
-                    sprite_w,
-                    sprite_h
-                );
+            let groups = find_duplicates(&tiles, hash_opts)?;
+            println!("Found {} group(s) of identical sprites.", groups);
+        }
+        Commands::VerifyRemap {
+            old,
+            new,
+            map,
+            hash_opts,
+        } => {
+            info!("Remap verification mode.");
+
+            info!("Loading old tileset: {}", old);
+            let tiles_old = load(Path::new(old))?;
+
+            info!("Loading new tileset: {}", new);
+            let tiles_new = load(Path::new(new))?;
+
+            info!("Loading mapping: {}", map);
+            let map = load_remap(Path::new(map))?;
+
+            let problems = verify_remap(
+                &tiles_old.load_atlases()?,
+                &tiles_new.load_atlases()?,
+                &map,
+                hash_opts,
+            );
+            for problem in &problems {
+                println!("{}", problem);
             }
 
-            let mut atlas = TileAtlas {
-                sprite_w,
-                sprite_h,
-                tiles_x: img.width() / sprite_w,
-                tiles_y: img.height() / sprite_h,
-                tiles_start,
-                img,
-                tiles_end: tiles_start,
-            };
-            atlas.tiles_end = atlas.tiles_start + atlas.tiles_total();
-            if do_dump {
-                atlas.dump_sprites_to_dir(&sprites_path);
+            if !problems.is_empty() {
+                println!(
+                    "{} of {} mapping(s) do not hold.",
+                    problems.len(),
+                    map.len()
+                );
+                return Ok(EXIT_PROBLEMS_FOUND);
             }
-
-            tiles_start = atlas.tiles_end;
-
-            atlases.push(atlas);
+            println!("All {} mapping(s) hold.", map.len());
         }
-
-        for tiles_new in &self.tiles_new {
-            for tile in &tiles_new.tiles {
-                for id in &tile.base.id.0 {
-                    let mut cloned = tile.base.clone();
-                    cloned.id = SingleOrVec::from_single(id.to_owned());
-                    if do_hash {
-                        hash_sprites(&mut cloned.fg, &atlases);
-                        hash_sprites(&mut cloned.bg, &atlases);
-                    }
-                    if cloned.rotates.is_none() {
-                        cloned.rotates = Some(cloned.multitile);
-                    }
-
-                    for at in &tile.additional_tiles {
-                        for at_id in &at.id.0 {
-                            let mut cloned_at = at.clone();
-                            cloned_at.id = SingleOrVec::from_single(id.to_owned() + "_" + at_id);
-                            if do_hash {
-                                hash_sprites(&mut cloned_at.fg, &atlases);
-                                hash_sprites(&mut cloned_at.bg, &atlases);
-                            }
-                            cloned_at.rotates = Some(true);
-                            cloned_at.height_3d = cloned.height_3d;
-                            ret.push(cloned_at);
+        Commands::Coverage {
+            tileset,
+            game_data,
+            history,
+        } => {
+            info!("Coverage mode.");
+
+            info!("Loading tileset:  {}", tileset);
+            let tiles = load(Path::new(tileset))?;
+
+            info!("Loading game data: {}", game_data);
+            let game = GameData::load(Path::new(game_data))?;
+
+            let result = coverage(&tiles, &game);
+            print!("{}", result);
+            if let Some(path) = history {
+                info!("Recording history: {}", path);
+                record_history(Path::new(path), &coverage_metrics(&result))?;
+            }
+        }
+        Commands::History { file, svg } => {
+            info!("History mode.");
+
+            let samples = read_history(Path::new(file))?;
+            for (metric, samples) in by_metric(&samples) {
+                let first = samples[0].value;
+                let last = samples[samples.len() - 1].value;
+                println!(
+                    "{}: {} over {} run(s), first {}",
+                    metric,
+                    last,
+                    samples.len(),
+                    first
+                );
+            }
+            if let Some(path) = svg {
+                info!("Writing trend charts: {}", path);
+                write_output(Path::new(path), render_trends(&samples))?;
+            }
+        }
+        Commands::WhatIf {
+            tileset,
+            replacements,
+            json,
+            hash_opts,
+        } => {
+            if *json {
+                progress::disable();
+            } else {
+                info!("Sheet replacement mode.");
+                info!("Loading tileset:  {}", tileset);
+            }
+            let tiles = load(Path::new(tileset))?;
+            let mut replaced = load(Path::new(tileset))?;
+            replace_sheets(&mut replaced, replacements)?;
+
+            let opts = CompareOptions {
+                hash: *hash_opts,
+                order: IdOrder::Natural,
+                pixels_only: false,
+                low_memory: false,
+                hash_cache: false,
+            };
+            if let Some(note) = compare::collision_note(&opts).filter(|_| !*json) {
+                info!("{}", note);
+            }
+            let result = compare(&tiles, &replaced, &opts)?;
+            let changed = result.changed.as_deref();
+            if *json {
+                let report = WhatIf {
+                    replaced: replacements,
+                    changed,
+                };
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                match changed {
+                    None => println!("Duplicate ids prevent telling which ids would change."),
+                    Some(ids) => {
+                        println!("{} id(s) would change:", ids.len());
+                        for id in ids {
+                            println!("  {}", id);
                         }
                     }
-
-                    ret.push(cloned);
                 }
             }
         }
+        Commands::Stats { tileset, json } => {
+            if *json {
+                progress::disable();
+            } else {
+                info!("Tileset statistics mode.");
+                info!("Loading tileset:  {}", tileset);
+            }
+            let tiles = load(Path::new(tileset))?;
+            let atlases = tiles.load_atlases()?;
+            let stats = tileset_stats(&tiles, &atlases);
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                println!("tiles-new entries: {}", stats.tiles_new);
+                println!(
+                    "ids: {} ({} multitile, {} animated)",
+                    stats.ids, stats.multitile_ids, stats.animated_ids
+                );
+                for atlas in &stats.atlases {
+                    println!(
+                        "{}: {} sprite(s), {:.1}% filled, {} unreferenced{}",
+                        atlas.file,
+                        atlas.sprites,
+                        100.0 * atlas.fill_ratio,
+                        atlas.unreferenced,
+                        if atlas.filler { " (filler)" } else { "" }
+                    );
+                }
+                println!(
+                    "sprites: {} ({} unreferenced)",
+                    stats.sprites, stats.unreferenced
+                );
+            }
+        }
+        Commands::Unused {
+            tileset,
+            thumbnails,
+        } => {
+            info!("Unused sprite search mode.");
+
+            info!("Loading tileset:  {}", tileset);
+            let tiles = load(Path::new(tileset))?;
+            let atlases = tiles.load_atlases()?;
+
+            let unused = unused_sprites(&tiles, &atlases);
+            for sheet in tiles.tiles_new.iter().filter(|x| tiles.is_filler(x)) {
+                println!("{}: skipped as filler", sheet.file);
+            }
+            for sheet in &unused {
+                println!(
+                    "{}: {} of {} sprite(s) unused: {}",
+                    sheet.atlas.file,
+                    sheet.indices.len(),
+                    sheet.atlas.tiles_total(),
+                    sheet.ranges()
+                );
+            }
+            let total: usize = unused.iter().map(|x| x.indices.len()).sum();
+            println!("Found {} unused sprite(s).", total);
 
-        ret.sort();
-        (ret, atlases)
-    }
-}
-
-fn dump_variations(vars: &Vec<SingleTile>, ts: &Tileset) {
-    let dump = serde_json::to_string_pretty(&vars).unwrap();
-    std::fs::write(ts.base_path.join("dump.json"), dump).unwrap();
-}
-
-fn find_duplicates(vars: &Vec<SingleTile>) -> Vec<&str> {
-    let mut ids: Vec<&str> = vars.iter().map(|x| x.id.0[0].as_str()).collect();
-    ids.sort_unstable();
-    let (_, dups) = ids.partition_dedup();
-    dups.to_vec()
-}
-
-fn dump_duplicates(dups: &Vec<&str>, ts: &Tileset) {
-    let dump = dups.join("\n");
-    std::fs::write(ts.base_path.join("duplicates.txt"), dump).unwrap();
-}
-
-fn dump_exclusives(exc: &HashSet<&str>, ts: &Tileset) {
-    let mut elems: Vec<&str> = exc.iter().cloned().collect();
-    elems.sort();
-    let dump = elems.join("\n");
-    std::fs::write(ts.base_path.join("exclusives.txt"), dump).unwrap();
-}
-
-fn dump_diffs(elems: &HashSet<&SingleTile>, ts: &Tileset) {
-    let mut elems: Vec<&str> = elems.iter().map(|x| x.id.0[0].as_str()).collect();
-    elems.sort();
-    let dump = elems.join("\n");
-    std::fs::write(ts.base_path.join("different.txt"), dump).unwrap();
-}
-
-fn compare_tilesets(ts1: &Tileset, ts2: &Tileset) {
-    let vars1 = ts1.generate_variations(true, true).0;
-    let vars2 = ts2.generate_variations(true, true).0;
+            if let Some(dir) = thumbnails {
+                info!("Writing unused sprites to {}", dir);
+                dump_unused(&unused, Path::new(dir))?;
+            }
+        }
+        Commands::Validate { tileset, hash_opts } => {
+            info!("Tileset validation mode.");
 
-    {
-        dump_variations(&vars1, ts1);
-        dump_variations(&vars2, ts2);
-    }
+            info!("Loading tileset:  {}", tileset);
+            let tiles = load(Path::new(tileset))?;
 
-    let do_diff: bool = {
-        let dups1 = find_duplicates(&vars1);
-        let dups2 = find_duplicates(&vars2);
-        dump_duplicates(&dups1, ts1);
-        dump_duplicates(&dups2, ts2);
-        dups1.is_empty() && dups2.is_empty()
-    };
+            info!("Validating...");
+            let problems = validate::validate_tileset(&tiles, hash_opts)?;
 
-    let ids_1: HashSet<&str> = vars1.iter().map(|x| x.id.0[0].as_str()).collect();
-    let ids_2: HashSet<&str> = vars2.iter().map(|x| x.id.0[0].as_str()).collect();
+            for problem in &problems {
+                println!("{}", problem);
+            }
 
-    {
-        let in_1_only: HashSet<&str> = ids_1.difference(&ids_2).cloned().collect();
-        let in_2_only: HashSet<&str> = ids_2.difference(&ids_1).cloned().collect();
+            if !problems.is_empty() {
+                println!("Found {} problem(s).", problems.len());
+                return Ok(EXIT_PROBLEMS_FOUND);
+            }
+            println!("No problems found.");
+        }
+        Commands::LintFile { file } => {
+            let lints = lint_file(Path::new(file))?;
+            for lint in &lints {
+                println!("{}", lint);
+            }
+            if !lints.is_empty() {
+                return Ok(EXIT_PROBLEMS_FOUND);
+            }
+        }
+        Commands::All {
+            tileset,
+            baseline,
+            game_data,
+            artifacts,
+            hash_opts,
+            bytewise_sort,
+            audience,
+        } => {
+            info!("Full build mode.");
+
+            info!("Loading tileset:  {}", tileset);
+            let mut tiles = load(Path::new(tileset))?;
+
+            let artifacts = cli.output_dir.as_deref().unwrap_or(Path::new(artifacts));
+            let out_dir = create_dated_dir(artifacts)?;
+            info!("Writing artifacts to {}", out_dir.to_string_lossy());
+
+            // Decoded once for every report
+            let atlases = tiles.load_checked_atlases()?;
+
+            info!("Validating...");
+            let problems = validate::validate_atlases(&tiles, &atlases, hash_opts)?;
+            write_problems(&problems, &out_dir.join("validate.txt"))?;
+            println!("Found {} problem(s).", problems.len());
+
+            let stats = tileset_stats(&tiles, &atlases);
+            write_output(
+                &out_dir.join("stats.json"),
+                serde_json::to_string_pretty(&stats)?,
+            )?;
+
+            if let Some(game_data) = game_data {
+                info!("Loading game data: {}", game_data);
+                let game = GameData::load(Path::new(game_data))?;
+                let result = coverage(&tiles, &game);
+                write_output(&out_dir.join("coverage.txt"), result.to_string())?;
+            }
 
-        dump_exclusives(&in_1_only, ts1);
-        dump_exclusives(&in_2_only, ts2);
-    }
-    if do_diff {
-        let idx1: HashSet<&SingleTile> = vars1.iter().collect();
-        let idx2: HashSet<&SingleTile> = vars2.iter().collect();
-
-        let in_1_only: HashSet<&SingleTile> = idx1
-            .difference(&idx2)
-            .cloned()
-            .filter(|x| ids_2.contains(x.id.0[0].as_str()))
-            .collect();
-        let in_2_only: HashSet<&SingleTile> = idx2
-            .difference(&idx1)
-            .cloned()
-            .filter(|x| ids_1.contains(x.id.0[0].as_str()))
-            .collect();
+            let mut exit_code = EXIT_OK;
+            if let Some(baseline) = baseline {
+                info!("Loading baseline: {}", baseline);
+                let mut base = load(Path::new(baseline))?;
+                base.out_path = out_dir.join("baseline");
+                tiles.out_path = out_dir.join("tileset");
+                create_output_dir(&base.out_path)?;
+                create_output_dir(&tiles.out_path)?;
+
+                info!("Running comparison...");
+                let opts = CompareOptions {
+                    hash: *hash_opts,
+                    order: if *bytewise_sort {
+                        IdOrder::Bytewise
+                    } else {
+                        IdOrder::Natural
+                    },
+                    pixels_only: false,
+                    low_memory: false,
+                    hash_cache: false,
+                };
+                if let Some(note) = compare::collision_note(&opts) {
+                    info!("{}", note);
+                }
+                let hashes = hash_sprite_indices(&atlases, tiles.used_sprites(), hash_opts);
+                let result = compare::compare_hashed(
+                    &base,
+                    &tiles,
+                    base.hash_used_sprites(hash_opts)?,
+                    (atlases, hashes),
+                    &opts,
+                )?;
+                let reporter = Reporter {
+                    audience: *audience,
+                    html: (*audience != Audience::Ci).then(|| out_dir.join("report.html")),
+                    heatmaps: None,
+                    sheet: None,
+                    badge: Some(out_dir.join("badge.svg")),
+                    markdown: Some(out_dir.join("summary.md")),
+                    markdown_images: None,
+                    highlight: None,
+                    dump_sprites: false,
+                    shared_sprites: false,
+                    history: None,
+                    expand_groups: false,
+                    read_only: false,
+                    check: false,
+                    lang: cli.lang,
+                };
+                reporter.write(&result, &base, &tiles, &opts)?;
+                exit_code = reporter.exit_code(&result);
+            }
 
-        dump_diffs(&in_1_only, ts1);
-        dump_diffs(&in_2_only, ts2);
-    } else {
-        eprintln!(
-            "WARNING: duplicate tiles found in at least one tileset, diff will not be generated."
-        );
-    }
-}
+            if !problems.is_empty() {
+                return Ok(EXIT_PROBLEMS_FOUND);
+            }
+            return Ok(exit_code);
+        }
+        Commands::Ci => {
+            info!("GitHub Actions mode.");
+            progress::disable();
+
+            let inputs = ActionInputs::from_env()?;
+            info!("Loading tileset:  {}", inputs.tileset.display());
+            let tiles = load(&inputs.tileset)?;
+            let lints = lint_file(&tiles.base_path.join("tile_config.json"))?;
+            for lint in &lints {
+                println!("{}", github::annotation(lint));
+            }
 
-fn load_ids_file(base_path: &Path) -> Option<Vec<String>> {
-    assert!(base_path.exists());
-    assert!(base_path.is_file());
+            let result = match &inputs.baseline {
+                Some(baseline) => {
+                    info!("Loading baseline: {}", baseline.display());
+                    let base = load(baseline)?;
+                    info!("Running comparison...");
+                    Some(compare(&base, &tiles, &CompareOptions::default())?)
+                }
+                None => None,
+            };
 
-    let reader = BufReader::new(File::open(base_path).expect("Cannot open ids file."));
+            let summary = github::render_summary(&inputs.tileset, &lints, result.as_ref());
+            if !github::append_to_env_file("GITHUB_STEP_SUMMARY", &summary)? {
+                println!("{}", summary);
+            }
+            let outputs = github::outputs(&lints, result.as_ref());
+            if !github::append_to_env_file("GITHUB_OUTPUT", &outputs)? {
+                print!("{}", outputs);
+            }
 
-    let mut ret = vec![];
+            if !lints.is_empty() {
+                return Ok(EXIT_PROBLEMS_FOUND);
+            }
+        }
+        Commands::Compose { source, output } => {
+            info!("Tileset compose mode.");
+
+            info!("Composing {} into {}", source, output);
+            let summary = compose(Path::new(source), Path::new(output))?;
+            info!(
+                "Packed {} sprite(s) into {} sheet(s) with {} tile entries.",
+                summary.sprites, summary.sheets, summary.entries
+            );
+            for file in &summary.skipped {
+                info!("Skipped sheet '{}' (fallback or no sprites).", file);
+            }
+        }
+        Commands::Decompose { tileset, output } => {
+            info!("Tileset decompose mode.");
 
-    for line in reader.lines() {
-        ret.push(line.unwrap());
-    }
+            info!("Loading tileset:  {}", tileset);
+            let tiles = load(Path::new(tileset))?;
 
-    Some(ret)
-}
+            info!("Decomposing into {}", output);
+            let summary = decompose(&tiles, Path::new(output))?;
+            info!(
+                "Wrote {} sprite(s) ({} unused) and {} tile entries from {} sheet(s).",
+                summary.sprites, summary.unused_sprites, summary.entries, summary.sheets
+            );
+        }
+        Commands::CompareSources { a, b } => {
+            info!("Source tree comparison mode.");
 
-fn extract_tiles(ts: &Tileset, ids: &[String], out_dir: &Path) {
-    let (vars, atlases) = ts.generate_variations(false, false);
-    let (vars_hashed, _) = ts.generate_variations(true, true);
-
-    let vars_hm: HashMap<&str, usize> = vars
-        .iter()
-        .enumerate()
-        .map(|x| (x.1.id.0[0].as_str(), x.0))
-        .collect();
-
-    for id in ids {
-        if let Some(&idx) = vars_hm.get(id.as_str()) {
-            let this_tile_dir: PathBuf = out_dir.join(id);
-            std::fs::create_dir_all(&this_tile_dir).unwrap();
-
-            let out_json = this_tile_dir.join(id.to_owned() + ".json");
-
-            let tile_hashed = &vars_hashed[idx];
-            let out_str = serde_json::to_string_pretty(tile_hashed).unwrap();
-            std::fs::write(out_json, out_str).unwrap();
-
-            let variation = &vars[idx];
-
-            //let mut fg_ctr: usize = 0;
-            for fg in &variation.fg.0 {
-                for tile_id in &fg.id.0 {
-                    save_tile_as(&atlases, *tile_id, out_dir);
-                    /*
-                    let out_png =
-                        this_tile_dir.join(id.to_owned() + &format!("_fg_{}.png", fg_ctr));
-                    fg_ctr += 1;
-                    save_tile_as(&atlases, *tile_id, &out_png);
-                    */
+            info!("Comparing {} against {}", a, b);
+            let diff = diff_sources(Path::new(a), Path::new(b))?;
+            for line in diff.lines() {
+                println!("{}", line);
+            }
+            if diff.is_empty() {
+                println!("Source trees are identical.");
+            }
+        }
+        Commands::CompareExtracted { a, b } => {
+            info!("Extracted tiles comparison mode.");
+
+            info!("Comparing {} against {}", a, b);
+            let diff = diff_extracted(Path::new(a), Path::new(b))?;
+            for (title, ids) in [
+                ("Only in A", &diff.only_in_a),
+                ("Only in B", &diff.only_in_b),
+            ] {
+                if !ids.is_empty() {
+                    println!("{} ({}):", title, ids.len());
+                    for id in ids {
+                        println!("  {}", id);
+                    }
                 }
             }
-
-            //let mut bg_ctr: usize = 0;
-            for bg in &variation.bg.0 {
-                for tile_id in &bg.id.0 {
-                    save_tile_as(&atlases, *tile_id, out_dir);
-                    /*
-                    let out_png =
-                        this_tile_dir.join(id.to_owned() + &format!("_bg_{}.png", bg_ctr));
-                    bg_ctr += 1;
-                    save_tile_as(&atlases, *tile_id, &out_png);
-                    */
+            if !diff.changed.is_empty() {
+                println!("Changed ({}):", diff.changed.len());
+                for (id, changes) in &diff.changed {
+                    println!("  {}", id);
+                    for change in changes {
+                        println!(
+                            "    {}: {} -> {}",
+                            change.field, change.before, change.after
+                        );
+                    }
                 }
             }
-        } else {
-            eprintln!("Failed to find tile with id {}", id);
+            if diff.is_empty() {
+                println!("Extracted tiles are identical.");
+            }
+        }
+        Commands::Lookup { dir, id } => match read_dumped(Path::new(dir), id)? {
+            Some(tile) => println!("{}", serde_json::to_string_pretty(&tile)?),
+            None => {
+                error!("No definition of '{}' in {}", id, dir);
+                return Ok(EXIT_PROBLEMS_FOUND);
+            }
+        },
+        Commands::Explain { code } => match code {
+            Some(code) => print!("{}", code.explain()),
+            None => {
+                for code in Code::all() {
+                    println!("{} {:<20} {}", code, code.name(), code.summary());
+                }
+            }
+        },
+        Commands::Selftest { dir } => {
+            info!("Self test mode.");
+            progress::disable();
+
+            let work_dir = match dir {
+                Some(dir) => PathBuf::from(dir),
+                None => std::env::temp_dir().join(format!(
+                    "tileset-comparator-selftest-{}",
+                    std::process::id()
+                )),
+            };
+            let checks = selftest(&work_dir);
+            if dir.is_none() {
+                let _ = std::fs::remove_dir_all(&work_dir);
+            }
+            let checks = checks?;
+            for check in &checks {
+                if check.passed() {
+                    println!("ok      {}", check.name);
+                    continue;
+                }
+                println!("FAILED  {}", check.name);
+                match (&check.expected, &check.actual) {
+                    (None, _) => println!("        not expected"),
+                    (_, None) => println!("        not produced"),
+                    _ => {
+                        if let Some((line, expected, actual)) = check.first_difference() {
+                            println!("        line {}: expected '{}'", line, expected);
+                            println!("        line {}: got      '{}'", line, actual);
+                        }
+                    }
+                }
+            }
+            let failed = checks.iter().filter(|x| !x.passed()).count();
+            println!(
+                "{} of {} output(s) as expected.",
+                checks.len() - failed,
+                checks.len()
+            );
+            if failed > 0 {
+                return Ok(EXIT_PROBLEMS_FOUND);
+            }
         }
     }
-}
 
-#[derive(Parser)]
-struct Cli {
-    #[clap(subcommand)]
-    command: Commands,
-}
-
-#[derive(Subcommand)]
-enum Commands {
-    Compare { a: String, b: String },
-    Extract { tileset: String, ids_file: String },
+    Ok(EXIT_OK)
 }
 
-fn main() {
-    let cli = Cli::parse();
+/// Fills in the options missing from the command line from the tool config.
+fn apply_defaults(cli: &mut Cli, matches: &ArgMatches, config: &ToolConfig) {
+    let sub = matches.subcommand().map(|x| x.1);
+    // Only valid for arguments of the subcommand, including global ones, by
+    // their kebab-case names
+    let given = |name: &str| sub.is_some_and(|x| x.occurrences_of(name) > 0);
+    let given_global = |name: &str| matches.occurrences_of(name) > 0 || given(name);
 
-    match &cli.command {
-        Commands::Compare { a, b } => {
-            println!("Tileset comparison mode.");
-
-            println!("Loading tileset A:  {}", a);
-            let tiles_a = load_tileset(Path::new(a));
+    if cli.output_dir.is_none() {
+        cli.output_dir = config.output_dir.clone();
+    }
+    if let Some(jobs) = config.jobs.filter(|_| !given_global("jobs")) {
+        cli.jobs = jobs;
+    }
+    if let Some(lang) = config.lang.filter(|_| !given_global("lang")) {
+        cli.lang = lang;
+    }
+    cli.allow.extend(&config.allow);
 
-            println!("Loading tileset B: {}", b);
-            let tiles_b = load_tileset(Path::new(b));
+    let command = match &mut cli.command {
+        Some(x) => x,
+        None => return,
+    };
+    let hash_opts = match command {
+        Commands::Compare { hash_opts, .. }
+        | Commands::Compare3 { hash_opts, .. }
+        | Commands::Review { hash_opts, .. }
+        | Commands::Extract { hash_opts, .. }
+        | Commands::FindSprite { hash_opts, .. }
+        | Commands::Dedupe { hash_opts, .. }
+        | Commands::VerifyRemap { hash_opts, .. }
+        | Commands::WhatIf { hash_opts, .. }
+        | Commands::Validate { hash_opts, .. }
+        | Commands::Merge { hash_opts, .. }
+        | Commands::All { hash_opts, .. } => Some(hash_opts),
+        _ => None,
+    };
+    if let Some(opts) = hash_opts {
+        if !given("fast-hash") {
+            opts.fast_hash = config.hash.mode == Some(HashMode::Fast);
+        }
+        if let Some(x) = config.hash.algo.filter(|_| !given("hash-algo")) {
+            opts.hash_algo = x;
+        }
+        if let Some(x) = config.hash.bits.filter(|_| !given("hash-bits")) {
+            opts.hash_bits = x;
+        }
+        if let Some(x) = config.hash.null_sprite.filter(|_| !given("null-sprite")) {
+            opts.null_sprite = x;
+        }
+        if opts.similarity.is_none() {
+            opts.similarity = config.hash.similarity;
+        }
+        if opts.alpha_threshold.is_none() {
+            opts.alpha_threshold = config.hash.alpha_threshold;
+        }
+        if opts.fuzz.is_none() {
+            opts.fuzz = config.hash.fuzz;
+        }
+        if let Some(x) = config.hash.fuzz_pixels.filter(|_| !given("fuzz-pixels")) {
+            opts.fuzz_pixels = x;
+        }
+    }
 
-            if tiles_a.is_none() || tiles_b.is_none() {
-                println!("Aborted.");
-                return;
+    match command {
+        Commands::Compare {
+            filter,
+            audience,
+            badge,
+            html,
+            heatmaps,
+            sheet,
+            dump_sprites,
+            shared_sprites,
+            bytewise_sort,
+            pixels_only,
+            low_memory,
+            hash_cache,
+            ..
+        } => {
+            let defaults = &config.compare;
+            if filter.is_none() {
+                *filter = defaults.filter.clone();
             }
-
-            println!("Running comparison...");
-
-            compare_tilesets(tiles_a.as_ref().unwrap(), tiles_b.as_ref().unwrap());
+            if let Some(x) = defaults.audience.filter(|_| !given("audience")) {
+                *audience = x;
+            }
+            for (value, default) in [
+                (badge, &defaults.badge),
+                (html, &defaults.html),
+                (heatmaps, &defaults.heatmaps),
+                (sheet, &defaults.sheet),
+            ] {
+                if value.is_none() {
+                    *value = default.clone();
+                }
+            }
+            *dump_sprites |= defaults.dump_sprites;
+            *shared_sprites |= defaults.shared_sprites && !*low_memory && !*hash_cache;
+            *bytewise_sort |= defaults.bytewise_sort;
+            *pixels_only |= defaults.pixels_only;
         }
-        Commands::Extract { tileset, ids_file } => {
-            println!("Tile extraction mode.");
-
-            println!("Loading tileset:  {}", tileset);
-            let tileset_dir = PathBuf::from(tileset);
-            let tiles = load_tileset(&tileset_dir);
-
-            println!("Loading ids file: {}", ids_file);
-            let ids = load_ids_file(Path::new(ids_file));
-
-            if tiles.is_none() || ids.is_none() {
-                println!("Aborted.");
-                return;
+        Commands::FindColor { tolerance, .. } => {
+            if let Some(x) = config.find_color.tolerance.filter(|_| !given("tolerance")) {
+                *tolerance = x;
             }
+        }
+        _ => {}
+    }
+}
 
-            println!("Extracting...");
-
-            extract_tiles(
-                tiles.as_ref().unwrap(),
-                ids.as_ref().unwrap(),
-                &tileset_dir.join("extracted"),
+fn main() {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    let result = ToolConfig::load(cli.config.as_deref()).and_then(|config| {
+        apply_defaults(&mut cli, &matches, &config);
+        run(&cli)
+    });
+    match result {
+        Ok(code) => {
+            // Keep lint output parseable line by line, and JSON output valid
+            let quiet = matches!(
+                cli.command,
+                Some(Commands::LintFile { .. })
+                    | Some(Commands::WhatIf { json: true, .. })
+                    | Some(Commands::Stats { json: true, .. })
+                    | Some(Commands::Lookup { .. })
             );
+            if !quiet && !cli.version_info {
+                info!("Done!");
+            }
+            std::process::exit(code);
+        }
+        Err(e) => {
+            error!("{}", e);
+            info!("Aborted.");
+            std::process::exit(e.exit_code());
         }
     }
-
-    println!("Done!");
 }
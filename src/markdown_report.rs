@@ -0,0 +1,215 @@
+use crate::atlas::HashOptions;
+use crate::compare::ComparisonResult;
+use crate::error::{write_output, Result};
+use crate::field_diff::field_changes;
+use crate::tileset::{SingleTile, Tileset};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Most rows per table; the rest are counted. Keeps reports of large
+/// changes under the 65536 character limit of a pull request comment.
+const MAX_ROWS: usize = 100;
+
+fn escape_cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+/// One side of a comparison, for looking up the sprites and sheet of an id.
+struct Side<'a> {
+    /// `a` or `b`, substituted for `{side}` in image links
+    name: &'static str,
+    /// Tiles with sprite indices (not hashes), by id
+    tiles: HashMap<&'a str, &'a SingleTile>,
+    sheets: HashMap<String, &'a str>,
+}
+
+impl<'a> Side<'a> {
+    fn new(name: &'static str, ts: &'a Tileset, vars: &'a [SingleTile]) -> Self {
+        Side {
+            name,
+            tiles: vars.iter().map(|x| (x.id.0[0].as_str(), x)).collect(),
+            sheets: ts.sheets_by_id(),
+        }
+    }
+
+    fn sheet(&self, id: &str) -> String {
+        self.sheets
+            .get(id)
+            .map_or(String::new(), |x| format!("`{}`", escape_cell(x)))
+    }
+
+    /// Image of the first sprite of an id, linked through `image_url` with
+    /// `{side}` and `{sprite}` replaced.
+    fn image(&self, id: &str, image_url: &str) -> String {
+        let sprite = self.tiles.get(id).and_then(|tile| {
+            tile.fg()
+                .iter()
+                .chain(tile.bg())
+                .flat_map(|x| x.id.0.iter())
+                .next()
+        });
+        match sprite {
+            Some(sprite) => format!(
+                "![sprite {}]({})",
+                sprite,
+                image_url
+                    .replace("{side}", self.name)
+                    .replace("{sprite}", &sprite.to_string())
+            ),
+            None => String::new(),
+        }
+    }
+}
+
+/// Appends a table with a row per id, cut short after `MAX_ROWS`.
+fn table(
+    md: &mut String,
+    title: &str,
+    header: &[&str],
+    ids: &[String],
+    row: impl Fn(&str) -> Vec<String>,
+) {
+    if ids.is_empty() {
+        return;
+    }
+    *md += &format!("### {} ({})\n\n", title, ids.len());
+    *md += &format!("| {} |\n", header.join(" | "));
+    *md += &format!("|{}\n", "---|".repeat(header.len()));
+    for id in ids.iter().take(MAX_ROWS) {
+        *md += &format!("| `{}` | {} |\n", escape_cell(id), row(id).join(" | "));
+    }
+    if ids.len() > MAX_ROWS {
+        *md += &format!("\nand {} more\n", ids.len() - MAX_ROWS);
+    }
+    *md += "\n";
+}
+
+fn bullet_list(md: &mut String, title: &str, items: &[String]) {
+    if items.is_empty() {
+        return;
+    }
+    *md += &format!("### {} ({})\n\n", title, items.len());
+    for item in items.iter().take(MAX_ROWS) {
+        *md += &format!("- {}\n", escape_cell(item));
+    }
+    if items.len() > MAX_ROWS {
+        *md += &format!("- and {} more\n", items.len() - MAX_ROWS);
+    }
+    *md += "\n";
+}
+
+/// Markdown summary of a comparison for a pull request comment: counts,
+/// and tables of added, removed and changed ids with their sheets and
+/// changes. With `image_url`, a link template such as
+/// `https://example.org/run/12/{side}/sprites/{sprite}.png`, tables also
+/// show the first sprite of every id from the uploaded `sprites` output
+/// directories, `{side}` being `a` or `b`.
+pub fn render_markdown_report(
+    ts_a: &Tileset,
+    ts_b: &Tileset,
+    result: &ComparisonResult,
+    opts: &HashOptions,
+    image_url: Option<&str>,
+) -> String {
+    let raw_a = ts_a.expand_variations(&result.atlases_a, false, opts);
+    let raw_b = ts_b.expand_variations(&result.atlases_b, false, opts);
+    let a = Side::new("a", ts_a, &raw_a);
+    let b = Side::new("b", ts_b, &raw_b);
+    let changed = result.changed.as_deref().unwrap_or_default();
+
+    let mut md = format!("## Tileset comparison: {}\n\n", result.status());
+    md += "| Added | Removed | Changed | Similar |\n|---:|---:|---:|---:|\n";
+    md += &format!(
+        "| {} | {} | {} | {} |\n\n",
+        result.only_in_b.len(),
+        result.only_in_a.len(),
+        result
+            .changed
+            .as_ref()
+            .map_or("?".to_string(), |x| x.len().to_string()),
+        result.similar.len()
+    );
+    if result.changed.is_none() {
+        md += "Duplicate ids prevented diffing definitions.\n\n";
+    }
+
+    let with_image = |header: &[&'static str]| -> Vec<&'static str> {
+        let mut ret = header.to_vec();
+        if image_url.is_some() {
+            ret.push("Sprite");
+        }
+        ret
+    };
+    let one_side = |side: &Side, id: &str| -> Vec<String> {
+        let mut ret = vec![side.sheet(id)];
+        if let Some(url) = image_url {
+            ret.push(side.image(id, url));
+        }
+        ret
+    };
+    table(
+        &mut md,
+        "Added",
+        &with_image(&["Id", "Sheet"]),
+        &result.only_in_b,
+        |id| one_side(&b, id),
+    );
+    table(
+        &mut md,
+        "Removed",
+        &with_image(&["Id", "Sheet"]),
+        &result.only_in_a,
+        |id| one_side(&a, id),
+    );
+
+    let pairs: HashMap<&str, (&SingleTile, &SingleTile)> = result
+        .changed_pairs()
+        .into_iter()
+        .map(|x| (x.0.id.0[0].as_str(), x))
+        .collect();
+    let mut header = vec!["Id", "Sheet", "Changes"];
+    if image_url.is_some() {
+        header.extend(["A", "B"]);
+    }
+    table(&mut md, "Changed", &header, changed, |id| {
+        let changes: Vec<String> = pairs.get(id).map_or(vec![], |(x, y)| {
+            field_changes(x, y, opts.hash_bits)
+                .iter()
+                .map(|c| {
+                    format!(
+                        "{}: `{}` → `{}`",
+                        c.field,
+                        escape_cell(&c.before),
+                        escape_cell(&c.after)
+                    )
+                })
+                .collect()
+        });
+        let mut ret = vec![b.sheet(id), changes.join("<br>")];
+        if let Some(url) = image_url {
+            ret.push(a.image(id, url));
+            ret.push(b.image(id, url));
+        }
+        ret
+    });
+
+    bullet_list(&mut md, "Fallback glyphs", &result.ascii_changes);
+    bullet_list(&mut md, "Overlay ordering", &result.ordering_changes);
+    bullet_list(&mut md, "Multitile subtiles", &result.subtile_changes);
+    md
+}
+
+/// Writes the Markdown summary of a comparison to `path`.
+pub fn write_markdown_report(
+    path: &Path,
+    ts_a: &Tileset,
+    ts_b: &Tileset,
+    result: &ComparisonResult,
+    opts: &HashOptions,
+    image_url: Option<&str>,
+) -> Result<()> {
+    write_output(
+        path,
+        render_markdown_report(ts_a, ts_b, result, opts, image_url),
+    )
+}
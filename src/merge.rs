@@ -0,0 +1,389 @@
+use crate::artifacts::today;
+use crate::atlas::{paste_sprites, save_png, HashOptions, TileAtlas, SHEET_COLUMNS};
+use crate::error::{write_output, Error, Result};
+use crate::id_filter::glob_match;
+use crate::provenance::{ProvenanceLog, ProvenanceRecord};
+use crate::raw_config::{id_value, read_tile_config, remap_entry};
+use crate::tileset::{TilesNew, Tileset};
+use crate::warnings::Code;
+use image::{GenericImage, GenericImageView, RgbaImage, SubImage};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Sprite size and offset, which sprites must share to live in one sheet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct SheetKind {
+    w: u32,
+    h: u32,
+    offset_x: i32,
+    offset_y: i32,
+}
+
+impl SheetKind {
+    fn of(ts: &Tileset, sheet: &TilesNew) -> SheetKind {
+        SheetKind {
+            w: sheet.sprite_width.unwrap_or(ts.tile_info().width),
+            h: sheet.sprite_height.unwrap_or(ts.tile_info().height),
+            offset_x: sheet.sprite_offset_x.unwrap_or(0),
+            offset_y: sheet.sprite_offset_y.unwrap_or(0),
+        }
+    }
+}
+
+/// What a merge changed in the target tileset.
+#[derive(Debug, Default)]
+pub struct MergeSummary {
+    /// Ids copied from the source tileset
+    pub merged: Vec<String>,
+    /// Merged ids whose previous definition in the target was replaced
+    pub replaced: Vec<String>,
+    pub sprites_copied: usize,
+    /// Sprites not copied because the target already had identical ones
+    pub sprites_reused: usize,
+}
+
+/// An entry of the source tileset selected for merging.
+struct Graft {
+    sheet: usize,
+    /// Selected ids of the entry
+    ids: Vec<String>,
+    /// All ids of the entry
+    entry_ids: Vec<String>,
+    raw: Value,
+    sprites: BTreeSet<u32>,
+}
+
+fn read_raw_config(ts: &Tileset) -> Result<(PathBuf, Value)> {
+    let path = ts.base_path.join("tile_config.json");
+    let raw = read_tile_config(&path)?;
+    Ok((path, raw))
+}
+
+fn same_pixels(a: &SubImage<&RgbaImage>, b: &SubImage<&RgbaImage>) -> bool {
+    a.dimensions() == b.dimensions() && a.pixels().zip(b.pixels()).all(|(x, y)| x.2 == y.2)
+}
+
+fn unused_sheet_name(ts: &Tileset, kind: SheetKind, taken: &[String]) -> String {
+    let base = format!("merged_{}x{}", kind.w, kind.h);
+    let mut name = format!("{}.png", base);
+    let mut n = 1;
+    while ts.base_path.join(&name).exists() || taken.contains(&name) {
+        n += 1;
+        name = format!("{}_{}.png", base, n);
+    }
+    name
+}
+
+/// Entries of `b` with at least one id matching a pattern, warning about
+/// patterns that match nothing.
+fn select_grafts(
+    b: &Tileset,
+    raw_b: &Value,
+    patterns: &[String],
+    opts: &HashOptions,
+) -> Vec<Graft> {
+    let mut matched = vec![false; patterns.len()];
+    let mut ret = vec![];
+    for (sheet_idx, sheet) in b.tiles_new.iter().enumerate() {
+        for (tile_idx, tile) in sheet.tiles.iter().enumerate() {
+            let mut ids = vec![];
+            for id in &tile.base.id.0 {
+                let mut any = false;
+                for (i, pattern) in patterns.iter().enumerate() {
+                    if glob_match(pattern, id) {
+                        matched[i] = true;
+                        any = true;
+                    }
+                }
+                if any {
+                    ids.push(id.clone());
+                }
+            }
+            if ids.is_empty() {
+                continue;
+            }
+
+            let mut raw = raw_b["tiles-new"][sheet_idx]["tiles"][tile_idx].clone();
+            raw["id"] = id_value(&ids);
+
+            let mut sprites = BTreeSet::new();
+            for t in std::iter::once(&tile.base).chain(tile.additional_tiles.iter()) {
+                for spidw in t.variations() {
+                    sprites.extend(
+                        spidw
+                            .id
+                            .0
+                            .iter()
+                            .filter(|x| !opts.null_sprite.is_null(**x))
+                            // All but the negative placeholder fit
+                            .filter_map(|&x| u32::try_from(x).ok()),
+                    );
+                }
+            }
+
+            ret.push(Graft {
+                sheet: sheet_idx,
+                ids,
+                entry_ids: tile.base.id.0.clone(),
+                raw,
+                sprites,
+            });
+        }
+    }
+
+    for (pattern, matched) in patterns.iter().zip(matched) {
+        if !matched {
+            b.warn(
+                Code::UnknownId,
+                None,
+                format!("no tile entry matches '{}'", pattern),
+            );
+        }
+    }
+    ret
+}
+
+/// Removes the given ids from every entry of a raw config, dropping entries
+/// left without ids, and returns the ids that were found.
+fn remove_ids(raw: &mut Value, ids: &HashSet<&str>) -> HashSet<String> {
+    let mut removed = HashSet::new();
+    let sheets = match raw.get_mut("tiles-new") {
+        Some(Value::Array(sheets)) => sheets,
+        _ => return removed,
+    };
+    for sheet in sheets {
+        let tiles = match sheet.get_mut("tiles") {
+            Some(Value::Array(tiles)) => tiles,
+            _ => continue,
+        };
+        tiles.retain_mut(|entry| {
+            let entry_ids: Vec<String> = match &entry["id"] {
+                Value::String(id) => vec![id.clone()],
+                Value::Array(list) => list
+                    .iter()
+                    .filter_map(|x| x.as_str().map(str::to_string))
+                    .collect(),
+                _ => return true,
+            };
+            let (gone, kept): (Vec<String>, Vec<String>) = entry_ids
+                .into_iter()
+                .partition(|x| ids.contains(x.as_str()));
+            if gone.is_empty() {
+                return true;
+            }
+            removed.extend(gone);
+            if kept.is_empty() {
+                return false;
+            }
+            entry["id"] = id_value(&kept);
+            true
+        });
+    }
+    removed
+}
+
+/// Copies the entries of `b` whose ids match any of the glob `patterns`
+/// into `a`, replacing existing definitions of those ids.
+///
+/// Sprites already present in `a` with identical pixels are reused; the rest
+/// are appended to `a`'s last atlas if it has the same sprite size and
+/// offset, or to new atlas files. Rewrites `a`'s `tile_config.json` and
+/// records the origin of every merged id in its provenance log.
+pub fn merge(
+    a: &Tileset,
+    b: &Tileset,
+    patterns: &[String],
+    opts: &HashOptions,
+) -> Result<MergeSummary> {
+    merge_as(a, b, patterns, opts, "merge", &b.base_path)
+}
+
+/// Like `merge`, but records the merged ids in the provenance log as coming
+/// from `source` through `command`, for commands that stage their input as
+/// a temporary tileset.
+pub(crate) fn merge_as(
+    a: &Tileset,
+    b: &Tileset,
+    patterns: &[String],
+    opts: &HashOptions,
+    command: &str,
+    source: &Path,
+) -> Result<MergeSummary> {
+    let (config_a, mut raw_a) = read_raw_config(a)?;
+    let (config_b, raw_b) = read_raw_config(b)?;
+
+    let grafts = select_grafts(b, &raw_b, patterns, opts);
+    if grafts.is_empty() {
+        return Ok(MergeSummary::default());
+    }
+
+    let atlases_a = a.load_atlases()?;
+    let atlases_b = b.load_atlases()?;
+    let exact = HashOptions::default();
+
+    let mut existing: HashMap<(SheetKind, u64), Vec<(&TileAtlas, u32)>> = HashMap::new();
+    for (atlas, sheet) in atlases_a.iter().zip(&a.tiles_new) {
+        let kind = SheetKind::of(a, sheet);
+        for idx in atlas.tiles_start..atlas.tiles_end {
+            existing
+                .entry((kind, atlas.get_sprite_hash(idx, &exact)))
+                .or_default()
+                .push((atlas, idx));
+        }
+    }
+
+    let mut summary = MergeSummary::default();
+    let mut remap: HashMap<u32, u32> = HashMap::new();
+    let mut to_copy: BTreeMap<SheetKind, Vec<(u32, usize)>> = BTreeMap::new();
+    let mut seen: HashSet<u32> = HashSet::new();
+
+    for graft in &grafts {
+        for &idx in &graft.sprites {
+            if !seen.insert(idx) {
+                continue;
+            }
+            let sheet = atlases_b.iter().position(|x| x.in_bounds(idx));
+            let sheet = sheet.ok_or_else(|| Error::BadTileset {
+                path: config_b.clone(),
+                message: format!(
+                    "tile '{}' uses sprite {} outside all atlases",
+                    graft.ids[0], idx
+                ),
+            })?;
+            let kind = SheetKind::of(b, &b.tiles_new[sheet]);
+            let sprite = atlases_b[sheet].get_sprite(idx);
+            let hash = atlases_b[sheet].get_sprite_hash(idx, &exact);
+            let same = existing.get(&(kind, hash)).and_then(|candidates| {
+                candidates
+                    .iter()
+                    .find(|(atlas, a_idx)| same_pixels(&atlas.get_sprite(*a_idx), &sprite))
+            });
+            match same {
+                Some((_, a_idx)) => {
+                    remap.insert(idx, *a_idx);
+                    summary.sprites_reused += 1;
+                }
+                None => to_copy.entry(kind).or_default().push((idx, sheet)),
+            }
+        }
+    }
+
+    let mut next_index = atlases_a.last().map(|x| x.tiles_end).unwrap_or(0);
+    let mut new_sheets: Vec<String> = vec![];
+    let mut sheet_of_kind: HashMap<SheetKind, usize> = HashMap::new();
+    for (i, sheet) in a.tiles_new.iter().enumerate().rev() {
+        sheet_of_kind.insert(SheetKind::of(a, sheet), i);
+    }
+
+    // Only the last atlas can grow without shifting the indices of later ones,
+    // so sprites that fit it are placed first.
+    let last = atlases_a
+        .len()
+        .checked_sub(1)
+        .filter(|&i| atlases_a[i].is_evenly_divided());
+    let last_kind = last.map(|i| SheetKind::of(a, &a.tiles_new[i]));
+    let mut groups: Vec<(SheetKind, Vec<(u32, usize)>)> = to_copy.into_iter().collect();
+    groups.sort_by_key(|x| Some(x.0) != last_kind);
+
+    for (kind, sprites) in &groups {
+        let count = sprites.len() as u32;
+        let images: Vec<SubImage<&RgbaImage>> = sprites
+            .iter()
+            .map(|(idx, sheet)| atlases_b[*sheet].get_sprite(*idx))
+            .collect();
+        let append_to = last.filter(|_| new_sheets.is_empty() && last_kind == Some(*kind));
+
+        let (columns, dest) = match append_to {
+            Some(i) => {
+                let atlas = &atlases_a[i];
+                let rows = count.div_ceil(atlas.tiles_x);
+                let path = a.base_path.join(&atlas.file);
+                let mut img = RgbaImage::new(atlas.img.width(), (atlas.tiles_y + rows) * kind.h);
+                img.copy_from(&atlas.img, 0, 0)
+                    .map_err(|source| Error::SaveImage {
+                        path: path.clone(),
+                        source,
+                    })?;
+                paste_sprites(&mut img, &path, atlas.tiles_y, atlas.tiles_x, &images)?;
+                save_png(&img, &path)?;
+                (atlas.tiles_x, i)
+            }
+            None => {
+                let columns = std::cmp::min(count, SHEET_COLUMNS);
+                let rows = count.div_ceil(columns);
+                let name = unused_sheet_name(a, *kind, &new_sheets);
+                let path = a.base_path.join(&name);
+                let mut img = RgbaImage::new(columns * kind.w, rows * kind.h);
+                paste_sprites(&mut img, &path, 0, columns, &images)?;
+                save_png(&img, &path)?;
+
+                let mut sheet = json!({
+                    "file": name,
+                    "sprite_width": kind.w,
+                    "sprite_height": kind.h,
+                });
+                if kind.offset_x != 0 {
+                    sheet["sprite_offset_x"] = Value::from(kind.offset_x);
+                }
+                if kind.offset_y != 0 {
+                    sheet["sprite_offset_y"] = Value::from(kind.offset_y);
+                }
+                sheet["tiles"] = json!([]);
+                if let Some(Value::Array(sheets)) = raw_a.get_mut("tiles-new") {
+                    sheets.push(sheet);
+                }
+                new_sheets.push(name);
+                (columns, a.tiles_new.len() + new_sheets.len() - 1)
+            }
+        };
+
+        for (k, (idx, _)) in sprites.iter().enumerate() {
+            remap.insert(*idx, next_index + k as u32);
+        }
+        next_index += count.div_ceil(columns) * columns;
+        sheet_of_kind.insert(*kind, dest);
+        summary.sprites_copied += sprites.len();
+    }
+
+    let merged: HashSet<&str> = grafts
+        .iter()
+        .flat_map(|x| x.ids.iter().map(|x| x.as_str()))
+        .collect();
+    let replaced = remove_ids(&mut raw_a, &merged);
+
+    let mut provenance = ProvenanceLog::load(&a.base_path)?;
+    let date = today();
+    let sheet_count = a.tiles_new.len() + new_sheets.len();
+    for graft in grafts {
+        let kind = SheetKind::of(b, &b.tiles_new[graft.sheet]);
+        let dest = sheet_of_kind.get(&kind).copied().unwrap_or(sheet_count - 1);
+        let mut raw = graft.raw;
+        remap_entry(&mut raw, &remap);
+        if let Some(Value::Array(tiles)) = raw_a["tiles-new"][dest].get_mut("tiles") {
+            tiles.push(raw);
+        }
+
+        for id in &graft.ids {
+            provenance.record(
+                id,
+                ProvenanceRecord {
+                    command: command.to_string(),
+                    date: date.clone(),
+                    source: source.to_string_lossy().to_string(),
+                    sheet: b.tiles_new[graft.sheet].file.clone(),
+                    entry: graft.entry_ids.clone(),
+                },
+            );
+            if replaced.contains(id) {
+                summary.replaced.push(id.clone());
+            }
+        }
+        summary.merged.extend(graft.ids);
+    }
+
+    write_output(&config_a, serde_json::to_string_pretty(&raw_a)?)?;
+    provenance.save()?;
+
+    Ok(summary)
+}
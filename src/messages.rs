@@ -0,0 +1,113 @@
+use clap::ArgEnum;
+use serde::Deserialize;
+
+/// Language of the human-readable reports. Machine formats, such as id
+/// lists and JSON dumps, are the same in every language.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Lang {
+    #[default]
+    En,
+    Ru,
+}
+
+/// A user-facing string of the reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Msg {
+    Verdict,
+    VerdictUnknown,
+    VerdictDifferent,
+    VerdictIdentical,
+    DuplicatesInA,
+    DuplicatesInB,
+    OnlyInA,
+    OnlyInB,
+    Changed,
+    Similar,
+    AsciiChanges,
+    OrderingChanges,
+    SubtileChanges,
+    ReportTitle,
+    NotCompared,
+    ColumnId,
+    ColumnChanges,
+    ColumnReview,
+    Accepted,
+    Rejected,
+    NeedsWork,
+    BadgeLabel,
+    BadgeUnknown,
+}
+
+fn en(msg: Msg) -> &'static str {
+    match msg {
+        Msg::Verdict => "Verdict",
+        Msg::VerdictUnknown => "unknown, duplicate ids prevented the diff",
+        Msg::VerdictDifferent => "tilesets differ",
+        Msg::VerdictIdentical => "tilesets are identical",
+        Msg::DuplicatesInA => "Duplicates in A",
+        Msg::DuplicatesInB => "Duplicates in B",
+        Msg::OnlyInA => "Only in A",
+        Msg::OnlyInB => "Only in B",
+        Msg::Changed => "Changed",
+        Msg::Similar => "Perceptually similar",
+        Msg::AsciiChanges => "Fallback glyphs",
+        Msg::OrderingChanges => "Overlay ordering",
+        Msg::SubtileChanges => "Multitile subtiles",
+        Msg::ReportTitle => "Tileset comparison",
+        Msg::NotCompared => "Not compared: duplicate ids found.",
+        Msg::ColumnId => "id",
+        Msg::ColumnChanges => "changes",
+        Msg::ColumnReview => "review",
+        Msg::Accepted => "accepted",
+        Msg::Rejected => "rejected",
+        Msg::NeedsWork => "needs work",
+        Msg::BadgeLabel => "tiles changed",
+        Msg::BadgeUnknown => "unknown",
+    }
+}
+
+fn ru(msg: Msg) -> &'static str {
+    match msg {
+        Msg::Verdict => "Итог",
+        Msg::VerdictUnknown => "неизвестно, повторяющиеся id помешали сравнению",
+        Msg::VerdictDifferent => "тайлсеты различаются",
+        Msg::VerdictIdentical => "тайлсеты совпадают",
+        Msg::DuplicatesInA => "Повторы в A",
+        Msg::DuplicatesInB => "Повторы в B",
+        Msg::OnlyInA => "Только в A",
+        Msg::OnlyInB => "Только в B",
+        Msg::Changed => "Изменены",
+        Msg::Similar => "Визуально похожи",
+        Msg::AsciiChanges => "Запасные символы",
+        Msg::OrderingChanges => "Порядок оверлеев",
+        Msg::SubtileChanges => "Части мультитайлов",
+        Msg::ReportTitle => "Сравнение тайлсетов",
+        Msg::NotCompared => "Не сравнивались: найдены повторяющиеся id.",
+        Msg::ColumnId => "id",
+        Msg::ColumnChanges => "изменения",
+        Msg::ColumnReview => "проверка",
+        Msg::Accepted => "принято",
+        Msg::Rejected => "отклонено",
+        Msg::NeedsWork => "нужна доработка",
+        Msg::BadgeLabel => "изменено тайлов",
+        Msg::BadgeUnknown => "неизвестно",
+    }
+}
+
+impl Lang {
+    /// Language tag, e.g. for the `lang` attribute of HTML reports.
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Ru => "ru",
+        }
+    }
+
+    pub fn text(self, msg: Msg) -> &'static str {
+        match self {
+            Lang::En => en(msg),
+            Lang::Ru => ru(msg),
+        }
+    }
+}
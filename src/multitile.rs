@@ -0,0 +1,127 @@
+use crate::id_order::IdOrder;
+use crate::tileset::{CompositeTile, Tileset};
+use std::collections::HashMap;
+
+/// Subtiles the game draws a connecting multitile with, named by the ids of
+/// its `additional_tiles`.
+pub const CONNECTION_SUBTILES: &[&str] = &[
+    "center",
+    "corner",
+    "edge",
+    "t_connection",
+    "end_piece",
+    "unconnected",
+];
+/// Subtiles only drawn in some states, such as broken windows or open doors,
+/// which a complete multitile may leave out.
+pub const STATE_SUBTILES: &[&str] = &["broken", "open"];
+
+/// Standard subtiles of one multitile id.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Subtiles {
+    pub id: String,
+    pub present: Vec<&'static str>,
+    /// Connection subtiles without a definition
+    pub missing: Vec<&'static str>,
+}
+
+fn subtiles(id: &str, tile: &CompositeTile) -> Subtiles {
+    let has = |name: &&str| {
+        tile.additional_tiles
+            .iter()
+            .any(|x| x.id.0.iter().any(|x| x == name))
+    };
+    Subtiles {
+        id: id.to_string(),
+        present: CONNECTION_SUBTILES
+            .iter()
+            .chain(STATE_SUBTILES)
+            .copied()
+            .filter(has)
+            .collect(),
+        missing: CONNECTION_SUBTILES
+            .iter()
+            .copied()
+            .filter(|x| !has(x))
+            .collect(),
+    }
+}
+
+/// Standard subtiles of every id passing the id filter that is a multitile
+/// or has `additional_tiles`, in definition order.
+pub fn multitile_subtiles(ts: &Tileset) -> Vec<Subtiles> {
+    let mut ret = vec![];
+    for tile in ts.tiles_new.iter().flat_map(|x| x.tiles.iter()) {
+        if !tile.base.multitile && tile.additional_tiles.is_empty() {
+            continue;
+        }
+        for id in tile.base.id.0.iter().filter(|x| ts.keeps(x)) {
+            ret.push(subtiles(id, tile));
+        }
+    }
+    ret
+}
+
+/// Multitiles of both tilesets whose missing subtiles differ, e.g.
+/// `t_wall: A lacks corner; B lacks edge, end_piece`.
+pub fn describe_subtile_changes(a: &[Subtiles], b: &[Subtiles], order: IdOrder) -> Vec<String> {
+    let by_id: HashMap<&str, &Subtiles> = b.iter().map(|x| (x.id.as_str(), x)).collect();
+    let mut a: Vec<&Subtiles> = a.iter().collect();
+    a.sort_by(|x, y| order.cmp(&x.id, &y.id));
+    let lacks = |side: &str, x: &Subtiles| match x.missing.as_slice() {
+        [] => format!("{} complete", side),
+        missing => format!("{} lacks {}", side, missing.join(", ")),
+    };
+    a.into_iter()
+        .filter_map(|x| {
+            let y = by_id.get(x.id.as_str())?;
+            if x.missing == y.missing {
+                return None;
+            }
+            Some(format!("{}: {}; {}", x.id, lacks("A", x), lacks("B", y)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tileset(subtiles: &str) -> Tileset {
+        let json = format!(
+            r#"{{
+              "tile_info": [ {{ "width": 32, "height": 32 }} ],
+              "tiles-new": [ {{ "file": "main.png", "tiles": [
+                {{ "id": "t_floor", "fg": 0 }},
+                {{ "id": "t_wall", "fg": 1, "multitile": true, "additional_tiles": [{}] }}
+              ] }} ]
+            }}"#,
+            subtiles
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn finds_missing_subtiles() {
+        let a = multitile_subtiles(&tileset(
+            r#"{ "id": "center", "fg": 2 }, { "id": "broken", "fg": 3 }"#,
+        ));
+        assert_eq!(a.len(), 1);
+        assert_eq!(a[0].present, ["center", "broken"]);
+        assert_eq!(
+            a[0].missing,
+            ["corner", "edge", "t_connection", "end_piece", "unconnected"]
+        );
+
+        let full: Vec<String> = CONNECTION_SUBTILES
+            .iter()
+            .map(|x| format!(r#"{{ "id": "{}", "fg": 2 }}"#, x))
+            .collect();
+        let b = multitile_subtiles(&tileset(&full.join(", ")));
+        assert!(b[0].missing.is_empty());
+        assert_eq!(
+            describe_subtile_changes(&a, &b, IdOrder::Natural),
+            ["t_wall: A lacks corner, edge, t_connection, end_piece, unconnected; B complete"]
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use crate::single_or_vec::SingleOrVec;
+use crate::sprite_id_with_weight::{SpriteIdWithWeight, NEGATIVE_SPRITE_ID};
+use clap::ArgEnum;
+use serde::Deserialize;
+
+/// Convention for sprite indices that mean "no sprite" rather than real art.
+///
+/// The game itself treats every index as a real sprite, but some editors and
+/// packing scripts emit `0` or `-1` as placeholders. Placeholder indices are
+/// removed from `fg`/`bg` before hashing, so a placeholder compares equal to
+/// an absent sprite instead of being hashed as whatever happens to sit in the
+/// atlas at that position.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NullSprite {
+    /// Every index refers to real art
+    #[default]
+    None,
+    /// Index 0 is a placeholder
+    Zero,
+    /// Negative indices (usually -1) are placeholders
+    Negative,
+}
+
+impl NullSprite {
+    pub fn is_null(self, id: u64) -> bool {
+        match self {
+            NullSprite::None => false,
+            NullSprite::Zero => id == 0,
+            NullSprite::Negative => id == NEGATIVE_SPRITE_ID,
+        }
+    }
+
+    /// Removes placeholder indices from a sprite list. A list left with
+    /// only placeholders becomes absent, like the sprite it stands in for.
+    pub fn strip(self, list: &mut Option<SingleOrVec<SpriteIdWithWeight>>) {
+        let ids = match list {
+            Some(ids) if self != NullSprite::None => ids,
+            _ => return,
+        };
+        let had_sprites = !ids.0.is_empty();
+        for spidw in &mut ids.0 {
+            spidw.id.0.retain(|id| !self.is_null(*id));
+        }
+        ids.0.retain(|spidw| !spidw.id.0.is_empty());
+        if had_sprites && ids.0.is_empty() {
+            *list = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(json: &str) -> Option<SingleOrVec<SpriteIdWithWeight>> {
+        Some(serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn strips_zero() {
+        let mut fg = list(r#"[0, { "weight": 2, "sprite": [0, 5] }, -1]"#);
+        NullSprite::Zero.strip(&mut fg);
+        assert_eq!(fg, list(r#"[{ "weight": 2, "sprite": 5 }, -1]"#));
+    }
+
+    #[test]
+    fn strips_negative() {
+        let mut fg = list(r#"[-1, 0, { "weight": 2, "sprite": [-1, 5] }]"#);
+        NullSprite::Negative.strip(&mut fg);
+        assert_eq!(fg, list(r#"[0, { "weight": 2, "sprite": 5 }]"#));
+
+        let mut none = list("[-1, 0]");
+        NullSprite::None.strip(&mut none);
+        assert_eq!(none, list("[-1, 0]"));
+    }
+
+    #[test]
+    fn placeholder_only_list_becomes_absent() {
+        let mut fg = list("[-1, { \"weight\": 3, \"sprite\": [-2, -1] }]");
+        NullSprite::Negative.strip(&mut fg);
+        assert_eq!(fg, None);
+
+        let mut zero = list("0");
+        NullSprite::Zero.strip(&mut zero);
+        assert_eq!(zero, None);
+    }
+}
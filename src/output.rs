@@ -0,0 +1,246 @@
+use crate::atlas::{HashBits, HashOptions};
+use crate::compare::{describe_variation_changes, ComparisonResult};
+use crate::dump_index::write_dump;
+use crate::error::{write_output, Result};
+use crate::field_diff::{field_changes, FieldChange};
+use crate::id_order::IdOrder;
+use crate::property_matrix::render_matrix;
+use crate::sprite_tag::full_tag;
+use crate::tileset::{SingleTile, Tileset};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+fn dump_variations(vars: &[SingleTile], ts: &Tileset, order: IdOrder) -> Result<()> {
+    let mut vars: Vec<&SingleTile> = vars.iter().collect();
+    vars.sort_by(|a, b| order.cmp(&a.id.0[0], &b.id.0[0]).then_with(|| a.cmp(b)));
+    write_dump(&vars, &ts.out_path)
+}
+
+#[derive(Serialize)]
+struct DumpMeta {
+    hash_mode: &'static str,
+    hash_algo: &'static str,
+    hash_bits: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    similarity: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alpha_threshold: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fuzz: Option<f32>,
+}
+
+pub fn dump_meta(opts: &HashOptions, out_dir: &Path) -> Result<()> {
+    let meta = DumpMeta {
+        hash_mode: opts.hash_mode(),
+        hash_algo: opts.hash_algo.name(),
+        hash_bits: opts.hash_bits.bits(),
+        similarity: opts.similarity,
+        alpha_threshold: opts.alpha_threshold,
+        fuzz: opts.fuzz,
+    };
+    let dump = serde_json::to_string_pretty(&meta)?;
+    write_output(&out_dir.join("meta.json"), dump)
+}
+
+pub fn dump_id_list(ids: &[String], ts: &Tileset, file_name: &str) -> Result<()> {
+    write_output(&ts.out_path.join(file_name), ids.join("\n"))
+}
+
+/// Field changes of one id, as written to `different.json`.
+#[derive(Serialize)]
+pub struct TileChanges<'a> {
+    pub id: &'a str,
+    /// Lines of the definitions in tileset A's and B's tile_config.json
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_a: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_b: Option<usize>,
+    pub changes: Vec<FieldChange>,
+}
+
+impl<'a> TileChanges<'a> {
+    pub fn new(
+        a: &'a SingleTile,
+        b: &SingleTile,
+        ts1: &Tileset,
+        ts2: &Tileset,
+        bits: HashBits,
+    ) -> Self {
+        let id = a.id.0[0].as_str();
+        TileChanges {
+            id,
+            line_a: ts1.source.line(id),
+            line_b: ts2.source.line(id),
+            changes: field_changes(a, b, bits),
+        }
+    }
+}
+
+/// Entries of tileset B listing at least this many ids, such as all corpses
+/// sharing one sprite, are reported as one group in `different.txt` when
+/// every changed id among them changed the same way.
+pub const GROUP_MIN_IDS: usize = 20;
+
+/// Index of the tile entry of `ts` listing each id, for entries listing at
+/// least `GROUP_MIN_IDS` ids.
+fn id_groups(ts: &Tileset) -> HashMap<&str, usize> {
+    ts.tiles_new
+        .iter()
+        .flat_map(|x| x.tiles.iter())
+        .filter(|x| x.base.id.0.len() >= GROUP_MIN_IDS)
+        .enumerate()
+        .flat_map(|(k, x)| x.base.id.0.iter().map(move |id| (id.as_str(), k)))
+        .collect()
+}
+
+/// Writes the ids that differ, each annotated with its variation changes
+/// going from tileset A to tileset B and the lines defining it, and followed
+/// by the fields that changed, into `different.txt`, and the same field
+/// changes into `different.json`, in the output directories of both tilesets.
+/// Unless `expand_groups` is set, large groups of ids that changed the same
+/// way take a single entry of `different.txt`.
+fn dump_diffs(
+    elems: &[(&SingleTile, &SingleTile)],
+    ts1: &Tileset,
+    ts2: &Tileset,
+    expand_groups: bool,
+    bits: HashBits,
+) -> Result<()> {
+    let tiles: Vec<TileChanges> = elems
+        .iter()
+        .map(|(a, b)| TileChanges::new(a, b, ts1, ts2, bits))
+        .collect();
+
+    let groups = if expand_groups {
+        HashMap::new()
+    } else {
+        id_groups(ts2)
+    };
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (k, tile) in tiles.iter().enumerate() {
+        if let Some(group) = groups.get(tile.id) {
+            members.entry(*group).or_default().push(k);
+        }
+    }
+    // First member of each collapsed group, and the number of its members
+    let mut collapsed: HashMap<usize, usize> = HashMap::new();
+    let mut hidden: HashSet<usize> = HashSet::new();
+    for list in members.values().filter(|x| x.len() > 1) {
+        let first = &tiles[list[0]];
+        if list.iter().all(|k| tiles[*k].changes == first.changes) {
+            collapsed.insert(list[0], list.len());
+            hidden.extend(&list[1..]);
+        }
+    }
+
+    let mut lines: Vec<String> = vec![];
+    for (k, ((a, b), tile)) in elems.iter().zip(&tiles).enumerate() {
+        if hidden.contains(&k) {
+            continue;
+        }
+        let id = tile.id;
+        let at = match (tile.line_a, tile.line_b) {
+            (Some(la), Some(lb)) => format!(" [lines {} -> {}]", la, lb),
+            _ => String::new(),
+        };
+        let desc = describe_variation_changes(a, b);
+        lines.push(match (collapsed.get(&k), desc) {
+            (Some(count), desc) => {
+                let sharing = match b.variations().next().and_then(|x| x.id.0.first()) {
+                    Some(sprite) => format!("sharing sprite {}", full_tag(*sprite, bits)),
+                    None => "sharing one definition".to_string(),
+                };
+                let desc = desc.map_or(String::new(), |x| format!(": {}", x));
+                format!(
+                    "group of {} ids {} changed, e.g. {}{}{}",
+                    count, sharing, id, desc, at
+                )
+            }
+            (None, Some(desc)) => format!("{}: {}{}", id, desc, at),
+            (None, None) => format!("{}{}", id, at),
+        });
+        for change in &tile.changes {
+            lines.push(format!(
+                "  {}: {} -> {}",
+                change.field, change.before, change.after
+            ));
+        }
+    }
+    let structured = tiles;
+    let text = lines.join("\n");
+    let json = serde_json::to_string_pretty(&structured)?;
+    for ts in [ts1, ts2] {
+        write_output(&ts.out_path.join("different.txt"), &text)?;
+        write_output(&ts.out_path.join("different.json"), &json)?;
+    }
+    Ok(())
+}
+
+/// Writes the sprites of both tilesets into their output directories,
+/// returning whether either `sprites` directory already existed.
+pub fn write_sprites(result: &ComparisonResult, ts1: &Tileset, ts2: &Tileset) -> Result<bool> {
+    let existed_a = ts1.dump_sprites(&result.atlases_a)?;
+    let existed_b = ts2.dump_sprites(&result.atlases_b)?;
+    Ok(existed_a || existed_b)
+}
+
+/// Writes the variation dumps and hashing metadata of both tilesets.
+pub fn write_dumps(
+    result: &ComparisonResult,
+    ts1: &Tileset,
+    ts2: &Tileset,
+    opts: &HashOptions,
+    order: IdOrder,
+) -> Result<()> {
+    dump_variations(&result.variations_a, ts1, order)?;
+    dump_variations(&result.variations_b, ts2, order)?;
+    dump_meta(opts, &ts1.out_path)?;
+    dump_meta(opts, &ts2.out_path)
+}
+
+/// Writes duplicates, exclusives, diffs, fallback glyph, overlay ordering and
+/// multitile subtile changes, and the property matrix, into the output
+/// directories of both tilesets.
+pub fn write_id_lists(
+    result: &ComparisonResult,
+    ts1: &Tileset,
+    ts2: &Tileset,
+    expand_groups: bool,
+    bits: HashBits,
+) -> Result<()> {
+    dump_id_list(&result.duplicates_a, ts1, "duplicates.txt")?;
+    dump_id_list(&result.duplicates_b, ts2, "duplicates.txt")?;
+    dump_id_list(&result.only_in_a, ts1, "exclusives.txt")?;
+    dump_id_list(&result.only_in_b, ts2, "exclusives.txt")?;
+    dump_id_list(&result.ascii_changes, ts1, "ascii.txt")?;
+    dump_id_list(&result.ascii_changes, ts2, "ascii.txt")?;
+    dump_id_list(&result.ordering_changes, ts1, "ordering.txt")?;
+    dump_id_list(&result.ordering_changes, ts2, "ordering.txt")?;
+    dump_id_list(&result.subtile_changes, ts1, "subtiles.txt")?;
+    dump_id_list(&result.subtile_changes, ts2, "subtiles.txt")?;
+    let matrix = render_matrix(&result.properties);
+    write_output(&ts1.out_path.join("properties.txt"), &matrix)?;
+    write_output(&ts2.out_path.join("properties.txt"), &matrix)?;
+
+    if result.changed.is_some() {
+        let pairs = result.changed_pairs();
+        dump_diffs(&pairs, ts1, ts2, expand_groups, bits)?;
+    }
+
+    Ok(())
+}
+
+/// Writes sprites, variation dumps, duplicates, exclusives and diffs
+/// of a comparison into the output directories of both tilesets.
+pub fn write_comparison(
+    result: &ComparisonResult,
+    ts1: &Tileset,
+    ts2: &Tileset,
+    opts: &HashOptions,
+    order: IdOrder,
+) -> Result<()> {
+    write_sprites(result, ts1, ts2)?;
+    write_dumps(result, ts1, ts2, opts, order)?;
+    write_id_lists(result, ts1, ts2, false, opts.hash_bits)
+}
@@ -0,0 +1,27 @@
+use crate::error::Result;
+
+/// Sets the number of worker threads used for hashing; 0 means one per CPU
+/// core. Must be called before any parallel work.
+pub fn set_jobs(jobs: usize) -> Result<()> {
+    if jobs != 0 {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()?;
+    }
+    Ok(())
+}
+
+/// Number of worker threads parallel work currently runs on.
+pub fn current_jobs() -> usize {
+    rayon::current_num_threads()
+}
+
+/// Runs `f` with parallel work limited to `jobs` worker threads.
+pub fn with_jobs<T, F>(jobs: usize, f: F) -> Result<T>
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+    Ok(pool.install(f))
+}
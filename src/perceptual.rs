@@ -0,0 +1,102 @@
+use image::{GenericImageView, Rgba, RgbaImage, SubImage};
+
+/// Width of the downscaled grid used by the difference hash; one column more
+/// than the number of bits per row since bits compare horizontal neighbours.
+const DHASH_W: u32 = 9;
+const DHASH_H: u32 = 8;
+
+/// Bits in a perceptual hash.
+const PHASH_BITS: u32 = DHASH_H * (DHASH_W - 1);
+
+fn luma(px: &Rgba<u8>) -> u32 {
+    // Composite onto black so transparent pixels compare as dark.
+    let [r, g, b, a] = px.0;
+    let l = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+    l * a as u32 / 255
+}
+
+/// Perceptual signature of a sprite. The difference hash captures structure
+/// but is blind to flat recolors, so the mean color is kept alongside it.
+#[derive(Clone, Copy, Debug)]
+pub struct PerceptualHash {
+    pub dhash: u64,
+    pub mean: [u8; 4],
+}
+
+impl PerceptualHash {
+    pub fn new(img: &SubImage<&RgbaImage>) -> Self {
+        PerceptualHash {
+            dhash: dhash(img),
+            mean: mean_color(img),
+        }
+    }
+
+    /// Whether the two sprites are at least `similarity` percent alike.
+    pub fn is_similar(&self, other: &PerceptualHash, similarity: f32) -> bool {
+        let similarity = similarity.clamp(0.0, 100.0);
+        let max_channel_diff = ((100.0 - similarity) / 100.0 * 255.0).floor() as i32;
+        let colors_close = self
+            .mean
+            .iter()
+            .zip(other.mean.iter())
+            .all(|(a, b)| (*a as i32 - *b as i32).abs() <= max_channel_diff);
+        colors_close && distance(self.dhash, other.dhash) <= max_distance(similarity)
+    }
+}
+
+fn mean_color(img: &SubImage<&RgbaImage>) -> [u8; 4] {
+    let mut sum = [0u64; 4];
+    let mut count = 0u64;
+    for (_, _, px) in img.pixels() {
+        for (s, c) in sum.iter_mut().zip(px.0.iter()) {
+            *s += *c as u64;
+        }
+        count += 1;
+    }
+    let mut ret = [0u8; 4];
+    for (r, s) in ret.iter_mut().zip(sum.iter()) {
+        *r = (s / std::cmp::max(count, 1)) as u8;
+    }
+    ret
+}
+
+/// Difference hash: average the sprite down to a 9x8 luma grid and set one
+/// bit per cell depending on whether it's brighter than its right neighbour.
+/// Re-exports and faint noise leave most bits intact.
+fn dhash(img: &SubImage<&RgbaImage>) -> u64 {
+    let (w, h) = img.dimensions();
+    let mut grid = [[0u32; DHASH_W as usize]; DHASH_H as usize];
+    for (gy, row) in grid.iter_mut().enumerate() {
+        for (gx, cell) in row.iter_mut().enumerate() {
+            let x0 = gx as u32 * w / DHASH_W;
+            let x1 = std::cmp::max(x0 + 1, (gx as u32 + 1) * w / DHASH_W);
+            let y0 = gy as u32 * h / DHASH_H;
+            let y1 = std::cmp::max(y0 + 1, (gy as u32 + 1) * h / DHASH_H);
+            let mut sum = 0;
+            for y in y0..std::cmp::min(y1, h) {
+                for x in x0..std::cmp::min(x1, w) {
+                    sum += luma(&img.get_pixel(x, y));
+                }
+            }
+            *cell = sum / ((x1 - x0) * (y1 - y0));
+        }
+    }
+
+    let mut ret: u64 = 0;
+    for row in &grid {
+        for pair in row.windows(2) {
+            ret = (ret << 1) | (pair[0] > pair[1]) as u64;
+        }
+    }
+    ret
+}
+
+/// Largest number of differing bits for two hashes to count as at least
+/// `similarity` percent similar.
+fn max_distance(similarity: f32) -> u32 {
+    ((100.0 - similarity) / 100.0 * PHASH_BITS as f32).floor() as u32
+}
+
+fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
@@ -0,0 +1,84 @@
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const BAR_WIDTH: usize = 30;
+/// Redraws are throttled so that drawing never dominates fast phases.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turns progress bars off for the rest of the run, e.g. for CI logs.
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// Progress of one long-running phase, drawn on stderr as
+/// `Hashing sprites [#########           ] 1200/4000` while stderr is a
+/// terminal. Can be advanced from several threads at once. The final count
+/// stays on screen once the phase is dropped.
+pub struct Progress {
+    label: &'static str,
+    total: usize,
+    done: AtomicUsize,
+    last_draw: Mutex<Option<Instant>>,
+    visible: bool,
+}
+
+impl Progress {
+    pub fn new(label: &'static str, total: usize) -> Self {
+        Progress {
+            label,
+            total,
+            done: AtomicUsize::new(0),
+            last_draw: Mutex::new(None),
+            visible: total > 0
+                && ENABLED.load(Ordering::Relaxed)
+                && std::io::stderr().is_terminal(),
+        }
+    }
+
+    /// Counts one more item as processed.
+    pub fn inc(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if !self.visible {
+            return;
+        }
+        // Another thread is drawing, it'll show a recent enough count
+        let mut last_draw = match self.last_draw.try_lock() {
+            Ok(x) => x,
+            Err(_) => return,
+        };
+        // The final count is drawn on drop
+        if done >= self.total || last_draw.is_some_and(|x| x.elapsed() < REDRAW_INTERVAL) {
+            return;
+        }
+        *last_draw = Some(Instant::now());
+        self.draw(done);
+    }
+
+    fn draw(&self, done: usize) {
+        let filled = BAR_WIDTH * done.min(self.total) / self.total;
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r{} [{}{}] {}/{}",
+            self.label,
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            done,
+            self.total
+        );
+        let _ = stderr.flush();
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.visible {
+            self.draw(self.done.load(Ordering::Relaxed));
+            eprintln!();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::id_order::IdOrder;
+use crate::tileset::SingleTile;
+use std::collections::{HashMap, HashSet};
+
+/// Properties compared per id, in column order.
+pub const PROPERTIES: [&str; 6] = ["fg", "bg", "rotates", "multitile", "animated", "height_3d"];
+
+/// Which properties of an id present in both tilesets match, in the order
+/// of `PROPERTIES`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PropertyRow {
+    pub id: String,
+    pub matches: [bool; PROPERTIES.len()],
+}
+
+fn property_matches(a: &SingleTile, b: &SingleTile) -> [bool; PROPERTIES.len()] {
+    [
+        a.fg == b.fg,
+        a.bg == b.bg,
+        a.rotates == b.rotates,
+        a.multitile == b.multitile,
+        a.animated == b.animated,
+        a.height_3d == b.height_3d,
+    ]
+}
+
+/// One row per id defined in both tilesets, using the first definition of
+/// duplicated ids.
+pub fn property_matrix(
+    vars_a: &[SingleTile],
+    vars_b: &[SingleTile],
+    order: IdOrder,
+) -> Vec<PropertyRow> {
+    let mut by_id: HashMap<&str, &SingleTile> = HashMap::new();
+    for var in vars_b {
+        by_id.entry(var.id.0[0].as_str()).or_insert(var);
+    }
+    let mut seen = HashSet::new();
+    let mut ret: Vec<PropertyRow> = vars_a
+        .iter()
+        .filter_map(|a| {
+            let id = a.id.0[0].as_str();
+            let b = by_id.get(id)?;
+            seen.insert(id).then(|| PropertyRow {
+                id: id.to_string(),
+                matches: property_matches(a, b),
+            })
+        })
+        .collect();
+    ret.sort_by(|x, y| order.cmp(&x.id, &y.id));
+    ret
+}
+
+/// Renders the matrix as an aligned table with a ✓ or ✗ per property,
+/// followed by the number of ids matching in each property.
+pub fn render_matrix(rows: &[PropertyRow]) -> String {
+    let id_w = rows.iter().map(|x| x.id.chars().count()).max().unwrap_or(0);
+    let id_w = std::cmp::max(id_w, "id".len());
+    let mut ret = format!("{:<w$}  {}\n", "id", PROPERTIES.join("  "), w = id_w);
+    for row in rows {
+        let mut line = format!("{:<w$}", row.id, w = id_w);
+        for (name, matches) in PROPERTIES.iter().zip(row.matches) {
+            let mark = if matches { "✓" } else { "✗" };
+            line += &format!("  {:<w$}", mark, w = name.len());
+        }
+        ret += line.trim_end();
+        ret += "\n";
+    }
+    let totals: Vec<String> = PROPERTIES
+        .iter()
+        .enumerate()
+        .map(|(k, name)| {
+            let matching = rows.iter().filter(|x| x.matches[k]).count();
+            format!("{} {}/{}", name, matching, rows.len())
+        })
+        .collect();
+    ret += &format!("\nMatching: {}\n", totals.join(", "));
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiles(defs: &[&str]) -> Vec<SingleTile> {
+        defs.iter()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn marks_matching_properties() {
+        let a = tiles(&[
+            r#"{ "id": "t_wall", "fg": 1, "rotates": true }"#,
+            r#"{ "id": "t_floor", "fg": 2 }"#,
+            r#"{ "id": "t_gone", "fg": 3 }"#,
+        ]);
+        let b = tiles(&[
+            r#"{ "id": "t_floor", "fg": 2, "height_3d": 2 }"#,
+            r#"{ "id": "t_wall", "fg": 4, "rotates": true }"#,
+        ]);
+        let rows = property_matrix(&a, &b, IdOrder::Natural);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].id, "t_floor");
+        assert_eq!(rows[0].matches, [true, true, true, true, true, false]);
+        assert_eq!(rows[1].matches, [false, true, true, true, true, true]);
+
+        let table = render_matrix(&rows);
+        assert!(table.starts_with("id       fg  bg  rotates  multitile  animated  height_3d\n"));
+        assert!(table.contains("\nt_wall   ✗   ✓   ✓        ✓          ✓         ✓\n"));
+        assert!(table.ends_with(
+            "Matching: fg 1/2, bg 2/2, rotates 2/2, multitile 2/2, animated 2/2, height_3d 1/2\n"
+        ));
+    }
+}
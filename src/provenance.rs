@@ -0,0 +1,69 @@
+use crate::error::{write_output, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const PROVENANCE_FILE_NAME: &str = "provenance.json";
+
+/// Where a tile entry written by a mutating command came from.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProvenanceRecord {
+    pub command: String,
+    pub date: String,
+    /// Directory of the source tileset
+    pub source: String,
+    /// Sheet of the source tileset the entry was defined in
+    pub sheet: String,
+    /// All ids of the source entry
+    pub entry: Vec<String>,
+}
+
+/// Per-id origin of tile entries, kept next to `tile_config.json` and
+/// updated by every mutating command.
+pub struct ProvenanceLog {
+    path: PathBuf,
+    records: BTreeMap<String, ProvenanceRecord>,
+}
+
+impl ProvenanceLog {
+    /// Loads the log of the given tileset directory, or starts an empty one.
+    pub fn load(tileset_dir: &Path) -> Result<ProvenanceLog> {
+        let path = tileset_dir.join(PROVENANCE_FILE_NAME);
+        let records = if path.is_file() {
+            let data = std::fs::read_to_string(&path).map_err(|source| Error::ReadInput {
+                path: path.clone(),
+                source,
+            })?;
+            serde_json::from_str(&data).map_err(|source| Error::ParseConfig {
+                path: path.clone(),
+                source,
+            })?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(ProvenanceLog { path, records })
+    }
+
+    pub fn record(&mut self, id: &str, record: ProvenanceRecord) {
+        self.records.insert(id.to_string(), record);
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.records.contains_key(id)
+    }
+
+    /// Moves the records of renamed ids to their new ids, all at once so
+    /// that renames can swap ids.
+    pub fn rename(&mut self, map: &BTreeMap<String, String>) {
+        let moved: Vec<(String, ProvenanceRecord)> = map
+            .iter()
+            .filter_map(|(old, new)| Some((new.clone(), self.records.remove(old)?)))
+            .collect();
+        self.records.extend(moved);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dump = serde_json::to_string_pretty(&self.records)?;
+        write_output(&self.path, dump)
+    }
+}
@@ -0,0 +1,101 @@
+use crate::error::{Error, Result};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Reads a JSON file as an untyped value, keeping fields and key order that
+/// the typed structs don't model, for commands that rewrite configs.
+pub fn read_json(path: &Path) -> Result<Value> {
+    let data = std::fs::read_to_string(path).map_err(|source| Error::ReadInput {
+        path: path.to_owned(),
+        source,
+    })?;
+    serde_json::from_str(&data).map_err(|source| Error::ParseConfig {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Reads a `tile_config.json` as an untyped value. A legacy single-sheet
+/// config, with `file`, `tiles` and `ascii` at the top level, is rewritten
+/// into a one-entry `tiles-new`, like `Tileset::load` does.
+pub fn read_tile_config(path: &Path) -> Result<Value> {
+    let mut config = read_json(path)?;
+    if let Value::Object(obj) = &mut config {
+        if !obj.contains_key("tiles-new") && obj.contains_key("file") {
+            let mut sheet = Map::new();
+            for key in ["file", "tiles", "ascii"] {
+                if let Some(value) = obj.remove(key) {
+                    sheet.insert(key.to_string(), value);
+                }
+            }
+            obj.insert("tiles-new".to_string(), Value::from(vec![sheet]));
+        }
+    }
+    Ok(config)
+}
+
+/// `id` value of a tile entry: a string for one id, a list otherwise.
+pub fn id_value(ids: &[String]) -> Value {
+    match ids {
+        [single] => Value::from(single.as_str()),
+        _ => Value::from(ids.to_vec()),
+    }
+}
+
+fn visit_sprite_value(value: &mut Value, f: &mut dyn FnMut(&mut Value)) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                visit_sprite_value(item, f);
+            }
+        }
+        Value::Object(obj) => {
+            if let Some(sprite) = obj.get_mut("sprite") {
+                visit_sprite_value(sprite, f);
+            }
+        }
+        _ => f(value),
+    }
+}
+
+/// Calls `f` on every sprite reference of a raw tile entry: each number (or,
+/// in compose sources, name) in `fg` and `bg`, whether given directly, in a
+/// list, or as the `sprite` of weighted variations, including those of
+/// `additional_tiles`.
+pub fn visit_sprites(entry: &mut Value, f: &mut dyn FnMut(&mut Value)) {
+    for key in ["fg", "bg"] {
+        if let Some(value) = entry.get_mut(key) {
+            visit_sprite_value(value, f);
+        }
+    }
+    if let Some(Value::Array(additional)) = entry.get_mut("additional_tiles") {
+        for at in additional {
+            visit_sprites(at, f);
+        }
+    }
+}
+
+/// Calls `f` on every tile entry of a raw tile config.
+pub fn visit_entries(raw: &mut Value, f: &mut dyn FnMut(&mut Value)) {
+    if let Some(Value::Array(sheets)) = raw.get_mut("tiles-new") {
+        for sheet in sheets {
+            if let Some(Value::Array(tiles)) = sheet.get_mut("tiles") {
+                tiles.iter_mut().for_each(&mut *f);
+            }
+        }
+    }
+}
+
+/// Rewrites the sprite indices of a raw tile entry.
+pub fn remap_entry(entry: &mut Value, remap: &HashMap<u32, u32>) {
+    visit_sprites(entry, &mut |value| {
+        let new = value
+            .as_u64()
+            .and_then(|x| u32::try_from(x).ok())
+            .and_then(|x| remap.get(&x).copied());
+        if let Some(new) = new {
+            *value = Value::from(new);
+        }
+    });
+}
@@ -0,0 +1,107 @@
+use crate::atlas::{hash_sprite_indices, HashOptions, TileAtlas};
+use crate::error::{Error, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// Reads an old→new sprite index mapping, a JSON object such as
+/// `{"12": 40, "13": 41}`.
+pub fn load_remap(path: &Path) -> Result<BTreeMap<u32, u32>> {
+    if !path.is_file() {
+        return Err(Error::NoInputFile(path.to_owned()));
+    }
+    let text = std::fs::read_to_string(path).map_err(|source| Error::ReadInput {
+        path: path.to_owned(),
+        source,
+    })?;
+    let raw: BTreeMap<String, u32> =
+        serde_json::from_str(&text).map_err(|source| Error::ParseConfig {
+            path: path.to_owned(),
+            source,
+        })?;
+    raw.into_iter()
+        .map(|(old, new)| match old.parse() {
+            Ok(old) => Ok((old, new)),
+            Err(_) => Err(Error::BadRemap {
+                path: path.to_owned(),
+                message: format!("key '{}' is not a sprite index", old),
+            }),
+        })
+        .collect()
+}
+
+/// A mapping entry that does not hold.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RemapProblem {
+    /// The old index is outside all atlases of the old tileset
+    OldMissing { old: u32 },
+    /// The new index is outside all atlases of the new tileset
+    NewMissing { old: u32, new: u32 },
+    /// Both sprites exist, but differ
+    Differs { old: u32, new: u32 },
+}
+
+impl fmt::Display for RemapProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemapProblem::OldMissing { old } => {
+                write!(f, "{}: not a sprite of the old tileset", old)
+            }
+            RemapProblem::NewMissing { old, new } => {
+                write!(f, "{} -> {}: not a sprite of the new tileset", old, new)
+            }
+            RemapProblem::Differs { old, new } => {
+                write!(f, "{} -> {}: sprites differ", old, new)
+            }
+        }
+    }
+}
+
+/// Checks that every mapped sprite of the old atlases looks the same as its
+/// new index in the new atlases, as drawn in game.
+pub fn verify_remap(
+    old_atlases: &[TileAtlas],
+    new_atlases: &[TileAtlas],
+    map: &BTreeMap<u32, u32>,
+    opts: &HashOptions,
+) -> Vec<RemapProblem> {
+    let old_hashes = hash_sprite_indices(old_atlases, map.keys().copied(), opts);
+    let new_hashes = hash_sprite_indices(new_atlases, map.values().copied(), opts);
+    map.iter()
+        .filter_map(
+            |(&old, &new)| match (old_hashes.get(&old), new_hashes.get(&new)) {
+                (None, _) => Some(RemapProblem::OldMissing { old }),
+                (_, None) => Some(RemapProblem::NewMissing { old, new }),
+                (Some(a), Some(b)) if a != b => Some(RemapProblem::Differs { old, new }),
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Atlas of 1x1 sprites with the given red values.
+    fn atlas(reds: &[u8]) -> TileAtlas {
+        let pixels: Vec<[u8; 4]> = reds.iter().map(|&x| [x, 0, 0, 255]).collect();
+        TileAtlas::from_pixels(&pixels, 0)
+    }
+
+    #[test]
+    fn reports_broken_entries() {
+        let old = [atlas(&[10, 20, 30])];
+        let new = [atlas(&[30, 10, 99])];
+        let map = BTreeMap::from([(0, 5), (1, 2), (2, 0), (3, 0)]);
+        let problems = verify_remap(&old, &new, &map, &HashOptions::default());
+        assert_eq!(
+            problems,
+            [
+                RemapProblem::NewMissing { old: 0, new: 5 },
+                RemapProblem::Differs { old: 1, new: 2 },
+                RemapProblem::OldMissing { old: 3 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use crate::error::{create_output_dir, write_output, Error, Result};
+use crate::provenance::ProvenanceLog;
+use crate::raw_config::read_tile_config;
+use crate::tileset::Tileset;
+use crate::workspace::LOCK_FILE_NAME;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+/// Reads an old→new id mapping: a JSON object such as
+/// `{"t_wall_old": "t_wall"}` if the file name ends in `.json`, otherwise
+/// CSV lines of `old,new`. Blank lines, lines starting with `#` and an
+/// `old,new` header are skipped.
+pub fn load_id_mapping(path: &Path) -> Result<BTreeMap<String, String>> {
+    if !path.is_file() {
+        return Err(Error::NoInputFile(path.to_owned()));
+    }
+    let text = std::fs::read_to_string(path).map_err(|source| Error::ReadInput {
+        path: path.to_owned(),
+        source,
+    })?;
+    let bad = |message: String| Error::BadIdMapping {
+        path: path.to_owned(),
+        message,
+    };
+    let pairs: Vec<(String, String)> = if path.extension().is_some_and(|x| x == "json") {
+        let map: BTreeMap<String, String> =
+            serde_json::from_str(&text).map_err(|source| Error::ParseConfig {
+                path: path.to_owned(),
+                source,
+            })?;
+        map.into_iter().collect()
+    } else {
+        let mut pairs = vec![];
+        for (k, line) in text.lines().enumerate() {
+            let line = line.trim_start_matches('\u{feff}').trim();
+            if line.is_empty() || line.starts_with('#') || (k == 0 && line == "old,new") {
+                continue;
+            }
+            match line.split_once(',') {
+                Some((old, new)) if !new.contains(',') => {
+                    pairs.push((old.trim().to_string(), new.trim().to_string()))
+                }
+                _ => return Err(bad(format!("line {} is not `old,new`", k + 1))),
+            }
+        }
+        pairs
+    };
+
+    let mut map = BTreeMap::new();
+    for (old, new) in pairs {
+        if old.is_empty() || new.is_empty() {
+            return Err(bad(format!("empty id in '{}' -> '{}'", old, new)));
+        }
+        if let Some(prev) = map.insert(old.clone(), new) {
+            return Err(bad(format!(
+                "'{}' is mapped more than once, first to '{}'",
+                old, prev
+            )));
+        }
+    }
+    Ok(map)
+}
+
+/// What renaming changed in a tile config.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RenameSummary {
+    /// Ids renamed in tile entries, counting every definition
+    pub renamed: usize,
+    /// Ids renamed in `overlay_ordering`
+    pub ordering: usize,
+    /// Mapped ids found nowhere in the config
+    pub missing: Vec<String>,
+}
+
+/// Renames the ids of an `id` value, a string or a list of strings.
+fn rename_id_value(
+    value: &mut Value,
+    map: &BTreeMap<String, String>,
+    found: &mut BTreeSet<String>,
+) -> usize {
+    let mut renamed = 0;
+    let mut rename = |id: &mut Value| {
+        if let Some(new) = id.as_str().and_then(|x| map.get(x)) {
+            found.insert(id.as_str().unwrap_or_default().to_string());
+            *id = Value::from(new.as_str());
+            renamed += 1;
+        }
+    };
+    match value {
+        Value::Array(ids) => ids.iter_mut().for_each(&mut rename),
+        id => rename(id),
+    }
+    renamed
+}
+
+fn id_strings(value: &Value) -> Vec<&str> {
+    match value {
+        Value::Array(ids) => ids.iter().filter_map(|x| x.as_str()).collect(),
+        id => id.as_str().into_iter().collect(),
+    }
+}
+
+/// Renames ids in the tile entries and `overlay_ordering` of a raw tile
+/// config, all at once so that mappings can swap ids. Fails without
+/// changing anything if an id would end up defined more than once where it
+/// wasn't before.
+pub fn rename_ids(
+    raw: &mut Value,
+    map: &BTreeMap<String, String>,
+    path: &Path,
+) -> Result<RenameSummary> {
+    let entries = |raw: &Value| -> Vec<Value> {
+        raw.get("tiles-new")
+            .and_then(|x| x.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.get("tiles").and_then(|x| x.as_array()))
+            .flatten()
+            .filter_map(|x| x.get("id").cloned())
+            .collect()
+    };
+    let mut before: HashMap<String, usize> = HashMap::new();
+    let mut after: HashMap<String, usize> = HashMap::new();
+    for id_value in entries(raw) {
+        for id in id_strings(&id_value) {
+            *before.entry(id.to_string()).or_default() += 1;
+            let new = map.get(id).map_or(id, |x| x.as_str());
+            *after.entry(new.to_string()).or_default() += 1;
+        }
+    }
+    let clashes: Vec<&str> = map
+        .values()
+        .filter(|new| {
+            after.get(*new).copied().unwrap_or(0) > before.get(*new).copied().unwrap_or(0).max(1)
+        })
+        .map(|x| x.as_str())
+        .collect::<BTreeSet<&str>>()
+        .into_iter()
+        .collect();
+    if !clashes.is_empty() {
+        return Err(Error::BadTileset {
+            path: path.to_owned(),
+            message: format!(
+                "renaming would define these ids twice: {}",
+                clashes.join(", ")
+            ),
+        });
+    }
+
+    let mut summary = RenameSummary::default();
+    let mut found = BTreeSet::new();
+    if let Some(Value::Array(sheets)) = raw.get_mut("tiles-new") {
+        for sheet in sheets {
+            if let Some(Value::Array(tiles)) = sheet.get_mut("tiles") {
+                for id in tiles.iter_mut().filter_map(|x| x.get_mut("id")) {
+                    summary.renamed += rename_id_value(id, map, &mut found);
+                }
+            }
+        }
+    }
+    if let Some(Value::Array(ordering)) = raw.get_mut("overlay_ordering") {
+        for id in ordering.iter_mut().filter_map(|x| x.get_mut("id")) {
+            summary.ordering += rename_id_value(id, map, &mut found);
+        }
+    }
+    summary.missing = map
+        .keys()
+        .filter(|x| !found.contains(*x))
+        .cloned()
+        .collect();
+    Ok(summary)
+}
+
+fn copy_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        create_output_dir(parent)?;
+    }
+    std::fs::copy(from, to)
+        .map(|_| ())
+        .map_err(|source| Error::WriteOutput {
+            path: to.to_owned(),
+            source,
+        })
+}
+
+/// Copies the files of a tileset directory, and sheets in subdirectories,
+/// into `dir`.
+fn copy_tileset(ts: &Tileset, dir: &Path) -> Result<()> {
+    let read_err = |source| Error::ReadInput {
+        path: ts.base_path.clone(),
+        source,
+    };
+    for entry in std::fs::read_dir(&ts.base_path).map_err(read_err)? {
+        let entry = entry.map_err(read_err)?;
+        if entry.path().is_file() && entry.file_name() != LOCK_FILE_NAME {
+            copy_file(&entry.path(), &dir.join(entry.file_name()))?;
+        }
+    }
+    for sheet in &ts.tiles_new {
+        let from = ts.base_path.join(&sheet.file);
+        let to = dir.join(&sheet.file);
+        if from.is_file() && !to.is_file() {
+            copy_file(&from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+/// Renames ids of a tileset according to `map`, rewriting its
+/// `tile_config.json` and provenance log in place, or those of a copy of
+/// the tileset in `out_dir`.
+pub fn rename(
+    ts: &Tileset,
+    map: &BTreeMap<String, String>,
+    out_dir: Option<&Path>,
+) -> Result<RenameSummary> {
+    let config = ts.base_path.join("tile_config.json");
+    let mut raw = read_tile_config(&config)?;
+    let summary = rename_ids(&mut raw, map, &config)?;
+
+    let dir = match out_dir {
+        Some(dir) => {
+            create_output_dir(dir)?;
+            copy_tileset(ts, dir)?;
+            dir
+        }
+        None => &ts.base_path,
+    };
+    write_output(
+        &dir.join("tile_config.json"),
+        serde_json::to_string_pretty(&raw)?,
+    )?;
+
+    let mut provenance = ProvenanceLog::load(dir)?;
+    if map.keys().any(|x| provenance.contains(x)) {
+        provenance.rename(map);
+        provenance.save()?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn renames_entries_and_ordering() {
+        let mut raw = json!({
+            "tiles-new": [ { "file": "a.png", "tiles": [
+                { "id": ["t_a", "t_b"], "fg": 0 },
+                { "id": "t_c", "fg": 1 }
+            ] } ],
+            "overlay_ordering": [ { "id": ["t_c", "t_x"], "order": 1 } ]
+        });
+        let map = BTreeMap::from([
+            ("t_a".to_string(), "t_b".to_string()),
+            ("t_b".to_string(), "t_a".to_string()),
+            ("t_c".to_string(), "t_d".to_string()),
+            ("t_gone".to_string(), "t_e".to_string()),
+        ]);
+        let summary = rename_ids(&mut raw, &map, Path::new("x")).unwrap();
+        assert_eq!(summary.renamed, 3);
+        assert_eq!(summary.ordering, 1);
+        assert_eq!(summary.missing, ["t_gone"]);
+        assert_eq!(raw["tiles-new"][0]["tiles"][0]["id"], json!(["t_b", "t_a"]));
+        assert_eq!(raw["tiles-new"][0]["tiles"][1]["id"], json!("t_d"));
+        assert_eq!(raw["overlay_ordering"][0]["id"], json!(["t_d", "t_x"]));
+
+        let clash = BTreeMap::from([("t_a".to_string(), "t_d".to_string())]);
+        assert!(rename_ids(&mut raw, &clash, Path::new("x")).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use crate::atlas::{paste_sprites, save_png, TileAtlas};
+use crate::error::{write_output, Result};
+use crate::raw_config::{read_tile_config, remap_entry, visit_entries, visit_sprites};
+use crate::tileset::Tileset;
+use image::RgbaImage;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// What repacking changed in a tileset.
+#[derive(Debug, Default)]
+pub struct RepackSummary {
+    /// Sheets rebuilt from their referenced sprites
+    pub sheets: Vec<String>,
+    /// Sheets removed from `tile_config.json` because they had no tiles and
+    /// no referenced sprites; their images are left on disk
+    pub dropped: Vec<String>,
+    /// Unreferenced sprite slots removed
+    pub removed: usize,
+}
+
+/// Sprite indices referenced by any tile entry of a raw tile config,
+/// regardless of id filters.
+fn referenced_sprites(raw: &mut Value) -> BTreeSet<u32> {
+    let mut ret = BTreeSet::new();
+    visit_entries(raw, &mut |entry| {
+        visit_sprites(entry, &mut |value| {
+            if let Some(idx) = value.as_u64().and_then(|x| u32::try_from(x).ok()) {
+                ret.insert(idx);
+            }
+        })
+    });
+    ret
+}
+
+/// Builds an atlas of `kept` sprites in order, as wide as the old one if
+/// there are enough of them.
+fn packed_atlas(atlas: &TileAtlas, kept: &[u32]) -> Result<RgbaImage> {
+    let count = kept.len() as u32;
+    let columns = std::cmp::min(count, atlas.tiles_x.max(1));
+    let rows = count.div_ceil(columns);
+    let mut img = RgbaImage::new(columns * atlas.sprite_w, rows * atlas.sprite_h);
+    let sprites: Vec<_> = kept.iter().map(|&x| atlas.get_sprite(x)).collect();
+    paste_sprites(&mut img, Path::new(&atlas.file), 0, columns, &sprites)?;
+    Ok(img)
+}
+
+/// Rebuilds every sheet of a tileset from only the sprites its tile entries
+/// reference, in their old order, and rewrites every sprite index to match.
+/// Sheets with fallback glyphs are kept whole, since glyphs are found by
+/// their position in the sheet. Rewrites the sheets and `tile_config.json`
+/// in place.
+pub fn repack(ts: &Tileset) -> Result<RepackSummary> {
+    let config = ts.base_path.join("tile_config.json");
+    let mut raw = read_tile_config(&config)?;
+    let atlases = ts.load_atlases()?;
+    let used = referenced_sprites(&mut raw);
+
+    let mut summary = RepackSummary::default();
+    let mut remap: HashMap<u32, u32> = HashMap::new();
+    let mut dropped = vec![];
+    let mut next_start = 0;
+    for (k, (sheet, atlas)) in ts.tiles_new.iter().zip(&atlases).enumerate() {
+        let start = next_start;
+        let kept: Vec<u32> = used
+            .range(atlas.tiles_start..atlas.tiles_end)
+            .copied()
+            .collect();
+        let whole = !sheet.ascii.is_empty() || kept.len() as u32 == atlas.tiles_total();
+        let kept: Vec<u32> = if whole {
+            (atlas.tiles_start..atlas.tiles_end).collect()
+        } else if kept.is_empty() && sheet.tiles.is_empty() {
+            dropped.push(k);
+            summary.dropped.push(sheet.file.clone());
+            summary.removed += atlas.tiles_total() as usize;
+            kept
+        } else if kept.is_empty() {
+            // An image can't be empty, so keep one slot
+            vec![atlas.tiles_start]
+        } else {
+            kept
+        };
+        if !whole && !kept.is_empty() {
+            save_png(
+                &packed_atlas(atlas, &kept)?,
+                &ts.base_path.join(&sheet.file),
+            )?;
+            summary.sheets.push(sheet.file.clone());
+            summary.removed += (atlas.tiles_total() - kept.len() as u32) as usize;
+        }
+        for (new, &old) in (start..).zip(&kept) {
+            if new != old {
+                remap.insert(old, new);
+            }
+        }
+        next_start = start + kept.len() as u32;
+    }
+
+    if let Some(Value::Array(sheets)) = raw.get_mut("tiles-new") {
+        for &k in dropped.iter().rev() {
+            sheets.remove(k);
+        }
+    }
+    if !remap.is_empty() {
+        visit_entries(&mut raw, &mut |entry| remap_entry(entry, &remap));
+    }
+    write_output(&config, serde_json::to_string_pretty(&raw)?)?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn finds_sprites_of_every_entry() {
+        let mut raw = json!({
+            "tiles-new": [
+                { "file": "a.png", "tiles": [
+                    { "id": "t_a", "fg": 3, "bg": [{ "sprite": 1, "weight": 1 }] },
+                    { "id": "t_b", "fg": [5, 6], "additional_tiles": [
+                        { "id": "center", "fg": 9 }
+                    ] }
+                ] },
+                { "file": "b.png", "tiles": [ { "id": "t_c", "fg": 3 } ] }
+            ]
+        });
+        let used = referenced_sprites(&mut raw);
+        assert_eq!(used, BTreeSet::from([1, 3, 5, 6, 9]));
+    }
+}
@@ -0,0 +1,262 @@
+use crate::compare::{CompareOptions, ComparisonResult};
+use crate::comparison_sheet::write_comparison_sheet;
+use crate::error::{write_output, Result, EXIT_OK, EXIT_PROBLEMS_FOUND};
+use crate::heatmap::write_heatmaps;
+use crate::highlight::write_highlight_tileset;
+use crate::history::{comparison_metrics, record_history};
+use crate::html_report::write_comparison_report;
+use crate::info;
+use crate::json_report::write_json_report;
+use crate::markdown_report::write_markdown_report;
+use crate::messages::Lang;
+use crate::output::{dump_id_list, write_dumps, write_id_lists, write_sprites};
+use crate::sprite_store::write_shared_sprites;
+use crate::tileset::Tileset;
+use crate::transform::describe_transforms;
+use clap::ArgEnum;
+use serde::Deserialize;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+/// Who a comparison report is for. Each audience is a preset of the
+/// sections written and of how much progress is printed.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Audience {
+    /// Pictures: an HTML report, heatmaps, a side-by-side sheet and sprite
+    /// dumps if asked for, no JSON dumps
+    Artist,
+    /// Every list and JSON dump, with progress messages; sprite dumps, the
+    /// HTML report, heatmaps and side-by-side sheet only when asked for
+    #[default]
+    Maintainer,
+    /// Id lists and JSON dumps only, no progress messages, and exit status 1
+    /// if the tilesets differ
+    Ci,
+}
+
+/// A part of the comparison output that an audience may skip.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    /// One PNG per sprite index in `sprites/`, with `--dump-sprites`
+    Sprites,
+    /// `dump.json` and `meta.json`, plus `report.json` in A's output directory
+    Dumps,
+    /// `duplicates.txt`, `exclusives.txt`, `different.txt`, `different.json`,
+    /// `ascii.txt`, `ordering.txt`, `subtiles.txt`, `properties.txt` and
+    /// `transforms.txt`
+    IdLists,
+    /// HTML report, into `report.html` unless a path is given
+    Html,
+    /// Per-pixel heatmaps and diff images, into `heatmaps/` unless a path is
+    /// given
+    Heatmaps,
+    /// Side-by-side image of changed ids, into `comparison.png` unless a
+    /// path is given
+    Sheet,
+}
+
+impl Audience {
+    pub fn wants(self, section: Section) -> bool {
+        match self {
+            Audience::Maintainer => {
+                !matches!(section, Section::Html | Section::Heatmaps | Section::Sheet)
+            }
+            Audience::Artist => section != Section::Dumps,
+            Audience::Ci => matches!(section, Section::Dumps | Section::IdLists),
+        }
+    }
+}
+
+/// Writes the outputs of a comparison for an audience. Paths given
+/// explicitly are always written; the audience decides everything else.
+#[derive(Debug, Default)]
+pub struct Reporter {
+    pub audience: Audience,
+    pub html: Option<PathBuf>,
+    pub heatmaps: Option<PathBuf>,
+    pub sheet: Option<PathBuf>,
+    pub badge: Option<PathBuf>,
+    /// Markdown summary for a pull request comment
+    pub markdown: Option<PathBuf>,
+    /// Link template of sprite images in the Markdown summary, with
+    /// `{side}` and `{sprite}` placeholders
+    pub markdown_images: Option<String>,
+    /// Directory to write a copy of B into with changed sprites tinted, for
+    /// loading in the game
+    pub highlight: Option<PathBuf>,
+    /// Write every sprite of both tilesets into their `sprites/` directories
+    pub dump_sprites: bool,
+    /// With `dump_sprites`, write the sprites of both tilesets into one
+    /// content-addressed store linked from both `sprites/` directories
+    pub shared_sprites: bool,
+    /// List every id of large groups that changed the same way in
+    /// `different.txt`, instead of one entry per group
+    pub expand_groups: bool,
+    /// CSV history to append the summary metrics of the run to
+    pub history: Option<PathBuf>,
+    /// Print the verdict to stdout instead of writing any file
+    pub read_only: bool,
+    /// Fail the run unless the tilesets are known to be identical, whatever
+    /// the audience
+    pub check: bool,
+    /// Language of the verdict, the HTML report and the badge
+    pub lang: Lang,
+}
+
+impl Reporter {
+    /// Prints a progress message, unless the audience is CI.
+    pub fn say<S: Display>(&self, message: S) {
+        if self.audience != Audience::Ci {
+            info!("{}", message);
+        }
+    }
+
+    /// Where to write the HTML report: the explicit path, or `report.html`
+    /// in A's output directory if the audience wants one.
+    fn html_path(&self, ts1: &Tileset) -> Option<PathBuf> {
+        self.html.clone().or_else(|| {
+            self.audience
+                .wants(Section::Html)
+                .then(|| ts1.out_path.join("report.html"))
+        })
+    }
+
+    fn heatmaps_path(&self, ts1: &Tileset) -> Option<PathBuf> {
+        self.heatmaps.clone().or_else(|| {
+            self.audience
+                .wants(Section::Heatmaps)
+                .then(|| ts1.out_path.join("heatmaps"))
+        })
+    }
+
+    fn sheet_path(&self, ts1: &Tileset) -> Option<PathBuf> {
+        self.sheet.clone().or_else(|| {
+            self.audience
+                .wants(Section::Sheet)
+                .then(|| ts1.out_path.join("comparison.png"))
+        })
+    }
+
+    /// Writes every section of `result` the audience wants into the output
+    /// directories of both tilesets, plus the explicitly requested files.
+    /// In read-only mode, prints the verdict and writes nothing.
+    pub fn write(
+        &self,
+        result: &ComparisonResult,
+        ts1: &Tileset,
+        ts2: &Tileset,
+        opts: &CompareOptions,
+    ) -> Result<()> {
+        if self.read_only {
+            print!("{}", result.verdict(self.lang));
+            return Ok(());
+        }
+        if self.dump_sprites && self.audience.wants(Section::Sprites) {
+            let existed = if self.shared_sprites {
+                let summary = write_shared_sprites(result, ts1, ts2, &opts.hash)?;
+                self.say(format!(
+                    "Stored {} distinct sprite(s) for {} sprite(s), {} of B's shared with A.",
+                    summary.stored, summary.linked, summary.shared
+                ));
+                summary.existed
+            } else {
+                let existed = write_sprites(result, ts1, ts2)?;
+                if opts.low_memory || opts.hash_cache {
+                    self.say(
+                        "NOTE: only the sprites of ids that differ were dumped with --low-memory or --hash-cache.",
+                    );
+                }
+                existed
+            };
+            if existed {
+                self.say("NOTE: sprites were written into directories left by an earlier run, which may still hold sprites of that run.");
+            }
+        }
+        if self.audience.wants(Section::Dumps) {
+            write_dumps(result, ts1, ts2, &opts.hash, opts.order)?;
+            let path = ts1.out_path.join("report.json");
+            write_json_report(&path, result, ts1, ts2, &opts.hash)?;
+        }
+        if self.audience.wants(Section::IdLists) {
+            write_id_lists(result, ts1, ts2, self.expand_groups, opts.hash.hash_bits)?;
+            let transforms = describe_transforms(result, &opts.hash);
+            if !transforms.is_empty() {
+                self.say(format!(
+                    "{} sprite(s) were flipped, rotated or shifted.",
+                    transforms.len()
+                ));
+            }
+            dump_id_list(&transforms, ts1, "transforms.txt")?;
+            dump_id_list(&transforms, ts2, "transforms.txt")?;
+        }
+
+        if let Some(path) = self.html_path(ts1) {
+            self.say(format!("Writing HTML report: {}", path.display()));
+            write_comparison_report(&path, ts1, ts2, result, &opts.hash, self.lang)?;
+        }
+
+        if let Some(dir) = self.heatmaps_path(ts1) {
+            self.say(format!("Writing heatmaps: {}", dir.display()));
+            let written = write_heatmaps(result, &opts.hash, &dir)?;
+            self.say(format!("{} sprite(s) changed in place.", written));
+        }
+
+        if let Some(path) = self.sheet_path(ts1) {
+            self.say(format!("Writing comparison sheet: {}", path.display()));
+            write_comparison_sheet(&path, ts1, ts2, result, &opts.hash)?;
+        }
+
+        if let Some(dir) = &self.highlight {
+            self.say(format!("Writing highlighted tileset: {}", dir.display()));
+            let summary = write_highlight_tileset(result, ts2, dir, &opts.hash)?;
+            self.say(format!("{} sprite(s) tinted.", summary.sprites));
+            if !summary.unmarked.is_empty() {
+                self.say(format!(
+                    "Not tinted, all sprites shared with unchanged ids: {}",
+                    summary.unmarked.join(", ")
+                ));
+            }
+        }
+
+        if let Some(path) = &self.markdown {
+            self.say(format!("Writing Markdown summary: {}", path.display()));
+            write_markdown_report(
+                path,
+                ts1,
+                ts2,
+                result,
+                &opts.hash,
+                self.markdown_images.as_deref(),
+            )?;
+        }
+
+        if let Some(path) = &self.badge {
+            self.say(format!("Writing badge: {}", path.display()));
+            write_output(path, result.badge(self.lang))?;
+        }
+
+        if let Some(path) = &self.history {
+            self.say(format!("Recording history: {}", path.display()));
+            record_history(path, &comparison_metrics(result))?;
+        }
+
+        Ok(())
+    }
+
+    /// Exit status of a comparison: CI fails the run when the tilesets differ,
+    /// everyone else only cares whether the reports were written. In check
+    /// mode, duplicates preventing the diff fail the run too.
+    pub fn exit_code(&self, result: &ComparisonResult) -> i32 {
+        let fails = if self.check {
+            result.changed.is_none() || result.differs()
+        } else {
+            self.audience == Audience::Ci && result.differs()
+        };
+        if fails {
+            EXIT_PROBLEMS_FOUND
+        } else {
+            EXIT_OK
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use crate::atlas::{save_png, TileAtlas};
+use crate::error::{write_output, Result};
+use crate::raw_config::{read_tile_config, remap_entry, visit_entries};
+use crate::tileset::Tileset;
+use image::{GenericImageView, RgbaImage};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// A sprite size, written as `<width>x<height>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for SpriteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let parsed = s
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+        match parsed {
+            Some((width, height)) if width > 0 && height > 0 => Ok(SpriteSize { width, height }),
+            _ => Err(format!(
+                "expected <width>x<height>, e.g. 32x32, got '{}'",
+                s
+            )),
+        }
+    }
+}
+
+/// What reslicing changed in a tileset.
+#[derive(Debug, Default)]
+pub struct ResliceSummary {
+    /// Sheets re-cut into the new sprite size
+    pub sheets: Vec<String>,
+    /// Sprite indices, before reslicing, that lost visible pixels to
+    /// cropping
+    pub cropped: Vec<u32>,
+    /// Sprite indices that moved because an earlier sheet changed size
+    pub moved: usize,
+}
+
+/// A sheet re-cut into a new sprite size.
+struct Resliced {
+    img: RgbaImage,
+    slots: u32,
+    /// Where the old sprites sit within the new ones
+    shift: (i32, i32),
+    cropped: Vec<u32>,
+}
+
+/// Re-cuts an atlas into `width`x`height` sprites, keeping the sheet about
+/// as wide as it was if it has enough sprites. Old sprites are centered
+/// horizontally and aligned to the bottom of the new ones, padded or cropped
+/// as needed.
+fn reslice_atlas(atlas: &TileAtlas, width: u32, height: u32) -> Resliced {
+    let count = atlas.tiles_total();
+    let columns = (atlas.img.width() / width).clamp(1, std::cmp::max(1, count));
+    let rows = count.div_ceil(columns);
+    let shift = (
+        (width as i32 - atlas.sprite_w as i32).div_euclid(2),
+        height as i32 - atlas.sprite_h as i32,
+    );
+    let mut img = RgbaImage::new(columns * width, rows * height);
+    let mut cropped = vec![];
+    for k in 0..count {
+        let sprite = atlas.get_sprite(atlas.tiles_start + k);
+        let x0 = (k % columns * width) as i32;
+        let y0 = (k / columns * height) as i32;
+        let mut lost = false;
+        for (x, y, px) in sprite.pixels() {
+            let (nx, ny) = (x as i32 + shift.0, y as i32 + shift.1);
+            if nx >= 0 && ny >= 0 && nx < width as i32 && ny < height as i32 {
+                img.put_pixel((x0 + nx) as u32, (y0 + ny) as u32, px);
+            } else if px.0[3] != 0 {
+                lost = true;
+            }
+        }
+        if lost {
+            cropped.push(atlas.tiles_start + k);
+        }
+    }
+    Resliced {
+        img,
+        slots: columns * rows,
+        shift,
+        cropped,
+    }
+}
+
+fn set_or_remove(sheet: &mut Value, key: &str, value: i32) {
+    if let Value::Object(obj) = sheet {
+        if value == 0 {
+            obj.remove(key);
+        } else {
+            obj.insert(key.to_string(), Value::from(value));
+        }
+    }
+}
+
+/// Re-cuts every sheet of a tileset whose sprites aren't `width`x`height`
+/// into sprites of that size, adjusting sprite offsets so that sprites are
+/// drawn where they were, and renumbers the sprites of later sheets if the
+/// number of slots changed. Rewrites the sheets and `tile_config.json` in
+/// place.
+pub fn reslice(ts: &Tileset, width: u32, height: u32) -> Result<ResliceSummary> {
+    let config = ts.base_path.join("tile_config.json");
+    let mut raw = read_tile_config(&config)?;
+    let atlases = ts.load_atlases()?;
+
+    let mut summary = ResliceSummary::default();
+    let mut remap: HashMap<u32, u32> = HashMap::new();
+    let mut next_start = 0;
+    for (k, (sheet, atlas)) in ts.tiles_new.iter().zip(&atlases).enumerate() {
+        let start = next_start;
+        let slots = if atlas.is_empty() || (atlas.sprite_w, atlas.sprite_h) == (width, height) {
+            atlas.tiles_total()
+        } else {
+            let resliced = reslice_atlas(atlas, width, height);
+            save_png(&resliced.img, &ts.base_path.join(&sheet.file))?;
+            let raw_sheet = &mut raw["tiles-new"][k];
+            raw_sheet["sprite_width"] = Value::from(width);
+            raw_sheet["sprite_height"] = Value::from(height);
+            set_or_remove(
+                raw_sheet,
+                "sprite_offset_x",
+                atlas.offset_x - resliced.shift.0,
+            );
+            set_or_remove(
+                raw_sheet,
+                "sprite_offset_y",
+                atlas.offset_y - resliced.shift.1,
+            );
+            summary.sheets.push(sheet.file.clone());
+            summary.cropped.extend(resliced.cropped);
+            resliced.slots
+        };
+        if start != atlas.tiles_start {
+            for idx in atlas.tiles_start..atlas.tiles_end {
+                remap.insert(idx, start + idx - atlas.tiles_start);
+            }
+            summary.moved += atlas.tiles_total() as usize;
+        }
+        next_start = start + slots;
+    }
+
+    if !remap.is_empty() {
+        visit_entries(&mut raw, &mut |entry| remap_entry(entry, &remap));
+    }
+    write_output(&config, serde_json::to_string_pretty(&raw)?)?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn pads_and_crops_around_the_bottom_center() {
+        // Three 2x2 sprites in a row, the second with a pixel in its top left
+        let mut img = RgbaImage::new(6, 2);
+        img.put_pixel(2, 0, Rgba([255, 0, 0, 255]));
+        img.put_pixel(5, 1, Rgba([0, 255, 0, 255]));
+
+        let padded = reslice_atlas(&TileAtlas::from_image(img.clone(), 2, 2), 4, 4);
+        assert_eq!(padded.shift, (1, 2));
+        assert_eq!(padded.slots, 3);
+        assert_eq!(padded.img.dimensions(), (4, 12));
+        assert_eq!(padded.img.get_pixel(1, 6), &Rgba([255, 0, 0, 255]));
+        assert!(padded.cropped.is_empty());
+
+        let cropped = reslice_atlas(&TileAtlas::from_image(img, 2, 2), 1, 1);
+        assert_eq!(cropped.shift, (-1, -1));
+        assert_eq!(cropped.slots, 3);
+        assert_eq!(cropped.img.dimensions(), (3, 1));
+        assert_eq!(cropped.img.get_pixel(2, 0), &Rgba([0, 255, 0, 255]));
+        assert_eq!(cropped.cropped, [1]);
+    }
+}
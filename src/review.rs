@@ -0,0 +1,240 @@
+use crate::error::{write_output, Error, Result};
+use crate::html_report::ReportSide;
+use crate::messages::Msg;
+use image::{GenericImageView, RgbaImage, SubImage};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+pub const REVIEW_FILE_NAME: &str = "review.json";
+
+/// Most sprites previewed per side of an id.
+const PREVIEW_MAX_SPRITES: usize = 4;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Decision {
+    Accept,
+    Reject,
+    NeedsWork,
+}
+
+impl Decision {
+    pub fn message(self) -> Msg {
+        match self {
+            Decision::Accept => Msg::Accepted,
+            Decision::Reject => Msg::Rejected,
+            Decision::NeedsWork => Msg::NeedsWork,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ReviewEntry {
+    pub decision: Decision,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+}
+
+/// Per-id review decisions on a comparison, kept in `review.json` in A's
+/// output directory so that a review can be resumed and its decisions
+/// shown in the HTML report.
+#[derive(Debug, Default)]
+pub struct ReviewLog {
+    path: PathBuf,
+    pub decisions: BTreeMap<String, ReviewEntry>,
+}
+
+impl ReviewLog {
+    /// Loads the decisions in the given output directory, or starts an
+    /// empty log.
+    pub fn load(out_dir: &Path) -> Result<ReviewLog> {
+        let path = out_dir.join(REVIEW_FILE_NAME);
+        let decisions = if path.is_file() {
+            let data = std::fs::read_to_string(&path).map_err(|source| Error::ReadInput {
+                path: path.clone(),
+                source,
+            })?;
+            serde_json::from_str(&data).map_err(|source| Error::ParseConfig {
+                path: path.clone(),
+                source,
+            })?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(ReviewLog { path, decisions })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ReviewEntry> {
+        self.decisions.get(id)
+    }
+
+    pub fn record(&mut self, id: &str, entry: ReviewEntry) {
+        self.decisions.insert(id.to_string(), entry);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dump = serde_json::to_string_pretty(&self.decisions)?;
+        write_output(&self.path, dump)
+    }
+}
+
+/// What the reviewer answered for one id.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Answer {
+    Decided(ReviewEntry),
+    Skip,
+    Quit,
+}
+
+/// Asks for a decision and an optional note until a valid key is given.
+/// End of input counts as quitting.
+pub fn ask<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> std::io::Result<Answer> {
+    let decision = loop {
+        write!(
+            output,
+            "[a]ccept, [r]eject, [n]eeds work, [s]kip or [q]uit? "
+        )?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(Answer::Quit);
+        }
+        match line.trim().to_lowercase().as_str() {
+            "a" => break Decision::Accept,
+            "r" => break Decision::Reject,
+            "n" => break Decision::NeedsWork,
+            "s" => return Ok(Answer::Skip),
+            "q" => return Ok(Answer::Quit),
+            _ => continue,
+        }
+    };
+    write!(output, "Note (optional): ")?;
+    output.flush()?;
+    let mut note = String::new();
+    input.read_line(&mut note)?;
+    Ok(Answer::Decided(ReviewEntry {
+        decision,
+        note: note.trim().to_string(),
+    }))
+}
+
+/// Foreground sprites of an id, the first of each variation.
+fn tile_sprites<'a>(side: &ReportSide<'a>, id: &str) -> Vec<SubImage<&'a RgbaImage>> {
+    let tile = match side.tiles.get(id) {
+        Some(x) => x,
+        None => return vec![],
+    };
+    tile.fg()
+        .iter()
+        .filter_map(|x| x.indices().next())
+        .filter_map(|sprite| {
+            let atlas = side.atlases.iter().find(|x| x.in_bounds(sprite))?;
+            Some(atlas.get_sprite(sprite))
+        })
+        .take(PREVIEW_MAX_SPRITES)
+        .collect()
+}
+
+fn push_pixel(line: &mut String, top: [u8; 4], bottom: [u8; 4]) {
+    match (top[3] != 0, bottom[3] != 0) {
+        (false, false) => line.push(' '),
+        (true, false) => line.push_str(&format!(
+            "\x1b[38;2;{};{};{}m▀\x1b[0m",
+            top[0], top[1], top[2]
+        )),
+        (false, true) => line.push_str(&format!(
+            "\x1b[38;2;{};{};{}m▄\x1b[0m",
+            bottom[0], bottom[1], bottom[2]
+        )),
+        (true, true) => line.push_str(&format!(
+            "\x1b[38;2;{};{};{};48;2;{};{};{}m▀\x1b[0m",
+            top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+        )),
+    }
+}
+
+/// Draws sprites side by side with 24-bit color half blocks, two pixel
+/// rows per line.
+pub fn terminal_preview(sprites: &[SubImage<&RgbaImage>]) -> String {
+    let height = sprites.iter().map(|x| x.height()).max().unwrap_or(0);
+    let mut ret = String::new();
+    for y in (0..height).step_by(2) {
+        let mut line = String::new();
+        for sprite in sprites {
+            let (w, h) = sprite.dimensions();
+            let px = |x, y| {
+                if y < h {
+                    sprite.get_pixel(x, y).0
+                } else {
+                    [0; 4]
+                }
+            };
+            for x in 0..w {
+                push_pixel(&mut line, px(x, y), px(x, y + 1));
+            }
+            line.push_str("  ");
+        }
+        ret += line.trim_end_matches(' ');
+        ret += "\n";
+    }
+    ret
+}
+
+/// Previews of the sprites of an id before and after.
+pub fn preview(a: &ReportSide, b: &ReportSide, id: &str) -> String {
+    let before = tile_sprites(a, id);
+    let after = tile_sprites(b, id);
+    let mut ret = String::new();
+    for (title, sprites) in [("Before:", before), ("After:", after)] {
+        ret += title;
+        if sprites.is_empty() {
+            ret += " none\n";
+        } else {
+            ret += "\n";
+            ret += &terminal_preview(&sprites);
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn asks_until_answered() {
+        let mut out = vec![];
+        let answer = ask(&mut Cursor::new("x\nN\nhalo on the left\n"), &mut out).unwrap();
+        assert_eq!(
+            answer,
+            Answer::Decided(ReviewEntry {
+                decision: Decision::NeedsWork,
+                note: "halo on the left".to_string(),
+            })
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap().matches("[a]ccept").count(),
+            2
+        );
+
+        let answer = ask(&mut Cursor::new("s\n"), &mut vec![]).unwrap();
+        assert_eq!(answer, Answer::Skip);
+        let answer = ask(&mut Cursor::new(""), &mut vec![]).unwrap();
+        assert_eq!(answer, Answer::Quit);
+    }
+
+    #[test]
+    fn serializes_decisions() {
+        let entry = ReviewEntry {
+            decision: Decision::Accept,
+            note: String::new(),
+        };
+        assert_eq!(
+            serde_json::to_string(&entry).unwrap(),
+            r#"{"decision":"accept"}"#
+        );
+    }
+}
@@ -0,0 +1,553 @@
+use crate::atlas::{hash_all_sprites, save_png, HashOptions};
+use crate::compare::{compare, CompareOptions};
+use crate::compose::{compose, walk_sorted};
+use crate::coverage::{coverage, GameData};
+use crate::decompose::decompose;
+use crate::dedupe::duplicate_sprites;
+use crate::error::{create_output_dir, write_output, Error, Result};
+use crate::extract::extract_tiles;
+use crate::hash_algo::HashAlgo;
+use crate::merge::merge;
+use crate::messages::Lang;
+use crate::rename::rename;
+use crate::repack::repack;
+use crate::report::{Audience, Reporter};
+use crate::reslice::reslice;
+use crate::stats::tileset_stats;
+use crate::tileset::Tileset;
+use crate::unused::unused_sprites;
+use crate::validate::validate_tileset;
+use image::{Rgba, RgbaImage};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Expected outputs of the self test, as sections headed by `=== <name> ===`.
+const GOLDEN: &str = include_str!("../golden/selftest.txt");
+
+/// Sprites per row of the fixture sheets.
+const COLUMNS: u32 = 4;
+
+/// A fixture tileset under construction, with sprite indices assigned sheet
+/// by sheet.
+#[derive(Default)]
+struct Builder {
+    next: u32,
+    sheets: Vec<Value>,
+    images: Vec<(String, RgbaImage)>,
+}
+
+impl Builder {
+    /// Adds a sheet of `count` sprites of `size` drawn `offset` from the
+    /// tile, with the entries `tiles` returns given the index of each of its
+    /// sprites. Every sprite is a striped pattern of a color of its own; the
+    /// last row is padded with empty slots.
+    fn sheet(
+        &mut self,
+        file: &str,
+        size: (u32, u32),
+        offset: (i32, i32),
+        count: u32,
+        tiles: impl FnOnce(&dyn Fn(u32) -> u32) -> Vec<Value>,
+    ) -> &mut Value {
+        let start = self.next;
+        let (w, h) = size;
+        let columns = COLUMNS.min(count);
+        let rows = count.div_ceil(columns);
+        let mut img = RgbaImage::new(columns * w, rows * h);
+        for k in 0..count {
+            let g = start + k;
+            let color = Rgba([
+                (40 + g * 37 % 200) as u8,
+                (g * 91 % 256) as u8,
+                (255 - g * 53 % 256) as u8,
+                255,
+            ]);
+            for y in 0..h {
+                for x in 0..w {
+                    if !(x + y + g).is_multiple_of(3) {
+                        img.put_pixel(k % columns * w + x, k / columns * h + y, color);
+                    }
+                }
+            }
+        }
+        self.images.push((file.to_string(), img));
+        self.next += columns * rows;
+
+        let mut sheet = json!({
+            "file": file,
+            "sprite_width": w,
+            "sprite_height": h,
+            "tiles": tiles(&|k| start + k),
+        });
+        if offset.0 != 0 {
+            sheet["sprite_offset_x"] = json!(offset.0);
+        }
+        if offset.1 != 0 {
+            sheet["sprite_offset_y"] = json!(offset.1);
+        }
+        self.sheets.push(sheet);
+        self.sheets.last_mut().unwrap()
+    }
+}
+
+/// The tile config and sheets of a miniature tileset with the usual kinds
+/// of entries: multitiles, rotations, weighted and animated variations,
+/// overlays, sprites larger than the tile and fallback glyphs. Version B
+/// changes, removes and adds a few ids of version A.
+fn fixture(b: bool) -> (Value, Vec<(String, RgbaImage)>) {
+    let mut ts = Builder::default();
+    ts.sheet("terrain.png", (4, 4), (0, 0), 11, |s| {
+        vec![
+            json!({
+                "id": "t_wall",
+                "fg": s(0),
+                "multitile": true,
+                "additional_tiles": [
+                    { "id": "center", "fg": s(1) },
+                    { "id": "corner", "fg": [s(2), s(3), s(4), s(5)] },
+                    { "id": "edge", "fg": [s(6), s(7)] },
+                    { "id": "end_piece", "fg": [s(2), s(3), s(4), s(5)] },
+                    { "id": "t_connection", "fg": [s(2), s(3), s(4), s(5)] },
+                    { "id": "unconnected", "fg": s(0) }
+                ]
+            }),
+            json!({ "id": "t_floor", "fg": [
+                { "weight": 1, "sprite": s(8) },
+                { "weight": if b { 3 } else { 2 }, "sprite": s(9) }
+            ] }),
+            json!({ "id": ["t_grass", "t_grass_long"], "fg": s(10), "bg": s(8) }),
+        ]
+    });
+    ts.sheet("furniture.png", (4, 4), (0, 0), 6, |s| {
+        vec![
+            json!({ "id": "f_chair", "fg": [s(0), s(1), s(2), s(3)], "rotates": true }),
+            json!({ "id": "f_table", "fg": s(4), "bg": s(5) }),
+        ]
+    });
+    ts.sheet("items.png", (4, 4), (0, 0), 3, |s| {
+        vec![
+            json!({ "id": "hat", "fg": s(0) }),
+            json!({ "id": "shirt", "fg": s(1) }),
+            json!({ "id": "backpack", "fg": s(2) }),
+        ]
+    });
+    ts.sheet("overlays.png", (4, 4), (0, 0), 4, |s| {
+        vec![
+            json!({ "id": "overlay_worn_hat", "fg": s(0) }),
+            json!({ "id": "overlay_worn_shirt", "fg": s(1) }),
+            json!({ "id": "overlay_worn_backpack", "fg": s(2) }),
+            json!({ "id": "overlay_mutation_HORNS", "fg": s(3) }),
+        ]
+    });
+    ts.sheet("monsters.png", (4, 8), (0, -4), 3, |s| {
+        let mut tiles = vec![json!({ "id": "mon_zombie", "fg": s(0) })];
+        if b {
+            tiles.push(json!({ "id": "mon_zombie_brute", "fg": s(2) }));
+        } else {
+            tiles.push(json!({ "id": "mon_dog", "fg": s(1) }));
+        }
+        tiles
+    });
+    ts.sheet("animated.png", (4, 4), (0, 0), 6, |s| {
+        vec![
+            json!({ "id": "fd_fire", "animated": true, "fg": [
+                { "weight": 1, "sprite": s(0) },
+                { "weight": 1, "sprite": s(1) },
+                { "weight": 1, "sprite": s(2) },
+                { "weight": 1, "sprite": s(3) }
+            ] }),
+            json!({ "id": "fd_smoke", "animated": true, "fg": [
+                { "weight": 2, "sprite": s(4) },
+                { "weight": 1, "sprite": s(5) }
+            ] }),
+        ]
+    });
+    ts.sheet("vehicles.png", (4, 4), (0, 0), 3, |s| {
+        vec![
+            json!({ "id": "vp_frame", "fg": s(0), "rotates": true }),
+            json!({ "id": "vp_wheel", "fg": s(1), "rotates": b }),
+            json!({ "id": "vp_seat", "fg": s(2), "rotates": true }),
+        ]
+    });
+    ts.sheet("fields.png", (4, 4), (0, 0), 2, |s| {
+        vec![
+            json!({ "id": "fd_blood", "fg": s(0) }),
+            json!({ "id": "fd_acid", "fg": s(1) }),
+        ]
+    });
+    ts.sheet("large.png", (8, 8), (-2, -4), 2, |s| {
+        vec![
+            json!({ "id": "f_tree", "fg": s(0), "bg": s(1) }),
+            json!({ "id": "t_tree_young", "fg": s(1) }),
+        ]
+    });
+    ts.sheet("misc.png", (4, 4), (0, 0), 4, |s| {
+        vec![
+            json!({ "id": "cursor", "fg": s(0) }),
+            json!({ "id": "highlight", "fg": s(1) }),
+            json!({ "id": ["player_male", "player_female"], "fg": s(2) }),
+            json!({ "id": "npc_male", "fg": s(3) }),
+        ]
+    });
+    // Only the first sprite is referenced
+    ts.sheet("spare.png", (4, 4), (0, 0), 5, |s| {
+        vec![json!({ "id": "t_rock", "fg": s(0) })]
+    });
+    let fallback = ts.sheet("fallback.png", (4, 4), (0, 0), 8, |_| vec![]);
+    fallback["ascii"] = json!([
+        { "offset": 0, "bold": false, "color": "WHITE" },
+        { "offset": 4, "bold": true, "color": if b { "LIGHT_RED" } else { "RED" } }
+    ]);
+
+    let config = json!({
+        "tile_info": [{ "pixelscale": 1, "width": 4, "height": 4 }],
+        "tiles-new": ts.sheets,
+        "overlay_ordering": [
+            { "id": "worn_backpack", "order": if b { 5000 } else { 500 } },
+            { "id": ["worn_hat", "worn_shirt"], "order": 1000 }
+        ]
+    });
+    let mut images = ts.images;
+    if b {
+        // Repaint a corner of the first chair sprite
+        images[1].1.put_pixel(0, 0, Rgba([255, 255, 255, 255]));
+    }
+    (config, images)
+}
+
+/// Game objects for the coverage of the fixture.
+fn game_data() -> Value {
+    json!([
+        { "type": "terrain", "id": ["t_wall", "t_floor", "t_grass", "t_rock"] },
+        { "type": "terrain", "id": "t_wall_glass", "looks_like": "t_wall" },
+        { "type": "terrain", "id": "t_grass_long", "copy-from": "t_grass" },
+        { "type": "terrain", "id": "t_lava" },
+        { "type": "furniture", "id": ["f_chair", "f_table", "f_tree", "f_bench"] },
+        { "type": "ARMOR", "id": ["hat", "shirt", "backpack"] },
+        { "type": "MONSTER", "id": ["mon_zombie", "mon_dog"] },
+        { "type": "MONSTER", "id": "mon_zombie_brute", "copy-from": "mon_zombie" },
+        { "type": "mutation", "id": "HORNS" }
+    ])
+}
+
+fn write_fixture(dir: &Path, b: bool) -> Result<()> {
+    create_output_dir(dir)?;
+    let (config, images) = fixture(b);
+    for (file, img) in &images {
+        save_png(img, &dir.join(file))?;
+    }
+    write_output(
+        &dir.join("tile_config.json"),
+        serde_json::to_string_pretty(&config)?,
+    )
+}
+
+/// One output of the self test next to what it should be.
+pub struct Check {
+    pub name: String,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl Check {
+    pub fn passed(&self) -> bool {
+        self.expected == self.actual
+    }
+
+    /// Line number and both versions of the first line that differs.
+    pub fn first_difference(&self) -> Option<(usize, &str, &str)> {
+        let expected = self.expected.as_deref().unwrap_or_default();
+        let actual = self.actual.as_deref().unwrap_or_default();
+        let mut a = expected.lines();
+        let mut b = actual.lines();
+        for line in 1.. {
+            match (a.next(), b.next()) {
+                (None, None) => return None,
+                (x, y) if x != y => return Some((line, x.unwrap_or(""), y.unwrap_or(""))),
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Splits golden text into its named sections.
+fn sections(text: &str) -> Vec<(String, String)> {
+    let mut ret: Vec<(String, String)> = vec![];
+    for line in text.lines() {
+        let header = line
+            .strip_prefix("=== ")
+            .and_then(|x| x.strip_suffix(" ==="));
+        match (header, ret.last_mut()) {
+            (Some(name), _) => ret.push((name.to_string(), String::new())),
+            (None, Some((_, body))) => {
+                body.push_str(line);
+                body.push('\n');
+            }
+            (None, None) => {}
+        }
+    }
+    ret
+}
+
+/// Joins named outputs into golden text, each ending in a newline.
+pub fn render_sections(outputs: &[(String, String)]) -> String {
+    let mut ret = String::new();
+    for (name, body) in outputs {
+        ret += &format!("=== {} ===\n{}", name, body);
+        if !body.is_empty() && !body.ends_with('\n') {
+            ret.push('\n');
+        }
+    }
+    ret
+}
+
+/// Names of the files below `dir`, one per line.
+fn file_names(dir: &Path) -> Result<String> {
+    let mut files = vec![];
+    walk_sorted(dir, &mut files)?;
+    let names: Vec<String> = files
+        .iter()
+        .filter_map(|x| x.strip_prefix(dir).ok())
+        .map(|x| format!("{}\n", x.to_string_lossy().replace('\\', "/")))
+        .collect();
+    Ok(names.concat())
+}
+
+/// What a command rewriting the tileset in `dir` left: its summary, then
+/// the tile config and the hash of every sprite by index.
+fn rewritten<T: std::fmt::Debug>(
+    name: &str,
+    summary: &T,
+    dir: &Path,
+    opts: &HashOptions,
+) -> Result<Vec<(String, String)>> {
+    let ts = Tileset::load(dir)?;
+    let config = std::fs::read_to_string(dir.join("tile_config.json")).map_err(|source| {
+        Error::ReadInput {
+            path: dir.join("tile_config.json"),
+            source,
+        }
+    })?;
+    let sprites: Vec<String> = hash_all_sprites(&ts.load_atlases()?, opts)
+        .iter()
+        .map(|(idx, hash)| format!("{}: {}\n", idx, hash))
+        .collect();
+    Ok(vec![
+        (format!("{}/summary", name), format!("{:#?}\n", summary)),
+        (format!("{}/tile_config.json", name), config),
+        (format!("{}/sprites", name), sprites.concat()),
+    ])
+}
+
+/// Text files below `dir`, by path relative to `root`, except the JSON
+/// report, which carries the tool version.
+fn text_outputs(root: &Path, dir: &Path, into: &mut Vec<(String, String)>) -> Result<()> {
+    let mut files = vec![];
+    walk_sorted(dir, &mut files)?;
+    for path in files {
+        let is_text = matches!(
+            path.extension().and_then(|x| x.to_str()),
+            Some("txt" | "json" | "md")
+        );
+        if !is_text || path.file_name() == Some("report.json".as_ref()) {
+            continue;
+        }
+        let text = std::fs::read_to_string(&path).map_err(|source| Error::ReadInput {
+            path: path.clone(),
+            source,
+        })?;
+        let name = path.strip_prefix(root).unwrap_or(&path);
+        into.push((name.to_string_lossy().replace('\\', "/"), text));
+    }
+    Ok(())
+}
+
+/// Writes the fixture tilesets into `dir`, runs validate, stats, unused,
+/// dedupe, coverage, compare with a Markdown summary and extract on them,
+/// and decompose, compose, merge, rename, repack and reslice on copies of
+/// them, and returns their outputs by name. Sprites are hashed with xxh64,
+/// which gives the same hashes on every platform. `dir` must be new or
+/// empty: it's never cleared, since it may not be one the tool created.
+pub fn run_pipeline(dir: &Path) -> Result<Vec<(String, String)>> {
+    if std::fs::read_dir(dir).is_ok_and(|mut x| x.next().is_some()) {
+        return Err(Error::DirNotEmpty(dir.to_owned()));
+    }
+    write_fixture(&dir.join("a"), false)?;
+    write_fixture(&dir.join("b"), true)?;
+    let game_dir = dir.join("game");
+    create_output_dir(&game_dir)?;
+    write_output(
+        &game_dir.join("objects.json"),
+        serde_json::to_string_pretty(&game_data())?,
+    )?;
+
+    let opts = HashOptions {
+        hash_algo: HashAlgo::Xxh64,
+        ..HashOptions::default()
+    };
+    let mut a = Tileset::load(&dir.join("a"))?;
+    let mut b = Tileset::load(&dir.join("b"))?;
+    let out = dir.join("out");
+    a.out_path = out.join("a");
+    b.out_path = out.join("b");
+    create_output_dir(&a.out_path)?;
+    create_output_dir(&b.out_path)?;
+    let mut ret = vec![];
+
+    let problems = validate_tileset(&a, &opts)?;
+    let lines: Vec<String> = problems.iter().map(|x| format!("{}\n", x)).collect();
+    ret.push(("validate".to_string(), lines.concat()));
+
+    let atlases = a.load_atlases()?;
+    let stats = serde_json::to_string_pretty(&tileset_stats(&a, &atlases))?;
+    ret.push(("stats".to_string(), stats));
+
+    let unused: Vec<String> = unused_sprites(&a, &atlases)
+        .iter()
+        .map(|x| format!("{}: {}\n", x.atlas.file, x.ranges()))
+        .collect();
+    ret.push(("unused".to_string(), unused.concat()));
+
+    let groups: Vec<String> = duplicate_sprites(&atlases, &opts)
+        .iter()
+        .map(|x| format!("{:?}\n", x))
+        .collect();
+    ret.push(("dedupe".to_string(), groups.concat()));
+
+    let game = GameData::load(&game_dir)?;
+    let result = coverage(&a, &game);
+    let mut text = String::new();
+    for (category, stats) in &result.categories {
+        text += &format!(
+            "{}: {} covered, fallback {:?}, missing {:?}\n",
+            category, stats.covered, stats.fallback, stats.missing
+        );
+    }
+    text += &format!("stale: {:?}\n", result.stale);
+    ret.push(("coverage".to_string(), text));
+
+    let compare_opts = CompareOptions {
+        hash: opts,
+        ..CompareOptions::default()
+    };
+    let result = compare(&a, &b, &compare_opts)?;
+    ret.push(("compare/verdict".to_string(), result.verdict(Lang::En)));
+    let reporter = Reporter {
+        audience: Audience::Ci,
+        markdown: Some(out.join("summary.md")),
+        markdown_images: Some("{side}/sprites/{sprite}.png".to_string()),
+        ..Reporter::default()
+    };
+    reporter.write(&result, &a, &b, &compare_opts)?;
+    let mut outputs = vec![];
+    text_outputs(&out, &out, &mut outputs)?;
+    ret.extend(
+        outputs
+            .into_iter()
+            .map(|(k, v)| (format!("compare/{}", k), v)),
+    );
+
+    let extracted = dir.join("extracted");
+    extract_tiles(&a, None, &extracted, &opts)?;
+    ret.push(("extract/files".to_string(), file_names(&extracted)?));
+
+    let source = dir.join("source");
+    let summary = decompose(&a, &source)?;
+    ret.push(("decompose/summary".to_string(), format!("{:#?}\n", summary)));
+    ret.push(("decompose/files".to_string(), file_names(&source)?));
+    let composed = dir.join("composed");
+    let summary = compose(&source, &composed)?;
+    ret.extend(rewritten("compose", &summary, &composed, &opts)?);
+
+    // The others rewrite the tileset they're given, so each gets a copy of A
+    let merged = dir.join("merged");
+    write_fixture(&merged, false)?;
+    let patterns = ["f_chair".to_string(), "mon_zombie_*".to_string()];
+    let summary = merge(&Tileset::load(&merged)?, &b, &patterns, &opts)?;
+    ret.extend(rewritten("merge", &summary, &merged, &opts)?);
+
+    let renamed = dir.join("renamed");
+    let map = BTreeMap::from([
+        ("t_rock".to_string(), "t_stone".to_string()),
+        ("worn_hat".to_string(), "worn_cap".to_string()),
+        ("t_lava".to_string(), "t_magma".to_string()),
+    ]);
+    let summary = rename(&a, &map, Some(&renamed))?;
+    ret.extend(rewritten("rename", &summary, &renamed, &opts)?);
+
+    let repacked = dir.join("repacked");
+    write_fixture(&repacked, false)?;
+    let summary = repack(&Tileset::load(&repacked)?)?;
+    ret.extend(rewritten("repack", &summary, &repacked, &opts)?);
+
+    // Pads the height of most sprites and crops the width of large ones
+    let resliced = dir.join("resliced");
+    write_fixture(&resliced, false)?;
+    let summary = reslice(&Tileset::load(&resliced)?, 4, 8)?;
+    ret.extend(rewritten("reslice", &summary, &resliced, &opts)?);
+    Ok(ret)
+}
+
+/// Runs the pipeline in `dir` and checks every output against the golden
+/// outputs shipped with the tool, including outputs missing on either side.
+pub fn selftest(dir: &Path) -> Result<Vec<Check>> {
+    let actual = sections(&render_sections(&run_pipeline(dir)?));
+    let expected = sections(GOLDEN);
+    let mut ret: Vec<Check> = expected
+        .iter()
+        .map(|(name, body)| Check {
+            name: name.clone(),
+            expected: Some(body.clone()),
+            actual: actual.iter().find(|x| &x.0 == name).map(|x| x.1.clone()),
+        })
+        .collect();
+    for (name, body) in &actual {
+        if !expected.iter().any(|x| &x.0 == name) {
+            ret.push(Check {
+                name: name.clone(),
+                expected: None,
+                actual: Some(body.clone()),
+            });
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Set `BLESS_GOLDEN=1` to rewrite the golden outputs after an intended
+    /// change of them; they are compiled in, so run again to check them.
+    #[test]
+    fn matches_golden_outputs() {
+        let dir = std::env::temp_dir().join(format!("tileset-selftest-{}", std::process::id()));
+        if std::env::var_os("BLESS_GOLDEN").is_some() {
+            let outputs = run_pipeline(&dir).unwrap();
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("golden/selftest.txt");
+            std::fs::write(path, render_sections(&outputs)).unwrap();
+            let _ = std::fs::remove_dir_all(&dir);
+        }
+        let checks = selftest(&dir).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        let failed: Vec<_> = checks.iter().filter(|x| !x.passed()).collect();
+        for check in &failed {
+            eprintln!("{}: {:?}", check.name, check.first_difference());
+        }
+        assert!(failed.is_empty());
+        assert!(checks.len() > 10);
+    }
+
+    #[test]
+    fn keeps_directories_it_did_not_create() {
+        let dir =
+            std::env::temp_dir().join(format!("tileset-selftest-kept-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("notes.txt"), "mine").unwrap();
+        assert!(matches!(run_pipeline(&dir), Err(Error::DirNotEmpty(_))));
+        assert!(dir.join("notes.txt").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::compose::walk_sorted;
+use crate::error::{Error, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+/// Differences in the layout of two compose.py-style source trees: which
+/// files were renamed, moved, edited, added or removed. Files are matched by
+/// contents, so a sprite renamed in place is reported as a rename even though
+/// composing either tree gives the same atlas.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SourceDiff {
+    /// Directories whose files all moved along with them, as (A, B). Files
+    /// renamed inside a moved directory are listed under `renamed`.
+    pub moved_dirs: Vec<(String, String)>,
+    /// Files with the same contents under a new name in the same directory
+    pub renamed: Vec<(String, String)>,
+    /// Files with the same contents in another directory
+    pub moved: Vec<(String, String)>,
+    /// Files at the same path with different contents
+    pub modified: Vec<String>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Directories only in B, other than moved directories
+    pub added_dirs: Vec<String>,
+    pub removed_dirs: Vec<String>,
+}
+
+impl SourceDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == SourceDiff::default()
+    }
+
+    /// One line per difference, e.g. `renamed: a/t_wall.png -> a/t_wall_1.png`.
+    pub fn lines(&self) -> Vec<String> {
+        let mut ret = vec![];
+        let pairs = [
+            ("moved folder", &self.moved_dirs),
+            ("renamed", &self.renamed),
+            ("moved", &self.moved),
+        ];
+        for (kind, list) in pairs {
+            ret.extend(
+                list.iter()
+                    .map(|(a, b)| format!("{}: {} -> {}", kind, a, b)),
+            );
+        }
+        let paths = [
+            ("modified", &self.modified),
+            ("added", &self.added),
+            ("removed", &self.removed),
+            ("added folder", &self.added_dirs),
+            ("removed folder", &self.removed_dirs),
+        ];
+        for (kind, list) in paths {
+            ret.extend(list.iter().map(|x| format!("{}: {}", kind, x)));
+        }
+        ret
+    }
+}
+
+/// Hash of the contents of every file below `root`, by `/`-separated path
+/// relative to `root`.
+fn hash_tree(root: &Path) -> Result<BTreeMap<String, u64>> {
+    if !root.is_dir() {
+        return Err(Error::NoTilesetDir(root.to_owned()));
+    }
+    let mut files = vec![];
+    walk_sorted(root, &mut files)?;
+    let mut ret = BTreeMap::new();
+    for path in files {
+        let bytes = std::fs::read(&path).map_err(|source| Error::ReadInput {
+            path: path.clone(),
+            source,
+        })?;
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        let rel = path.strip_prefix(root).unwrap_or(&path);
+        let rel: Vec<String> = rel
+            .components()
+            .map(|x| x.as_os_str().to_string_lossy().to_string())
+            .collect();
+        ret.insert(rel.join("/"), hasher.finish());
+    }
+    Ok(ret)
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |x| x.0)
+}
+
+/// Every directory containing a file, at any depth.
+fn dirs_of(files: &BTreeMap<String, u64>) -> BTreeSet<String> {
+    let mut ret = BTreeSet::new();
+    for path in files.keys() {
+        let mut dir = parent(path);
+        while !dir.is_empty() && ret.insert(dir.to_string()) {
+            dir = parent(dir);
+        }
+    }
+    ret
+}
+
+/// Sorted hashes of the files below `dir`.
+fn contents_of(files: &BTreeMap<String, u64>, dir: &str) -> Vec<u64> {
+    let mut ret: Vec<u64> = files
+        .iter()
+        .filter(|(path, _)| is_within(path, dir))
+        .map(|(_, hash)| *hash)
+        .collect();
+    ret.sort_unstable();
+    ret
+}
+
+fn is_within(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir).is_some_and(|x| x.starts_with('/'))
+}
+
+/// Diffs two trees of file hashes by relative path.
+pub fn diff_files(a: &BTreeMap<String, u64>, b: &BTreeMap<String, u64>) -> SourceDiff {
+    let mut ret = SourceDiff::default();
+    let dirs_a = dirs_of(a);
+    let dirs_b = dirs_of(b);
+
+    // Outermost directories first, so a moved folder's subfolders aren't
+    // reported on their own
+    let mut gone: Vec<&String> = dirs_a.difference(&dirs_b).collect();
+    gone.sort_by_key(|x| (x.matches('/').count(), x.to_string()));
+    let new: Vec<&String> = dirs_b.difference(&dirs_a).collect();
+    for da in gone {
+        if ret.moved_dirs.iter().any(|(x, _)| is_within(da, x)) {
+            continue;
+        }
+        let contents = contents_of(a, da);
+        let target = new.iter().find(|db| {
+            !ret.moved_dirs
+                .iter()
+                .any(|(_, x)| x == **db || is_within(db, x))
+                && contents_of(b, db) == contents
+        });
+        if let Some(db) = target {
+            ret.moved_dirs.push((da.clone(), db.to_string()));
+        }
+    }
+    // Where a file of A would be in B if it only moved with its folder
+    let moved_to = |path: &str| -> Option<String> {
+        ret.moved_dirs.iter().find_map(|(da, db)| {
+            is_within(path, da).then(|| format!("{}{}", db, &path[da.len()..]))
+        })
+    };
+
+    let mut matched_b: BTreeSet<String> = BTreeSet::new();
+    let mut only_a: BTreeMap<u64, Vec<(&String, String)>> = BTreeMap::new();
+    for (path, hash) in a {
+        let target = moved_to(path);
+        match (b.get(target.as_ref().unwrap_or(path)), target) {
+            (Some(other), None) if other != hash => ret.modified.push(path.clone()),
+            (Some(other), target) if other == hash => {
+                matched_b.insert(target.unwrap_or_else(|| path.clone()));
+            }
+            (_, target) => {
+                let target = target.unwrap_or_else(|| path.clone());
+                only_a.entry(*hash).or_default().push((path, target));
+            }
+        }
+    }
+    for (path, hash) in b {
+        if matched_b.contains(path) || a.contains_key(path) {
+            continue;
+        }
+        let same = only_a.get_mut(hash).filter(|x| !x.is_empty());
+        match same.map(|x| x.remove(0)) {
+            Some((old, target)) if parent(&target) == parent(path) => {
+                ret.renamed.push((old.clone(), path.clone()))
+            }
+            Some((old, _)) => ret.moved.push((old.clone(), path.clone())),
+            None => ret.added.push(path.clone()),
+        }
+    }
+    ret.removed = only_a
+        .into_values()
+        .flatten()
+        .map(|x| x.0.clone())
+        .collect();
+    ret.removed.sort();
+
+    ret.removed_dirs = dirs_a
+        .difference(&dirs_b)
+        .filter(|x| {
+            !ret.moved_dirs
+                .iter()
+                .any(|(d, _)| d == *x || is_within(x, d))
+        })
+        .cloned()
+        .collect();
+    ret.added_dirs = dirs_b
+        .difference(&dirs_a)
+        .filter(|x| {
+            !ret.moved_dirs
+                .iter()
+                .any(|(_, d)| d == *x || is_within(x, d))
+        })
+        .cloned()
+        .collect();
+    ret
+}
+
+/// Diffs the files and directories of two compose.py-style source trees.
+pub fn diff_sources(a: &Path, b: &Path) -> Result<SourceDiff> {
+    Ok(diff_files(&hash_tree(a)?, &hash_tree(b)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(files: &[(&str, u64)]) -> BTreeMap<String, u64> {
+        files.iter().map(|(p, h)| (p.to_string(), *h)).collect()
+    }
+
+    #[test]
+    fn detects_renames_and_moved_folders() {
+        let a = tree(&[
+            ("tile_info.json", 1),
+            ("pngs_normal_32x32/terrain/t_wall.png", 2),
+            ("pngs_normal_32x32/terrain/t_wall.json", 3),
+            ("pngs_normal_32x32/t_floor.png", 4),
+            ("pngs_normal_32x32/t_door.png", 5),
+            ("pngs_normal_32x32/t_door.json", 6),
+        ]);
+        let b = tree(&[
+            ("tile_info.json", 1),
+            ("pngs_normal_32x32/walls/t_wall.png", 2),
+            ("pngs_normal_32x32/walls/t_wall.json", 3),
+            ("pngs_normal_32x32/t_floor_1.png", 4),
+            ("pngs_normal_32x32/doors/t_door.png", 5),
+            ("pngs_normal_32x32/t_door.json", 7),
+        ]);
+        let diff = diff_files(&a, &b);
+        let pair = |x: &str, y: &str| (x.to_string(), y.to_string());
+        assert_eq!(
+            diff.moved_dirs,
+            [pair("pngs_normal_32x32/terrain", "pngs_normal_32x32/walls")]
+        );
+        assert_eq!(
+            diff.renamed,
+            [pair(
+                "pngs_normal_32x32/t_floor.png",
+                "pngs_normal_32x32/t_floor_1.png"
+            )]
+        );
+        assert_eq!(
+            diff.moved,
+            [pair(
+                "pngs_normal_32x32/t_door.png",
+                "pngs_normal_32x32/doors/t_door.png"
+            )]
+        );
+        assert_eq!(diff.modified, ["pngs_normal_32x32/t_door.json"]);
+        assert_eq!(diff.added_dirs, ["pngs_normal_32x32/doors"]);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert!(diff.removed_dirs.is_empty());
+    }
+
+    #[test]
+    fn detects_renames_inside_moved_folders() {
+        let a = tree(&[
+            ("pngs_a_1x1/terrain/t_floor_1.png", 1),
+            ("pngs_a_1x1/terrain/t_floor.json", 2),
+        ]);
+        let b = tree(&[
+            ("pngs_a_1x1/ground/t_floor_a.png", 1),
+            ("pngs_a_1x1/ground/t_floor.json", 2),
+        ]);
+        let diff = diff_files(&a, &b);
+        let pair = |x: &str, y: &str| (x.to_string(), y.to_string());
+        assert_eq!(
+            diff.moved_dirs,
+            [pair("pngs_a_1x1/terrain", "pngs_a_1x1/ground")]
+        );
+        assert_eq!(
+            diff.renamed,
+            [pair(
+                "pngs_a_1x1/terrain/t_floor_1.png",
+                "pngs_a_1x1/ground/t_floor_a.png"
+            )]
+        );
+        assert!(diff.moved.is_empty() && diff.added_dirs.is_empty());
+    }
+
+    #[test]
+    fn identical_trees() {
+        let a = tree(&[("tile_info.json", 1), ("pngs_a_1x1/x.png", 2)]);
+        assert!(diff_files(&a, &a).is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+use crate::tileset::Tileset;
+use std::collections::HashMap;
+
+/// Step of a path into a JSON document.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// Records the line every JSON object starts on, by path. A lenient scanner:
+/// the text has already been parsed by serde, so malformed input only needs
+/// to not panic.
+struct Scanner<'a> {
+    text: &'a [u8],
+    pos: usize,
+    line: usize,
+    path: Vec<Step>,
+    objects: HashMap<Vec<Step>, usize>,
+}
+
+impl Scanner<'_> {
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.text.get(self.pos) {
+            match c {
+                b'\n' => self.line += 1,
+                b' ' | b'\t' | b'\r' => {}
+                _ => return,
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Skips past the next byte if it's `c`.
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.text.get(self.pos) == Some(&c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn string(&mut self) -> String {
+        // Opening quote
+        self.pos += 1;
+        let start = self.pos;
+        while let Some(&c) = self.text.get(self.pos) {
+            match c {
+                b'"' => break,
+                b'\\' => self.pos += 1,
+                b'\n' => self.line += 1,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        let raw = &self.text[start..self.pos.min(self.text.len())];
+        self.pos += 1;
+        let raw = String::from_utf8_lossy(raw);
+        // Keys with escapes are rare, decode them like serde did
+        serde_json::from_str(&format!("\"{}\"", raw)).unwrap_or_else(|_| raw.to_string())
+    }
+
+    fn value(&mut self) {
+        self.skip_whitespace();
+        match self.text.get(self.pos) {
+            Some(b'{') => {
+                self.objects.insert(self.path.clone(), self.line);
+                self.pos += 1;
+                while !self.eat(b'}') && self.pos < self.text.len() {
+                    self.skip_whitespace();
+                    if self.text.get(self.pos) != Some(&b'"') {
+                        self.pos += 1;
+                        continue;
+                    }
+                    let key = self.string();
+                    self.eat(b':');
+                    self.path.push(Step::Key(key));
+                    self.value();
+                    self.path.pop();
+                    self.eat(b',');
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut index = 0;
+                while !self.eat(b']') && self.pos < self.text.len() {
+                    self.path.push(Step::Index(index));
+                    self.value();
+                    self.path.pop();
+                    self.eat(b',');
+                    index += 1;
+                }
+            }
+            Some(b'"') => {
+                self.string();
+            }
+            Some(_) => {
+                while let Some(c) = self.text.get(self.pos) {
+                    if matches!(c, b',' | b'}' | b']') || c.is_ascii_whitespace() {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+/// Where in tile_config.json things are defined, as 1-based line numbers.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    /// Lines of the tile entries defining each expanded id, in file order;
+    /// duplicate ids have several
+    ids: HashMap<String, Vec<usize>>,
+    /// Line of each sheet entry, by image file
+    sheets: HashMap<String, usize>,
+    /// Line of the overlay_ordering entry listing each id
+    ordering: HashMap<String, usize>,
+}
+
+impl SourceMap {
+    /// Maps the definitions of `ts` back to `text`, the tile_config.json it
+    /// was parsed from. `legacy` if the sheet sat at the top level of the
+    /// file rather than in `tiles-new`.
+    pub fn new(text: &str, ts: &Tileset, legacy: bool) -> SourceMap {
+        let mut scanner = Scanner {
+            text: text.as_bytes(),
+            pos: 0,
+            line: 1,
+            path: vec![],
+            objects: HashMap::new(),
+        };
+        scanner.value();
+        let objects = scanner.objects;
+        let line_of = |path: &[Step]| objects.get(path).copied();
+        let key = |x: &str| Step::Key(x.to_string());
+
+        let mut ret = SourceMap::default();
+        for (i, sheet) in ts.tiles_new.iter().enumerate() {
+            let sheet_path = if legacy {
+                vec![]
+            } else {
+                vec![key("tiles-new"), Step::Index(i)]
+            };
+            if let Some(line) = line_of(&sheet_path) {
+                ret.sheets.insert(sheet.file.clone(), line);
+            }
+            for (j, tile) in sheet.tiles.iter().enumerate() {
+                let mut tile_path = sheet_path.clone();
+                tile_path.extend([key("tiles"), Step::Index(j)]);
+                let line = match line_of(&tile_path) {
+                    Some(line) => line,
+                    None => continue,
+                };
+                for id in &tile.base.id.0 {
+                    ret.ids.entry(id.clone()).or_default().push(line);
+                    for (k, at) in tile.additional_tiles.iter().enumerate() {
+                        let mut at_path = tile_path.clone();
+                        at_path.extend([key("additional_tiles"), Step::Index(k)]);
+                        let at_line = line_of(&at_path).unwrap_or(line);
+                        for at_id in &at.id.0 {
+                            let full_id = format!("{}_{}", id, at_id);
+                            ret.ids.entry(full_id).or_default().push(at_line);
+                        }
+                    }
+                }
+            }
+        }
+        for (i, elem) in ts.overlay_ordering.iter().enumerate() {
+            if let Some(line) = line_of(&[key("overlay_ordering"), Step::Index(i)]) {
+                for id in &elem.id.0 {
+                    ret.ordering.entry(id.clone()).or_insert(line);
+                }
+            }
+        }
+        ret
+    }
+
+    /// Line of the first definition of an expanded tile id.
+    pub fn line(&self, id: &str) -> Option<usize> {
+        self.lines(id).first().copied()
+    }
+
+    /// Lines of every definition of an expanded tile id.
+    pub fn lines(&self, id: &str) -> &[usize] {
+        self.ids.get(id).map_or(&[], |x| x.as_slice())
+    }
+
+    pub fn sheet_line(&self, file: &str) -> Option<usize> {
+        self.sheets.get(file).copied()
+    }
+
+    pub fn ordering_line(&self, id: &str) -> Option<usize> {
+        self.ordering.get(id).copied()
+    }
+}
+
+/// ` (line 12)` to append to a message, or nothing if the line is unknown.
+pub fn cite(line: Option<usize>) -> String {
+    line.map_or(String::new(), |x| format!(" (line {})", x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"{
+  "tile_info": [ { "width": 32, "height": 32 } ],
+  "tiles-new": [
+    {
+      "file": "main.png",
+      "tiles": [
+        { "id": "t_wall", "fg": 1 },
+        {
+          "id": [ "t_door", "t_door_o" ],
+          "fg": 2,
+          "additional_tiles": [
+            { "id": "center", "fg": 3 }
+          ]
+        },
+        { "id": "t_wall", "fg": 4 }
+      ]
+    }
+  ],
+  "overlay_ordering": [
+    { "id": "worn_hat", "order": 1 }
+  ]
+}"#;
+
+    #[test]
+    fn maps_ids_to_lines() {
+        let ts: Tileset = serde_json::from_str(CONFIG).unwrap();
+        let map = SourceMap::new(CONFIG, &ts, false);
+        assert_eq!(map.lines("t_wall"), [7, 15]);
+        assert_eq!(map.line("t_door_o"), Some(8));
+        assert_eq!(map.line("t_door_o_center"), Some(12));
+        assert_eq!(map.line("t_floor"), None);
+        assert_eq!(map.sheet_line("main.png"), Some(4));
+        assert_eq!(map.ordering_line("worn_hat"), Some(20));
+    }
+}
@@ -1,10 +1,16 @@
 use crate::single_or_vec::SingleOrVec;
 use serde::{Deserialize, Serialize};
 
+/// Stand-in for negative sprite indices: one past the largest index, so that
+/// no real sprite index can be taken for it.
+pub const NEGATIVE_SPRITE_ID: u64 = u32::MAX as u64 + 1;
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize, Ord, PartialOrd, Eq, PartialEq, Hash)]
-#[serde(from = "SpriteIdSource")]
+#[serde(try_from = "SpriteIdSource")]
 pub struct SpriteIdWithWeight {
-    pub id: SingleOrVec<u32>,
+    /// Sprite indices, which fit in a `u32`, or once hashed, sprite hashes
+    /// of up to 64 bits
+    pub id: SingleOrVec<u64>,
     pub weight: Option<u32>,
 }
 
@@ -12,21 +18,91 @@ pub struct SpriteIdWithWeight {
 #[serde(untagged)]
 #[serde(deny_unknown_fields)]
 enum SpriteIdSource {
-    IdOnly(SingleOrVec<u32>),
+    IdOnly(SingleOrVec<i64>),
     WithWeight {
         weight: u32,
-        sprite: SingleOrVec<u32>,
+        sprite: SingleOrVec<i64>,
+    },
+    /// As serialized into dumps and extracted definitions, whose hashes may
+    /// not fit in an `i64`
+    Dumped {
+        id: SingleOrVec<u64>,
+        weight: Option<u32>,
     },
 }
 
-impl From<SpriteIdSource> for SpriteIdWithWeight {
-    fn from(other: SpriteIdSource) -> SpriteIdWithWeight {
-        match other {
-            SpriteIdSource::IdOnly(id) => SpriteIdWithWeight { id, weight: None },
+impl SpriteIdWithWeight {
+    /// Sprite indices, for ids not yet replaced by hashes.
+    pub fn indices(&self) -> impl Iterator<Item = u32> + '_ {
+        // Every index fits but the negative placeholder, which is outside
+        // every atlas either way
+        self.id
+            .0
+            .iter()
+            .map(|&x| u32::try_from(x).unwrap_or(u32::MAX))
+    }
+}
+
+/// Sprite indices as written in a tile config, with negative ones replaced
+/// by `NEGATIVE_SPRITE_ID`. Indices too large for a `u32` are an error.
+fn to_sprite_ids(raw: SingleOrVec<i64>) -> Result<SingleOrVec<u64>, String> {
+    raw.0
+        .into_iter()
+        .map(|x| match u32::try_from(x) {
+            Ok(idx) => Ok(u64::from(idx)),
+            Err(_) if x < 0 => Ok(NEGATIVE_SPRITE_ID),
+            Err(_) => Err(format!("sprite index {} is out of range", x)),
+        })
+        .collect::<Result<_, _>>()
+        .map(SingleOrVec)
+}
+
+impl TryFrom<SpriteIdSource> for SpriteIdWithWeight {
+    type Error = String;
+
+    fn try_from(other: SpriteIdSource) -> Result<SpriteIdWithWeight, String> {
+        Ok(match other {
+            SpriteIdSource::IdOnly(id) => SpriteIdWithWeight {
+                id: to_sprite_ids(id)?,
+                weight: None,
+            },
             SpriteIdSource::WithWeight { weight, sprite } => SpriteIdWithWeight {
                 weight: Some(weight),
-                id: sprite,
+                id: to_sprite_ids(sprite)?,
             },
-        }
+            SpriteIdSource::Dumped { id, weight } => SpriteIdWithWeight { id, weight },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Vec<u64> {
+        serde_json::from_str::<SpriteIdWithWeight>(json)
+            .unwrap()
+            .id
+            .0
+    }
+
+    #[test]
+    fn parses_negative_indices() {
+        assert_eq!(parse("-1"), [NEGATIVE_SPRITE_ID]);
+        assert_eq!(
+            parse(r#"{ "weight": 2, "sprite": [-5, 3] }"#),
+            [NEGATIVE_SPRITE_ID, 3]
+        );
+        // The largest index is a real sprite, not the placeholder
+        assert_eq!(parse("4294967295"), [u32::MAX as u64]);
+        assert_ne!(u32::MAX as u64, NEGATIVE_SPRITE_ID);
+    }
+
+    #[test]
+    fn rejects_indices_too_large() {
+        let err = serde_json::from_str::<SpriteIdWithWeight>("[1, 4294967296]").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("sprite index 4294967296 is out of range"));
     }
 }
@@ -0,0 +1,182 @@
+use crate::atlas::{save_png, HashOptions, TileAtlas};
+use crate::compare::ComparisonResult;
+use crate::error::{create_output_dir, Error, Result};
+use crate::progress::Progress;
+use crate::tileset::Tileset;
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Directory in A's output directory holding the sprites of both tilesets
+/// when they share a store.
+pub const STORE_DIR_NAME: &str = "sprite-store";
+
+/// What writing the sprites of two tilesets into a shared store did.
+#[derive(Debug, Default)]
+pub struct StoreSummary {
+    /// Distinct sprites written to the store
+    pub stored: usize,
+    /// Sprite indices of both tilesets, each a link to a stored sprite
+    pub linked: usize,
+    /// Sprites of B identical to a sprite of A
+    pub shared: usize,
+    /// Whether the store or a `sprites` directory already existed, so that
+    /// files of an earlier run may remain
+    pub existed: bool,
+}
+
+/// Content-addressed sprites, one PNG per distinct sprite named after its
+/// hash. Sprites whose hashes collide, or only match under the fast hash,
+/// get a numbered suffix.
+struct SpriteStore {
+    dir: PathBuf,
+    /// Stored sprites by hash, with their paths and the order they were
+    /// stored in
+    by_hash: HashMap<u64, Vec<(RgbaImage, PathBuf, usize)>>,
+    stored: usize,
+}
+
+impl SpriteStore {
+    /// A store in `dir`, created if missing. An existing directory is
+    /// written into rather than replaced, since it may not be one this run
+    /// wrote.
+    fn create(dir: &Path) -> Result<SpriteStore> {
+        create_output_dir(dir)?;
+        Ok(SpriteStore {
+            dir: dir.to_owned(),
+            by_hash: HashMap::new(),
+            stored: 0,
+        })
+    }
+
+    /// Path of the stored copy of `img`, storing it first if it's new, and
+    /// how many sprites were stored before it.
+    fn put(&mut self, img: RgbaImage, hash: u64) -> Result<(PathBuf, usize)> {
+        let same = self.by_hash.entry(hash).or_default();
+        if let Some((_, path, serial)) = same.iter().find(|x| x.0 == img) {
+            return Ok((path.clone(), *serial));
+        }
+        let name = match same.len() {
+            0 => format!("{:010}.png", hash),
+            n => format!("{:010}_{}.png", hash, n + 1),
+        };
+        let path = self.dir.join(name);
+        save_png(&img, &path)?;
+        same.push((img, path.clone(), self.stored));
+        self.stored += 1;
+        Ok((path, self.stored - 1))
+    }
+}
+
+/// Hard links `to` to `from`, copying it instead where links aren't
+/// supported, e.g. across file systems. A file already at `to` is replaced.
+fn link_or_copy(from: &Path, to: &Path) -> Result<()> {
+    let _ = std::fs::remove_file(to);
+    if std::fs::hard_link(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to)
+        .map(|_| ())
+        .map_err(|source| Error::WriteOutput {
+            path: to.to_owned(),
+            source,
+        })
+}
+
+/// Stores every sprite of `atlases` and writes links to them, named by
+/// index, into the `sprites` output directory of `ts`. Returns how many are
+/// among the first `earlier` sprites stored.
+fn link_sprites(
+    store: &mut SpriteStore,
+    atlases: &[TileAtlas],
+    ts: &Tileset,
+    opts: &HashOptions,
+    earlier: usize,
+    progress: &Progress,
+) -> Result<usize> {
+    let sprites_path = ts.out_path.join("sprites");
+    create_output_dir(&sprites_path)?;
+    let mut found = 0;
+    for atlas in atlases {
+        for idx in atlas.tiles_start..atlas.tiles_end {
+            let hash = atlas.get_sprite_hash(idx, opts);
+            let (stored, serial) = store.put(atlas.get_sprite(idx).to_image(), hash)?;
+            link_or_copy(&stored, &sprites_path.join(format!("{}.png", idx)))?;
+            if serial < earlier {
+                found += 1;
+            }
+            progress.inc();
+        }
+    }
+    Ok(found)
+}
+
+/// Writes the sprites of both tilesets once into `sprite-store/` in A's
+/// output directory, and their `sprites` directories as links into it, so
+/// that a sprite both tilesets have takes the space of one and shows up as
+/// the same file from either side.
+pub fn write_shared_sprites(
+    result: &ComparisonResult,
+    ts1: &Tileset,
+    ts2: &Tileset,
+    opts: &HashOptions,
+) -> Result<StoreSummary> {
+    let store_dir = ts1.out_path.join(STORE_DIR_NAME);
+    let existed = [
+        &store_dir,
+        &ts1.out_path.join("sprites"),
+        &ts2.out_path.join("sprites"),
+    ]
+    .iter()
+    .any(|x| x.exists());
+    let mut store = SpriteStore::create(&store_dir)?;
+    let total = result
+        .atlases_a
+        .iter()
+        .chain(&result.atlases_b)
+        .map(|x| x.tiles_total() as usize)
+        .sum();
+    let progress = Progress::new("Dumping sprites", total);
+    link_sprites(&mut store, &result.atlases_a, ts1, opts, 0, &progress)?;
+    let stored_a = store.stored;
+    let shared = link_sprites(
+        &mut store,
+        &result.atlases_b,
+        ts2,
+        opts,
+        stored_a,
+        &progress,
+    )?;
+    Ok(StoreSummary {
+        stored: store.stored,
+        linked: total,
+        shared,
+        existed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn stores_each_sprite_once() {
+        let dir = std::env::temp_dir().join(format!("tileset-store-{}", std::process::id()));
+        let mut store = SpriteStore::create(&dir).unwrap();
+        let blank = RgbaImage::new(2, 2);
+        let mut dot = RgbaImage::new(2, 2);
+        dot.put_pixel(0, 0, Rgba([1, 2, 3, 255]));
+
+        let (first, serial) = store.put(blank.clone(), 7).unwrap();
+        assert_eq!(serial, 0);
+        assert_eq!(store.put(blank, 7).unwrap(), (first.clone(), 0));
+        // Same hash, different pixels
+        let (second, serial) = store.put(dot, 7).unwrap();
+        assert_eq!(serial, 1);
+        assert_eq!(first.file_name().unwrap(), "0000000007.png");
+        assert_eq!(second.file_name().unwrap(), "0000000007_2.png");
+        assert_eq!(store.stored, 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
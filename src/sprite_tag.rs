@@ -0,0 +1,137 @@
+use crate::atlas::HashBits;
+use std::collections::HashSet;
+
+/// Crockford's base32 alphabet, lowercased; avoids easily confused letters.
+const ALPHABET: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+/// Number of characters needed to encode a full 32-bit hash.
+pub const FULL_TAG_LEN: usize = 7;
+/// Number of characters needed to encode a full 64-bit hash.
+pub const FULL_WIDE_TAG_LEN: usize = 13;
+/// Tags never get shorter than this, even when fewer characters would be unique.
+pub const MIN_TAG_LEN: usize = 4;
+/// Length of a 32-bit sprite hash written out in decimal, as used for
+/// extracted file names; 64-bit ones are as long as they need to be.
+const DECIMAL_HASH_LEN: usize = 10;
+
+/// Encodes a sprite hash as base32, most significant bits first,
+/// so that tag prefixes behave like git short hashes. Tags of 32-bit runs
+/// take `FULL_TAG_LEN` characters and those of 64-bit runs
+/// `FULL_WIDE_TAG_LEN`, whatever the value; only the wide hashes standing in
+/// for colliding sprites of a 32-bit run take `FULL_WIDE_TAG_LEN` there too.
+pub fn full_tag(hash: u64, hash_bits: HashBits) -> String {
+    // Pad 32 bits to 35, or 64 to 65, so they split evenly into 5-bit groups.
+    let (len, bits) = match (hash_bits, u32::try_from(hash)) {
+        (HashBits::Bits32, Ok(_)) => (FULL_TAG_LEN, (hash as u128) << 3),
+        _ => (FULL_WIDE_TAG_LEN, (hash as u128) << 1),
+    };
+    (0..len)
+        .map(|i| {
+            let shift = 5 * (len - 1 - i);
+            ALPHABET[((bits >> shift) & 0x1f) as usize] as char
+        })
+        .collect()
+}
+
+/// Shortest tag length (at least `MIN_TAG_LEN`) at which all the given hashes
+/// have distinct tags.
+pub fn unique_tag_len<'a, I: IntoIterator<Item = &'a u64>>(hashes: I, bits: HashBits) -> usize {
+    let tags: Vec<String> = hashes
+        .into_iter()
+        .collect::<HashSet<&u64>>()
+        .into_iter()
+        .map(|x| full_tag(*x, bits))
+        .collect();
+    let longest = tags.iter().map(String::len).max().unwrap_or(FULL_TAG_LEN);
+    for len in MIN_TAG_LEN..longest {
+        let prefixes: HashSet<&str> = tags.iter().map(|x| &x[..len.min(x.len())]).collect();
+        if prefixes.len() == tags.len() {
+            return len;
+        }
+    }
+    longest
+}
+
+pub fn short_tag(hash: u64, len: usize, bits: HashBits) -> String {
+    let mut tag = full_tag(hash, bits);
+    tag.truncate(len);
+    tag
+}
+
+/// Returns all candidate hashes matching a user-supplied hash, which is
+/// either a decimal hash of at least 10 digits or a (possibly shortened)
+/// base32 tag.
+pub fn resolve<'a, I: IntoIterator<Item = &'a u64>>(
+    query: &str,
+    candidates: I,
+    bits: HashBits,
+) -> Vec<u64> {
+    let query = query.trim().to_lowercase();
+    let decimal = if query.len() >= DECIMAL_HASH_LEN {
+        query.parse::<u64>().ok()
+    } else {
+        None
+    };
+    let mut ret: Vec<u64> = candidates
+        .into_iter()
+        .cloned()
+        .collect::<HashSet<u64>>()
+        .into_iter()
+        .filter(|x| match decimal {
+            Some(d) => *x == d,
+            None => full_tag(*x, bits).starts_with(&query),
+        })
+        .collect();
+    ret.sort_unstable();
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_tags_by_hash_bits() {
+        assert_eq!(full_tag(0, HashBits::Bits32), "0000000");
+        assert_eq!(full_tag(u32::MAX as u64, HashBits::Bits32), "zzzzzzr");
+        assert_eq!(full_tag(1, HashBits::Bits64), "0000000000002");
+        assert_eq!(full_tag(u64::MAX, HashBits::Bits64), "zzzzzzzzzzzzy");
+        assert_eq!(full_tag(1, HashBits::Bits64).len(), FULL_WIDE_TAG_LEN);
+        assert_eq!(short_tag(u64::MAX, 5, HashBits::Bits64), "zzzzz");
+    }
+
+    #[test]
+    fn shortens_mixed_tags_without_panicking() {
+        // A collision stand-in among the 32-bit hashes of a run
+        let hashes = [7u64, 8, 1 << 40];
+        let len = unique_tag_len(&hashes, HashBits::Bits32);
+        assert!((MIN_TAG_LEN..=FULL_WIDE_TAG_LEN).contains(&len));
+        let tags: HashSet<String> = hashes
+            .iter()
+            .map(|x| short_tag(*x, len, HashBits::Bits32))
+            .collect();
+        assert_eq!(tags.len(), hashes.len());
+    }
+
+    #[test]
+    fn shortens_wide_tags() {
+        let hashes = [u64::MAX, u64::MAX - 1, 1 << 63];
+        let len = unique_tag_len(&hashes, HashBits::Bits64);
+        assert_eq!(len, FULL_WIDE_TAG_LEN);
+        assert_eq!(
+            unique_tag_len(&[1u64 << 63, 1], HashBits::Bits64),
+            MIN_TAG_LEN
+        );
+    }
+
+    #[test]
+    fn resolves_wide_tags_and_decimal_hashes() {
+        let hashes = [u64::MAX, 1 << 40, 5];
+        let tag = full_tag(1 << 40, HashBits::Bits64);
+        assert_eq!(resolve(&tag[..6], &hashes, HashBits::Bits64), [1 << 40]);
+        assert_eq!(
+            resolve(&u64::MAX.to_string(), &hashes, HashBits::Bits64),
+            [u64::MAX]
+        );
+        assert_eq!(resolve("zzzz", &hashes, HashBits::Bits64), [u64::MAX]);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::atlas::TileAtlas;
+use crate::tileset::Tileset;
+use image::GenericImageView;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// Sprite counts of one atlas.
+#[derive(Debug, Serialize)]
+pub struct AtlasStats {
+    pub file: String,
+    pub sprites: u32,
+    /// Sprites with at least one visible pixel
+    pub filled: u32,
+    /// Share of sprite slots that are filled
+    pub fill_ratio: f64,
+    /// Sprites no tile references
+    pub unreferenced: u32,
+    /// Whether the sheet is filler, its unreferenced sprites left out of
+    /// the tileset total
+    pub filler: bool,
+}
+
+/// Size metrics of a tileset, for tracking its growth over releases.
+#[derive(Debug, Serialize)]
+pub struct TilesetStats {
+    pub tiles_new: usize,
+    /// Distinct tile ids, not counting additional tiles
+    pub ids: usize,
+    pub multitile_ids: usize,
+    pub animated_ids: usize,
+    pub sprites: u32,
+    /// Unreferenced sprites of sheets that aren't filler
+    pub unreferenced: u32,
+    pub atlases: Vec<AtlasStats>,
+}
+
+fn atlas_stats(atlas: &TileAtlas, used: &BTreeSet<u32>, filler: bool) -> AtlasStats {
+    let range = atlas.tiles_start..atlas.tiles_end;
+    let filled = range
+        .clone()
+        .filter(|&x| atlas.get_sprite(x).pixels().any(|(_, _, px)| px.0[3] != 0))
+        .count() as u32;
+    let sprites = atlas.tiles_total();
+    AtlasStats {
+        file: atlas.file.clone(),
+        sprites,
+        filled,
+        fill_ratio: if sprites == 0 {
+            0.0
+        } else {
+            filled as f64 / sprites as f64
+        },
+        unreferenced: range.filter(|x| !used.contains(x)).count() as u32,
+        filler,
+    }
+}
+
+/// Counts the entries, ids and sprites of a tileset. Ids are counted once
+/// even if defined more than once.
+pub fn tileset_stats(ts: &Tileset, atlases: &[TileAtlas]) -> TilesetStats {
+    let mut ids = BTreeSet::new();
+    let mut multitile = BTreeSet::new();
+    let mut animated = BTreeSet::new();
+    for tile in ts.tiles_new.iter().flat_map(|x| x.tiles.iter()) {
+        for id in tile.base.id.0.iter().filter(|x| ts.keeps(x)) {
+            ids.insert(id.as_str());
+            if tile.base.multitile {
+                multitile.insert(id.as_str());
+            }
+            if tile.base.animated {
+                animated.insert(id.as_str());
+            }
+        }
+    }
+    let used = ts.used_sprites();
+    let atlases: Vec<AtlasStats> = atlases
+        .iter()
+        .zip(&ts.tiles_new)
+        .map(|(atlas, sheet)| atlas_stats(atlas, &used, ts.is_filler(sheet)))
+        .collect();
+    TilesetStats {
+        tiles_new: ts.tiles_new.len(),
+        ids: ids.len(),
+        multitile_ids: multitile.len(),
+        animated_ids: animated.len(),
+        sprites: atlases.iter().map(|x| x.sprites).sum(),
+        unreferenced: atlases
+            .iter()
+            .filter(|x| !x.filler)
+            .map(|x| x.unreferenced)
+            .sum(),
+        atlases,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_ids_once() {
+        let ts: Tileset = serde_json::from_str(
+            r#"{
+              "tile_info": [ { "width": 32, "height": 32 } ],
+              "tiles-new": [
+                { "file": "a.png", "tiles": [
+                  { "id": ["t_wall", "t_wall_red"], "fg": 0, "multitile": true },
+                  { "id": "t_water", "fg": [{ "sprite": 1, "weight": 1 }], "animated": true }
+                ] },
+                { "file": "b.png", "tiles": [ { "id": "t_wall", "fg": 2 } ] }
+              ]
+            }"#,
+        )
+        .unwrap();
+        let stats = tileset_stats(&ts, &[]);
+        assert_eq!(stats.tiles_new, 2);
+        assert_eq!(stats.ids, 3);
+        assert_eq!(stats.multitile_ids, 2);
+        assert_eq!(stats.animated_ids, 1);
+        assert_eq!(stats.sprites, 0);
+    }
+}
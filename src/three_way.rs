@@ -0,0 +1,144 @@
+use crate::compare::{find_duplicates, CompareOptions};
+use crate::error::{Error, Result};
+use crate::tileset::{SingleTile, Tileset};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// How an id changed in two forks of a common base tileset.
+#[derive(Debug, Default)]
+pub struct ThreeWayResult {
+    /// Changed, added or removed only in ours
+    pub ours: Vec<String>,
+    /// Changed, added or removed only in theirs
+    pub theirs: Vec<String>,
+    /// Changed in both to the same definition
+    pub both: Vec<String>,
+    /// Changed in both to different definitions
+    pub conflicts: Vec<String>,
+    pub unchanged: usize,
+}
+
+type Definitions<'a> = HashMap<&'a str, &'a SingleTile>;
+
+fn by_id<'a>(path: &Path, vars: &'a [SingleTile]) -> Result<Definitions<'a>> {
+    let dups = find_duplicates(vars);
+    if !dups.is_empty() {
+        return Err(Error::BadTileset {
+            path: path.to_owned(),
+            message: format!(
+                "duplicate ids prevent a three-way comparison: {}",
+                dups.join(", ")
+            ),
+        });
+    }
+    Ok(vars.iter().map(|x| (x.id.0[0].as_str(), x)).collect())
+}
+
+fn classify<'a>(
+    base: &Definitions<'a>,
+    ours: &Definitions<'a>,
+    theirs: &Definitions<'a>,
+    opts: &CompareOptions,
+) -> ThreeWayResult {
+    let mut ids: Vec<&str> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .copied()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    opts.order.sort(&mut ids);
+
+    let mut ret = ThreeWayResult::default();
+    for id in ids {
+        let (b, o, t) = (base.get(id), ours.get(id), theirs.get(id));
+        let list = match (b != o, b != t) {
+            (false, false) => {
+                ret.unchanged += 1;
+                continue;
+            }
+            (true, false) => &mut ret.ours,
+            (false, true) => &mut ret.theirs,
+            (true, true) if o == t => &mut ret.both,
+            (true, true) => &mut ret.conflicts,
+        };
+        list.push(id.to_string());
+    }
+    ret
+}
+
+/// Classifies every id of three tilesets by whether it changed from `base`
+/// in `ours`, in `theirs`, or in both, for merging two forks back together.
+/// Definitions are compared by sprite content, as in a two-way comparison.
+pub fn compare3(
+    base: &Tileset,
+    ours: &Tileset,
+    theirs: &Tileset,
+    opts: &CompareOptions,
+) -> Result<ThreeWayResult> {
+    let (vars_base, _) = base.generate_variations(true, &opts.hash)?;
+    let (vars_ours, _) = ours.generate_variations(true, &opts.hash)?;
+    let (vars_theirs, _) = theirs.generate_variations(true, &opts.hash)?;
+    Ok(classify(
+        &by_id(&base.base_path, &vars_base)?,
+        &by_id(&ours.base_path, &vars_ours)?,
+        &by_id(&theirs.base_path, &vars_theirs)?,
+        opts,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atlas::HashOptions;
+    use crate::id_order::IdOrder;
+
+    fn tiles(defs: &[(&str, u32)]) -> Vec<SingleTile> {
+        defs.iter()
+            .map(|(id, fg)| {
+                let json = format!(r#"{{ "id": "{}", "fg": {} }}"#, id, fg);
+                serde_json::from_str(&json).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn classifies_changes() {
+        let base = tiles(&[
+            ("same", 1),
+            ("ours", 1),
+            ("theirs", 1),
+            ("both", 1),
+            ("clash", 1),
+        ]);
+        let ours = tiles(&[
+            ("same", 1),
+            ("ours", 2),
+            ("theirs", 1),
+            ("both", 2),
+            ("clash", 2),
+            ("new", 1),
+        ]);
+        let theirs = tiles(&[("same", 1), ("ours", 1), ("both", 2), ("clash", 3)]);
+        let path = Path::new("");
+        let opts = CompareOptions {
+            hash: HashOptions::default(),
+            order: IdOrder::Natural,
+            pixels_only: false,
+            low_memory: false,
+            hash_cache: false,
+        };
+        let result = classify(
+            &by_id(path, &base).unwrap(),
+            &by_id(path, &ours).unwrap(),
+            &by_id(path, &theirs).unwrap(),
+            &opts,
+        );
+        assert_eq!(result.ours, ["new", "ours"]);
+        assert_eq!(result.theirs, ["theirs"]);
+        assert_eq!(result.both, ["both"]);
+        assert_eq!(result.conflicts, ["clash"]);
+        assert_eq!(result.unchanged, 1);
+    }
+}
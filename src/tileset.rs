@@ -0,0 +1,911 @@
+use crate::atlas::{hash_sprite_indices, hash_sprites, HashOptions, TileAtlas};
+use crate::error::{create_output_dir, Error, Result};
+use crate::extras::Extras;
+use crate::hash_cache::{CachedSheet, HashCache};
+use crate::id_filter::IdFilter;
+use crate::progress::Progress;
+use crate::single_or_vec::SingleOrVec;
+use crate::source_map::{cite, SourceMap};
+use crate::sprite_id_with_weight::SpriteIdWithWeight;
+use crate::tileset_config::TilesetConfig;
+use crate::warnings::{self, Code, Warning};
+use crate::{debug, verbose};
+use image::io::Reader as ImageReader;
+use image::{DynamicImage, RgbaImage};
+use rayon::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TilesetTileInfo {
+    #[serde(default = "default_pixelscale")]
+    pub pixelscale: f32,
+    #[serde(default = "default_retract_dist_min")]
+    pub retract_dist_min: f32,
+    #[serde(default = "default_retract_dist_max")]
+    pub retract_dist_max: f32,
+    #[serde(default)]
+    pub iso: bool,
+    pub width: u32,
+    pub height: u32,
+    #[serde(flatten)]
+    pub extra: Extras,
+}
+
+fn default_pixelscale() -> f32 {
+    1.0
+}
+
+fn default_retract_dist_min() -> f32 {
+    -1.0
+}
+
+fn default_retract_dist_max() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OverlayOrderElem {
+    pub id: SingleOrVec<String>,
+    pub order: i32,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct SingleTile {
+    pub id: SingleOrVec<String>,
+    /// `None` if the definition has no `fg` at all, as opposed to an empty
+    /// list
+    #[serde(
+        default,
+        deserialize_with = "deserialize_sprites",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub fg: Option<SingleOrVec<SpriteIdWithWeight>>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_sprites",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub bg: Option<SingleOrVec<SpriteIdWithWeight>>,
+    pub rotates: Option<bool>,
+    #[serde(default)]
+    pub multitile: bool,
+    #[serde(default)]
+    pub animated: bool,
+    #[serde(default)]
+    pub height_3d: i32,
+    #[serde(flatten)]
+    pub extra: Extras,
+}
+
+/// Reads a present `fg` or `bg`. An empty array would otherwise be taken
+/// for a single variation without sprites.
+fn deserialize_sprites<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<SingleOrVec<SpriteIdWithWeight>>, D::Error> {
+    let mut list = SingleOrVec::<SpriteIdWithWeight>::deserialize(deserializer)?;
+    list.0.retain(|x| !x.id.0.is_empty());
+    Ok(Some(list))
+}
+
+impl SingleTile {
+    /// Foreground variations, none if `fg` is absent.
+    pub fn fg(&self) -> &[SpriteIdWithWeight] {
+        self.fg.as_ref().map_or(&[], |x| &x.0)
+    }
+
+    /// Background variations, none if `bg` is absent.
+    pub fn bg(&self) -> &[SpriteIdWithWeight] {
+        self.bg.as_ref().map_or(&[], |x| &x.0)
+    }
+
+    /// Every foreground variation, then every background variation.
+    pub fn variations(&self) -> impl Iterator<Item = &SpriteIdWithWeight> {
+        self.fg().iter().chain(self.bg())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CompositeTile {
+    #[serde(flatten)]
+    pub base: SingleTile,
+    #[serde(default)]
+    pub additional_tiles: Vec<SingleTile>,
+    // Comments
+    #[serde(default, rename = "//")]
+    _comment: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SingleAscii {
+    pub offset: i32,
+    pub bold: bool,
+    pub color: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TilesNew {
+    pub file: String,
+    pub sprite_width: Option<u32>,
+    pub sprite_height: Option<u32>,
+    pub sprite_offset_x: Option<i32>,
+    pub sprite_offset_y: Option<i32>,
+    pub tiles: Vec<CompositeTile>,
+    #[serde(default)]
+    pub ascii: Vec<SingleAscii>,
+    // Comments
+    #[serde(default, rename = "//")]
+    _comment: String,
+    #[serde(flatten)]
+    pub extra: Extras,
+}
+
+/// Sheet of the legacy layout predating `tiles-new`, where `file`, `tiles`
+/// and `ascii` sit at the top level of `tile_config.json`.
+#[derive(Debug, Default, Deserialize)]
+struct LegacySheet {
+    file: Option<String>,
+    #[serde(default)]
+    tiles: Vec<CompositeTile>,
+    #[serde(default)]
+    ascii: Vec<SingleAscii>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Tileset {
+    #[serde(skip_deserializing)]
+    pub base_path: PathBuf,
+    /// Where generated artifacts for this tileset go
+    #[serde(skip)]
+    pub out_path: PathBuf,
+    #[serde(skip)]
+    pub config: TilesetConfig,
+    /// Only tiles with matching ids are expanded, hashed and compared
+    #[serde(skip)]
+    pub filter: Option<IdFilter>,
+    /// Lines in tile_config.json where tiles and sheets are defined
+    #[serde(skip)]
+    pub source: SourceMap,
+    /// Factor sprites are enlarged by when loaded, to compare them with a
+    /// tileset of a larger pixelscale
+    #[serde(skip)]
+    pub upscale: Option<u32>,
+    /// Factor sprites are shrunk by when loaded, to compare them with a
+    /// lower resolution variant of the tileset
+    #[serde(skip)]
+    pub downscale: Option<u32>,
+    /// Images to read sheets from instead of their file in the tileset
+    /// directory, by sheet file
+    #[serde(skip)]
+    pub sheet_images: HashMap<String, PathBuf>,
+    /// Sheets to decode before the others, e.g. those defining ids already
+    /// known to differ
+    #[serde(skip)]
+    pub priority_sheets: Vec<String>,
+    pub tile_info: Vec<TilesetTileInfo>,
+    /// Sheets; a legacy single-sheet config is loaded as one entry
+    #[serde(rename = "tiles-new", default)]
+    pub tiles_new: Vec<TilesNew>,
+    #[serde(default)]
+    pub overlay_ordering: Vec<OverlayOrderElem>,
+    // Must come before `extra`, which takes whatever fields are left
+    #[serde(flatten, skip_serializing)]
+    legacy: LegacySheet,
+    #[serde(flatten)]
+    pub extra: Extras,
+}
+
+impl Tileset {
+    /// Loads `tile_config.json` and `comparator.toml` from the tileset directory.
+    /// Fields unknown to this tool are an error unless `comparator.toml` enables
+    /// compatibility mode.
+    pub fn load(base_path: &Path) -> Result<Tileset> {
+        Self::load_impl(base_path, false)
+    }
+
+    /// Like `load`, but always in compatibility mode: unknown fields are kept
+    /// as opaque values, compared like any other field and reported by validate.
+    pub fn load_compat(base_path: &Path) -> Result<Tileset> {
+        Self::load_impl(base_path, true)
+    }
+
+    fn load_impl(base_path: &Path, compat: bool) -> Result<Tileset> {
+        if !base_path.is_dir() {
+            return Err(Error::NoTilesetDir(base_path.to_owned()));
+        }
+        Self::load_config_file(&base_path.join("tile_config.json"), compat)
+    }
+
+    /// Loads a tileset from its `tile_config.json`, the tileset directory
+    /// being the one the file is in. Images are not read.
+    pub fn load_config_file(base_tile_config: &Path, compat: bool) -> Result<Tileset> {
+        if !base_tile_config.is_file() {
+            return Err(Error::NoInputFile(base_tile_config.to_owned()));
+        }
+        let base_path = base_tile_config.parent().unwrap_or(Path::new(""));
+        let base_tile_config = base_tile_config.to_owned();
+
+        let tile_config_data =
+            std::fs::read_to_string(&base_tile_config).map_err(|source| Error::ReadInput {
+                path: base_tile_config.clone(),
+                source,
+            })?;
+
+        let mut tileset: Tileset =
+            serde_json::from_str(&tile_config_data).map_err(|source| Error::ParseConfig {
+                path: base_tile_config.clone(),
+                source,
+            })?;
+        tileset.base_path = base_path.to_owned();
+        tileset.out_path = base_path.to_owned();
+        tileset.config = TilesetConfig::load(base_path)?;
+
+        let legacy = tileset.legacy.file.is_some();
+        tileset.normalize_layout(&base_tile_config)?;
+        tileset.source = SourceMap::new(&tile_config_data, &tileset, legacy);
+
+        if tileset.tile_info.is_empty() {
+            return Err(Error::BadTileset {
+                path: base_tile_config,
+                message: "'tile_info' is empty".to_string(),
+            });
+        }
+        if tileset.tile_info().width == 0 || tileset.tile_info().height == 0 {
+            return Err(Error::BadTileset {
+                path: base_tile_config,
+                message: "tile size in 'tile_info' must not be zero".to_string(),
+            });
+        }
+
+        if !compat && !tileset.config.compat {
+            if let Some((_, field)) = tileset.unknown_fields().first() {
+                return Err(Error::BadTileset {
+                    path: base_tile_config,
+                    message: format!("{} (compatibility mode accepts unknown fields)", field),
+                });
+            }
+        }
+
+        Ok(tileset)
+    }
+
+    /// Moves the sheet of a legacy config into `tiles_new`.
+    fn normalize_layout(&mut self, config_path: &Path) -> Result<()> {
+        let legacy = std::mem::take(&mut self.legacy);
+        let bad = |message: &str| Error::BadTileset {
+            path: config_path.to_owned(),
+            message: message.to_string(),
+        };
+        match legacy.file {
+            Some(_) if !self.tiles_new.is_empty() => {
+                Err(bad("has both 'tiles-new' and a legacy top-level 'file'"))
+            }
+            Some(file) => {
+                self.tiles_new.push(TilesNew {
+                    file,
+                    sprite_width: None,
+                    sprite_height: None,
+                    sprite_offset_x: None,
+                    sprite_offset_y: None,
+                    tiles: legacy.tiles,
+                    ascii: legacy.ascii,
+                    _comment: String::new(),
+                    extra: Extras::default(),
+                });
+                Ok(())
+            }
+            None if !legacy.tiles.is_empty() || !legacy.ascii.is_empty() => {
+                Err(bad("has legacy top-level 'tiles' but no 'file'"))
+            }
+            None if self.tiles_new.is_empty() => Err(bad("has neither 'tiles-new' nor 'file'")),
+            None => Ok(()),
+        }
+    }
+
+    /// Fields this version of the tool doesn't know, described, along with
+    /// the sheet they appear in.
+    pub fn unknown_fields(&self) -> Vec<(Option<&str>, String)> {
+        let mut ret = vec![];
+        for name in self.extra.names() {
+            ret.push((None, format!("unknown top-level field '{}'", name)));
+        }
+        for name in self.tile_info.iter().flat_map(|x| x.extra.names()) {
+            ret.push((None, format!("unknown field '{}' in tile_info", name)));
+        }
+        for sheet in &self.tiles_new {
+            let file = Some(sheet.file.as_str());
+            for name in sheet.extra.names() {
+                let desc = format!("unknown field '{}' in sheet '{}'", name, sheet.file);
+                ret.push((file, desc));
+            }
+            for tile in &sheet.tiles {
+                let id = tile.base.id.0.join(", ");
+                for name in tile.base.extra.names() {
+                    ret.push((file, format!("unknown field '{}' in tile '{}'", name, id)));
+                }
+                for at in &tile.additional_tiles {
+                    for name in at.extra.names() {
+                        let desc = format!(
+                            "unknown field '{}' in additional tile '{}' of '{}'",
+                            name,
+                            at.id.0.join(", "),
+                            id
+                        );
+                        ret.push((file, desc));
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    /// The `tile_info` entry in effect. The game reads every entry in turn,
+    /// so each overrides those before it.
+    pub fn tile_info(&self) -> &TilesetTileInfo {
+        // Loading fails if there are none
+        &self.tile_info[self.tile_info.len() - 1]
+    }
+
+    /// Messages for `tile_info` entries setting a tile size, pixelscale or
+    /// iso mode that a later entry overrides.
+    pub fn tile_info_conflicts(&self) -> Vec<String> {
+        let last = self.tile_info();
+        let mut ret = vec![];
+        for (k, info) in self.tile_info.iter().enumerate() {
+            let mut differs = vec![];
+            if (info.width, info.height) != (last.width, last.height) {
+                differs.push(format!(
+                    "size {}x{} instead of {}x{}",
+                    info.width, info.height, last.width, last.height
+                ));
+            }
+            if info.pixelscale != last.pixelscale {
+                differs.push(format!(
+                    "pixelscale {} instead of {}",
+                    info.pixelscale, last.pixelscale
+                ));
+            }
+            if info.iso != last.iso {
+                differs.push(format!("iso {} instead of {}", info.iso, last.iso));
+            }
+            if !differs.is_empty() {
+                ret.push(format!(
+                    "tile_info entry {} of {} sets {}, but the game uses the last entry",
+                    k + 1,
+                    self.tile_info.len(),
+                    differs.join(", ")
+                ));
+            }
+        }
+        ret
+    }
+
+    /// Whether a warning is suppressed on the command line, in this tileset's
+    /// config, or in the config for the given sheet.
+    pub fn allows(&self, code: Code, sheet: Option<&str>) -> bool {
+        code.is_allowed() || self.config.allows(code, sheet)
+    }
+
+    pub fn warn<S: Into<String>>(&self, code: Code, sheet: Option<&str>, message: S) {
+        if !self.allows(code, sheet) {
+            warnings::print_warning(&Warning::new(code, message));
+        }
+    }
+
+    /// Whether a tile id passes the id filter.
+    pub fn keeps(&self, id: &str) -> bool {
+        self.filter.as_ref().is_none_or(|x| x.matches(id))
+    }
+
+    /// Image file of the tiles-new entry defining each expanded tile id.
+    pub fn sheets_by_id(&self) -> HashMap<String, &str> {
+        let mut ret = HashMap::new();
+        for tiles_new in &self.tiles_new {
+            for tile in &tiles_new.tiles {
+                for id in &tile.base.id.0 {
+                    ret.insert(id.clone(), tiles_new.file.as_str());
+                    for at in &tile.additional_tiles {
+                        for at_id in &at.id.0 {
+                            ret.insert(id.to_owned() + "_" + at_id, tiles_new.file.as_str());
+                        }
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    /// Whether a sheet is filler, left out of unused sprite and coverage
+    /// analyses: as set in `comparator.toml`, otherwise if its image is named
+    /// like a fallback sheet or it only has fallback glyphs.
+    pub fn is_filler(&self, sheet: &TilesNew) -> bool {
+        self.config.filler(&sheet.file).unwrap_or_else(|| {
+            let stem = Path::new(&sheet.file)
+                .file_stem()
+                .map_or(String::new(), |x| x.to_string_lossy().to_lowercase());
+            stem.contains("fallback") || (sheet.tiles.is_empty() && !sheet.ascii.is_empty())
+        })
+    }
+
+    /// Sprite indices used by the tiles that pass the id filter.
+    pub fn used_sprites(&self) -> BTreeSet<u32> {
+        let mut ret = BTreeSet::new();
+        let mut add = |tile: &SingleTile| {
+            for spidw in tile.variations() {
+                ret.extend(spidw.indices());
+            }
+        };
+        for tile in self.tiles_new.iter().flat_map(|x| x.tiles.iter()) {
+            for id in &tile.base.id.0 {
+                if self.keeps(id) {
+                    add(&tile.base);
+                }
+                for at in &tile.additional_tiles {
+                    for at_id in &at.id.0 {
+                        if self.keeps(&format!("{}_{}", id, at_id)) {
+                            add(at);
+                        }
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    pub(crate) fn sheet_path(&self, tiles_new: &TilesNew) -> PathBuf {
+        match self.sheet_images.get(&tiles_new.file) {
+            Some(path) => path.clone(),
+            None => self.base_path.join(&tiles_new.file),
+        }
+    }
+
+    fn decode_sheet(&self, tiles_new: &TilesNew) -> Result<RgbaImage> {
+        let start = Instant::now();
+        let img_path = self.sheet_path(tiles_new);
+        let img_raw: DynamicImage = ImageReader::open(&img_path)
+            .map_err(|source| Error::ReadInput {
+                path: img_path.clone(),
+                source,
+            })?
+            .decode()
+            .map_err(|source| Error::DecodeImage {
+                path: img_path.clone(),
+                source,
+            })?;
+        debug!(
+            "Decoded '{}' of {} in {:.2?}",
+            tiles_new.file,
+            self.base_path.display(),
+            start.elapsed()
+        );
+        Ok(img_raw.to_rgba8())
+    }
+
+    /// Decodes every sheet in parallel, starting with `priority_sheets`.
+    pub fn load_atlases(&self) -> Result<Vec<TileAtlas>> {
+        let progress = Progress::new("Decoding atlases", self.tiles_new.len());
+        let mut order: Vec<usize> = (0..self.tiles_new.len()).collect();
+        // Stable, so the rest keep their definition order
+        order.sort_by_key(|&k| !self.priority_sheets.contains(&self.tiles_new[k].file));
+        let decoded: Vec<Mutex<Option<Result<RgbaImage>>>> =
+            self.tiles_new.iter().map(|_| Mutex::new(None)).collect();
+        // Tasks spawned first are started first
+        rayon::scope_fifo(|scope| {
+            for k in order {
+                let (decoded, progress) = (&decoded, &progress);
+                scope.spawn_fifo(move |_| {
+                    let img = self.decode_sheet(&self.tiles_new[k]);
+                    *decoded[k].lock().unwrap() = Some(img);
+                    progress.inc();
+                });
+            }
+        });
+
+        let mut tiles_start: u32 = 0;
+        let mut atlases: Vec<TileAtlas> = vec![];
+        for (tiles_new, img) in self.tiles_new.iter().zip(decoded) {
+            let img = img.into_inner().unwrap().unwrap()?;
+            let atlas = self.atlas_from(tiles_new, img, tiles_start)?;
+            tiles_start = atlas.tiles_end;
+            atlases.push(atlas);
+        }
+
+        Ok(atlases)
+    }
+
+    /// Atlas of a decoded sheet whose sprites are numbered from
+    /// `tiles_start`, scaled as the tileset asks.
+    fn atlas_from(
+        &self,
+        tiles_new: &TilesNew,
+        img: RgbaImage,
+        tiles_start: u32,
+    ) -> Result<TileAtlas> {
+        let sprite_w = tiles_new.sprite_width.unwrap_or(self.tile_info().width);
+        let sprite_h = tiles_new.sprite_height.unwrap_or(self.tile_info().height);
+
+        if sprite_w == 0 || sprite_h == 0 {
+            return Err(Error::BadTileset {
+                path: self.sheet_path(tiles_new),
+                message: "sprite size must not be zero".to_string(),
+            });
+        }
+
+        let mut atlas = TileAtlas {
+            file: tiles_new.file.clone(),
+            sprite_w,
+            sprite_h,
+            offset_x: tiles_new.sprite_offset_x.unwrap_or(0),
+            offset_y: tiles_new.sprite_offset_y.unwrap_or(0),
+            tile_w: self.tile_info().width,
+            tile_h: self.tile_info().height,
+            tiles_x: img.width() / sprite_w,
+            tiles_y: img.height() / sprite_h,
+            tiles_start,
+            img,
+            tiles_end: tiles_start,
+            slots: None,
+        };
+        atlas.tiles_end = atlas.tiles_start + atlas.tiles_total();
+        if let Some(factor) = self.upscale {
+            atlas = atlas.upscaled(factor);
+        }
+        if let Some(factor) = self.downscale {
+            atlas = atlas.downscaled(factor);
+        }
+        Ok(atlas)
+    }
+
+    /// Warns about an atlas that doesn't divide into sprites, and with -v
+    /// prints its geometry.
+    fn warn_about_atlas(&self, atlas: &TileAtlas, evenly_divided: bool) {
+        verbose!(
+            "Atlas '{}' of {}: {}x{} sprites of {}x{}, indices {}..{}",
+            atlas.file,
+            self.base_path.display(),
+            atlas.tiles_x,
+            atlas.tiles_y,
+            atlas.sprite_w,
+            atlas.sprite_h,
+            atlas.tiles_start,
+            atlas.tiles_end
+        );
+        if atlas.is_empty() {
+            self.warn(
+                Code::AtlasIndivisible,
+                Some(&atlas.file),
+                format!(
+                    "image '{}' is smaller than a single sprite of size {}x{}",
+                    atlas.file, atlas.sprite_w, atlas.sprite_h
+                ),
+            );
+        } else if !evenly_divided {
+            self.warn(
+                Code::AtlasIndivisible,
+                Some(&atlas.file),
+                format!(
+                    "image '{}' cannot be properly divided into sprites of size {}x{}",
+                    atlas.file, atlas.sprite_w, atlas.sprite_h
+                ),
+            );
+        }
+    }
+
+    /// Expands every tile entry into one `SingleTile` per id, with additional
+    /// tiles as separate entries, sorted. Ids rejected by the id filter are
+    /// left out, and their sprites are not hashed.
+    pub fn expand_variations(
+        &self,
+        atlases: &[TileAtlas],
+        do_hash: bool,
+        opts: &HashOptions,
+    ) -> Vec<SingleTile> {
+        let hashes: HashMap<u32, u64> = if do_hash {
+            hash_sprite_indices(atlases, self.used_sprites(), opts)
+        } else {
+            HashMap::new()
+        };
+        self.expand_with_hashes(do_hash, &hashes, opts)
+    }
+
+    /// `expand_variations` with the hashes of the used sprites at hand.
+    pub fn expand_with_hashes(
+        &self,
+        do_hash: bool,
+        hashes: &HashMap<u32, u64>,
+        opts: &HashOptions,
+    ) -> Vec<SingleTile> {
+        let mut ret = Vec::with_capacity(self.tiles_new.len());
+        for tiles_new in &self.tiles_new {
+            let warn_out_of_range = |id: &str, out_of_range: Vec<u64>| {
+                for sprite in out_of_range {
+                    self.warn(
+                        Code::OutOfRange,
+                        Some(&tiles_new.file),
+                        format!(
+                            "tile '{}' sprite {} outside all atlas ranges{}",
+                            id,
+                            sprite,
+                            cite(self.source.line(id))
+                        ),
+                    );
+                }
+            };
+            for tile in &tiles_new.tiles {
+                for id in &tile.base.id.0 {
+                    let mut cloned = tile.base.clone();
+                    cloned.id = SingleOrVec::from_single(id.to_owned());
+                    opts.null_sprite.strip(&mut cloned.fg);
+                    opts.null_sprite.strip(&mut cloned.bg);
+                    let keep = self.keeps(id);
+                    if do_hash && keep {
+                        for list in cloned.fg.iter_mut().chain(cloned.bg.iter_mut()) {
+                            warn_out_of_range(id, hash_sprites(list, hashes));
+                        }
+                    }
+                    if cloned.rotates.is_none() {
+                        cloned.rotates = Some(cloned.multitile);
+                    }
+
+                    for at in &tile.additional_tiles {
+                        for at_id in &at.id.0 {
+                            let at_full_id = id.to_owned() + "_" + at_id;
+                            if !self.keeps(&at_full_id) {
+                                continue;
+                            }
+                            let mut cloned_at = at.clone();
+                            cloned_at.id = SingleOrVec::from_single(at_full_id);
+                            opts.null_sprite.strip(&mut cloned_at.fg);
+                            opts.null_sprite.strip(&mut cloned_at.bg);
+                            if do_hash {
+                                let at_full_id = &cloned_at.id.0[0];
+                                let lists = cloned_at.fg.iter_mut().chain(cloned_at.bg.iter_mut());
+                                for list in lists {
+                                    warn_out_of_range(at_full_id, hash_sprites(list, hashes));
+                                }
+                            }
+                            cloned_at.rotates = Some(true);
+                            cloned_at.height_3d = cloned.height_3d;
+                            ret.push(cloned_at);
+                        }
+                    }
+
+                    if keep {
+                        ret.push(cloned);
+                    }
+                }
+            }
+        }
+
+        ret.sort();
+        ret
+    }
+
+    pub fn generate_variations(
+        &self,
+        do_hash: bool,
+        opts: &HashOptions,
+    ) -> Result<(Vec<SingleTile>, Vec<TileAtlas>)> {
+        let atlases = self.load_checked_atlases()?;
+        let ret = self.expand_variations(&atlases, do_hash, opts);
+        Ok((ret, atlases))
+    }
+
+    /// `load_atlases`, warning about tile_info and sheets that don't divide
+    /// into sprites.
+    pub fn load_checked_atlases(&self) -> Result<Vec<TileAtlas>> {
+        let atlases = self.load_atlases()?;
+
+        for message in self.tile_info_conflicts() {
+            self.warn(Code::TileInfoConflict, None, message);
+        }
+        for atlas in &atlases {
+            self.warn_about_atlas(atlas, atlas.is_evenly_divided());
+        }
+        Ok(atlases)
+    }
+
+    /// The first half of `generate_variations` with hashing: the atlases,
+    /// and the hashes of the used sprites for `expand_with_hashes`, to be
+    /// checked or adjusted in between.
+    pub fn hash_used_sprites(
+        &self,
+        opts: &HashOptions,
+    ) -> Result<(Vec<TileAtlas>, HashMap<u32, u64>)> {
+        let atlases = self.load_checked_atlases()?;
+        let hashes = hash_sprite_indices(&atlases, self.used_sprites(), opts);
+        Ok((atlases, hashes))
+    }
+
+    /// `hash_used_sprites` decoding, hashing and dropping one sheet at a
+    /// time, so that memory holds a single decoded sheet rather than all of
+    /// them. Only sheets with fallback glyphs are kept whole; `load_sprites`
+    /// brings back the sprites of the others that are needed later. With a
+    /// cache, sheets hashed by an earlier run aren't decoded at all, and
+    /// other sheets without fallback glyphs are added to it.
+    pub fn hash_used_sprites_lazily(
+        &self,
+        opts: &HashOptions,
+        cache: Option<&HashCache>,
+    ) -> Result<(Vec<TileAtlas>, HashMap<u32, u64>)> {
+        for message in self.tile_info_conflicts() {
+            self.warn(Code::TileInfoConflict, None, message);
+        }
+        let used = self.used_sprites();
+        let progress = Progress::new("Hashing sheets", self.tiles_new.len());
+        let mut hashes: HashMap<u32, u64> = HashMap::new();
+        let mut tiles_start: u32 = 0;
+        let mut atlases: Vec<TileAtlas> = vec![];
+        for tiles_new in &self.tiles_new {
+            // Fallback glyphs are compared by pixels, so their sheets are
+            // always decoded
+            let cache = cache.filter(|_| tiles_new.ascii.is_empty());
+            let key = match cache {
+                Some(cache) => Some(cache.key(self, tiles_new, opts)?),
+                None => None,
+            };
+            let cached = cache.zip(key).and_then(|(cache, key)| cache.get(key));
+            if let Some(key) = key {
+                let found = if cached.is_some() { "hit" } else { "miss" };
+                debug!(
+                    "Hash cache {} for '{}' of {} ({:016x})",
+                    found,
+                    tiles_new.file,
+                    self.base_path.display(),
+                    key
+                );
+            }
+            if let Some(cached) = cached {
+                let atlas = cached.atlas(&tiles_new.file, tiles_start);
+                self.warn_about_atlas(&atlas, cached.evenly_divided);
+                hashes.extend(cached.hashes_from(tiles_start));
+                tiles_start = atlas.tiles_end;
+                atlases.push(atlas);
+                progress.inc();
+                continue;
+            }
+
+            let img = self.decode_sheet(tiles_new)?;
+            let mut atlas = self.atlas_from(tiles_new, img, tiles_start)?;
+            self.warn_about_atlas(&atlas, atlas.is_evenly_divided());
+            let range = atlas.tiles_start..atlas.tiles_end;
+            // Cached entries hold every sprite, for runs with other filters
+            let indices: Vec<u32> = match key {
+                Some(_) => range.collect(),
+                None => used.range(range).copied().collect(),
+            };
+            let sheet_hashes: HashMap<u32, u64> = indices
+                .into_par_iter()
+                .map(|x| (x, atlas.get_sprite_hash(x, opts)))
+                .collect();
+            if let Some((cache, key)) = cache.zip(key) {
+                cache.put(key, &CachedSheet::new(&atlas, &sheet_hashes))?;
+            }
+            hashes.extend(sheet_hashes);
+            if tiles_new.ascii.is_empty() {
+                atlas.retain_sprites(&BTreeSet::new());
+            }
+            tiles_start = atlas.tiles_end;
+            atlases.push(atlas);
+            progress.inc();
+        }
+        Ok((atlases, hashes))
+    }
+
+    /// Brings the sprites in `keep` back into atlases that dropped them,
+    /// decoding again only the sheets that hold any of them.
+    pub fn load_sprites(&self, atlases: &mut [TileAtlas], keep: &BTreeSet<u32>) -> Result<()> {
+        let stale: Vec<usize> = atlases
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| {
+                x.slots.is_some() && keep.range(x.tiles_start..x.tiles_end).next().is_some()
+            })
+            .map(|(k, _)| k)
+            .collect();
+        let progress = Progress::new("Loading sprites", stale.len());
+        for k in stale {
+            let tiles_new = &self.tiles_new[k];
+            let img = self.decode_sheet(tiles_new)?;
+            let mut atlas = self.atlas_from(tiles_new, img, atlases[k].tiles_start)?;
+            atlas.retain_sprites(keep);
+            atlases[k] = atlas;
+            progress.inc();
+        }
+        Ok(())
+    }
+
+    /// Writes every sprite the given atlases hold into the `sprites` output
+    /// directory, named by index. The directory is created if missing but
+    /// never emptied, since it may not be one this run wrote; returns whether
+    /// it already existed, so that sprites of an earlier dump may remain.
+    pub fn dump_sprites(&self, atlases: &[TileAtlas]) -> Result<bool> {
+        let sprites_path = self.out_path.join("sprites");
+        let existed = sprites_path.exists();
+        create_output_dir(&sprites_path)?;
+        let total = atlases
+            .iter()
+            .flat_map(|x| (x.tiles_start..x.tiles_end).filter(|idx| x.has_sprite(*idx)))
+            .count();
+        let progress = Progress::new("Dumping sprites", total);
+        for atlas in atlases {
+            atlas.dump_sprites_to_dir(&sprites_path, &progress)?;
+        }
+        Ok(existed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn additional_tile_ids_with_cjk_and_emoji() {
+        let config = r#"{
+            "tile_info": [{ "width": 32, "height": 32 }],
+            "tiles-new": [{
+                "file": "tiles.png",
+                "tiles": [{
+                    "id": ["t_墙", "t_🧱"],
+                    "//": "墙 means wall",
+                    "fg": 0,
+                    "multitile": true,
+                    "additional_tiles": [{ "id": "中心", "fg": 1 }]
+                }]
+            }]
+        }"#;
+        let ts: Tileset = serde_json::from_str(config).unwrap();
+        let vars = ts.expand_variations(&[], false, &HashOptions::default());
+        let ids: Vec<&str> = vars.iter().map(|x| x.id.0[0].as_str()).collect();
+        assert_eq!(ids, ["t_墙", "t_墙_中心", "t_🧱", "t_🧱_中心"]);
+    }
+
+    #[test]
+    fn last_tile_info_wins() {
+        let config = r#"{
+            "tile_info": [
+                { "width": 32, "height": 32 },
+                { "width": 16, "height": 16, "pixelscale": 2 },
+                { "width": 16, "height": 16, "pixelscale": 2 }
+            ],
+            "tiles-new": [{ "file": "tiles.png", "tiles": [] }]
+        }"#;
+        let ts: Tileset = serde_json::from_str(config).unwrap();
+        assert_eq!((ts.tile_info().width, ts.tile_info().pixelscale), (16, 2.0));
+        assert_eq!(
+            ts.tile_info_conflicts(),
+            [
+                "tile_info entry 1 of 3 sets size 32x32 instead of 16x16, pixelscale 1 instead \
+             of 2, but the game uses the last entry"
+            ]
+        );
+    }
+
+    #[test]
+    fn recognizes_filler_sheets() {
+        let config = r#"{
+            "tile_info": [{ "width": 32, "height": 32 }],
+            "tiles-new": [
+                { "file": "tiles.png", "tiles": [] },
+                { "file": "Fallback.png", "tiles": [] },
+                { "file": "glyphs.png", "tiles": [],
+                  "ascii": [{ "offset": 0, "bold": false, "color": "WHITE" }] }
+            ]
+        }"#;
+        let mut ts: Tileset = serde_json::from_str(config).unwrap();
+        let filler =
+            |ts: &Tileset| -> Vec<bool> { ts.tiles_new.iter().map(|x| ts.is_filler(x)).collect() };
+        assert_eq!(filler(&ts), [false, true, true]);
+
+        ts.config = toml::from_str(
+            "[sheets.\"tiles.png\"]\nfiller = true\n[sheets.\"Fallback.png\"]\nfiller = false",
+        )
+        .unwrap();
+        assert_eq!(filler(&ts), [true, false, true]);
+    }
+}
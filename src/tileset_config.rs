@@ -0,0 +1,73 @@
+use crate::error::{Error, Result};
+use crate::warnings::Code;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Per-tileset settings, read from `comparator.toml` next to `tile_config.json`.
+///
+/// ```toml
+/// allow = ["W003", "W007"]
+/// compat = true
+///
+/// [sheets."fallback.png"]
+/// allow = ["atlas-indivisible"]
+///
+/// [sheets."glyphs.png"]
+/// filler = true
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TilesetConfig {
+    /// Warning codes accepted for the whole tileset
+    #[serde(default)]
+    pub allow: Vec<Code>,
+    /// Accept `tile_config.json` fields unknown to this tool
+    #[serde(default)]
+    pub compat: bool,
+    /// Overrides for individual tiles-new entries, by image file name
+    #[serde(default)]
+    pub sheets: HashMap<String, SheetConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SheetConfig {
+    /// Warning codes accepted for this sheet only
+    #[serde(default)]
+    pub allow: Vec<Code>,
+    /// Whether the sheet is filler, such as fallback glyphs, whose sprites
+    /// are not meant to be referenced by tiles; unset means guess from the
+    /// sheet
+    pub filler: Option<bool>,
+}
+
+pub const CONFIG_FILE_NAME: &str = "comparator.toml";
+
+impl TilesetConfig {
+    /// Loads the config for the tileset at `base_path`; a missing file means defaults.
+    pub fn load(base_path: &Path) -> Result<TilesetConfig> {
+        let path = base_path.join(CONFIG_FILE_NAME);
+        if !path.is_file() {
+            return Ok(TilesetConfig::default());
+        }
+        let data = std::fs::read_to_string(&path).map_err(|source| Error::ReadInput {
+            path: path.clone(),
+            source,
+        })?;
+        toml::from_str(&data).map_err(|source| Error::ParseToml { path, source })
+    }
+
+    pub fn allows(&self, code: Code, sheet: Option<&str>) -> bool {
+        self.allow.contains(&code)
+            || sheet
+                .and_then(|x| self.sheets.get(x))
+                .is_some_and(|x| x.allow.contains(&code))
+    }
+
+    /// Whether the config marks the sheet drawn from image `file` as filler
+    /// or not, if it says.
+    pub fn filler(&self, file: &str) -> Option<bool> {
+        self.sheets.get(file).and_then(|x| x.filler)
+    }
+}
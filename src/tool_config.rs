@@ -0,0 +1,182 @@
+use crate::atlas::HashBits;
+use crate::error::{Error, Result};
+use crate::hash_algo::HashAlgo;
+use crate::id_filter::IdFilter;
+use crate::messages::Lang;
+use crate::null_sprite::NullSprite;
+use crate::report::Audience;
+use crate::warnings::Code;
+use serde::{Deserialize, Deserializer};
+use std::path::{Path, PathBuf};
+
+/// Tool config looked up in the working directory unless `--config` is given.
+pub const TOOL_CONFIG_FILE_NAME: &str = "tileset-comparator.toml";
+
+/// Default options for every run, so long flag lists needn't be repeated.
+/// Options given on the command line take precedence; flags set here can't
+/// be turned off from the command line.
+///
+/// ```toml
+/// output_dir = "comparisons"
+/// jobs = 4
+/// lang = "ru"
+/// allow = ["W003"]
+///
+/// [hash]
+/// mode = "fast"
+/// algo = "xxh64"
+/// bits = 64
+/// null_sprite = "negative"
+/// alpha_threshold = 1
+///
+/// [compare]
+/// filter = "t_*"
+/// audience = "artist"
+/// html = "report.html"
+///
+/// [find_color]
+/// tolerance = 8
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolConfig {
+    pub output_dir: Option<PathBuf>,
+    pub jobs: Option<usize>,
+    pub lang: Option<Lang>,
+    /// Warning codes suppressed in addition to those given with --allow
+    #[serde(default)]
+    pub allow: Vec<Code>,
+    /// Defaults for every command hashing sprites
+    #[serde(default)]
+    pub hash: HashDefaults,
+    #[serde(default)]
+    pub compare: CompareDefaults,
+    #[serde(default)]
+    pub find_color: FindColorDefaults,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashMode {
+    Exact,
+    Fast,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HashDefaults {
+    pub mode: Option<HashMode>,
+    pub algo: Option<HashAlgo>,
+    #[serde(default, deserialize_with = "deserialize_bits")]
+    pub bits: Option<HashBits>,
+    pub null_sprite: Option<NullSprite>,
+    pub similarity: Option<f32>,
+    pub alpha_threshold: Option<u8>,
+    pub fuzz: Option<f32>,
+    pub fuzz_pixels: Option<usize>,
+}
+
+/// Defaults for `compare`: which ids to compare and which artifacts to emit.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompareDefaults {
+    #[serde(default, deserialize_with = "deserialize_filter")]
+    pub filter: Option<IdFilter>,
+    pub audience: Option<Audience>,
+    pub badge: Option<String>,
+    pub html: Option<String>,
+    pub heatmaps: Option<String>,
+    pub sheet: Option<String>,
+    #[serde(default)]
+    pub dump_sprites: bool,
+    #[serde(default)]
+    pub shared_sprites: bool,
+    #[serde(default)]
+    pub bytewise_sort: bool,
+    #[serde(default)]
+    pub pixels_only: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FindColorDefaults {
+    pub tolerance: Option<u8>,
+}
+
+fn deserialize_filter<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<IdFilter>, D::Error> {
+    let filter = String::deserialize(deserializer)?;
+    filter.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Hash width as the number it is on the command line, 32 or 64.
+fn deserialize_bits<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<HashBits>, D::Error> {
+    match u32::deserialize(deserializer)? {
+        32 => Ok(Some(HashBits::Bits32)),
+        64 => Ok(Some(HashBits::Bits64)),
+        x => Err(serde::de::Error::custom(format!(
+            "hash bits must be 32 or 64, not {}",
+            x
+        ))),
+    }
+}
+
+impl ToolConfig {
+    /// Loads the config at `path`, or `tileset-comparator.toml` in the
+    /// working directory if there is one; otherwise returns defaults.
+    pub fn load(path: Option<&Path>) -> Result<ToolConfig> {
+        let path = match path {
+            Some(x) => x.to_owned(),
+            None if Path::new(TOOL_CONFIG_FILE_NAME).is_file() => {
+                PathBuf::from(TOOL_CONFIG_FILE_NAME)
+            }
+            None => return Ok(ToolConfig::default()),
+        };
+        let data = std::fs::read_to_string(&path).map_err(|source| Error::ReadInput {
+            path: path.clone(),
+            source,
+        })?;
+        toml::from_str(&data).map_err(|source| Error::ParseToml { path, source })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_defaults() {
+        let config: ToolConfig = toml::from_str(
+            r#"
+            jobs = 2
+            lang = "ru"
+            allow = ["W003"]
+            [hash]
+            mode = "fast"
+            algo = "blake3"
+            bits = 64
+            null_sprite = "negative"
+            [compare]
+            filter = "re:t_.*"
+            audience = "ci"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.jobs, Some(2));
+        assert_eq!(config.lang, Some(Lang::Ru));
+        assert_eq!(config.allow, [Code::DuplicateId]);
+        assert_eq!(config.hash.mode, Some(HashMode::Fast));
+        assert_eq!(config.hash.algo, Some(HashAlgo::Blake3));
+        assert_eq!(config.hash.bits, Some(HashBits::Bits64));
+        assert_eq!(config.hash.null_sprite, Some(NullSprite::Negative));
+        assert!(config.compare.filter.unwrap().matches("t_wall"));
+        assert_eq!(config.compare.audience, Some(Audience::Ci));
+
+        assert!(toml::from_str::<ToolConfig>("[compare]\nfilter = \"re:(\"").is_err());
+        assert!(toml::from_str::<ToolConfig>("colour = true").is_err());
+        assert!(toml::from_str::<ToolConfig>("[hash]\nbits = 48").is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use crate::atlas::HashOptions;
+use crate::compare::ComparisonResult;
+use crate::heatmap::changed_in_place;
+use image::{GenericImageView, Rgba};
+use std::fmt;
+
+/// A mechanical edit turning one sprite into another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transform {
+    FlipHorizontal,
+    FlipVertical,
+    /// Clockwise, square sprites only
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Moved by one pixel, with only transparent pixels pushed out
+    Shift {
+        dx: i32,
+        dy: i32,
+    },
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transform::FlipHorizontal => write!(f, "flipped horizontally"),
+            Transform::FlipVertical => write!(f, "flipped vertically"),
+            Transform::Rotate90 => write!(f, "rotated 90° clockwise"),
+            Transform::Rotate180 => write!(f, "rotated 180°"),
+            Transform::Rotate270 => write!(f, "rotated 90° counterclockwise"),
+            Transform::Shift { dx, dy } => write!(f, "shifted by ({}, {})", dx, dy),
+        }
+    }
+}
+
+const SHIFTS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// Pixels are equal if both are fully transparent, whatever their color.
+fn same_pixel(a: Rgba<u8>, b: Rgba<u8>) -> bool {
+    a == b || (a.0[3] == 0 && b.0[3] == 0)
+}
+
+impl Transform {
+    /// Where the pixel at `(x, y)` of a `w`x`h` sprite comes from, if anywhere.
+    fn source(self, x: u32, y: u32, w: u32, h: u32) -> Option<(u32, u32)> {
+        match self {
+            Transform::FlipHorizontal => Some((w - 1 - x, y)),
+            Transform::FlipVertical => Some((x, h - 1 - y)),
+            Transform::Rotate90 => Some((y, w - 1 - x)),
+            Transform::Rotate180 => Some((w - 1 - x, h - 1 - y)),
+            Transform::Rotate270 => Some((h - 1 - y, x)),
+            Transform::Shift { dx, dy } => {
+                let sx = x.checked_add_signed(-dx).filter(|x| *x < w)?;
+                let sy = y.checked_add_signed(-dy).filter(|y| *y < h)?;
+                Some((sx, sy))
+            }
+        }
+    }
+
+    /// Whether applying the transform to `a` gives `b`.
+    fn maps<I: GenericImageView<Pixel = Rgba<u8>>>(self, a: &I, b: &I) -> bool {
+        let (w, h) = a.dimensions();
+        if b.dimensions() != (w, h) {
+            return false;
+        }
+        let rotates = matches!(self, Transform::Rotate90 | Transform::Rotate270);
+        if rotates && w != h {
+            return false;
+        }
+        let transparent = |px: Rgba<u8>| px.0[3] == 0;
+        let pixels_match = (0..h).all(|y| {
+            (0..w).all(|x| match self.source(x, y, w, h) {
+                Some((sx, sy)) => same_pixel(a.get_pixel(sx, sy), b.get_pixel(x, y)),
+                None => transparent(b.get_pixel(x, y)),
+            })
+        });
+        // A shift must not push visible pixels off the sprite
+        let nothing_lost = match self {
+            Transform::Shift { dx, dy } => (0..h).all(|y| {
+                (0..w).all(|x| {
+                    let kept_x = x.checked_add_signed(dx).is_some_and(|x| x < w);
+                    let kept_y = y.checked_add_signed(dy).is_some_and(|y| y < h);
+                    (kept_x && kept_y) || transparent(a.get_pixel(x, y))
+                })
+            }),
+            _ => true,
+        };
+        pixels_match && nothing_lost
+    }
+}
+
+/// The simple transform turning sprite `a` into sprite `b`, if any: a flip,
+/// a rotation by a multiple of 90°, or a one pixel shift.
+pub fn detect_transform<I: GenericImageView<Pixel = Rgba<u8>>>(a: &I, b: &I) -> Option<Transform> {
+    let mut candidates = vec![
+        Transform::FlipHorizontal,
+        Transform::FlipVertical,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+    ];
+    candidates.extend(SHIFTS.iter().map(|&(dx, dy)| Transform::Shift { dx, dy }));
+    candidates.into_iter().find(|t| t.maps(a, b))
+}
+
+/// Every sprite of the changed ids that changed in place by a simple
+/// transform, as `<id> <fg|bg> <variation> <sprite>: <transform>`.
+pub fn describe_transforms(result: &ComparisonResult, opts: &HashOptions) -> Vec<String> {
+    changed_in_place(result, opts)
+        .iter()
+        .filter_map(|x| {
+            let transform = detect_transform(&x.a, &x.b)?;
+            Some(format!(
+                "{} {} {} {}: {}",
+                x.id, x.role, x.variation, x.position, transform
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    const CLEAR: Rgba<u8> = Rgba([0, 0, 0, 0]);
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    /// 4x4 sprite, transparent except for the given pixels.
+    fn sprite(pixels: &[(u32, u32, Rgba<u8>)]) -> RgbaImage {
+        let mut img = RgbaImage::from_pixel(4, 4, CLEAR);
+        for &(x, y, px) in pixels {
+            img.put_pixel(x, y, px);
+        }
+        img
+    }
+
+    #[test]
+    fn detects_flips_and_rotations() {
+        let a = sprite(&[(0, 0, RED), (1, 0, BLUE)]);
+        let flipped = sprite(&[(3, 0, RED), (2, 0, BLUE)]);
+        let rotated = sprite(&[(3, 0, RED), (3, 1, BLUE)]);
+        assert_eq!(
+            detect_transform(&a, &flipped),
+            Some(Transform::FlipHorizontal)
+        );
+        assert_eq!(detect_transform(&a, &rotated), Some(Transform::Rotate90));
+        assert_eq!(detect_transform(&rotated, &a), Some(Transform::Rotate270));
+    }
+
+    #[test]
+    fn detects_shifts_without_losing_pixels() {
+        let a = sprite(&[(1, 0, RED), (2, 1, BLUE)]);
+        let shifted = sprite(&[(1, 1, RED), (2, 2, BLUE)]);
+        assert_eq!(
+            detect_transform(&a, &shifted),
+            Some(Transform::Shift { dx: 0, dy: 1 })
+        );
+
+        let edge = sprite(&[(3, 1, RED)]);
+        let cut = sprite(&[]);
+        assert_eq!(detect_transform(&edge, &cut), None);
+    }
+
+    #[test]
+    fn repainted_sprites_have_no_transform() {
+        let a = sprite(&[(0, 0, RED)]);
+        let b = sprite(&[(0, 0, BLUE)]);
+        assert_eq!(detect_transform(&a, &b), None);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::atlas::{save_png, TileAtlas};
+use crate::error::{create_output_dir, Result};
+use crate::progress::Progress;
+use crate::tileset::Tileset;
+use std::path::Path;
+
+/// Sprites of one atlas that no tile references.
+pub struct UnusedSprites<'a> {
+    pub atlas: &'a TileAtlas,
+    pub indices: Vec<u32>,
+}
+
+impl UnusedSprites<'_> {
+    /// Indices as a comma-separated list of runs, e.g. `3, 7-12, 40`.
+    pub fn ranges(&self) -> String {
+        let mut runs: Vec<(u32, u32)> = vec![];
+        for &idx in &self.indices {
+            match runs.last_mut() {
+                Some((_, end)) if *end + 1 == idx => *end = idx,
+                _ => runs.push((idx, idx)),
+            }
+        }
+        runs.iter()
+            .map(|&(start, end)| {
+                if start == end {
+                    start.to_string()
+                } else {
+                    format!("{}-{}", start, end)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Sprite indices of every atlas that no fg or bg of any tile or additional
+/// tile refers to, for atlases with at least one. Filler sheets are skipped.
+pub fn unused_sprites<'a>(ts: &Tileset, atlases: &'a [TileAtlas]) -> Vec<UnusedSprites<'a>> {
+    let used = ts.used_sprites();
+    atlases
+        .iter()
+        .zip(&ts.tiles_new)
+        .filter(|(_, sheet)| !ts.is_filler(sheet))
+        .map(|(atlas, _)| UnusedSprites {
+            atlas,
+            indices: (atlas.tiles_start..atlas.tiles_end)
+                .filter(|x| !used.contains(x))
+                .collect(),
+        })
+        .filter(|x| !x.indices.is_empty())
+        .collect()
+}
+
+/// Writes every unused sprite into `dir` as `<index>.png`, named like the
+/// sprites dumped by compare and extract.
+pub fn dump_unused(unused: &[UnusedSprites], dir: &Path) -> Result<()> {
+    create_output_dir(dir)?;
+    let total = unused.iter().map(|x| x.indices.len()).sum();
+    let progress = Progress::new("Dumping sprites", total);
+    for sheet in unused {
+        for &idx in &sheet.indices {
+            let sprite = sheet.atlas.get_sprite(idx).to_image();
+            save_png(&sprite, &dir.join(format!("{}.png", idx)))?;
+            progress.inc();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collapses_runs() {
+        let atlas = TileAtlas::from_pixels(&[[0; 4]], 0);
+        let unused = UnusedSprites {
+            atlas: &atlas,
+            indices: vec![3, 7, 8, 9, 12, 13],
+        };
+        assert_eq!(unused.ranges(), "3, 7-9, 12-13");
+    }
+}
@@ -0,0 +1,333 @@
+use crate::atlas::{HashOptions, TileAtlas};
+use crate::compare::find_duplicates;
+use crate::dump_index::{DUMP_FILE_NAME, DUMP_INDEX_FILE_NAME};
+use crate::error::{Error, Result};
+use crate::multitile::multitile_subtiles;
+use crate::review::REVIEW_FILE_NAME;
+use crate::source_map::cite;
+use crate::tileset::{SingleTile, Tileset};
+use crate::tileset_config::CONFIG_FILE_NAME;
+use crate::warnings::{Code, Warning};
+use crate::workspace::LOCK_FILE_NAME;
+use std::collections::HashSet;
+
+const OVERLAY_PREFIX: &str = "overlay_";
+const OVERLAY_GENDER_PREFIXES: &[&str] = &["male_", "female_"];
+const OVERLAY_KIND_PREFIXES: &[&str] = &["mutation_active_", "mutation_", "worn_", "wielded_"];
+
+/// Files a tileset directory may hold besides its sheets: files the game
+/// reads, this tool's settings, and the outputs it writes next to a tileset.
+const KNOWN_FILES: &[&str] = &[
+    "tile_config.json",
+    "tileset.txt",
+    "layering.json",
+    CONFIG_FILE_NAME,
+    "provenance.json",
+    LOCK_FILE_NAME,
+    DUMP_FILE_NAME,
+    DUMP_INDEX_FILE_NAME,
+    "meta.json",
+    "duplicates.txt",
+    "exclusives.txt",
+    "different.txt",
+    "different.json",
+    "ascii.txt",
+    "ordering.txt",
+    "subtiles.txt",
+    "properties.txt",
+    "transforms.txt",
+    "report.html",
+    "report.json",
+    "comparison.png",
+    REVIEW_FILE_NAME,
+];
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "bmp", "tga", "webp"];
+
+/// Keys under which an overlay tile may be listed in `overlay_ordering`:
+/// the id without `overlay_`, and the same with the gender and overlay kind
+/// prefixes stripped, e.g. `overlay_female_mutation_HORNS` yields
+/// `female_mutation_HORNS`, `mutation_HORNS` and `HORNS`.
+fn overlay_ordering_keys(id: &str) -> Vec<&str> {
+    let mut ret = vec![];
+    let mut rest = match id.strip_prefix(OVERLAY_PREFIX) {
+        Some(x) => x,
+        None => return ret,
+    };
+    ret.push(rest);
+    if let Some(x) = OVERLAY_GENDER_PREFIXES
+        .iter()
+        .find_map(|p| rest.strip_prefix(p))
+    {
+        rest = x;
+        ret.push(rest);
+    }
+    if let Some(x) = OVERLAY_KIND_PREFIXES
+        .iter()
+        .find_map(|p| rest.strip_prefix(p))
+    {
+        ret.push(x);
+    }
+    ret
+}
+
+/// Adds overlay ordering problems, each with its line in tile_config.json.
+fn check_overlay_ordering(
+    ts: &Tileset,
+    vars: &[SingleTile],
+    problems: &mut Vec<(Warning, Option<usize>)>,
+) {
+    let ordered: HashSet<&str> = ts
+        .overlay_ordering
+        .iter()
+        .flat_map(|x| x.id.0.iter().map(|x| x.as_str()))
+        .collect();
+
+    let mut overlay_keys: HashSet<&str> = HashSet::new();
+    for var in vars {
+        let id = var.id.0[0].as_str();
+        let keys = overlay_ordering_keys(id);
+        if keys.is_empty() {
+            continue;
+        }
+        if !keys.iter().any(|x| ordered.contains(x)) {
+            let problem = Warning::new(
+                Code::OverlayUnordered,
+                format!("overlay tile '{}' has no overlay_ordering entry", id),
+            );
+            problems.push((problem, ts.source.line(id)));
+        }
+        overlay_keys.extend(keys);
+    }
+
+    for elem in &ts.overlay_ordering {
+        for id in &elem.id.0 {
+            if !overlay_keys.contains(id.as_str()) {
+                let problem = Warning::new(
+                    Code::OrderingUnmatched,
+                    format!(
+                        "overlay_ordering entry '{}' does not match any overlay tile",
+                        id
+                    ),
+                );
+                problems.push((problem, ts.source.ordering_line(id)));
+            }
+        }
+    }
+}
+
+/// Files in the tileset directory that no sheet uses, with their names:
+/// images as orphaned, anything else not known to be needed as junk.
+/// Subdirectories are not checked.
+fn orphaned_files(ts: &Tileset) -> Result<Vec<(Warning, String)>> {
+    let read_err = |source| Error::ReadInput {
+        path: ts.base_path.clone(),
+        source,
+    };
+    let used: HashSet<&str> = ts.tiles_new.iter().map(|x| x.file.as_str()).collect();
+    let mut names = vec![];
+    for entry in std::fs::read_dir(&ts.base_path).map_err(read_err)? {
+        let path = entry.map_err(read_err)?.path();
+        if !path.is_dir() {
+            names.push(
+                path.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+            );
+        }
+    }
+    names.sort();
+
+    let mut ret = vec![];
+    for name in names {
+        if used.contains(name.as_str()) || KNOWN_FILES.contains(&name.as_str()) {
+            continue;
+        }
+        let extension = name.rsplit_once('.').map(|x| x.1.to_lowercase());
+        let problem = if extension.is_some_and(|x| IMAGE_EXTENSIONS.contains(&x.as_str())) {
+            Warning::new(
+                Code::OrphanedImage,
+                format!("image '{}' is not used by any sheet", name),
+            )
+        } else {
+            Warning::new(
+                Code::JunkFile,
+                format!("file '{}' is not used by the game", name),
+            )
+        };
+        ret.push((problem, name));
+    }
+    Ok(ret)
+}
+
+/// A problem found in tile_config.json alone.
+pub struct ConfigProblem<'a> {
+    pub warning: Warning,
+    /// Sheet the problem belongs to, for per-sheet suppression
+    pub sheet: Option<&'a str>,
+    /// Line in tile_config.json, if known
+    pub line: Option<usize>,
+}
+
+/// Problems found in tile_config.json alone, without reading any image.
+/// Suppressions are not applied.
+pub fn lint_config<'a>(ts: &'a Tileset, vars: &[SingleTile]) -> Vec<ConfigProblem<'a>> {
+    let mut problems = vec![];
+    let mut push = |warning, sheet, line| {
+        problems.push(ConfigProblem {
+            warning,
+            sheet,
+            line,
+        })
+    };
+
+    for tiles_new in &ts.tiles_new {
+        if tiles_new.tiles.is_empty() {
+            push(
+                Warning::new(
+                    Code::EmptyTiles,
+                    format!("entry for '{}' has no tiles", tiles_new.file),
+                ),
+                Some(&tiles_new.file),
+                ts.source.sheet_line(&tiles_new.file),
+            );
+        }
+    }
+
+    for message in ts.tile_info_conflicts() {
+        push(Warning::new(Code::TileInfoConflict, message), None, None);
+    }
+
+    for dup in find_duplicates(vars) {
+        // Cite the redefinition rather than the original
+        let lines = ts.source.lines(dup);
+        let warning = Warning::new(Code::DuplicateId, format!("duplicate tile id '{}'", dup));
+        push(warning, None, lines.get(1).or(lines.first()).copied());
+    }
+
+    let mut ordering = vec![];
+    check_overlay_ordering(ts, vars, &mut ordering);
+    for (warning, line) in ordering {
+        push(warning, None, line);
+    }
+
+    let sheet_of = ts.sheets_by_id();
+    for subtiles in multitile_subtiles(ts) {
+        if subtiles.missing.is_empty() {
+            continue;
+        }
+        let warning = Warning::new(
+            Code::IncompleteMultitile,
+            format!(
+                "multitile '{}' lacks subtiles {}",
+                subtiles.id,
+                subtiles.missing.join(", ")
+            ),
+        );
+        let sheet = sheet_of.get(&subtiles.id).copied();
+        push(warning, sheet, ts.source.line(&subtiles.id));
+    }
+
+    for (sheet, field) in ts.unknown_fields() {
+        let line = sheet.and_then(|x| ts.source.sheet_line(x));
+        push(Warning::new(Code::UnknownField, field), sheet, line);
+    }
+
+    problems
+}
+
+/// Checks a single tileset for problems that make it unfit for publishing.
+/// Problems suppressed on the command line or in the tileset's config are left out.
+pub fn validate_tileset(ts: &Tileset, opts: &HashOptions) -> Result<Vec<Warning>> {
+    validate_atlases(ts, &ts.load_atlases()?, opts)
+}
+
+/// `validate_tileset` with the atlases of `ts` already decoded.
+pub fn validate_atlases(
+    ts: &Tileset,
+    atlases: &[TileAtlas],
+    opts: &HashOptions,
+) -> Result<Vec<Warning>> {
+    let mut problems = vec![];
+    // Sheet each problem belongs to, for per-sheet suppression
+    let mut sheets: Vec<Option<&str>> = vec![];
+
+    for atlas in atlases {
+        if atlas.is_empty() {
+            problems.push(Warning::new(
+                Code::AtlasIndivisible,
+                format!(
+                    "image '{}' ({}x{}) is smaller than a single sprite of size {}x{}",
+                    atlas.file,
+                    atlas.img.width(),
+                    atlas.img.height(),
+                    atlas.sprite_w,
+                    atlas.sprite_h
+                ),
+            ));
+            sheets.push(Some(&atlas.file));
+        } else if !atlas.is_evenly_divided() {
+            problems.push(Warning::new(
+                Code::AtlasIndivisible,
+                format!(
+                    "image '{}' ({}x{}) cannot be evenly divided into sprites of size {}x{}",
+                    atlas.file,
+                    atlas.img.width(),
+                    atlas.img.height(),
+                    atlas.sprite_w,
+                    atlas.sprite_h
+                ),
+            ));
+            sheets.push(Some(&atlas.file));
+        }
+    }
+
+    let vars = ts.expand_variations(atlases, false, opts);
+    for mut problem in lint_config(ts, &vars) {
+        problem.warning.message += &cite(problem.line);
+        problems.push(problem.warning);
+        sheets.push(problem.sheet);
+    }
+
+    let sheet_of = ts.sheets_by_id();
+    for var in &vars {
+        let sprites = var
+            .fg()
+            .iter()
+            .map(|x| ("fg", x))
+            .chain(var.bg().iter().map(|x| ("bg", x)));
+        for (role, spidw) in sprites {
+            for sprite in spidw.indices() {
+                if !atlases.iter().any(|x| x.in_bounds(sprite)) {
+                    problems.push(Warning::new(
+                        Code::OutOfRange,
+                        format!(
+                            "tile '{}' {} sprite {} is outside all atlas ranges{}",
+                            var.id.0[0],
+                            role,
+                            sprite,
+                            cite(ts.source.line(&var.id.0[0]))
+                        ),
+                    ));
+                    sheets.push(sheet_of.get(&var.id.0[0]).cloned());
+                }
+            }
+        }
+    }
+
+    let mut problems: Vec<Warning> = problems
+        .into_iter()
+        .zip(sheets)
+        .filter(|(problem, sheet)| !ts.allows(problem.code, *sheet))
+        .map(|x| x.0)
+        .collect();
+
+    // Suppressible per file, like sheets
+    for (problem, name) in orphaned_files(ts)? {
+        if !ts.allows(problem.code, Some(&name)) {
+            problems.push(problem);
+        }
+    }
+
+    Ok(problems)
+}
@@ -0,0 +1,116 @@
+use crate::sprite_id_with_weight::SpriteIdWithWeight;
+use std::fmt;
+
+/// One step of an edit script turning one variation list into another.
+/// Variations are matched by their sprite hashes; a matched variation whose
+/// weight changed is reported as reweighted rather than removed and re-added.
+#[derive(Debug, PartialEq, Eq)]
+pub enum VariationEdit<'a> {
+    Kept(&'a SpriteIdWithWeight),
+    Reweighted {
+        from: &'a SpriteIdWithWeight,
+        to: &'a SpriteIdWithWeight,
+    },
+    Added(&'a SpriteIdWithWeight),
+    Removed(&'a SpriteIdWithWeight),
+}
+
+/// Minimal edit script between two variation lists, based on their longest
+/// common subsequence.
+pub fn edit_script<'a>(
+    a: &'a [SpriteIdWithWeight],
+    b: &'a [SpriteIdWithWeight],
+) -> Vec<VariationEdit<'a>> {
+    // lcs[i][j] is the LCS length of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i].id == b[j].id {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                std::cmp::max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ret = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].id == b[j].id {
+            if a[i].weight == b[j].weight {
+                ret.push(VariationEdit::Kept(&a[i]));
+            } else {
+                ret.push(VariationEdit::Reweighted {
+                    from: &a[i],
+                    to: &b[j],
+                });
+            }
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ret.push(VariationEdit::Removed(&a[i]));
+            i += 1;
+        } else {
+            ret.push(VariationEdit::Added(&b[j]));
+            j += 1;
+        }
+    }
+    ret.extend(a[i..].iter().map(VariationEdit::Removed));
+    ret.extend(b[j..].iter().map(VariationEdit::Added));
+    ret
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct EditSummary {
+    pub kept: usize,
+    pub reweighted: usize,
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl EditSummary {
+    pub fn new(script: &[VariationEdit]) -> Self {
+        let mut ret = EditSummary::default();
+        for edit in script {
+            match edit {
+                VariationEdit::Kept(_) => ret.kept += 1,
+                VariationEdit::Reweighted { .. } => ret.reweighted += 1,
+                VariationEdit::Added(_) => ret.added += 1,
+                VariationEdit::Removed(_) => ret.removed += 1,
+            }
+        }
+        ret
+    }
+
+    pub fn is_unchanged(&self) -> bool {
+        self.reweighted == 0 && self.added == 0 && self.removed == 0
+    }
+}
+
+impl fmt::Display for EditSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_unchanged() {
+            return write!(f, "unchanged");
+        }
+        let mut parts = vec![];
+        let mut counts = vec![];
+        if self.added > 0 {
+            counts.push(format!("+{}", self.added));
+        }
+        if self.removed > 0 {
+            counts.push(format!("-{}", self.removed));
+        }
+        if !counts.is_empty() {
+            let plural = if self.added + self.removed == 1 {
+                ""
+            } else {
+                "s"
+            };
+            parts.push(format!("{} variation{}", counts.join(" "), plural));
+        }
+        if self.reweighted > 0 {
+            parts.push(format!("{} reweighted", self.reweighted));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
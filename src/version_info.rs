@@ -0,0 +1,33 @@
+use crate::atlas::HASH_VERSION;
+use crate::json_report::REPORT_FORMAT_VERSION;
+
+/// tile_config.json layouts this version reads.
+const CONFIG_LAYOUTS: &[&str] = &[
+    "tiles-new (one entry per sheet)",
+    "legacy single sheet (top-level file and tiles)",
+];
+
+/// Cargo features this build was compiled with. The crate has none yet.
+const FEATURES: &[&str] = &[];
+
+/// Tool version and the versions of every format it reads or writes, for
+/// bug reports and for telling apart reports made by different builds.
+pub fn version_info() -> String {
+    let features = if FEATURES.is_empty() {
+        "none".to_string()
+    } else {
+        FEATURES.join(", ")
+    };
+    format!(
+        "tileset-comparator {}\n\
+         tile_config.json layouts: {}\n\
+         report.json format: {}\n\
+         sprite hash: {}\n\
+         features: {}",
+        env!("CARGO_PKG_VERSION"),
+        CONFIG_LAYOUTS.join("; "),
+        REPORT_FORMAT_VERSION,
+        HASH_VERSION,
+        features
+    )
+}
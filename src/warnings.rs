@@ -0,0 +1,362 @@
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Stable identifier of a warning or lint. Numbers are never reused, so
+/// codes can be referenced from scripts, configs and bug reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Code {
+    AtlasIndivisible = 1,
+    EmptyTiles = 2,
+    DuplicateId = 3,
+    OverlayUnordered = 4,
+    OrderingUnmatched = 5,
+    DiffSkipped = 6,
+    UnknownId = 7,
+    UnknownField = 8,
+    OrphanedImage = 9,
+    JunkFile = 10,
+    OutOfRange = 14,
+    PixelscaleMismatch = 15,
+    IncompleteMultitile = 16,
+    TileInfoConflict = 17,
+    HashCollision = 18,
+}
+
+struct CodeInfo {
+    code: Code,
+    name: &'static str,
+    summary: &'static str,
+    explanation: &'static str,
+}
+
+const CODES: &[CodeInfo] = &[
+    CodeInfo {
+        code: Code::AtlasIndivisible,
+        name: "atlas-indivisible",
+        summary: "atlas image size is not a multiple of the sprite size",
+        explanation: "The width or height of a tiles-new image is not an exact multiple of the \
+sprite size used for it, so the last column or row of pixels does not form whole sprites and \
+is ignored. This usually means the wrong sprite_width/sprite_height was set for the sheet, or \
+the image was cropped or padded by accident.
+
+Typical fixes:
+  - set sprite_width/sprite_height on the tiles-new entry to the sheet's real sprite size
+  - pad the image with transparent pixels up to a whole number of sprites",
+    },
+    CodeInfo {
+        code: Code::EmptyTiles,
+        name: "empty-tiles",
+        summary: "tiles-new entry has an empty tiles array",
+        explanation: "A tiles-new entry defines no tiles, so none of the sprites in its image \
+can be used by the game except through sprite indices from other entries.
+
+Typical fixes:
+  - move the tile definitions for this sheet into its entry
+  - remove the entry and the image if the sheet is no longer used",
+    },
+    CodeInfo {
+        code: Code::DuplicateId,
+        name: "duplicate-id",
+        summary: "the same tile id is defined more than once",
+        explanation: "After expanding id lists and additional_tiles, two definitions share the \
+same id. The game silently uses one of them, and the comparator refuses to diff tilesets with \
+duplicates since it can't tell which definition is meant.
+
+Typical fixes:
+  - remove the stale definition
+  - check for an additional_tiles id clashing with a standalone tile (e.g. t_wall_center)",
+    },
+    CodeInfo {
+        code: Code::OverlayUnordered,
+        name: "overlay-unordered",
+        summary: "overlay tile has no overlay_ordering entry",
+        explanation: "A tile that looks like an overlay (its id starts with overlay_) is not \
+covered by any overlay_ordering entry, so it is drawn with the default order and may end up \
+above or below other overlays unexpectedly.
+
+Typical fixes:
+  - add the overlay's id (without the overlay_ prefix) to overlay_ordering
+  - ignore it with --allow W004 if the default order is intended",
+    },
+    CodeInfo {
+        code: Code::OrderingUnmatched,
+        name: "ordering-unmatched",
+        summary: "overlay_ordering entry matches no overlay tile",
+        explanation: "An overlay_ordering entry references an id for which the tileset has no \
+overlay tile, so the entry has no effect. Often left behind after overlays were renamed.
+
+Typical fixes:
+  - remove the entry
+  - fix the id to match the renamed overlay",
+    },
+    CodeInfo {
+        code: Code::DiffSkipped,
+        name: "diff-skipped",
+        summary: "duplicate ids prevented generating the diff",
+        explanation: "At least one of the compared tilesets defines some id more than once (see \
+W003 and duplicates.txt), so definitions can't be matched up and different.txt is not written.
+
+Typical fixes:
+  - remove the duplicates listed in duplicates.txt and compare again",
+    },
+    CodeInfo {
+        code: Code::UnknownId,
+        name: "unknown-id",
+        summary: "requested tile id is not defined by the tileset",
+        explanation: "An id passed to extract does not match any tile, including ids generated \
+from additional_tiles, or an id or pattern passed to merge does not match any tile entry of \
+the source tileset.
+
+Typical fixes:
+  - check the spelling of the id
+  - for multitile parts with extract, use the generated id, e.g. t_wall_center
+  - merge copies whole entries, so use the entry id, e.g. t_wall",
+    },
+    CodeInfo {
+        code: Code::UnknownField,
+        name: "unknown-field",
+        summary: "tile_config.json has a field this tool doesn't know",
+        explanation: "In compatibility mode, fields this version of the tool doesn't know are \
+kept as opaque values instead of failing the load. They take part in comparisons, but the tool \
+can't check them, and a misspelled known field (e.g. \"rotate\" for \"rotates\") looks the same \
+as a new one.
+
+Typical fixes:
+  - check the spelling of the field
+  - if the game added the field, update the tool, or allow this code until then",
+    },
+    CodeInfo {
+        code: Code::OrphanedImage,
+        name: "orphaned-image",
+        summary: "image in the tileset directory is not used by any sheet",
+        explanation: "An image file next to tile_config.json is not the file of any tiles-new \
+entry, so the game never loads it. Stale sheets left behind after repacking or renaming \
+routinely end up in releases and bloat downloads.
+
+Typical fixes:
+  - delete the image
+  - if it is a sheet that should be used, add a tiles-new entry for it",
+    },
+    CodeInfo {
+        code: Code::JunkFile,
+        name: "junk-file",
+        summary: "tileset directory contains a file the game doesn't use",
+        explanation: "A file next to tile_config.json is neither an image nor a file the game \
+or this tool is known to read or write, e.g. an editor project, a backup, or an archive. It \
+gets shipped with the tileset for no benefit.
+
+Typical fixes:
+  - delete the file, or move it out of the tileset directory
+  - allow this code in comparator.toml if the file is meant to be shipped",
+    },
+    CodeInfo {
+        code: Code::OutOfRange,
+        name: "out-of-range",
+        summary: "sprite index is outside all atlas ranges",
+        explanation: "A tile's fg or bg refers to a sprite index that doesn't fall into any \
+atlas: it is past the end of the last image, or the images got smaller since the config was \
+written. The game shows nothing (or an error) for such sprites. Out-of-range sprites hash to \
+0, so they also compare equal to each other.
+
+Typical fixes:
+  - re-export the config after repacking the sheets so indices match again
+  - check that tiles-new entries are listed in the same order as when the indices were assigned",
+    },
+    CodeInfo {
+        code: Code::PixelscaleMismatch,
+        name: "pixelscale-mismatch",
+        summary: "compared tilesets have incompatible pixelscales",
+        explanation: "The tile_info pixelscale of the two tilesets differs, and the larger is not \
+a whole multiple of the smaller. The game enlarges sprites by their pixelscale, so the same \
+art drawn for different scales has different pixel sizes and nearly every sprite compares as \
+changed. When one scale is a whole multiple of the other, the smaller tileset's sprites are \
+enlarged to match instead and this warning is not shown.
+
+Typical fixes:
+  - compare against a tileset drawn for the same pixelscale
+  - rescale one tileset's images so the scales divide evenly",
+    },
+    CodeInfo {
+        code: Code::IncompleteMultitile,
+        name: "incomplete-multitile",
+        summary: "multitile lacks standard connection subtiles",
+        explanation: "A tile with additional_tiles does not define every subtile the game uses \
+to draw connections: center, corner, edge, t_connection, end_piece and unconnected. The game \
+draws the base tile in place of a missing subtile, so walls, fences and similar terrain show \
+seams or unconnected pieces where they meet. The broken and open subtiles are only drawn in \
+some states and may be left out.
+
+Typical fixes:
+  - draw the missing subtiles and list them in additional_tiles
+  - drop additional_tiles and multitile if the tile is not meant to connect",
+    },
+    CodeInfo {
+        code: Code::TileInfoConflict,
+        name: "tile-info-conflict",
+        summary: "tile_info entries disagree",
+        explanation: "tile_info lists more than one entry, and they set different tile sizes, \
+pixelscales or iso modes. The game reads every entry in turn, so the last one wins and the \
+earlier values have no effect; sheets without sprite_width/sprite_height get the last entry's \
+tile size. The comparator does the same.
+
+Typical fixes:
+  - remove the stale entries, keeping the one the tileset is drawn for
+  - move the intended values into the last entry",
+    },
+    CodeInfo {
+        code: Code::HashCollision,
+        name: "hash-collision",
+        summary: "sprites that differ share a sprite hash",
+        explanation: "Two sprites of the compared tilesets hash the same although their pixels \
+differ. Sprites are compared by hash, so left alone the collision could hide a changed sprite; \
+compare checks the pixels of every pair of sprites with equal hashes and compares the colliding \
+ones by pixels instead, giving them stand-in hashes in dumps and reports. The check needs every \
+sprite decoded, so it's skipped with --low-memory and --hash-cache, and with --fast-hash, which \
+may miss differences anyway.
+
+Typical fixes:
+  - nothing is wrong with the tilesets; the result already accounts for the collision
+  - pass --hash-bits 64, which makes collisions unlikely even among hundreds of thousands of \
+sprites",
+    },
+];
+
+/// Bit per code number, set if that code is suppressed.
+static ALLOWED: AtomicU64 = AtomicU64::new(0);
+/// Every warning printed so far, for reports.
+static EMITTED: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+impl Code {
+    fn info(self) -> &'static CodeInfo {
+        CODES.iter().find(|x| x.code == self).unwrap()
+    }
+
+    pub fn name(self) -> &'static str {
+        self.info().name
+    }
+
+    pub fn is_allowed(self) -> bool {
+        ALLOWED.load(Ordering::Relaxed) & (1 << self as u64) != 0
+    }
+
+    pub fn explain(self) -> String {
+        let info = self.info();
+        format!(
+            "{} {}: {}\n\n{}\n",
+            self, info.name, info.summary, info.explanation
+        )
+    }
+
+    pub fn all() -> impl Iterator<Item = Code> {
+        CODES.iter().map(|x| x.code)
+    }
+
+    pub fn summary(self) -> &'static str {
+        self.info().summary
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "W{:03}", *self as u32)
+    }
+}
+
+impl FromStr for Code {
+    type Err = String;
+
+    /// Accepts either the code (`W014`, `w14`) or its name (`out-of-range`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = s
+            .strip_prefix('W')
+            .or_else(|| s.strip_prefix('w'))
+            .and_then(|x| x.parse::<u32>().ok());
+        CODES
+            .iter()
+            .find(|x| Some(x.code as u32) == number || x.name == s)
+            .map(|x| x.code)
+            .ok_or_else(|| format!("unknown warning code '{}'", s))
+    }
+}
+
+impl<'de> Deserialize<'de> for Code {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Suppresses the given codes for the rest of the run.
+pub fn allow(codes: &[Code]) {
+    for code in codes {
+        ALLOWED.fetch_or(1 << *code as u64, Ordering::Relaxed);
+    }
+}
+
+/// A warning or lint result tied to its code.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Warning {
+    pub code: Code,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new<S: Into<String>>(code: Code, message: S) -> Self {
+        Warning {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Serialized as `{"code": "W001", "name": "atlas-indivisible", "message": ...}`.
+impl Serialize for Warning {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Warning", 3)?;
+        s.serialize_field("code", &self.code.to_string())?;
+        s.serialize_field("name", self.code.name())?;
+        s.serialize_field("message", &self.message)?;
+        s.end()
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+/// Prints a warning to stderr unless its code is suppressed.
+pub fn warn<S: Into<String>>(code: Code, message: S) {
+    if !code.is_allowed() {
+        let warning = Warning::new(code, message);
+        print_warning(&warning);
+        if let Ok(mut emitted) = EMITTED.lock() {
+            emitted.push(warning);
+        }
+    }
+}
+
+/// Every warning printed by `warn` so far, sorted so that the order does
+/// not depend on which thread warned first.
+pub fn emitted() -> Vec<Warning> {
+    let mut ret = EMITTED.lock().map(|x| x.clone()).unwrap_or_default();
+    ret.sort();
+    ret
+}
+
+/// Forgets the warnings printed so far, before a run that reports its own.
+pub fn clear_emitted() {
+    if let Ok(mut emitted) = EMITTED.lock() {
+        emitted.clear();
+    }
+}
+
+/// Prints a warning to stderr regardless of suppression.
+pub fn print_warning(warning: &Warning) {
+    eprintln!("WARN {}", warning);
+}
@@ -0,0 +1,56 @@
+use crate::tileset_config::CONFIG_FILE_NAME;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How often watched files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Files a comparison of the tileset in `dir` reads: tile_config.json, the
+/// sheet images it lists, and comparator.toml. Read leniently, since the
+/// config may be saved half-written while being edited.
+pub fn watched_files(dir: &Path) -> Vec<PathBuf> {
+    let config_path = dir.join("tile_config.json");
+    let mut ret = vec![config_path.clone(), dir.join(CONFIG_FILE_NAME)];
+    let config: Value = match std::fs::read_to_string(&config_path)
+        .ok()
+        .and_then(|x| serde_json::from_str(&x).ok())
+    {
+        Some(x) => x,
+        None => return ret,
+    };
+    let sheets = config
+        .get("tiles-new")
+        .and_then(Value::as_array)
+        .map_or(vec![], |x| x.iter().collect());
+    let legacy = std::iter::once(&config);
+    for sheet in sheets.into_iter().chain(legacy) {
+        if let Some(file) = sheet.get("file").and_then(Value::as_str) {
+            ret.push(dir.join(file));
+        }
+    }
+    ret
+}
+
+/// Modification time of each file, `None` if it doesn't exist.
+fn modified(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|x| std::fs::metadata(x).and_then(|x| x.modified()).ok())
+        .collect()
+}
+
+/// Blocks until one of the files is created, modified or deleted, and then
+/// until they stop changing, so that a save in several steps counts once.
+pub fn wait_for_change(files: &[PathBuf]) {
+    let before = modified(files);
+    let mut last = before.clone();
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let now = modified(files);
+        if now != before && now == last {
+            return;
+        }
+        last = now;
+    }
+}
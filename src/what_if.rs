@@ -0,0 +1,55 @@
+use crate::error::{Error, Result};
+use crate::tileset::Tileset;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A sheet image to swap for another, written as `<sheet file>=<new image>`.
+#[derive(Clone, Debug, Serialize)]
+pub struct SheetReplacement {
+    pub file: String,
+    pub with: PathBuf,
+}
+
+impl FromStr for SheetReplacement {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((file, with)) if !file.is_empty() && !with.is_empty() => Ok(SheetReplacement {
+                file: file.to_string(),
+                with: PathBuf::from(with),
+            }),
+            _ => Err(format!("expected <sheet file>=<new image>, got '{}'", s)),
+        }
+    }
+}
+
+/// Makes `ts` read the replaced sheets from their new images. Nothing on
+/// disk is touched.
+pub fn replace_sheets(ts: &mut Tileset, replacements: &[SheetReplacement]) -> Result<()> {
+    for replacement in replacements {
+        if !ts.tiles_new.iter().any(|x| x.file == replacement.file) {
+            return Err(Error::BadTileset {
+                path: ts.base_path.clone(),
+                message: format!("has no sheet '{}'", replacement.file),
+            });
+        }
+        if !replacement.with.is_file() {
+            return Err(Error::NoInputFile(replacement.with.clone()));
+        }
+        ts.sheet_images
+            .insert(replacement.file.clone(), replacement.with.clone());
+    }
+    Ok(())
+}
+
+/// Outcome of `what-if`, printed as JSON with `--json`. Fields are only ever
+/// added, so scripts can rely on them.
+#[derive(Serialize)]
+pub struct WhatIf<'a> {
+    pub replaced: &'a [SheetReplacement],
+    /// Ids whose appearance would change, `null` if duplicate ids prevented
+    /// the comparison
+    pub changed: Option<&'a [String]>,
+}
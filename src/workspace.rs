@@ -0,0 +1,221 @@
+use crate::error::{create_output_dir, Error, Result};
+use crate::file_name::{sanitize, unique_name};
+use crate::info;
+use clap::{ArgEnum, Args};
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const LOCK_FILE_NAME: &str = ".tileset-comparator.lock";
+const RUNS_DIR_NAME: &str = ".tileset-comparator";
+const PROBE_FILE_NAME: &str = ".tileset-comparator.probe";
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How concurrent invocations against the same tileset directory are kept
+/// from clobbering each other's outputs.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorkspaceMode {
+    /// Write outputs into the output directory of each tileset, waiting for
+    /// other runs writing there to finish first
+    Lock,
+    /// Write outputs into a fresh per-invocation directory inside the output
+    /// directory of each tileset
+    Isolated,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct WorkspaceOptions {
+    /// How to share tileset directories with concurrently running invocations
+    #[clap(long, arg_enum, default_value = "lock")]
+    workspace: WorkspaceMode,
+    /// Seconds to wait for another invocation's lock before giving up
+    #[clap(long, default_value = "600")]
+    lock_timeout: u64,
+}
+
+/// Lock file held for the duration of a run, removed on drop.
+struct DirLock {
+    path: PathBuf,
+}
+
+impl DirLock {
+    fn acquire(dir: &Path, timeout: Duration) -> Result<DirLock> {
+        let path = dir.join(LOCK_FILE_NAME);
+        let started = Instant::now();
+        let mut announced = false;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let _ = write!(file, "{}", std::process::id());
+                    return Ok(DirLock { path });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if !announced {
+                        let holder = std::fs::read_to_string(&path).unwrap_or_default();
+                        info!(
+                            "Waiting for another invocation (pid {}) to release {}",
+                            holder.trim(),
+                            path.to_string_lossy()
+                        );
+                        announced = true;
+                    }
+                    if started.elapsed() >= timeout {
+                        return Err(Error::LockTimeout(path));
+                    }
+                    std::thread::sleep(LOCK_POLL_INTERVAL);
+                }
+                Err(source) => return Err(Error::WriteOutput { path, source }),
+            }
+        }
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Output directories for one invocation, one per input tileset.
+pub struct Workspace {
+    pub dirs: Vec<PathBuf>,
+    _locks: Vec<DirLock>,
+}
+
+/// Locks the given tileset directories, writing outputs next to the tilesets.
+pub fn lock_dirs(bases: &[&Path], timeout: Duration) -> Result<Workspace> {
+    // Lock in a canonical order so two runs comparing A/B and B/A can't deadlock,
+    // and lock each directory once even if it's passed twice.
+    let mut unique: Vec<PathBuf> = bases
+        .iter()
+        .map(|x| x.canonicalize().unwrap_or_else(|_| x.to_path_buf()))
+        .collect();
+    unique.sort();
+    unique.dedup();
+    let locks = unique
+        .iter()
+        .map(|x| DirLock::acquire(x, timeout))
+        .collect::<Result<Vec<DirLock>>>()?;
+    Ok(Workspace {
+        dirs: bases.iter().map(|x| x.to_path_buf()).collect(),
+        _locks: locks,
+    })
+}
+
+/// Checks that files can be created in `dir` by creating and removing one,
+/// so an unwritable directory is reported before any output is written.
+fn check_writable(dir: &Path) -> Result<()> {
+    let path = dir.join(PROBE_FILE_NAME);
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .and_then(|_| std::fs::remove_file(&path))
+        .map_err(|source| Error::OutputNotWritable {
+            path: dir.to_path_buf(),
+            source,
+        })
+}
+
+/// Per-user cache directory for outputs of tilesets in read-only locations.
+pub(crate) fn user_cache_dir() -> Option<PathBuf> {
+    let non_empty = |name| std::env::var_os(name).filter(|x| !x.is_empty());
+    let cache = non_empty("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| non_empty("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| non_empty("HOME").map(|x| PathBuf::from(x).join(".cache")))?;
+    Some(cache.join("tileset-comparator"))
+}
+
+/// Output directory, in the working directory, of comparing tilesets `a`
+/// and `b` without `--output-dir`: `comparison-<A>-vs-<B>`, named after the
+/// tileset directories, archives or revisions as given.
+pub fn comparison_dir(a: &str, b: &str) -> PathBuf {
+    let name = |x: &str| {
+        let path = Path::new(x);
+        let full = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        full.file_name()
+            .map_or("tileset".to_string(), |x| sanitize(&x.to_string_lossy()))
+    };
+    PathBuf::from(format!("comparison-{}-vs-{}", name(a), name(b)))
+}
+
+/// One subfolder of `output_dir` per tileset, named after the tileset
+/// directory, with `_2`, `_3`, ... added to repeated names.
+pub fn output_subdirs(output_dir: &Path, bases: &[&Path]) -> Result<Vec<PathBuf>> {
+    let mut taken = HashSet::new();
+    let mut ret = vec![];
+    for base in bases {
+        let full = base.canonicalize().unwrap_or_else(|_| base.to_path_buf());
+        let name = full
+            .file_name()
+            .map_or("tileset".into(), |x| x.to_string_lossy());
+        let dir = output_dir.join(unique_name(&sanitize(&name), &mut taken));
+        create_output_dir(&dir)?;
+        ret.push(dir);
+    }
+    Ok(ret)
+}
+
+impl WorkspaceOptions {
+    /// Output directories for the given tileset directories: per-tileset
+    /// subfolders of `output_dir`, such as the `comparison-<A>-vs-<B>`
+    /// directory of compare and review, or the tilesets themselves if none
+    /// is given. With `Isolated`, a fresh run directory inside each.
+    ///
+    /// An unwritable `output_dir` is an error. Tilesets in unwritable
+    /// directories, such as mounted release archives, get subfolders of the
+    /// user cache directory instead.
+    pub fn prepare(&self, bases: &[&Path], output_dir: Option<&Path>) -> Result<Workspace> {
+        let subdirs = match output_dir {
+            Some(output_dir) => {
+                create_output_dir(output_dir)?;
+                check_writable(output_dir)?;
+                output_subdirs(output_dir, bases)?
+            }
+            None => match bases
+                .iter()
+                .map(|x| check_writable(x))
+                .find_map(Result::err)
+            {
+                None => bases.iter().map(|x| x.to_path_buf()).collect(),
+                Some(err) => {
+                    let cache = user_cache_dir().ok_or(err)?;
+                    info!(
+                        "NOTE: tileset directories are not writable, writing outputs to {} instead",
+                        cache.to_string_lossy()
+                    );
+                    create_output_dir(&cache)?;
+                    check_writable(&cache)?;
+                    output_subdirs(&cache, bases)?
+                }
+            },
+        };
+        let roots: Vec<&Path> = subdirs.iter().map(PathBuf::as_path).collect();
+        match self.workspace {
+            WorkspaceMode::Lock => lock_dirs(&roots, Duration::from_secs(self.lock_timeout)),
+            WorkspaceMode::Isolated => {
+                let stamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|x| x.as_secs())
+                    .unwrap_or(0);
+                let run_name = format!("run-{}-{}", stamp, std::process::id());
+                let dirs: Vec<PathBuf> = roots
+                    .iter()
+                    .map(|x| x.join(RUNS_DIR_NAME).join(&run_name))
+                    .collect();
+                for dir in &dirs {
+                    create_output_dir(dir)?;
+                    info!("Writing outputs to {}", dir.to_string_lossy());
+                }
+                Ok(Workspace {
+                    dirs,
+                    _locks: vec![],
+                })
+            }
+        }
+    }
+}